@@ -8,40 +8,74 @@ use clap::{CommandFactory, Parser, Subcommand, ValueEnum, arg, command};
 use clap_complete::{generate, shells};
 use color_eyre::owo_colors::OwoColorize;
 use const_format::concatcp;
-use eyre::OptionExt;
+use eyre::{OptionExt, WrapErr};
 use opentelemetry::trace::TracerProvider;
 use shadow_rs::{Format, shadow};
 use std::env::temp_dir;
 use std::ffi::OsString;
 use std::fs::{self, File};
 use std::io::Write;
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use std::{env, io};
 use tokio::runtime::Builder;
-use tracing::{Span, trace};
-use tracing::{debug, info, trace_span};
+use tracing::{Span, trace, warn};
+use tracing::{debug, info, info_span, trace_span};
 use tracing_subscriber::Registry;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_tree::HierarchicalLayer;
+use url::Url;
+use zako_core::action_cache::ActionCache;
+use zako_core::action_cache_server::ActionCacheServer;
 use zako_core::builtin::extension::syscall::ENABLE_PRINT;
-use zako_core::camino::Utf8PathBuf;
-use zako_core::cas_store::CasStoreOptions;
+use zako_core::camino::{Utf8Path, Utf8PathBuf};
+use zako_core::cas::{Cas, LocalStore};
+use zako_core::cas_gc::{GcOptions, collect_local_cas};
+use zako_core::cas_server::{CasServer, CasServerOptions};
+use zako_core::cas_store::{CasStore, CasStoreOptions, DEFAULT_MAX_INLINED_BLOB_SIZE};
+use zako_core::cas_token::{
+    DEFAULT_TOKEN_SWEEP_INTERVAL, StaticTokenConfig, TokenInterceptor, TokenStore,
+};
+use zako_core::cas_upload::UploadQueueOptions;
+use zako_core::cas_verify::{VerifyOptions, verify_local_cas};
+use zako_core::consts::{PACKAGE_LOCKFILE_NAME, REGISTRY_TOKEN_ENV, VENDOR_DIR_NAME};
 use zako_core::context::BuildContext;
+use zako_core::fetch::PackageCache;
+use zako_core::fetch::patch::Patches;
 use zako_core::hone::redb;
+use zako_core::http_cas::{HttpCas, HttpCasOptions};
+use zako_core::http_registry::{HttpRegistry, HttpRegistryOptions};
 use zako_core::intern::InternedAbsolutePath;
+use zako_core::local_action_cache::LocalActionCache;
+use zako_core::local_cas::LocalCas;
+use zako_core::local_registry::LocalRegistry;
+use zako_core::lockfile::{Lockfile, update};
+use zako_core::manifest::{self, ManifestFormat, ManifestSource};
+use zako_core::memory_cas::MemoryCas;
 use zako_core::node::node_key::ZakoKey;
 use zako_core::node::resolve_package::ResolvePackage;
+use zako_core::overrides::Overrides;
+use zako_core::package_archive;
 use zako_core::package_id::InternedPackageId;
 use zako_core::package_source::PackageSource;
 use zako_core::path::NeutralPath;
+use zako_core::protobuf::action_cache::action_cache_server::ActionCacheServer as ActionCacheServiceServer;
+use zako_core::protobuf::cas::content_addressable_storage_server::ContentAddressableStorageServer;
+use zako_core::reapi_cas::{ReapiCas, ReapiCasOptions};
 use zako_core::resource::ResourcePool;
 use zako_core::resource::heuristics::{
-    determine_memory_tti_for_cas, determine_memory_ttl_for_cas, determine_oxc_workers_config,
+    determine_local_cas_path, determine_memory_tti_for_cas, determine_memory_ttl_for_cas,
+    determine_oxc_workers_config, determine_package_cache_path, determine_user_overrides_path,
     determine_v8_workers_config,
 };
+use zako_core::tiered_cas::{CasTier, TierAccess};
+use zako_core::transport_server::TransportServer;
+use zako_core::vendor::{Vendor, vendor};
 use zako_core::worker::v8worker::V8Worker;
 use zako_core::worker::worker_pool::PoolConfig;
+use zako_core::workspace::Workspace;
 use zako_core::zako_cancel::{CancelSource, CancelToken};
 use zako_core::{HoneComputer, sysinfo};
 
@@ -148,6 +182,13 @@ enum SubCommands {
     GenerateComplete(GenerateCompleteArgs),
     ExportBuiltin(ExportBuiltinArgs),
     Make(MakeArgs),
+    Update(UpdateArgs),
+    Vendor(VendorArgs),
+    Package(PackageArgs),
+    Publish(PublishArgs),
+    Manifest(ManifestArgs),
+    CasServer(CasServerArgs),
+    Cache(CacheArgs),
     Bun(BunArgs),
     BunX(BunArgs),
     V8Snapshot(V8SnapshotArgs),
@@ -365,20 +406,55 @@ impl ExportBuiltinArgs {
 #[derive(clap::Args, Debug)]
 #[command(name = "make", about = "Build the package")]
 struct MakeArgs {
-    #[arg(long,default_value = ".", value_hint = clap::ValueHint::DirPath)]
-    package_root: String,
+    #[arg(
+        long,
+        value_hint = clap::ValueHint::DirPath,
+        help = "The package or workspace root, default to the workspace that encloses the current directory"
+    )]
+    package_root: Option<String>,
 
     #[arg(long,default_value = ".", value_hint = clap::ValueHint::DirPath)]
     package_relative_path: String,
 
-    #[arg(long)]
-    package_id: String,
+    #[arg(
+        long,
+        help = "The package to build, default to the package that encloses the current directory"
+    )]
+    package_id: Option<String>,
 
     #[arg(long,default_value = "./.zako/cache.db", value_hint = clap::ValueHint::FilePath)]
     database_file: String,
 
     #[arg(long, help = "Set the cpu counts to use")]
     concurrency: Option<usize>,
+
+    #[arg(
+        long,
+        value_hint = clap::ValueHint::Url,
+        help = "The remote caches to read from and upload to in order, like `http://cache.example.com:8080` or `grpc://cache.example.com:9092`, prefix `ro+` for read-only"
+    )]
+    remote_cache: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        num_args = 0..=1,
+        help = "Keep the local cas in memory instead of the cache directory, optionally limited to BYTES"
+    )]
+    memory_cas: Option<Option<u64>>,
+
+    #[arg(
+        long,
+        value_name = "DIR|URL",
+        help = "The registry to fetch the registry packages from, a directory or a `http://` url"
+    )]
+    registry: Option<String>,
+
+    #[arg(
+        long,
+        help = "Fail instead of updating `zako.lock`, like a dependency that is not locked yet"
+    )]
+    locked: bool,
 }
 
 impl MakeArgs {
@@ -387,8 +463,21 @@ impl MakeArgs {
 
         let concurrency = self.concurrency.unwrap_or(num_cpus::get()) as u64;
 
-        let package_root = Utf8PathBuf::from(self.package_root);
-        let package_root = package_root.canonicalize_utf8()?;
+        let workspace = match self.package_root.as_deref() {
+            Some(root) => Workspace::open(Utf8Path::new(root))
+                .wrap_err_with(|| format!("`{}` is not a package root", root))?,
+            None => Workspace::discover(Utf8Path::new(".")).wrap_err(
+                "the current directory is not in a package, \
+                 run `zako make` in a package directory or set `--package-root`",
+            )?,
+        };
+        let package_root = workspace.get_root().clone();
+
+        info!(
+            "use workspace {} with {} packages",
+            package_root,
+            workspace.get_members().len()
+        );
 
         info!("use concurrency {}", concurrency);
 
@@ -413,23 +502,77 @@ impl MakeArgs {
             Default::default(),
         );
 
-        let cas_store_options = CasStoreOptions {
-            max_cache_capacity: 4 * 1024,
-            max_cache_ttl: determine_memory_ttl_for_cas(&system),
-            max_cache_tti: determine_memory_tti_for_cas(&system),
-        };
+        let mut cas_store_options = cas_store_options(&system, None);
 
         let oxc_config = determine_oxc_workers_config(&system);
         let v8_config = determine_v8_workers_config(&system);
+        let user_overrides_path = determine_user_overrides_path(&system);
+
+        let remote_cas_tiers = self
+            .remote_cache
+            .iter()
+            .map(|spec| open_remote_cache(spec))
+            .collect::<eyre::Result<Vec<_>>>()?;
+
+        let local_cas: Box<dyn LocalStore> = match self.memory_cas {
+            Some(limit) => Box::new(MemoryCas::new(limit)),
+            None => {
+                let local_cas = LocalCas::new(determine_local_cas_path(&system));
+                cas_store_options.lease_dir = Some(local_cas.get_lease_path());
+                Box::new(local_cas)
+            }
+        };
 
-        let global_state = zako_core::global_state::GlobalState::new(
+        let global_state = zako_core::global_state::GlobalState::new_with_local_cas(
             system,
             resource_pool,
+            local_cas,
             cas_store_options,
+            remote_cas_tiers,
+            self.registry.as_deref().map(open_registry).transpose()?,
             oxc_config,
             v8_config,
         )?;
 
+        let lockfile_path = package_root.join(PACKAGE_LOCKFILE_NAME);
+        global_state
+            .lock_state()
+            .reset(Lockfile::load(lockfile_path.as_std_path())?, self.locked);
+
+        let vendor_dir = package_root.join(VENDOR_DIR_NAME);
+        if let Some(vendor) = Vendor::open(vendor_dir.as_std_path())? {
+            info!("resolve the vendored dependencies from {}", vendor_dir);
+            global_state.set_vendor(Some(vendor));
+        }
+
+        // only the patches and the overrides of the workspace root are used
+        let root_package = workspace.get_root_package();
+        let (root_manifest_path, _) =
+            manifest::find(&root_package.root).ok_or_eyre("the workspace root has no manifest")?;
+
+        let patches = Patches::load(root_package.root.as_std_path(), &root_package.manifest)?;
+        let mut overrides = Overrides::from_manifest(
+            &root_package.root,
+            root_manifest_path.as_std_path(),
+            &root_package.manifest,
+        )?;
+        // the user config is personal, only the overrides of the manifest are locked
+        let locked_overrides = overrides.to_locked();
+        overrides.merge(Overrides::load_user(&user_overrides_path)?);
+
+        for (alias, overridden) in overrides.iter() {
+            warn!(
+                "the dependency `@{}` is overridden by {} from {:?}",
+                alias, overridden.path, overridden.origin
+            );
+        }
+
+        global_state
+            .lock_state()
+            .record_local_changes(patches.to_locked(), locked_overrides)?;
+        global_state.set_patches(patches);
+        global_state.set_overrides(overrides);
+
         let hone = zako_core::HoneEngine::new(Arc::new(HoneComputer::new()), database)?;
 
         let package_source = PackageSource::Path {
@@ -445,12 +588,30 @@ impl MakeArgs {
 
         let cancel_source = CancelSource::new();
 
-        let package_id = InternedPackageId::try_parse(&self.package_id, &context.interner())?;
-        let package_path = InternedAbsolutePath::new(&package_root, global_state.interner())?;
+        workspace.register(&global_state)?;
 
-        global_state
-            .package_id_to_path()
-            .insert(package_id, package_path);
+        let package_id = match self.package_id.as_deref() {
+            Some(package_id) => InternedPackageId::try_parse(package_id, &context.interner())?,
+            None => {
+                // the member that encloses the current directory, the root package if none
+                let current_dir = Utf8PathBuf::try_from(env::current_dir()?.canonicalize()?)?;
+                let member = workspace
+                    .get_member_of(&current_dir)
+                    .unwrap_or(workspace.get_root_package());
+
+                info!("build the package {} at {}", member.get_id(), member.root);
+
+                member.get_package_id(global_state.interner())?
+            }
+        };
+
+        if !global_state.package_id_to_path().contains_key(&package_id) {
+            let package_path = InternedAbsolutePath::new(&package_root, global_state.interner())?;
+
+            global_state
+                .package_id_to_path()
+                .insert(package_id, package_path);
+        }
 
         let handle = global_state.handle();
 
@@ -468,10 +629,782 @@ impl MakeArgs {
             .await
         })())?;
 
+        if global_state.lock_state().is_changed() {
+            info!("update {}", lockfile_path);
+            global_state
+                .lock_state()
+                .snapshot()
+                .write(lockfile_path.as_std_path())?;
+        }
+
+        let uploads = handle.block_on(global_state.cas_store().flush_uploads());
+
+        for failure in uploads.failures.iter() {
+            warn!(
+                "blob {} is not uploaded to the remote cas: {}",
+                failure.digest.hex_blake3(),
+                failure.error
+            );
+        }
+
+        if !uploads.is_empty() {
+            info!("{}", uploads);
+        }
+
+        for report in global_state.cas_store().tier_reports() {
+            info!("cas tier {}", report);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(clap::Args, Debug)]
+#[command(
+    name = "update",
+    about = "Fetch the dependencies again and update their pins in `zako.lock`"
+)]
+struct UpdateArgs {
+    #[arg(
+        long,
+        value_hint = clap::ValueHint::DirPath,
+        help = "The package or workspace root, default to the workspace that encloses the current directory"
+    )]
+    package_root: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "DIR|URL",
+        help = "The registry to fetch the registry packages from, a directory or a `http://` url"
+    )]
+    registry: Option<String>,
+
+    #[arg(
+        help = "The names of the dependencies in the manifest to update with what they depend on, all of them if empty"
+    )]
+    dependencies: Vec<String>,
+}
+
+impl UpdateArgs {
+    pub fn invoke(self) -> eyre::Result<()> {
+        let system = sysinfo::System::new_all();
+
+        let workspace = match self.package_root.as_deref() {
+            Some(root) => Workspace::open(Utf8Path::new(root))?,
+            None => Workspace::discover(Utf8Path::new("."))?,
+        };
+        let package_root = workspace.get_root().clone();
+
+        // the dependencies of every package in the workspace, with the root that declares them
+        let dependencies = workspace
+            .get_members()
+            .iter()
+            .flat_map(|member| {
+                member
+                    .manifest
+                    .dependencies
+                    .iter()
+                    .flatten()
+                    .map(|(alias, source)| (alias, member.root.as_std_path(), source))
+            })
+            .collect::<Vec<_>>();
+
+        for name in self.dependencies.iter() {
+            if !dependencies.iter().any(|(alias, _, _)| *alias == name) {
+                return Err(eyre::eyre!(
+                    "the dependency `{}` is not in the manifests of the workspace",
+                    name
+                ));
+            }
+        }
+
+        let mut refreshed = Vec::new();
+        for (alias, _, source) in dependencies.iter() {
+            let selected =
+                self.dependencies.is_empty() || self.dependencies.iter().any(|name| *alias == name);
+
+            if selected && !refreshed.contains(*source) {
+                refreshed.push((*source).clone());
+            }
+        }
+
+        let dependencies = dependencies
+            .into_iter()
+            .map(|(_, root, source)| (root.to_path_buf(), source.clone()))
+            .collect::<Vec<_>>();
+
+        let mut cache = PackageCache::new(determine_package_cache_path(&system));
+        if let Some(registry) = self.registry.as_deref() {
+            cache = cache.with_registry(open_registry(registry)?);
+        }
+
+        let lockfile_path = package_root.join(PACKAGE_LOCKFILE_NAME);
+        let mut lockfile = Lockfile::load(lockfile_path.as_std_path())?;
+
+        let runtime = Builder::new_multi_thread().enable_all().build()?;
+
+        let changed = runtime.block_on(async {
+            let cas = open_local_cas_store(&system);
+            update(&mut lockfile, &cache, &cas, dependencies, &refreshed).await
+        })?;
+
+        if changed {
+            lockfile.write(lockfile_path.as_std_path())?;
+            println!("updated {}", lockfile_path);
+        } else {
+            println!("{} is up to date", lockfile_path);
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(clap::Args, Debug)]
+#[command(
+    name = "vendor",
+    about = "Copy the dependencies into `vendor/` so the builds need no network"
+)]
+struct VendorArgs {
+    #[arg(
+        long,
+        value_hint = clap::ValueHint::DirPath,
+        help = "The package or workspace root, default to the workspace that encloses the current directory"
+    )]
+    package_root: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "DIR|URL",
+        help = "The registry to fetch the registry packages from, a directory or a `http://` url"
+    )]
+    registry: Option<String>,
+}
+
+impl VendorArgs {
+    pub fn invoke(self) -> eyre::Result<()> {
+        let system = sysinfo::System::new_all();
+
+        let workspace = match self.package_root.as_deref() {
+            Some(root) => Workspace::open(Utf8Path::new(root))?,
+            None => Workspace::discover(Utf8Path::new("."))?,
+        };
+        let package_root = workspace.get_root().clone();
+
+        // the dependencies of every package in the workspace
+        let mut requested = Vec::new();
+        for (_, source) in workspace
+            .get_members()
+            .iter()
+            .flat_map(|member| member.manifest.dependencies.iter().flatten())
+        {
+            if !requested.contains(source) {
+                requested.push(source.clone());
+            }
+        }
+
+        let mut cache = PackageCache::new(determine_package_cache_path(&system));
+        if let Some(registry) = self.registry.as_deref() {
+            cache = cache.with_registry(open_registry(registry)?);
+        }
+
+        let lockfile_path = package_root.join(PACKAGE_LOCKFILE_NAME);
+        let mut lockfile = Lockfile::load(lockfile_path.as_std_path())?;
+        let vendor_dir = package_root.join(VENDOR_DIR_NAME);
+
+        let runtime = Builder::new_multi_thread().enable_all().build()?;
+
+        let report = runtime.block_on(async {
+            let cas = open_local_cas_store(&system);
+            vendor(
+                vendor_dir.as_std_path(),
+                &mut lockfile,
+                &cache,
+                &cas,
+                requested,
+            )
+            .await
+        })?;
+
+        lockfile.write(lockfile_path.as_std_path())?;
+
+        println!(
+            "vendored into {}: {} copied, {} up to date, {} removed",
+            vendor_dir, report.copied, report.reused, report.removed
+        );
+
+        Ok(())
+    }
+}
+
+/// The options of the [CasStore] of the commands, the leases are kept in the `lease_dir`.
+fn cas_store_options(system: &sysinfo::System, lease_dir: Option<PathBuf>) -> CasStoreOptions {
+    CasStoreOptions {
+        max_cache_capacity: 4 * 1024,
+        max_cache_ttl: determine_memory_ttl_for_cas(system),
+        max_cache_tti: determine_memory_tti_for_cas(system),
+        upload_queue: UploadQueueOptions::default(),
+        max_inlined_blob_size: DEFAULT_MAX_INLINED_BLOB_SIZE,
+        lease_dir,
+    }
+}
+
+/// The [CasStore] over the local cas only, for the commands that fetch the dependencies.
+fn open_local_cas_store(system: &sysinfo::System) -> CasStore {
+    let local_cas = LocalCas::new(determine_local_cas_path(system));
+    let options = cas_store_options(system, Some(local_cas.get_lease_path()));
+
+    CasStore::new(Box::new(local_cas), Vec::new(), options)
+}
+
+/// The root of the package to archive, the member that encloses the current directory by default.
+fn find_package_root(package_root: Option<&str>) -> eyre::Result<Utf8PathBuf> {
+    if let Some(root) = package_root {
+        return Ok(Utf8Path::new(root).canonicalize_utf8()?);
+    }
+
+    let workspace = Workspace::discover(Utf8Path::new(".")).wrap_err(
+        "the current directory is not in a package, \
+         run it in a package directory or set `--package-root`",
+    )?;
+    let current_dir = Utf8PathBuf::try_from(env::current_dir()?.canonicalize()?)?;
+
+    Ok(workspace
+        .get_member_of(&current_dir)
+        .unwrap_or(workspace.get_root_package())
+        .root
+        .clone())
+}
+
+#[derive(clap::Args, Debug)]
+#[command(
+    name = "package",
+    about = "Create the reproducible archive of the package that `zako publish` uploads"
+)]
+struct PackageArgs {
+    #[arg(
+        long,
+        value_hint = clap::ValueHint::DirPath,
+        help = "The package root, default to the package that encloses the current directory"
+    )]
+    package_root: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        value_hint = clap::ValueHint::FilePath,
+        help = "Where to write the archive, default to `.zako/package/<artifact>-<version>.tar.zst` in the package root"
+    )]
+    output: Option<String>,
+
+    #[arg(long, help = "Print the files in the archive instead of writing it")]
+    list: bool,
+}
+
+impl PackageArgs {
+    pub fn invoke(self) -> eyre::Result<()> {
+        let package_root = find_package_root(self.package_root.as_deref())?;
+        let archive = package_archive::create(&package_root)?;
+
+        if self.list {
+            for file in archive.files.iter() {
+                println!("{}", file);
+            }
+            return Ok(());
+        }
+
+        let output = match self.output {
+            Some(output) => Utf8PathBuf::from(output),
+            None => package_root
+                .join(".zako")
+                .join("package")
+                .join(archive.get_file_name()),
+        };
+
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&output, &archive.data)?;
+
+        println!(
+            "packaged {} files into {} ({})",
+            archive.files.len(),
+            output,
+            archive.get_integrity()
+        );
+
         Ok(())
     }
 }
 
+#[derive(clap::Args, Debug)]
+#[command(
+    name = "publish",
+    about = "Upload the archive of the package to a registry and add it to the index"
+)]
+struct PublishArgs {
+    #[arg(
+        long,
+        value_hint = clap::ValueHint::DirPath,
+        help = "The package root, default to the package that encloses the current directory"
+    )]
+    package_root: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "DIR|URL",
+        help = "The registry to publish to, a directory or a `http://` url that accepts `PUT`, \
+                the token in `ZAKO_REGISTRY_TOKEN` is sent as `Authorization: Bearer <TOKEN>`"
+    )]
+    registry: String,
+}
+
+impl PublishArgs {
+    pub fn invoke(self) -> eyre::Result<()> {
+        let package_root = find_package_root(self.package_root.as_deref())?;
+        let archive = package_archive::create(&package_root)?;
+
+        let registry = open_registry_with(
+            &self.registry,
+            HttpRegistryOptions {
+                // never a command line argument, it would be in the shell history and `ps`
+                bearer_token: env::var(REGISTRY_TOKEN_ENV).ok(),
+                ..Default::default()
+            },
+        )?;
+
+        let runtime = Builder::new_multi_thread().enable_all().build()?;
+        let entry = runtime.block_on(package_archive::publish(registry.as_ref(), &archive))?;
+
+        println!(
+            "published {}:{}@{} to {} ({})",
+            archive.package.group,
+            archive.package.artifact,
+            entry.version,
+            self.registry,
+            entry.integrity
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(clap::Args, Debug)]
+#[command(name = "manifest", about = "Work with the package manifest")]
+struct ManifestArgs {
+    #[command(subcommand)]
+    command: ManifestSubCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum ManifestSubCommands {
+    Convert(ManifestConvertArgs),
+}
+
+impl ManifestArgs {
+    pub fn invoke(self) -> eyre::Result<()> {
+        match self.command {
+            ManifestSubCommands::Convert(args) => args.invoke(),
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum ManifestFormatArg {
+    Toml,
+    Json,
+}
+
+impl From<ManifestFormatArg> for ManifestFormat {
+    fn from(value: ManifestFormatArg) -> Self {
+        match value {
+            ManifestFormatArg::Toml => ManifestFormat::Toml,
+            ManifestFormatArg::Json => ManifestFormat::Json,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+#[command(
+    name = "convert",
+    about = "Convert a manifest between `zako.toml` and `zako.json`"
+)]
+struct ManifestConvertArgs {
+    #[arg(value_hint = clap::ValueHint::FilePath, help = "The manifest to convert, a `.toml` or `.json` file")]
+    input: PathBuf,
+
+    #[arg(long, short = 'o', value_hint = clap::ValueHint::FilePath, help = "Write the converted manifest to this file instead of stdout")]
+    output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "The format to convert to, default to the extension of the output or the other format of the input"
+    )]
+    to: Option<ManifestFormatArg>,
+}
+
+impl ManifestConvertArgs {
+    pub fn invoke(self) -> eyre::Result<()> {
+        let from = ManifestFormat::from_path(&self.input).ok_or_else(|| {
+            eyre::eyre!(
+                "the manifest {:?} is neither a `.toml` nor a `.json` file",
+                self.input
+            )
+        })?;
+
+        let to = match (self.to, self.output.as_deref()) {
+            (Some(to), _) => to.into(),
+            (None, Some(output)) => ManifestFormat::from_path(output).ok_or_else(|| {
+                eyre::eyre!("can not tell the format of {:?}, set `--to`", output)
+            })?,
+            (None, None) => match from {
+                ManifestFormat::Toml => ManifestFormat::Json,
+                ManifestFormat::Json => ManifestFormat::Toml,
+            },
+        };
+
+        let content = fs::read_to_string(&self.input)?;
+        let package = ManifestSource::new(&self.input, content, from).parse()?;
+        let converted = manifest::to_string(&package, to)?;
+
+        match self.output {
+            Some(output) => {
+                fs::write(&output, converted)?;
+                info!("write the converted manifest to {:?}", output);
+            }
+            None => print!("{}", converted),
+        }
+
+        Ok(())
+    }
+}
+
+/// Open the remote cache that a `--remote-cache` points to, a `ro+` prefix makes it read-only.
+fn open_remote_cache(spec: &str) -> eyre::Result<CasTier> {
+    let (access, url) = match spec.strip_prefix("ro+") {
+        Some(url) => (TierAccess::ReadOnly, url),
+        None => (TierAccess::ReadWrite, spec),
+    };
+
+    let url = Url::parse(url)?;
+
+    let cas: Arc<dyn Cas> = match url.scheme() {
+        "http" | "https" => Arc::new(HttpCas::new(url.clone(), HttpCasOptions::default())?),
+        // a REAPI v2 server, like bazel-remote or Buildbarn
+        "grpc" | "grpcs" => Arc::new(ReapiCas::lazy(url.as_str(), ReapiCasOptions::default())?),
+        scheme => {
+            return Err(eyre::eyre!(
+                "unsupported remote cache scheme `{}`, expect `http`, `https`, `grpc` or `grpcs`",
+                scheme
+            ));
+        }
+    };
+
+    Ok(CasTier::new(url.as_str(), cas, access))
+}
+
+/// Open the registry that a `--registry` points to, a `http://` or `https://` url or a directory.
+fn open_registry(spec: &str) -> eyre::Result<Arc<dyn zako_core::registry::Registry>> {
+    open_registry_with(spec, HttpRegistryOptions::default())
+}
+
+/// Like [open_registry] but a http registry uses the `options`.
+fn open_registry_with(
+    spec: &str,
+    options: HttpRegistryOptions,
+) -> eyre::Result<Arc<dyn zako_core::registry::Registry>> {
+    if let Ok(url) = Url::parse(spec)
+        && (url.scheme() == "http" || url.scheme() == "https")
+    {
+        return Ok(Arc::new(HttpRegistry::new(url, options)?));
+    }
+
+    Ok(Arc::new(LocalRegistry::new(std::path::absolute(spec)?)))
+}
+
+#[derive(clap::Args, Debug)]
+#[command(
+    name = "cas-server",
+    about = "Serve a local content addressable storage over gRPC"
+)]
+struct CasServerArgs {
+    #[arg(long, default_value = "127.0.0.1:9092")]
+    listen: SocketAddr,
+
+    #[arg(
+        long,
+        help = "The address that clients use to reach this server, default to `--listen`"
+    )]
+    advertise: Option<SocketAddr>,
+
+    #[arg(long, value_hint = clap::ValueHint::DirPath, help = "The root of the CAS, default to the user cache directory")]
+    root: Option<PathBuf>,
+
+    #[arg(long, default_value = "1h", value_parser = humantime::parse_duration, help = "The lifetime of the issued transport tokens")]
+    token_ttl: Duration,
+
+    #[arg(
+        long,
+        value_hint = clap::ValueHint::FilePath,
+        help = "A toml file that contains pre-shared tokens, the clients need one to get a transport token"
+    )]
+    static_tokens: Option<PathBuf>,
+
+    #[arg(long, value_hint = clap::ValueHint::FilePath, help = "Also serve an action cache stored in this database file")]
+    action_cache: Option<PathBuf>,
+}
+
+impl CasServerArgs {
+    pub fn invoke(self) -> eyre::Result<()> {
+        let root = match self.root {
+            Some(root) => root,
+            None => determine_local_cas_path(&sysinfo::System::new()),
+        };
+        fs::create_dir_all(&root)?;
+        let root = root.canonicalize()?;
+
+        let mut tokens = TokenStore::new(self.token_ttl);
+
+        match self.static_tokens {
+            Some(static_tokens) => {
+                tokens = tokens.with_static_tokens(StaticTokenConfig::load(&static_tokens)?);
+            }
+            None => warn!(
+                "no `--static-tokens` is set, every client that reaches the cas server gets a transport token"
+            ),
+        }
+
+        let tokens = Arc::new(tokens);
+        let cas: Arc<dyn Cas> = Arc::new(LocalCas::new(root.clone()));
+
+        let mut options = CasServerOptions::new_default(
+            cas.clone(),
+            self.advertise.unwrap_or(self.listen).into(),
+        );
+        options.tokens = tokens.clone();
+
+        let action_cache = match self.action_cache {
+            Some(path) => {
+                let cache: Arc<dyn ActionCache> = Arc::new(LocalActionCache::open(&path)?);
+                Some(ActionCacheServiceServer::with_interceptor(
+                    ActionCacheServer::new(cache),
+                    TokenInterceptor::new(tokens.clone()),
+                ))
+            }
+            None => None,
+        };
+
+        let runtime = Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("zako-cas-server")
+            .build()?;
+
+        runtime.block_on(async move {
+            let _sweeper = tokens.spawn_sweeper(
+                &tokio::runtime::Handle::current(),
+                DEFAULT_TOKEN_SWEEP_INTERVAL,
+            );
+
+            info!("serve cas {:?} on {}", root, self.listen);
+
+            tonic::transport::Server::builder()
+                .trace_fn(|request| {
+                    info_span!(
+                        "cas server request",
+                        method = %request.method(),
+                        path = %request.uri().path()
+                    )
+                })
+                .add_service(ContentAddressableStorageServer::with_interceptor(
+                    CasServer::new(options),
+                    TokenInterceptor::new(tokens.clone()),
+                ))
+                .add_service(TransportServer::new(cas).into_authenticated_service(tokens.clone()))
+                .add_optional_service(action_cache)
+                .serve_with_shutdown(self.listen, shutdown_signal())
+                .await?;
+
+            info!("cas server stopped");
+
+            Ok(())
+        })
+    }
+}
+
+#[derive(clap::Args, Debug)]
+#[command(name = "cache", about = "Manage the local content addressable storage")]
+struct CacheArgs {
+    #[command(subcommand)]
+    command: CacheSubCommands,
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheSubCommands {
+    Verify(CacheVerifyArgs),
+    Gc(CacheGcArgs),
+}
+
+impl CacheArgs {
+    pub fn invoke(self) -> eyre::Result<()> {
+        match self.command {
+            CacheSubCommands::Verify(args) => args.invoke(),
+            CacheSubCommands::Gc(args) => args.invoke(),
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+#[command(
+    name = "verify",
+    about = "Re-hash every blob of the local CAS to find the corrupted ones"
+)]
+struct CacheVerifyArgs {
+    #[arg(long, help = "Move the corrupted blobs to the quarantine directory")]
+    repair: bool,
+
+    #[arg(long, value_hint = clap::ValueHint::DirPath, help = "The root of the CAS, default to the user cache directory")]
+    root: Option<PathBuf>,
+
+    #[arg(long, value_hint = clap::ValueHint::FilePath, help = "Write the report as json to this file")]
+    report: Option<PathBuf>,
+
+    #[arg(long, help = "The count of blobs that are hashed at the same time")]
+    concurrency: Option<usize>,
+}
+
+impl CacheVerifyArgs {
+    pub fn invoke(self) -> eyre::Result<()> {
+        let root = match self.root {
+            Some(root) => root,
+            None => determine_local_cas_path(&sysinfo::System::new()),
+        };
+        let cas = LocalCas::new(root);
+
+        let options = VerifyOptions {
+            repair: self.repair,
+            concurrency: self.concurrency.unwrap_or(num_cpus::get()),
+        };
+
+        let runtime = Builder::new_multi_thread().enable_all().build()?;
+        let report = runtime.block_on(verify_local_cas(&cas, &options))?;
+
+        if let Some(path) = self.report {
+            fs::write(&path, serde_json::to_vec_pretty(&report)?)?;
+            info!("write verify report to {:?}", path);
+        }
+
+        println!(
+            "checked {} blobs ({} bytes) in {:?}",
+            report.checked, report.checked_bytes, report.root
+        );
+
+        for path in report.unrecognized.iter() {
+            println!("unrecognized entry: {:?}", path);
+        }
+
+        for blob in report.corrupted.iter() {
+            match blob.quarantined_to.as_ref() {
+                Some(quarantined_to) => println!(
+                    "corrupted blob: {:?}, moved to {:?}",
+                    blob.path, quarantined_to
+                ),
+                None => println!("corrupted blob: {:?}", blob.path),
+            }
+        }
+
+        if !report.is_clean() && !report.repair {
+            return Err(eyre::eyre!(
+                "found {} corrupted blobs, rerun with `--repair` to quarantine them",
+                report.corrupted.len()
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(clap::Args, Debug)]
+#[command(
+    name = "gc",
+    about = "Remove the old blobs of the local CAS that no running build has pinned"
+)]
+struct CacheGcArgs {
+    #[arg(long, default_value = "30d", value_parser = humantime::parse_duration, help = "Remove the blobs that are not used for this long")]
+    max_age: Duration,
+
+    #[arg(long, help = "Report what would be removed without removing anything")]
+    dry_run: bool,
+
+    #[arg(long, value_hint = clap::ValueHint::DirPath, help = "The root of the CAS, default to the user cache directory")]
+    root: Option<PathBuf>,
+}
+
+impl CacheGcArgs {
+    pub fn invoke(self) -> eyre::Result<()> {
+        let root = match self.root {
+            Some(root) => root,
+            None => determine_local_cas_path(&sysinfo::System::new()),
+        };
+        let cas = LocalCas::new(root);
+
+        let report = collect_local_cas(
+            &cas,
+            &GcOptions {
+                max_age: self.max_age,
+                dry_run: self.dry_run,
+            },
+        )?;
+
+        println!(
+            "scanned {} blobs in {:?}, {} {} blobs ({} bytes), kept {} pinned blobs",
+            report.scanned,
+            report.root,
+            if report.dry_run {
+                "would remove"
+            } else {
+                "removed"
+            },
+            report.removed,
+            report.removed_bytes,
+            report.pinned
+        );
+
+        Ok(())
+    }
+}
+
+/// Resolves when the process receives ctrl-c or SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            warn!("failed to listen for ctrl-c: {}", err);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                warn!("failed to listen for SIGTERM: {}", err);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("shutdown signal received, draining connections");
+}
+
 #[derive(Debug, Clone, ValueEnum)]
 enum Shell {
     Bash,
@@ -778,6 +1711,13 @@ fn inner_main() -> eyre::Result<()> {
         SubCommands::Information(args) => args.invoke(),
         SubCommands::GenerateComplete(args) => args.invoke(),
         SubCommands::Make(args) => args.invoke(),
+        SubCommands::Update(args) => args.invoke(),
+        SubCommands::Vendor(args) => args.invoke(),
+        SubCommands::Package(args) => args.invoke(),
+        SubCommands::Publish(args) => args.invoke(),
+        SubCommands::Manifest(args) => args.invoke(),
+        SubCommands::CasServer(args) => args.invoke(),
+        SubCommands::Cache(args) => args.invoke(),
         SubCommands::ExportBuiltin(args) => args.invoke(),
         SubCommands::Bun(args) => run_bun(args.args),
         SubCommands::BunX(args) => run_bun({
@@ -3,6 +3,8 @@ use std::io::Result;
 fn main() -> Result<()> {
     tonic_prost_build::configure()
         .extern_path(".zako.v1.digest", "::zako_digest::protobuf")
+        // the chunks of the blobs are sent without copying
+        .bytes(".google.bytestream")
         .compile_protos(
             &[
                 "src/protobuf/fs.proto",
@@ -10,8 +12,15 @@ fn main() -> Result<()> {
                 "src/protobuf/cas.proto",
                 "src/protobuf/transport.proto",
                 "src/protobuf/range.proto",
+                "src/protobuf/action_cache.proto",
+                "src/protobuf/reapi/build/bazel/remote/execution/v2/remote_execution.proto",
+                "src/protobuf/reapi/google/bytestream/bytestream.proto",
+            ],
+            &[
+                "src/protobuf/",
+                "src/protobuf/reapi/",
+                "./../zako_digest/src/protobuf/",
             ],
-            &["src/protobuf/", "./../zako_digest/src/protobuf/"],
         )?;
     Ok(())
 }
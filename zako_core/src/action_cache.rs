@@ -0,0 +1,182 @@
+use async_trait::async_trait;
+use zako_digest::{Digest, DigestError};
+
+use crate::blob_handle::BlobHandle;
+use crate::path::{NeutralPath, PathError};
+use crate::protobuf::action_cache as proto;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ActionCacheError {
+    #[error("action cache io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("action cache storage error: {0}")]
+    Storage(String),
+    #[error("failed to decode the cached action result: {0}")]
+    Decode(#[from] prost::DecodeError),
+    #[error("invalid digest in action result: {0}")]
+    InvalidDigest(#[from] DigestError),
+    #[error("invalid output path in action result: {0}")]
+    InvalidPath(#[from] PathError),
+    #[error("the field `{0}` of the action result is missing")]
+    MissingField(&'static str),
+    #[error("remote action cache error: {0}")]
+    Remote(#[from] tonic::Status),
+}
+
+/// A regular file produced by an action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputFile {
+    /// Relative to the working directory of the action.
+    pub path: NeutralPath,
+    pub digest: Digest,
+    pub is_executable: bool,
+}
+
+/// A directory produced by an action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputDirectory {
+    /// Relative to the working directory of the action.
+    pub path: NeutralPath,
+    /// The digest of the encoded tree of the directory, the tree itself is stored in the cas.
+    pub tree_digest: Digest,
+}
+
+/// The result of an executed action.
+///
+/// It only refers to blobs, the content of outputs and the stdout/stderr live in the cas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActionResult {
+    pub output_files: Vec<OutputFile>,
+    pub output_directories: Vec<OutputDirectory>,
+    pub exit_code: i32,
+    pub stdout: Option<BlobHandle>,
+    pub stderr: Option<BlobHandle>,
+}
+
+impl ActionResult {
+    pub fn is_success(&self) -> bool {
+        self.exit_code == 0
+    }
+
+    /// All blobs that the result refers to.
+    ///
+    /// A cached result is only usable when all of them are still available.
+    pub fn referenced_digests(&self) -> impl Iterator<Item = &Digest> {
+        self.output_files
+            .iter()
+            .map(|file| &file.digest)
+            .chain(self.output_directories.iter().map(|dir| &dir.tree_digest))
+            .chain(self.stdout.iter().map(|handle| handle.digest()))
+            .chain(self.stderr.iter().map(|handle| handle.digest()))
+    }
+}
+
+impl TryFrom<proto::OutputFile> for OutputFile {
+    type Error = ActionCacheError;
+
+    fn try_from(value: proto::OutputFile) -> Result<Self, Self::Error> {
+        Ok(Self {
+            path: NeutralPath::try_from(value.path)?,
+            digest: value
+                .digest
+                .ok_or(ActionCacheError::MissingField("output_files.digest"))?
+                .try_into()?,
+            is_executable: value.is_executable,
+        })
+    }
+}
+
+impl From<OutputFile> for proto::OutputFile {
+    fn from(value: OutputFile) -> Self {
+        Self {
+            path: value.path.into(),
+            digest: Some(value.digest.into()),
+            is_executable: value.is_executable,
+        }
+    }
+}
+
+impl TryFrom<proto::OutputDirectory> for OutputDirectory {
+    type Error = ActionCacheError;
+
+    fn try_from(value: proto::OutputDirectory) -> Result<Self, Self::Error> {
+        Ok(Self {
+            path: NeutralPath::try_from(value.path)?,
+            tree_digest: value
+                .tree_digest
+                .ok_or(ActionCacheError::MissingField(
+                    "output_directories.tree_digest",
+                ))?
+                .try_into()?,
+        })
+    }
+}
+
+impl From<OutputDirectory> for proto::OutputDirectory {
+    fn from(value: OutputDirectory) -> Self {
+        Self {
+            path: value.path.into(),
+            tree_digest: Some(value.tree_digest.into()),
+        }
+    }
+}
+
+impl TryFrom<proto::ActionResult> for ActionResult {
+    type Error = ActionCacheError;
+
+    fn try_from(value: proto::ActionResult) -> Result<Self, Self::Error> {
+        let handle = |digest: Option<zako_digest::protobuf::Digest>| {
+            digest
+                .map(|digest| Digest::try_from(digest).map(BlobHandle::new_referenced))
+                .transpose()
+        };
+
+        Ok(Self {
+            output_files: value
+                .output_files
+                .into_iter()
+                .map(OutputFile::try_from)
+                .collect::<Result<_, _>>()?,
+            output_directories: value
+                .output_directories
+                .into_iter()
+                .map(OutputDirectory::try_from)
+                .collect::<Result<_, _>>()?,
+            exit_code: value.exit_code,
+            stdout: handle(value.stdout_digest)?,
+            stderr: handle(value.stderr_digest)?,
+        })
+    }
+}
+
+impl From<ActionResult> for proto::ActionResult {
+    fn from(value: ActionResult) -> Self {
+        Self {
+            output_files: value.output_files.into_iter().map(Into::into).collect(),
+            output_directories: value
+                .output_directories
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            exit_code: value.exit_code,
+            stdout_digest: value.stdout.map(|handle| (*handle.digest()).into()),
+            stderr_digest: value.stderr.map(|handle| (*handle.digest()).into()),
+        }
+    }
+}
+
+/// Maps the digest of an action to the result of executing it.
+///
+/// Unlike [crate::cas::Cas], the value is not addressed by its own content,
+/// so an entry can be overwritten.
+#[async_trait]
+pub trait ActionCache: Send + Sync + std::fmt::Debug {
+    /// Returns `None` if the action has no cached result.
+    async fn get(&self, action_digest: &Digest) -> Result<Option<ActionResult>, ActionCacheError>;
+
+    async fn put(
+        &self,
+        action_digest: &Digest,
+        result: &ActionResult,
+    ) -> Result<(), ActionCacheError>;
+}
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+
+use tonic::{Request, Response, Status};
+use zako_digest::Digest;
+
+use crate::action_cache::{ActionCache, ActionCacheError, ActionResult};
+use crate::protobuf::action_cache::{
+    self as proto, GetActionResultRequest, UpdateActionResultRequest,
+    action_cache_server::ActionCache as ActionCacheService,
+};
+
+/// Serve an [ActionCache] over gRPC.
+#[derive(Debug, Clone)]
+pub struct ActionCacheServer {
+    cache: Arc<dyn ActionCache + 'static>,
+}
+
+impl ActionCacheServer {
+    pub fn new(cache: Arc<dyn ActionCache + 'static>) -> Self {
+        Self { cache }
+    }
+}
+
+impl From<ActionCacheError> for Status {
+    fn from(err: ActionCacheError) -> Self {
+        match err {
+            ActionCacheError::Remote(status) => status,
+            ActionCacheError::InvalidDigest(_)
+            | ActionCacheError::InvalidPath(_)
+            | ActionCacheError::MissingField(_) => Status::invalid_argument(err.to_string()),
+            ActionCacheError::Io(_)
+            | ActionCacheError::Storage(_)
+            | ActionCacheError::Decode(_) => Status::internal(err.to_string()),
+        }
+    }
+}
+
+fn action_digest(digest: Option<zako_digest::protobuf::Digest>) -> Result<Digest, Status> {
+    Ok(digest
+        .ok_or_else(|| Status::invalid_argument("the action digest is missing"))?
+        .try_into()?)
+}
+
+#[tonic::async_trait]
+impl ActionCacheService for ActionCacheServer {
+    async fn get_action_result(
+        &self,
+        request: Request<GetActionResultRequest>,
+    ) -> Result<Response<proto::ActionResult>, Status> {
+        let digest = action_digest(request.into_inner().action_digest)?;
+
+        match self.cache.get(&digest).await? {
+            Some(result) => Ok(Response::new(result.into())),
+            None => Err(Status::not_found(format!(
+                "no cached result for action {}",
+                digest.hex_blake3()
+            ))),
+        }
+    }
+
+    async fn update_action_result(
+        &self,
+        request: Request<UpdateActionResultRequest>,
+    ) -> Result<Response<proto::ActionResult>, Status> {
+        let inner = request.into_inner();
+
+        let digest = action_digest(inner.action_digest)?;
+        let result: ActionResult = inner
+            .action_result
+            .ok_or_else(|| Status::invalid_argument("the action result is missing"))?
+            .try_into()?;
+
+        self.cache.put(&digest, &result).await?;
+
+        Ok(Response::new(result.into()))
+    }
+}
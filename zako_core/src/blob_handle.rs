@@ -2,7 +2,23 @@ use std::{hash::Hasher, pin::Pin, sync::Arc};
 use tokio::io::AsyncRead;
 use zako_digest::{Digest, blake3::Blake3Hash};
 
-use crate::{blob_range::BlobRange, cas_store::CasStore};
+use crate::{
+    blob_range::BlobRange,
+    cas::CasError,
+    cas_store::{CasStore, CasStoreError},
+};
+
+/// A part of inlined data that can be read without copying it.
+struct InlinedSlice {
+    data: Arc<Vec<u8>>,
+    span: std::ops::Range<usize>,
+}
+
+impl AsRef<[u8]> for InlinedSlice {
+    fn as_ref(&self) -> &[u8] {
+        &self.data[self.span.clone()]
+    }
+}
 
 /// A runtime handle to a blob.
 #[derive(Debug, Clone, rkyv::Deserialize, rkyv::Serialize, rkyv::Archive)]
@@ -65,14 +81,54 @@ impl BlobHandle {
         }
     }
 
+    /// The data of an inlined handle, `None` for a referenced one.
+    pub fn inlined_data(&self) -> Option<&Arc<Vec<u8>>> {
+        match &self.state {
+            BlobState::MemoryInlined { data } => Some(data),
+            BlobState::Referenced => None,
+        }
+    }
+
+    fn inlined_span(
+        &self,
+        data: &[u8],
+        range: &BlobRange,
+    ) -> Result<std::ops::Range<usize>, CasStoreError> {
+        let length = data.len() as u64;
+
+        if range.start() == 0 && range.length().is_none() {
+            return Ok(0..data.len());
+        }
+
+        if range.is_out_of_span_length(length) {
+            return Err(CasStoreError::CasError(
+                CasError::RequestedIndexOutOfRange {
+                    requested_range: *range,
+                    blob_digest: self.digest,
+                    blob_length: length,
+                },
+            ));
+        }
+
+        let end = range.end().unwrap_or(length);
+
+        Ok(range.start() as usize..end as usize)
+    }
+
+    /// Inlined data is served from the handle itself, it never touches the memory cache or the disk.
     pub async fn open(
         &self,
         store: &CasStore,
         range: &BlobRange,
     ) -> eyre::Result<Pin<Box<dyn AsyncRead + Send>>> {
         Ok(match &self.state {
-            // TODO: return inlined data
-            BlobState::MemoryInlined { .. } => store.open(&self.digest, range).await?,
+            BlobState::MemoryInlined { data } => {
+                let span = self.inlined_span(data, range)?;
+                Box::pin(std::io::Cursor::new(InlinedSlice {
+                    data: data.clone(),
+                    span,
+                }))
+            }
             // TODO: share the data
             // Issue URL: https://github.com/moefra/zako/issues/21
             BlobState::Referenced => store.open(&self.digest, range).await?,
@@ -85,7 +141,9 @@ impl BlobHandle {
         range: impl AsRef<BlobRange>,
     ) -> eyre::Result<Vec<u8>> {
         Ok(match &self.state {
-            BlobState::MemoryInlined { .. } => store.read(&self.digest, range.as_ref()).await?,
+            BlobState::MemoryInlined { data } => {
+                data[self.inlined_span(data, range.as_ref())?].to_vec()
+            }
             // TODO: share the data
             // Issue URL: https://github.com/moefra/zako/issues/20
             BlobState::Referenced => store.read(&self.digest, range.as_ref()).await?,
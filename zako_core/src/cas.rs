@@ -1,4 +1,5 @@
 use async_trait::async_trait;
+use camino::Utf8Path;
 use std::{path::PathBuf, pin::Pin};
 use thiserror::Error;
 use tokio::io::AsyncRead;
@@ -43,6 +44,33 @@ pub trait Cas: Send + Sync + 'static + std::fmt::Debug {
     async fn get_local_path(&self, digest: &Digest) -> Option<PathBuf>;
 }
 
+/// The blob stored by [LocalStore::store_reader].
+#[derive(Debug)]
+pub struct StoredBlob {
+    pub digest: Digest,
+    /// The content of the blob if it is not bigger than the `inline_limit`.
+    pub inline: Option<Vec<u8>>,
+}
+
+/// The [Cas] right after the memory cache of [crate::cas_store::CasStore], every blob of the build is stored here first.
+///
+/// See [crate::local_cas::LocalCas] and [crate::memory_cas::MemoryCas].
+#[async_trait]
+pub trait LocalStore: Cas {
+    /// Store the data whose digest is unknown yet.
+    ///
+    /// The content is returned in [StoredBlob::inline] only if it is not bigger than `inline_limit`.
+    async fn store_reader(
+        &self,
+        data: &mut (dyn AsyncRead + Send + Unpin),
+        inline_limit: usize,
+    ) -> Result<StoredBlob, CasError>;
+    /// Store a source file of the build, returns the digest of it.
+    ///
+    /// The content of a symlink is the path it points to.
+    async fn input_file(&self, path: &Utf8Path, is_symlink: bool) -> std::io::Result<Digest>;
+}
+
 #[derive(Error, Debug)]
 pub enum CasError {
     #[error("path {1:?} io error: {0:?}")]
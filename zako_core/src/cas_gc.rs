@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use tracing::{debug, warn};
+use zako_digest::blake3::Hash;
+
+use crate::cas::CasError;
+use crate::cas_lease::read_active_leases;
+use crate::cas_verify::{BlobEntry, scan};
+use crate::local_cas::LocalCas;
+
+#[derive(Debug, Clone)]
+pub struct GcOptions {
+    /// The blobs that are not modified for this long are removed unless pinned.
+    pub max_age: Duration,
+    /// Report what would be removed without removing anything.
+    pub dry_run: bool,
+}
+
+impl Default for GcOptions {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(30 * 24 * 60 * 60),
+            dry_run: false,
+        }
+    }
+}
+
+/// The result of [collect_local_cas].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GcReport {
+    pub root: PathBuf,
+    pub dry_run: bool,
+    pub scanned: usize,
+    pub removed: usize,
+    pub removed_bytes: u64,
+    /// The old blobs that are kept because a lease pins them.
+    pub pinned: usize,
+}
+
+struct Candidate {
+    entry: BlobEntry,
+    hash: Hash,
+    size: u64,
+}
+
+/// Remove the old blobs of the local cas, the blobs pinned by an active [crate::cas_lease::BlobLease] are kept.
+///
+/// The stale lease files are removed as well.
+pub fn collect_local_cas(cas: &LocalCas, options: &GcOptions) -> Result<GcReport, CasError> {
+    let mut report = GcReport {
+        root: cas.get_root().clone(),
+        dry_run: options.dry_run,
+        ..Default::default()
+    };
+
+    let lease_path = cas.get_lease_path();
+    let pinned = read_active_leases(&lease_path, !options.dry_run)?;
+
+    let cutoff = SystemTime::now()
+        .checked_sub(options.max_age)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let mut candidates = Vec::new();
+
+    for entry in scan(cas, &mut Vec::new())? {
+        report.scanned += 1;
+
+        let Ok(metadata) = entry.path.metadata() else {
+            continue;
+        };
+
+        if metadata.modified().is_ok_and(|modified| modified > cutoff) {
+            continue;
+        }
+
+        let Ok(hash) = ::blake3::Hash::from_hex(&entry.expected).map(Hash::from) else {
+            continue;
+        };
+
+        if pinned.contains(&hash) {
+            report.pinned += 1;
+            continue;
+        }
+
+        candidates.push(Candidate {
+            entry,
+            hash,
+            size: metadata.len(),
+        });
+    }
+
+    if candidates.is_empty() {
+        return Ok(report);
+    }
+
+    // a build may pin a candidate after the first read
+    let pinned = read_active_leases(&lease_path, false)?;
+
+    for candidate in candidates {
+        if pinned.contains(&candidate.hash) {
+            report.pinned += 1;
+            continue;
+        }
+
+        if !options.dry_run {
+            if let Err(err) = std::fs::remove_file(&candidate.entry.path) {
+                warn!("failed to remove blob {:?}: {}", candidate.entry.path, err);
+                continue;
+            }
+
+            debug!("removed blob {:?}", candidate.entry.path);
+        }
+
+        report.removed += 1;
+        report.removed_bytes += candidate.size;
+    }
+
+    Ok(report)
+}
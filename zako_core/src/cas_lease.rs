@@ -0,0 +1,249 @@
+//! Leases that stop the garbage collection from deleting the blobs an active build needs.
+//!
+//! Every process owns one lease file in [crate::local_cas::LocalCas::get_lease_path]:
+//!
+//! ```text
+//! # zako blob lease
+//! pid <owner pid>
+//! ttl <seconds>
+//! <blake3 hex of a pinned blob>
+//! ...
+//! ```
+//!
+//! A pinned blob is appended as a line, the append renews the lease as well.
+//! The lease expires `ttl` after the last modification of the file,
+//! it is stale once expired or its owner exits, see [read_active_leases].
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use parking_lot::Mutex;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use tracing::{debug, warn};
+use zako_digest::Digest;
+use zako_digest::blake3::Hash;
+use zako_shared::ConcurrentSet;
+
+use crate::cas::CasError;
+
+/// The extension of a lease file.
+pub static LEASE_FILE_EXTENSION: &str = "lease";
+
+/// A build that pins nothing for this long loses its lease.
+pub static DEFAULT_LEASE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+static LEASE_FILE_HEADER: &str = "# zako blob lease";
+
+/// The lease of the current process.
+///
+/// The lease file is created by the first [BlobLease::pin] and removed on drop.
+#[derive(Debug)]
+pub struct BlobLease {
+    path: PathBuf,
+    ttl: Duration,
+    pinned: ConcurrentSet<Hash>,
+    file: Mutex<Option<std::fs::File>>,
+}
+
+impl BlobLease {
+    /// The lease file is placed in `dir`.
+    pub fn new(dir: &Path, ttl: Duration) -> Self {
+        Self {
+            path: dir.join(format!(
+                "{}-{}.{}",
+                std::process::id(),
+                uuid::Uuid::new_v4(),
+                LEASE_FILE_EXTENSION
+            )),
+            ttl,
+            pinned: ConcurrentSet::default(),
+            file: Mutex::new(None),
+        }
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_pinned(&self, digest: &Digest) -> bool {
+        self.pinned.contains(&digest.blake3)
+    }
+
+    /// Pin the blob until the lease is released or expires.
+    ///
+    /// Pin before the blob is read or written, so a concurrent garbage collection can see it.
+    pub fn pin(&self, digest: &Digest) -> Result<(), CasError> {
+        if self.pinned.contains(&digest.blake3) {
+            return Ok(());
+        }
+
+        let io_error = |err| CasError::Io(err, Some(self.path.clone()));
+
+        let mut file = self.file.lock();
+
+        if file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                std::fs::create_dir_all(parent).map_err(io_error)?;
+            }
+
+            let mut created = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .map_err(io_error)?;
+
+            write!(
+                created,
+                "{}\npid {}\nttl {}\n",
+                LEASE_FILE_HEADER,
+                std::process::id(),
+                self.ttl.as_secs()
+            )
+            .map_err(io_error)?;
+
+            *file = Some(created);
+        }
+
+        if let Some(file) = file.as_mut()
+            && self.pinned.insert(digest.blake3)
+        {
+            // a single write, a reader never sees half of a line unless the disk is full
+            file.write_all(format!("{}\n", digest.hex_blake3()).as_bytes())
+                .map_err(|err| {
+                    self.pinned.remove(&digest.blake3);
+                    io_error(err)
+                })?;
+        }
+
+        Ok(())
+    }
+
+    /// Extend the lease without pinning anything new.
+    pub fn renew(&self) -> Result<(), CasError> {
+        if let Some(file) = self.file.lock().as_ref() {
+            file.set_modified(SystemTime::now())
+                .map_err(|err| CasError::Io(err, Some(self.path.clone())))?;
+        }
+
+        Ok(())
+    }
+
+    /// Unpin everything and remove the lease file.
+    pub fn release(&self) -> Result<(), CasError> {
+        let mut file = self.file.lock();
+
+        // the garbage collection removes the lease once it is expired
+        if file.take().is_some()
+            && let Err(err) = std::fs::remove_file(&self.path)
+            && err.kind() != std::io::ErrorKind::NotFound
+        {
+            return Err(CasError::Io(err, Some(self.path.clone())));
+        }
+
+        self.pinned.clear();
+
+        Ok(())
+    }
+}
+
+impl Drop for BlobLease {
+    fn drop(&mut self) {
+        if let Err(err) = self.release() {
+            warn!("failed to release the blob lease: {}", err);
+        }
+    }
+}
+
+/// A lease file read by [read_active_leases].
+#[derive(Debug)]
+struct LeaseFile {
+    pid: Option<u32>,
+    ttl: Option<Duration>,
+    pinned: Vec<Hash>,
+}
+
+fn parse_lease_file(content: &str) -> LeaseFile {
+    let mut lease = LeaseFile {
+        pid: None,
+        ttl: None,
+        pinned: Vec::new(),
+    };
+
+    for line in content.lines() {
+        if line.starts_with('#') {
+            continue;
+        } else if let Some(pid) = line.strip_prefix("pid ") {
+            lease.pid = pid.parse().ok();
+        } else if let Some(ttl) = line.strip_prefix("ttl ") {
+            lease.ttl = ttl.parse().ok().map(Duration::from_secs);
+        } else if let Ok(hash) = ::blake3::Hash::from_hex(line).map(Hash::from) {
+            lease.pinned.push(hash);
+        }
+    }
+
+    lease
+}
+
+fn is_process_alive(pid: u32) -> bool {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing(),
+    );
+
+    system.process(pid).is_some()
+}
+
+/// The blobs pinned by the leases in `dir` whose owner is alive and which are not expired.
+///
+/// The stale lease files are removed if `remove_stale` is set.
+pub fn read_active_leases(dir: &Path, remove_stale: bool) -> Result<HashSet<Hash>, CasError> {
+    let mut pinned = HashSet::new();
+
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(pinned),
+        Err(err) => return Err(CasError::Io(err, Some(dir.to_path_buf()))),
+    };
+
+    for entry in entries {
+        let path = entry
+            .map_err(|err| CasError::Io(err, Some(dir.to_path_buf())))?
+            .path();
+
+        if path.extension().and_then(|ext| ext.to_str()) != Some(LEASE_FILE_EXTENSION) {
+            continue;
+        }
+
+        // the owner may release it at the same time
+        let (Ok(content), Ok(metadata)) = (std::fs::read_to_string(&path), path.metadata()) else {
+            continue;
+        };
+
+        let lease = parse_lease_file(&content);
+
+        let expired = match (lease.ttl, metadata.modified()) {
+            (Some(ttl), Ok(modified)) => modified + ttl < SystemTime::now(),
+            _ => true,
+        };
+
+        let alive = lease.pid.is_some_and(is_process_alive);
+
+        if alive && !expired {
+            pinned.extend(lease.pinned);
+            continue;
+        }
+
+        debug!("lease {:?} is stale", path);
+
+        if remove_stale && let Err(err) = std::fs::remove_file(&path) {
+            warn!("failed to remove the stale lease {:?}: {}", path, err);
+        }
+    }
+
+    Ok(pinned)
+}
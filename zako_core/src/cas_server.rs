@@ -1,14 +1,13 @@
 use crate::cas::Cas;
+use crate::cas_token::{TokenStore, authenticate_static};
 use crate::protobuf::cas::{
     GetTransportDetailsRequest, NegotiateBlobsRequest, NegotiateBlobsResponse, TransportDetails,
     content_addressable_storage_server::ContentAddressableStorage,
 };
-use dashmap::DashMap;
 use futures::StreamExt; // 引入 Stream 扩展方法
 use std::pin::Pin;
 use std::sync::Arc;
 use tonic::{Request, Response, Status, Streaming};
-use uuid::Uuid;
 use zako_digest::Digest;
 use zako_digest::DigestError;
 
@@ -18,6 +17,8 @@ pub struct CasServerOptions {
     pub server_address: crate::protobuf::net::SocketAddress,
     pub buffered_io_count: usize,
     pub recommended_concurrency: usize,
+    /// Share it with the [crate::transport_server::TransportServer] to check the issued tokens.
+    pub tokens: Arc<TokenStore>,
 }
 
 impl CasServerOptions {
@@ -30,6 +31,7 @@ impl CasServerOptions {
             server_address,
             buffered_io_count: num_cpus::get(),
             recommended_concurrency: num_cpus::get(),
+            tokens: Arc::new(TokenStore::new(crate::cas_token::DEFAULT_TOKEN_TTL)),
         }
     }
 }
@@ -37,8 +39,7 @@ impl CasServerOptions {
 #[derive(Debug)]
 pub struct CasServer {
     cas: Arc<dyn Cas + Send + Sync + 'static>,
-    // TODO: TTL
-    tokens: Arc<DashMap<String, ()>>,
+    tokens: Arc<TokenStore>,
     server_address: crate::protobuf::net::SocketAddress,
     buffered_io_count: usize,
     recommended_concurrency: usize,
@@ -48,12 +49,16 @@ impl CasServer {
     pub fn new(options: CasServerOptions) -> Self {
         Self {
             cas: options.cas,
-            tokens: Arc::new(DashMap::new()),
+            tokens: options.tokens,
             server_address: options.server_address.into(),
             buffered_io_count: options.buffered_io_count,
             recommended_concurrency: options.recommended_concurrency,
         }
     }
+
+    pub fn token_store(&self) -> &Arc<TokenStore> {
+        &self.tokens
+    }
 }
 
 #[tonic::async_trait]
@@ -115,6 +120,12 @@ impl ContentAddressableStorage for CasServer {
         &self,
         request: Request<GetTransportDetailsRequest>,
     ) -> Result<Response<TransportDetails>, Status> {
+        // only a pre-shared static token gets a new token, so an issued token can not renew itself.
+        // without static tokens every caller that reaches the cas server gets one
+        if self.tokens.has_static_tokens() {
+            authenticate_static(&self.tokens, &request)?;
+        }
+
         let inner = request.into_inner();
 
         let mut used_protocol: Option<crate::protobuf::net::Protocol> = None;
@@ -136,13 +147,13 @@ impl ContentAddressableStorage for CasServer {
             ));
         }
 
-        let token = Uuid::new_v4().to_string();
-        self.tokens.insert(token.clone(), ());
+        let token = self.tokens.issue();
 
         Ok(Response::new(TransportDetails {
             server_addr: Some(self.server_address.clone()),
             auth_token: token,
             recommended_concurrency: self.recommended_concurrency as u32,
+            token_ttl_seconds: self.tokens.ttl().as_secs(),
         }))
     }
 }
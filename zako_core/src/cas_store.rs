@@ -1,28 +1,58 @@
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::Arc;
 use std::time::Duration;
 
 use crate::blob_handle::BlobHandle;
 use crate::blob_range::BlobRange;
-use crate::cas::{Cas, CasError};
-use crate::local_cas::LocalCas;
+use crate::cas::{Cas, CasError, LocalStore};
+use crate::cas_lease::{BlobLease, DEFAULT_LEASE_TTL};
+use crate::cas_upload::{UploadQueue, UploadQueueOptions, UploadReport};
+use crate::single_flight::SingleFlight;
+use crate::tiered_cas::{CasTier, TierReport, TierStats, TieredCas};
+use bytes::Bytes;
+use camino::Utf8Path;
 use moka::future::Cache;
 use tokio::io::AsyncRead;
 use tracing::instrument;
 use zako_digest::Digest;
+use zako_digest::blake3::Hash;
 
-pub type CasCache = zako_shared::FastAsyncCache<::zako_digest::blake3::Hash, ::std::vec::Vec<u8>>;
+pub type CasCache = zako_shared::FastAsyncCache<::zako_digest::blake3::Hash, ::bytes::Bytes>;
+
+/// Blobs that are not bigger than this are kept in the memory cache.
+pub static MEMORY_CACHE_BLOB_LIMIT: usize = 64 * 1024;
 
 #[derive(Debug, thiserror::Error)]
 pub enum CasStoreError {
     #[error("Get an error from cas")]
     CasError(#[from] CasError),
+    /// The error of a concurrent operation on the same blob, see [SingleFlight].
+    #[error(transparent)]
+    Shared(Arc<CasStoreError>),
+}
+
+impl From<Arc<CasStoreError>> for CasStoreError {
+    fn from(err: Arc<CasStoreError>) -> Self {
+        Arc::try_unwrap(err).unwrap_or_else(CasStoreError::Shared)
+    }
 }
 
 #[derive(Debug)]
 pub struct CasStore {
-    local: Box<LocalCas>,
-    remote: Option<Box<dyn Cas>>,
+    local: Arc<dyn LocalStore>,
+    /// The tiers after the memory and the local cas.
+    remote: Option<Arc<TieredCas>>,
+    /// Exists if the remote exists and is writable.
+    uploads: Option<UploadQueue>,
     memory: CasCache,
+    memory_stats: TierStats,
+    local_stats: TierStats,
+    /// Pins every blob this store touches.
+    lease: Option<Arc<BlobLease>>,
+    /// The stores and the read-throughs in flight, by the digest.
+    flights: SingleFlight<Hash, (), CasStoreError>,
+    max_inlined_blob_size: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -30,22 +60,53 @@ pub struct CasStoreOptions {
     pub max_cache_capacity: u64,
     pub max_cache_ttl: Duration,
     pub max_cache_tti: Duration,
+    pub upload_queue: UploadQueueOptions,
+    /// [CasStore::put_bytes] returns an inlined [BlobHandle] for the blob that is not bigger than this.
+    ///
+    /// Set it to zero to disable inlining.
+    pub max_inlined_blob_size: usize,
+    /// Pin the blobs of the build with a [BlobLease] in this directory, usually [crate::local_cas::LocalCas::get_lease_path].
+    pub lease_dir: Option<PathBuf>,
 }
 
+/// The default value of [CasStoreOptions::max_inlined_blob_size].
+pub static DEFAULT_MAX_INLINED_BLOB_SIZE: usize = 4 * 1024;
+
 impl CasStore {
+    /// The memory cache and the `local` are always the first two tiers, the `remotes` follow them in order.
+    ///
+    /// The `local` is usually a [crate::local_cas::LocalCas], a [crate::memory_cas::MemoryCas] keeps nothing on the disk.
     pub fn new(
-        local: Box<LocalCas>,
-        remote: Option<Box<dyn Cas>>,
+        local: Box<dyn LocalStore>,
+        remotes: Vec<CasTier>,
         options: CasStoreOptions,
     ) -> Self {
+        let local: Arc<dyn LocalStore> = Arc::from(local);
+        let remote = (!remotes.is_empty()).then(|| Arc::new(TieredCas::new(remotes)));
+        let uploads = remote
+            .as_ref()
+            .filter(|remote| remote.is_writable())
+            .map(|remote| {
+                UploadQueue::new(remote.clone(), local.clone(), options.upload_queue.clone())
+            });
+
         Self {
             local,
             remote,
+            uploads,
+            memory_stats: TierStats::default(),
+            local_stats: TierStats::default(),
+            lease: options
+                .lease_dir
+                .as_deref()
+                .map(|dir| Arc::new(BlobLease::new(dir, DEFAULT_LEASE_TTL))),
+            flights: SingleFlight::new(),
+            max_inlined_blob_size: options.max_inlined_blob_size,
             memory: Cache::builder()
                 // Max capacity
                 .max_capacity(options.max_cache_capacity)
                 // Weigher
-                .weigher(|_key, value: &Vec<u8>| -> u32 {
+                .weigher(|_key, value: &Bytes| -> u32 {
                     value.len().try_into().unwrap_or(u32::MAX)
                 })
                 // Time to live (TTL)
@@ -62,6 +123,8 @@ impl CasStore {
         digest: &Digest,
         range: &BlobRange,
     ) -> Result<Pin<Box<dyn AsyncRead + Send>>, CasStoreError> {
+        self.pin(digest).await?;
+
         if let Some(cached) = self.memory.get(&digest.blake3).await {
             let cached_len = cached.len() as u64;
             if cached_len < range.start() {
@@ -89,25 +152,64 @@ impl CasStore {
                 cached_len - range.start()
             };
 
-            return Ok(Box::pin(std::io::Cursor::new(
-                cached[range.start() as usize..(range.start() + length) as usize].to_vec(),
-            )));
+            self.memory_stats.hit();
+
+            return Ok(Box::pin(std::io::Cursor::new(cached.slice(
+                range.start() as usize..(range.start() + length) as usize,
+            ))));
         }
 
-        let local = self.local.fetch(digest, range).await;
+        self.memory_stats.miss();
+
+        let local = match self.local.fetch(digest, range).await {
+            Ok(local) => {
+                self.local_stats.hit();
+                return Ok(local);
+            }
+            Err(err @ CasError::RequestedIndexOutOfRange { .. }) => return Err(err.into()),
+            Err(err) => err,
+        };
 
-        if let Ok(local) = local {
-            return Ok(local);
+        self.local_stats.miss();
+
+        let Some(remote) = self.remote.as_ref() else {
+            return Err(local.into());
+        };
+
+        // read through, the blob is stored in the local cas and served from it
+        self.flights
+            .run(digest.blake3, self.read_through(remote, digest))
+            .await?;
+
+        Ok(self.local.fetch(digest, range).await?)
+    }
+
+    /// Fetch the whole blob from the remote into the local cas, skipped if an earlier caller did it.
+    async fn read_through(&self, remote: &TieredCas, digest: &Digest) -> Result<(), CasStoreError> {
+        if self.local.contains(digest).await {
+            return Ok(());
         }
 
-        if let Some(remote) = self.remote.as_ref() {
-            return remote
-                .fetch(digest, range)
-                .await
-                .map_err(|err| CasStoreError::CasError(err));
-        } else {
-            return local.map_err(|err| CasStoreError::CasError(err));
+        let mut reader = remote.fetch(digest, &BlobRange::full()).await?;
+        let stored = self
+            .local
+            .store_reader(&mut reader, MEMORY_CACHE_BLOB_LIMIT)
+            .await?;
+
+        if stored.digest != *digest {
+            return Err(CasError::Internal(format!(
+                "the remote cas returns blob {} for blob {}",
+                stored.digest.hex_blake3(),
+                digest.hex_blake3()
+            ))
+            .into());
         }
+
+        if let Some(inline) = stored.inline {
+            self.memory.insert(digest.blake3, Bytes::from(inline)).await;
+        }
+
+        Ok(())
     }
 
     pub async fn read(&self, digest: &Digest, range: &BlobRange) -> Result<Vec<u8>, CasStoreError> {
@@ -119,40 +221,161 @@ impl CasStore {
         Ok(bytes)
     }
 
-    pub fn get_local_cas(&self) -> &LocalCas {
-        &self.local
+    pub fn get_local_cas(&self) -> &dyn LocalStore {
+        self.local.as_ref()
+    }
+
+    /// The hit and miss statistics of every tier, the faster one comes first.
+    pub fn tier_reports(&self) -> Vec<TierReport> {
+        let mut reports = vec![
+            self.memory_stats.report("memory"),
+            self.local_stats.report("local"),
+        ];
+
+        if let Some(remote) = self.remote.as_ref() {
+            reports.extend(remote.reports());
+        }
+
+        reports
+    }
+
+    pub fn get_lease(&self) -> Option<&BlobLease> {
+        self.lease.as_deref()
+    }
+
+    /// Pin the blob so the garbage collection keeps it until this store is dropped.
+    pub async fn pin(&self, digest: &Digest) -> Result<(), CasStoreError> {
+        let Some(lease) = self.lease.as_ref().filter(|lease| !lease.is_pinned(digest)) else {
+            return Ok(());
+        };
+
+        // a new pin writes the lease file
+        let lease = lease.clone();
+        let digest = *digest;
+
+        tokio::task::spawn_blocking(move || lease.pin(&digest))
+            .await
+            .map_err(|err| CasError::Internal(format!("pin task failed: {}", err)))??;
+
+        Ok(())
+    }
+
+    pub fn get_upload_queue(&self) -> Option<&UploadQueue> {
+        self.uploads.as_ref()
     }
 
+    /// Wait for the queued uploads to the remote cas, see [UploadQueue::flush].
+    pub async fn flush_uploads(&self) -> UploadReport {
+        match self.uploads.as_ref() {
+            Some(uploads) => uploads.flush().await,
+            None => UploadReport::default(),
+        }
+    }
+
+    /// Put the bytes, small bytes are inlined into the returned handle and not stored at all.
+    ///
+    /// Call [CasStore::persist] before the blob is needed by the cas itself, like uploading it to the remote.
     pub async fn put_bytes(&self, bytes: Vec<u8>) -> Result<BlobHandle, CasStoreError> {
-        let blake3 = ::blake3::hash(&bytes);
-        let blake3_bytes = blake3.try_into().unwrap();
+        let digest = Digest::new(bytes.len() as u64, ::blake3::hash(&bytes).into());
+
+        if bytes.len() <= self.max_inlined_blob_size {
+            return Ok(BlobHandle::new_memory_inlined(digest, Arc::new(bytes)));
+        }
+
+        self.store_bytes(digest, Bytes::from(bytes)).await?;
+
+        Ok(BlobHandle::new_referenced(digest))
+    }
+
+    /// Make sure the blob of the handle is stored in the local cas and queued for uploading.
+    ///
+    /// Returns a referenced handle, the handle is returned as is if it is referenced already.
+    pub async fn persist(&self, handle: &BlobHandle) -> Result<BlobHandle, CasStoreError> {
+        let Some(data) = handle.inlined_data() else {
+            return Ok(handle.clone());
+        };
 
-        let digest = Digest::new(bytes.len() as u64, blake3_bytes);
+        let digest = *handle.digest();
 
-        // check if the bytes can be cached
-        if bytes.len() <= 1024 * 64
-        /* 64kb maybe good */
-        {
+        self.store_bytes(digest, Bytes::copy_from_slice(data))
+            .await?;
+
+        Ok(BlobHandle::new_referenced(digest))
+    }
+
+    async fn store_bytes(&self, digest: Digest, bytes: Bytes) -> Result<(), CasStoreError> {
+        self.pin(&digest).await?;
+
+        // the cache, the local cas and the upload queue share the same buffer
+        if bytes.len() <= MEMORY_CACHE_BLOB_LIMIT {
             self.memory.insert(digest.blake3, bytes.clone()).await;
         }
 
-        // put the bytes to the local cas
-        // TODO: clone data maybe too slow, find a new way!
-        // Issue URL: https://github.com/moefra/zako/issues/16
-        self.local
-            .store(&digest, Box::new(std::io::Cursor::new(bytes.clone())))
+        // concurrent stores of the same blob write it once
+        self.flights
+            .run(digest.blake3, async {
+                if self.local.contains(&digest).await {
+                    return Ok(());
+                }
+
+                Ok(self
+                    .local
+                    .store(&digest, Box::new(std::io::Cursor::new(bytes.clone())))
+                    .await?)
+            })
             .await?;
 
-        // put the bytes to the remote cas if exists
-        // TODO: add to remote maybe too slow, maybe we should add a individual task to do this
-        // Issue URL: https://github.com/moefra/zako/issues/15
-        if let Some(remote) = self.remote.as_ref() {
-            remote
-                .store(&digest, Box::new(std::io::Cursor::new(bytes.clone())))
-                .await?;
+        if let Some(uploads) = self.uploads.as_ref() {
+            uploads.enqueue(digest, Some(bytes));
+        }
+
+        Ok(())
+    }
+
+    /// Put the data without buffering all of it in memory.
+    ///
+    /// See [LocalStore::store_reader], small blobs are kept in the memory cache as well.
+    pub async fn put_reader(
+        &self,
+        mut reader: impl AsyncRead + Send + Unpin,
+    ) -> Result<BlobHandle, CasStoreError> {
+        let stored = self
+            .local
+            .store_reader(&mut reader, MEMORY_CACHE_BLOB_LIMIT)
+            .await?;
+        let digest = stored.digest;
+        let inline = stored.inline.map(Bytes::from);
+
+        // the digest is unknown until stored, the stored blob is fresh so the garbage collection skips it anyway
+        self.pin(&digest).await?;
+
+        if let Some(inline) = inline.as_ref() {
+            self.memory.insert(digest.blake3, inline.clone()).await;
+        }
+
+        // big blobs are read back from the local cas when uploading
+        if let Some(uploads) = self.uploads.as_ref() {
+            uploads.enqueue(digest, inline);
         }
 
-        // TODO: maybe we should add a memory inlined mode
         Ok(BlobHandle::new_referenced(digest))
     }
+
+    /// Put the content of the file, see [CasStore::put_reader].
+    pub async fn put_file(&self, path: &Path) -> Result<BlobHandle, CasStoreError> {
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|err| CasError::Io(err, Some(path.to_path_buf())))?;
+
+        self.put_reader(file).await
+    }
+
+    /// Store a source file of the build, see [LocalStore::input_file].
+    pub async fn input_file(&self, path: &Utf8Path, is_symlink: bool) -> std::io::Result<Digest> {
+        let digest = self.local.input_file(path, is_symlink).await?;
+
+        self.pin(&digest).await.map_err(std::io::Error::other)?;
+
+        Ok(digest)
+    }
 }
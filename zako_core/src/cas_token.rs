@@ -0,0 +1,243 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+use tracing::{debug, trace};
+use uuid::Uuid;
+use zako_shared::{ConcurrentMap, SafeSet};
+
+/// The metadata key that carries the token for the transport service.
+///
+/// The value is expected to look like `Bearer <token>`, a bare `<token>` is accepted too.
+pub static AUTH_TOKEN_METADATA_KEY: &str = "authorization";
+
+/// The scheme prefix of the [AUTH_TOKEN_METADATA_KEY] value.
+pub static AUTH_TOKEN_SCHEME: &str = "Bearer ";
+
+/// The default lifetime of an issued token.
+pub static DEFAULT_TOKEN_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// The default interval between two expiry sweeps.
+pub static DEFAULT_TOKEN_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum TokenStoreError {
+    #[error("failed to read token config file {1:?}: {0}")]
+    Io(#[source] std::io::Error, std::path::PathBuf),
+    #[error("failed to parse token config file {1:?}: {0}")]
+    Parse(#[source] toml::de::Error, std::path::PathBuf),
+    #[error("the static token at index {0} is empty")]
+    EmptyStaticToken(usize),
+}
+
+/// The config file of pre-shared tokens.
+///
+/// It looks like:
+///
+/// ```toml
+/// tokens = ["a-long-random-secret", "another-one"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaticTokenConfig {
+    #[serde(default)]
+    pub tokens: Vec<String>,
+}
+
+impl StaticTokenConfig {
+    pub fn load(path: &Path) -> Result<Self, TokenStoreError> {
+        let content =
+            std::fs::read(path).map_err(|err| TokenStoreError::Io(err, path.to_path_buf()))?;
+        let config: Self = toml::from_slice(&content)
+            .map_err(|err| TokenStoreError::Parse(err, path.to_path_buf()))?;
+
+        if let Some(index) = config
+            .tokens
+            .iter()
+            .position(|token| token.trim().is_empty())
+        {
+            return Err(TokenStoreError::EmptyStaticToken(index));
+        }
+
+        Ok(config)
+    }
+}
+
+/// Tokens that are allowed to access the transport service.
+///
+/// There are two kinds of token:
+///
+/// - issued tokens: created by [TokenStore::issue], they expire after the ttl.
+/// - static tokens: pre-shared tokens from a [StaticTokenConfig], they never expire.
+#[derive(Debug)]
+pub struct TokenStore {
+    /// The value is the time when the token expires.
+    issued: ConcurrentMap<String, Instant>,
+    statics: SafeSet<String>,
+    ttl: Duration,
+}
+
+impl TokenStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            issued: ConcurrentMap::default(),
+            statics: SafeSet::default(),
+            ttl,
+        }
+    }
+
+    pub fn with_static_tokens(mut self, config: StaticTokenConfig) -> Self {
+        self.statics.extend(config.tokens);
+        self
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn has_static_tokens(&self) -> bool {
+        !self.statics.is_empty()
+    }
+
+    /// Issue a new token that expires after the ttl.
+    pub fn issue(&self) -> String {
+        let token = Uuid::new_v4().to_string();
+        self.issued.insert(token.clone(), Instant::now() + self.ttl);
+        token
+    }
+
+    /// Revoke an issued token. Static tokens can not be revoked.
+    pub fn revoke(&self, token: &str) -> bool {
+        self.issued.remove(token).is_some()
+    }
+
+    /// Check if the token is a static token or an issued token that has not expired.
+    ///
+    /// An expired token is removed while checking.
+    pub fn validate(&self, token: &str) -> bool {
+        if self.statics.contains(token) {
+            return true;
+        }
+
+        let now = Instant::now();
+
+        if self
+            .issued
+            .remove_if(token, |_, expire_at| *expire_at <= now)
+            .is_some()
+        {
+            return false;
+        }
+
+        self.issued.contains_key(token)
+    }
+
+    /// Check if the token is a static token, issued tokens are not.
+    pub fn validate_static(&self, token: &str) -> bool {
+        self.statics.contains(token)
+    }
+
+    /// Remove all expired tokens, returns the count of removed tokens.
+    pub fn sweep_expired(&self) -> usize {
+        let now = Instant::now();
+        let before = self.issued.len();
+        self.issued.retain(|_, expire_at| *expire_at > now);
+        before.saturating_sub(self.issued.len())
+    }
+
+    /// The count of issued tokens that are still stored, expired but not swept ones included.
+    pub fn issued_count(&self) -> usize {
+        self.issued.len()
+    }
+
+    /// Spawn a task that calls [TokenStore::sweep_expired] periodically.
+    ///
+    /// The task stops once the store is dropped.
+    pub fn spawn_sweeper(
+        self: &Arc<Self>,
+        handle: &tokio::runtime::Handle,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        let store = Arc::downgrade(self);
+
+        handle.spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                ticker.tick().await;
+
+                let Some(store) = store.upgrade() else {
+                    break;
+                };
+
+                let removed = store.sweep_expired();
+
+                if removed != 0 {
+                    debug!("swept {} expired transport tokens", removed);
+                }
+            }
+        })
+    }
+}
+
+/// The [Interceptor] that rejects requests without a valid token from [TokenStore].
+#[derive(Debug, Clone)]
+pub struct TokenInterceptor {
+    store: Arc<TokenStore>,
+}
+
+impl TokenInterceptor {
+    pub fn new(store: Arc<TokenStore>) -> Self {
+        Self { store }
+    }
+}
+
+/// The token in [AUTH_TOKEN_METADATA_KEY] of the `request`.
+fn request_token<T>(request: &Request<T>) -> Result<&str, Status> {
+    let value = request
+        .metadata()
+        .get(AUTH_TOKEN_METADATA_KEY)
+        .ok_or_else(|| Status::unauthenticated("missing transport token"))?
+        .to_str()
+        .map_err(|_| Status::unauthenticated("transport token is not valid ascii"))?;
+
+    Ok(value
+        .strip_prefix(AUTH_TOKEN_SCHEME)
+        .unwrap_or(value)
+        .trim())
+}
+
+/// Check that the `request` carries a valid token of the `store` in [AUTH_TOKEN_METADATA_KEY].
+pub fn authenticate<T>(store: &TokenStore, request: &Request<T>) -> Result<(), Status> {
+    if !store.validate(request_token(request)?) {
+        trace!("reject request with an invalid or expired transport token");
+        return Err(Status::unauthenticated(
+            "transport token is invalid or expired",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Like [authenticate], but only a static token of the `store` is accepted.
+pub fn authenticate_static<T>(store: &TokenStore, request: &Request<T>) -> Result<(), Status> {
+    if !store.validate_static(request_token(request)?) {
+        trace!("reject request without a static token");
+        return Err(Status::unauthenticated(
+            "a static token is required to get a transport token",
+        ));
+    }
+
+    Ok(())
+}
+
+impl Interceptor for TokenInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        authenticate(&self.store, &request)?;
+
+        Ok(request)
+    }
+}
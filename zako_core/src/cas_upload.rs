@@ -0,0 +1,289 @@
+use std::fmt::Display;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tokio::io::AsyncRead;
+use tokio::sync::{Notify, Semaphore};
+use tracing::{debug, info, warn};
+use zako_digest::Digest;
+use zako_shared::ConcurrentSet;
+
+use crate::blob_range::BlobRange;
+use crate::cas::Cas;
+
+/// The interval between two progress reports while flushing.
+pub static UPLOAD_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct UploadQueueOptions {
+    /// The max bytes of blob content that pending uploads keep in memory.
+    ///
+    /// Once it is exceeded, the content is read back from the local cas when uploading.
+    pub max_pending_bytes: u64,
+    pub max_concurrency: usize,
+    /// Including the first attempt.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for UploadQueueOptions {
+    fn default() -> Self {
+        Self {
+            max_pending_bytes: 256 * 1024 * 1024,
+            max_concurrency: 16,
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UploadFailure {
+    pub digest: Digest,
+    pub error: String,
+}
+
+/// What the queue did since the last [UploadQueue::flush].
+#[derive(Debug, Clone, Default)]
+pub struct UploadReport {
+    pub uploaded: usize,
+    pub uploaded_bytes: u64,
+    /// Uploads skipped because the same digest was already in flight.
+    pub deduplicated: usize,
+    /// Uploads skipped because the remote has the blob already.
+    pub present: usize,
+    pub retried: usize,
+    pub failures: Vec<UploadFailure>,
+}
+
+impl UploadReport {
+    pub fn is_empty(&self) -> bool {
+        self.uploaded == 0
+            && self.deduplicated == 0
+            && self.present == 0
+            && self.failures.is_empty()
+    }
+}
+
+impl Display for UploadReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "uploaded {} blobs ({} bytes) to the remote cas, {} deduplicated, {} present, {} retried, {} failed",
+            self.uploaded,
+            self.uploaded_bytes,
+            self.deduplicated,
+            self.present,
+            self.retried,
+            self.failures.len()
+        )
+    }
+}
+
+#[derive(Debug)]
+enum UploadSource {
+    Memory(Bytes),
+    Local,
+}
+
+#[derive(Debug)]
+struct UploadQueueInner {
+    remote: Arc<dyn Cas>,
+    local: Arc<dyn Cas>,
+    options: UploadQueueOptions,
+    in_flight: ConcurrentSet<zako_digest::blake3::Hash>,
+    memory_budget: Arc<Semaphore>,
+    concurrency: Arc<Semaphore>,
+    pending: AtomicUsize,
+    pending_bytes: AtomicU64,
+    idle: Notify,
+    report: Mutex<UploadReport>,
+}
+
+/// Upload blobs to the remote cas in background tasks.
+///
+/// The build does not wait for the remote, call [UploadQueue::flush] at the end of the build.
+/// A failed upload does not fail anything, it is reported as a warning.
+#[derive(Debug, Clone)]
+pub struct UploadQueue {
+    inner: Arc<UploadQueueInner>,
+}
+
+impl UploadQueue {
+    pub fn new(remote: Arc<dyn Cas>, local: Arc<dyn Cas>, options: UploadQueueOptions) -> Self {
+        let budget = usize::try_from(options.max_pending_bytes)
+            .unwrap_or(usize::MAX)
+            .min(Semaphore::MAX_PERMITS);
+
+        Self {
+            inner: Arc::new(UploadQueueInner {
+                remote,
+                local,
+                memory_budget: Arc::new(Semaphore::new(budget)),
+                concurrency: Arc::new(Semaphore::new(options.max_concurrency.max(1))),
+                options,
+                in_flight: ConcurrentSet::default(),
+                pending: AtomicUsize::new(0),
+                pending_bytes: AtomicU64::new(0),
+                idle: Notify::new(),
+                report: Mutex::new(UploadReport::default()),
+            }),
+        }
+    }
+
+    /// The count of uploads that have not finished.
+    pub fn pending(&self) -> usize {
+        self.inner.pending.load(Ordering::Acquire)
+    }
+
+    /// Queue the blob for uploading, the blob must be stored in the local cas already.
+    ///
+    /// The `data` is kept in memory if the budget allows, otherwise the blob is read back from the local cas.
+    /// Nothing is uploaded if the remote has the blob already, like a blob stored by an earlier build.
+    ///
+    /// Must be called within a tokio runtime.
+    pub fn enqueue(&self, digest: Digest, data: Option<Bytes>) {
+        let inner = &self.inner;
+
+        if !inner.in_flight.insert(digest.blake3) {
+            inner.report.lock().deduplicated += 1;
+            return;
+        }
+
+        let (source, memory_permit) = match data.and_then(|data| {
+            let size = u32::try_from(data.len()).ok()?;
+            let permit = inner
+                .memory_budget
+                .clone()
+                .try_acquire_many_owned(size)
+                .ok()?;
+            Some((data, permit))
+        }) {
+            Some((data, permit)) => (UploadSource::Memory(data), Some(permit)),
+            None => (UploadSource::Local, None),
+        };
+
+        inner.pending.fetch_add(1, Ordering::AcqRel);
+        inner
+            .pending_bytes
+            .fetch_add(digest.size_bytes, Ordering::AcqRel);
+
+        let inner = self.inner.clone();
+
+        tokio::spawn(async move {
+            let _memory_permit = memory_permit;
+            // the semaphore is never closed
+            let _permit = inner.concurrency.clone().acquire_owned().await.ok();
+
+            inner.upload(&digest, &source).await;
+
+            inner.in_flight.remove(&digest.blake3);
+            inner
+                .pending_bytes
+                .fetch_sub(digest.size_bytes, Ordering::AcqRel);
+
+            if inner.pending.fetch_sub(1, Ordering::AcqRel) == 1 {
+                inner.idle.notify_waiters();
+            }
+        });
+    }
+
+    /// Wait until all queued uploads finish, logging the progress periodically.
+    ///
+    /// Returns what the queue did since the last flush.
+    pub async fn flush(&self) -> UploadReport {
+        let inner = &self.inner;
+
+        loop {
+            let idle = inner.idle.notified();
+            tokio::pin!(idle);
+            idle.as_mut().enable();
+
+            let pending = inner.pending.load(Ordering::Acquire);
+
+            if pending == 0 {
+                break;
+            }
+
+            if tokio::time::timeout(UPLOAD_PROGRESS_INTERVAL, idle)
+                .await
+                .is_err()
+            {
+                info!(
+                    "waiting for {} uploads ({} bytes) to the remote cas",
+                    inner.pending.load(Ordering::Acquire),
+                    inner.pending_bytes.load(Ordering::Acquire)
+                );
+            }
+        }
+
+        std::mem::take(&mut *inner.report.lock())
+    }
+}
+
+impl UploadQueueInner {
+    async fn upload(&self, digest: &Digest, source: &UploadSource) {
+        if self.remote.contains(digest).await {
+            self.report.lock().present += 1;
+            return;
+        }
+
+        let mut backoff = self.options.initial_backoff;
+        let mut attempt = 1;
+
+        loop {
+            let result = match source {
+                UploadSource::Memory(data) => {
+                    let reader: Box<dyn AsyncRead + Send + Unpin> =
+                        Box::new(std::io::Cursor::new(data.clone()));
+                    self.remote.store(digest, reader).await
+                }
+                UploadSource::Local => match self.local.fetch(digest, &BlobRange::full()).await {
+                    Ok(reader) => self.remote.store(digest, Box::new(reader)).await,
+                    Err(err) => Err(err),
+                },
+            };
+
+            let err = match result {
+                Ok(()) => {
+                    let mut report = self.report.lock();
+                    report.uploaded += 1;
+                    report.uploaded_bytes += digest.size_bytes;
+                    return;
+                }
+                Err(err) => err,
+            };
+
+            if attempt >= self.options.max_attempts {
+                warn!(
+                    "failed to upload blob {} to the remote cas after {} attempts: {}",
+                    digest.hex_blake3(),
+                    attempt,
+                    err
+                );
+                self.report.lock().failures.push(UploadFailure {
+                    digest: *digest,
+                    error: err.to_string(),
+                });
+                return;
+            }
+
+            debug!(
+                "failed to upload blob {} to the remote cas, retry in {:?}: {}",
+                digest.hex_blake3(),
+                backoff,
+                err
+            );
+            self.report.lock().retried += 1;
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.options.max_backoff);
+            attempt += 1;
+        }
+    }
+}
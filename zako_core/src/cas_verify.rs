@@ -0,0 +1,221 @@
+use std::path::{Path, PathBuf};
+
+use futures::StreamExt;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::cas::CasError;
+use crate::local_cas::LocalCas;
+
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    /// Move the corrupted blobs to the quarantine directory.
+    pub repair: bool,
+    /// The count of blobs that are hashed at the same time.
+    pub concurrency: usize,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        Self {
+            repair: false,
+            concurrency: num_cpus::get(),
+        }
+    }
+}
+
+/// A blob whose content does not match its path.
+#[derive(Debug, Clone, Serialize)]
+pub struct CorruptedBlob {
+    pub path: PathBuf,
+    /// The blake3 hex that the path claims.
+    pub expected: String,
+    /// The blake3 hex of the content, `None` if the blob can not be read.
+    pub actual: Option<String>,
+    pub size: Option<u64>,
+    pub error: Option<String>,
+    /// Where the blob was moved to when repairing.
+    pub quarantined_to: Option<PathBuf>,
+}
+
+/// The result of [verify_local_cas], it can be serialized to json and attached to a bug report.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyReport {
+    pub root: PathBuf,
+    pub repair: bool,
+    pub checked: usize,
+    pub checked_bytes: u64,
+    pub corrupted: Vec<CorruptedBlob>,
+    /// Entries that do not follow the layout of [LocalCas::get_path_for_digest].
+    pub unrecognized: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.corrupted.is_empty()
+    }
+}
+
+pub(crate) struct BlobEntry {
+    pub(crate) path: PathBuf,
+    /// The blake3 hex that the path claims.
+    pub(crate) expected: String,
+}
+
+struct HashOutcome {
+    entry: BlobEntry,
+    result: std::io::Result<(String, u64)>,
+}
+
+fn is_hex(s: &str) -> bool {
+    s.bytes()
+        .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn read_dir(path: &Path) -> Result<Vec<std::fs::DirEntry>, CasError> {
+    std::fs::read_dir(path)
+        .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+        .map_err(|err| CasError::Io(err, Some(path.to_path_buf())))
+}
+
+/// Collect the blobs under the root, the entries that are not blobs go to the `unrecognized`.
+pub(crate) fn scan(
+    cas: &LocalCas,
+    unrecognized: &mut Vec<PathBuf>,
+) -> Result<Vec<BlobEntry>, CasError> {
+    let root = cas.get_root();
+    let mut blobs = Vec::new();
+
+    if !root.exists() {
+        return Ok(blobs);
+    }
+
+    let quarantine = cas.get_quarantine_path();
+    let leases = cas.get_lease_path();
+    let staging = cas.get_staging_path();
+
+    for prefix in read_dir(root)? {
+        let prefix_path = prefix.path();
+        let prefix_name = prefix.file_name().to_string_lossy().to_string();
+
+        if prefix_path == quarantine || prefix_path == leases || prefix_path == staging {
+            continue;
+        }
+
+        if !prefix_path.is_dir() || prefix_name.len() != 2 || !is_hex(&prefix_name) {
+            unrecognized.push(prefix_path);
+            continue;
+        }
+
+        for blob in read_dir(&prefix_path)? {
+            let path = blob.path();
+            let name = blob.file_name().to_string_lossy().to_string();
+
+            if !path.is_file() || name.len() != 62 || !is_hex(&name) {
+                unrecognized.push(path);
+                continue;
+            }
+
+            blobs.push(BlobEntry {
+                path,
+                expected: format!("{}{}", prefix_name, name),
+            });
+        }
+    }
+
+    Ok(blobs)
+}
+
+fn hash_file(path: &Path) -> std::io::Result<(String, u64)> {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update_mmap(path)?;
+    Ok((hasher.finalize().to_hex().to_string(), hasher.count()))
+}
+
+fn quarantine(cas: &LocalCas, blob: &BlobEntry) -> Result<PathBuf, CasError> {
+    let quarantine = cas.get_quarantine_path();
+
+    std::fs::create_dir_all(&quarantine)
+        .map_err(|err| CasError::Io(err, Some(quarantine.clone())))?;
+
+    let target = quarantine.join(format!("{}.{}", blob.expected, uuid::Uuid::new_v4()));
+
+    std::fs::rename(&blob.path, &target)
+        .map_err(|err| CasError::Io(err, Some(blob.path.clone())))?;
+
+    Ok(target)
+}
+
+/// Re-hash every blob of the local cas and check it against its path.
+///
+/// Nothing is modified unless [VerifyOptions::repair] is set.
+pub async fn verify_local_cas(
+    cas: &LocalCas,
+    options: &VerifyOptions,
+) -> Result<VerifyReport, CasError> {
+    let mut report = VerifyReport {
+        root: cas.get_root().clone(),
+        repair: options.repair,
+        ..Default::default()
+    };
+
+    let blobs = scan(cas, &mut report.unrecognized)?;
+
+    debug!("verify {} blobs in {:?}", blobs.len(), cas.get_root());
+
+    let mut outcomes = futures::stream::iter(blobs)
+        .map(|entry| {
+            tokio::task::spawn_blocking(move || {
+                let result = hash_file(&entry.path);
+                HashOutcome { entry, result }
+            })
+        })
+        .buffer_unordered(options.concurrency.max(1));
+
+    while let Some(outcome) = outcomes.next().await {
+        let HashOutcome { entry, result } =
+            outcome.map_err(|err| CasError::Internal(format!("hash task failed: {}", err)))?;
+
+        report.checked += 1;
+
+        let mut corrupted = match result {
+            Ok((actual, size)) => {
+                report.checked_bytes += size;
+
+                if actual == entry.expected {
+                    continue;
+                }
+
+                CorruptedBlob {
+                    path: entry.path.clone(),
+                    expected: entry.expected.clone(),
+                    actual: Some(actual),
+                    size: Some(size),
+                    error: None,
+                    quarantined_to: None,
+                }
+            }
+            Err(err) => CorruptedBlob {
+                path: entry.path.clone(),
+                expected: entry.expected.clone(),
+                actual: None,
+                size: None,
+                error: Some(err.to_string()),
+                quarantined_to: None,
+            },
+        };
+
+        warn!("blob {:?} is corrupted", entry.path);
+
+        if options.repair {
+            corrupted.quarantined_to = Some(quarantine(cas, &entry)?);
+        }
+
+        report.corrupted.push(corrupted);
+    }
+
+    report.corrupted.sort_by(|a, b| a.path.cmp(&b.path));
+    report.unrecognized.sort();
+
+    Ok(report)
+}
@@ -46,9 +46,8 @@ pub async fn file<'c>(
 
     let is_executable = (!is_symlink) && is_executable(&physical_path);
 
-    let local_cas = build_ctx.cas_store().get_local_cas();
-
-    let digest = local_cas
+    let digest = build_ctx
+        .cas_store()
         .input_file(&physical_path, is_symlink)
         .await
         .map_err(|e| HoneError::IOError(e, physical_path.to_string()))?;
@@ -4,6 +4,8 @@ use zako_digest::blake3::Blake3Hash;
 use crate::{
     blob_range::BlobRange,
     computer::ZakoComputeContext,
+    intern::Resolvable,
+    manifest::ManifestSource,
     node::parse_manifest::{ParseManifest, ParseManifestResult},
     package::Package,
 };
@@ -18,7 +20,13 @@ pub async fn prase_manifest<'c>(
         .read(ctx.context().cas_store(), BlobRange::full())
         .await?;
 
-    let project: Package = toml::from_slice(&read).map_err(|e| eyre::eyre!(e))?;
+    let path = key.path.resolve(ctx.context().interner())?;
+    let content = String::from_utf8(read.to_vec())
+        .map_err(|_| eyre::eyre!("the manifest {:?} is not valid utf-8", path))?;
+
+    let project: Package = ManifestSource::new(path, content, key.format)
+        .parse()
+        .map_err(|e| eyre::eyre!(e))?;
 
     Ok((
         HashPair {
@@ -1,19 +1,23 @@
+use std::collections::{BTreeMap, HashSet};
 use std::path::Path;
 
 use ::rkyv::Archive;
-use camino::Utf8PathBuf;
-use eyre::{Context, OptionExt};
+use camino::{Utf8Path, Utf8PathBuf};
+use eyre::Context;
 use hone::{HoneResult, error::HoneError, status::HashPair};
+use smol_str::SmolStr;
 use zako_digest::blake3::Blake3Hash;
 
 use crate::{
-    blob_handle::BlobHandle,
     compute::file,
     computer::ZakoComputeContext,
     config::Configuration,
     configured_project::ConfiguredPackage,
     consts,
     context::BuildContext,
+    fetch::patch::{Patch, apply_patches},
+    intern::InternedAbsolutePath,
+    manifest::{self, ManifestSource},
     node::{
         node_key::ZakoKey,
         node_value::ZakoValue,
@@ -22,6 +26,11 @@ use crate::{
         resolve_package::{ResolvePackage, ResolvePackageResult},
     },
     package::ResolvingPackage,
+    package_graph::{
+        GraphDependency, GraphPackage, PackageGraph, dependency_requirement, split_package_id,
+    },
+    package_id::InternedPackageId,
+    package_source::PackageSource,
 };
 
 /// Compute and resolve a project file
@@ -38,30 +47,54 @@ pub async fn resolve_package<'c>(
         .resolved(interner)
         .map_err(|err| HoneError::UnexpectedError(format!("Interner error: {}", err)))?;
 
-    let input_hash: blake3::Hash = (package_id.as_str(), key.source.clone()).get_blake3();
+    let registered = ctx
+        .global_state()
+        .package_id_to_path()
+        .get(&key.package)
+        .map(|path| *path);
+
+    // only the fetched packages are locked
+    let fetched = key.root.is_none()
+        && registered.is_none()
+        && !matches!(key.source, PackageSource::Path { .. });
+
+    let locked = if fetched {
+        ctx.global_state()
+            .lock_state()
+            .pinned(&key.source)
+            .wrap_err_with(|| format!("failed to lock the package `{}`", package_id.as_str()))?
+    } else {
+        None
+    };
+
+    let input_hash: blake3::Hash =
+        (package_id.as_str(), key.source.clone(), locked.clone()).get_blake3();
 
     let interned_path = if let Some(root) = key.root {
         root.into()
+    } else if let Some(path) = registered {
+        path
     } else {
-        *(ctx
-            .global_state()
-            .package_id_to_path()
-            .get(&key.package)
-            .ok_or_eyre( format!(
-                "the package `{}` is not found in the global state. Only registered package is supported now ",
-                package_id.as_str()
-            ))?)
+        fetch_package_root(ctx, key, locked, package_id.as_str()).await?
     };
 
     let path_str = interner
         .resolve(interned_path)
         .map_err(|err| HoneError::UnexpectedError(format!("Interner error: {}", err)))?;
     let path = Utf8PathBuf::from(path_str);
-    let manifest = path.join(consts::PACKAGE_MANIFEST_FILE_NAME);
+    let (manifest_path, format) = manifest::find(&path).ok_or_else(|| {
+        eyre::eyre!(
+            "no `{}` or `{}` is found in {:?}",
+            consts::PACKAGE_MANIFEST_FILE_NAME,
+            consts::PACKAGE_JSON_MANIFEST_FILE_NAME,
+            path
+        )
+    })?;
 
     // TODO: Do not read the file into memory, just read the file into a blob handle
     // Issue URL: https://github.com/moefra/zako/issues/28
-    let (_, result) = file::read_text(raw_ctx, manifest).await?;
+    let (content, result) = file::read_text(raw_ctx, manifest_path.clone()).await?;
+    let source = ManifestSource::new(manifest_path.as_std_path(), content, format);
 
     // build new context
 
@@ -71,7 +104,9 @@ pub async fn resolve_package<'c>(
     let manifest = raw_ctx
         .request_with_context(
             ZakoKey::ParseManifest(ParseManifest {
-                blob_handle: BlobHandle::new_referenced(*result.content.digest()),
+                blob_handle: result.content.clone(),
+                format,
+                path: InternedAbsolutePath::new(&manifest_path, interner)?,
             }),
             &new_ctx,
         )
@@ -86,14 +121,16 @@ pub async fn resolve_package<'c>(
     }
     .clone();
 
-    // TODO: Resolve the configuration and dependencies
+    // TODO: Resolve the configuration
 
     // before intern it, calculate hash
     // only hash result `ResolvedPackage`
 
-    _ = parsed
-        .project
-        .validate()
+    source
+        .validate(&parsed.project)
+        .wrap_err("failed to validate project manifest")?;
+    source
+        .validate_labels(&parsed.project, interner)
         .wrap_err("failed to validate project manifest")?;
 
     let configuration = Configuration {
@@ -130,6 +167,8 @@ pub async fn resolve_package<'c>(
         }
     };
 
+    let dependencies = resolving.original.dependencies.clone().unwrap_or_default();
+
     let resolved = resolving.resolve(interner).map_err(|err| {
         eyre::eyre!(err).wrap_err(format!(
             "error while resolving project {:?}, path {:?}",
@@ -137,20 +176,357 @@ pub async fn resolve_package<'c>(
         ))
     })?;
 
-    let output_hash = resolved.get_blake3_hash(interner)?;
+    let id = resolved
+        .get_id()
+        .resolved(interner)
+        .wrap_err("failed to resolve the package id")?;
+
+    let (edges, dependencies) =
+        resolve_dependencies(raw_ctx, &new_ctx, &path, &key.source, dependencies)
+            .await
+            .wrap_err_with(|| {
+                format!(
+                    "failed to resolve the dependencies of the package `{}`",
+                    package_id
+                )
+            })?;
+
+    // the versions are unified once for the whole build, the dependencies keep their own edges
+    let graph = if key.root.is_none() {
+        Some(
+            resolve_graph(raw_ctx, &new_ctx, &id, edges.clone(), &dependencies)
+                .await
+                .wrap_err_with(|| {
+                    format!(
+                        "failed to unify the dependencies of the package `{}`",
+                        package_id
+                    )
+                })?,
+        )
+    } else {
+        None
+    };
+
+    let mut resolved_dependencies = BTreeMap::new();
+    for (alias, dependency) in graph
+        .as_ref()
+        .and_then(|graph| graph.get(&id))
+        .unwrap_or(&edges)
+        .dependencies
+        .iter()
+    {
+        resolved_dependencies.insert(
+            alias.clone(),
+            InternedPackageId::try_parse(&dependency.package, interner)
+                .wrap_err("failed to intern the dependency id")?,
+        );
+    }
+
+    let edges_hash: zako_digest::blake3::Hash = edges.get_blake3().into();
+    let graph_hash: zako_digest::blake3::Hash = graph.get_blake3().into();
+    let output_hash = (resolved.get_blake3_hash(interner)?, edges_hash, graph_hash).get_blake3();
 
     let configured = ConfiguredPackage {
         source: new_ctx.package_source().clone(),
         package: resolved,
+        dependencies: resolved_dependencies,
     };
 
     return Ok((
         HashPair {
             input_hash: input_hash.into(),
-            output_hash: output_hash,
+            output_hash: output_hash.into(),
         },
         ResolvePackageResult {
             package: configured,
+            edges,
+            dependencies,
+            graph,
         },
     ));
 }
+
+/// Resolve every dependency into its own [ResolvePackage] node.
+///
+/// Returns the direct edges of the package and the nodes of its dependencies.
+async fn resolve_dependencies<'c>(
+    raw_ctx: &'c ZakoComputeContext<'c>,
+    ctx: &BuildContext,
+    root: &Utf8Path,
+    source: &PackageSource,
+    dependencies: BTreeMap<SmolStr, PackageSource>,
+) -> HoneResult<(GraphPackage, BTreeMap<SmolStr, ResolvePackage>)> {
+    let results = futures::future::try_join_all(
+        dependencies
+            .iter()
+            .map(|(alias, source)| resolve_dependency(raw_ctx, ctx, root, alias.as_str(), source)),
+    )
+    .await?;
+
+    let mut package = GraphPackage {
+        source: source.clone(),
+        dependencies: BTreeMap::new(),
+    };
+    let mut nodes = BTreeMap::new();
+
+    for ((alias, source), (id, node)) in dependencies.iter().zip(results) {
+        package.dependencies.insert(
+            alias.clone(),
+            GraphDependency {
+                req: dependency_requirement(source, split_package_id(&id).1)
+                    .wrap_err_with(|| format!("the dependency `@{}` is invalid", alias))?,
+                package: id,
+            },
+        );
+        nodes.insert(alias.clone(), node);
+    }
+
+    Ok((package, nodes))
+}
+
+/// Collect the edges of every package that the root depends on and unify the graph of them.
+///
+/// The nodes are resolved already by [resolve_dependencies], requesting them again is cheap.
+async fn resolve_graph<'c>(
+    raw_ctx: &'c ZakoComputeContext<'c>,
+    ctx: &BuildContext,
+    id: &str,
+    package: GraphPackage,
+    dependencies: &BTreeMap<SmolStr, ResolvePackage>,
+) -> HoneResult<PackageGraph> {
+    let interner = raw_ctx.context().interner();
+
+    let mut graph = PackageGraph::new(id.to_string(), package);
+    let mut queue: Vec<ResolvePackage> = dependencies.values().cloned().collect();
+    let mut seen = HashSet::new();
+
+    while let Some(node) = queue.pop() {
+        if !seen.insert(node.clone()) {
+            continue;
+        }
+
+        let dependency_id = node
+            .package
+            .resolved(interner)
+            .wrap_err("failed to resolve the dependency id")?;
+
+        let result = raw_ctx
+            .request_with_context(ZakoKey::ResolvePackage(node), ctx)
+            .await
+            .wrap_err_with(|| format!("failed to resolve the package `{}`", dependency_id))?;
+
+        let resolved = match &*result.into_value() {
+            ZakoValue::ResolvePackage(resolved) => resolved.clone(),
+            _ => return Err(eyre::eyre!("expected resolve package result").into()),
+        };
+
+        queue.extend(resolved.dependencies.into_values());
+        graph.merge(PackageGraph::new(dependency_id.to_string(), resolved.edges));
+    }
+
+    graph.unify().map_err(|err| eyre::eyre!(err))?;
+
+    Ok(graph)
+}
+
+/// Fetch the package that is not registered and register its root.
+async fn fetch_package_root(
+    ctx: &BuildContext,
+    key: &ResolvePackage,
+    locked: Option<PackageSource>,
+    package_id: &str,
+) -> HoneResult<InternedAbsolutePath> {
+    if let PackageSource::Path { .. } = key.source {
+        return Err(eyre::eyre!(
+            "the package `{}` is not found in the global state",
+            package_id
+        )
+        .into());
+    }
+
+    let root = fetch_source(ctx, &key.source, locked, package_id).await?;
+    let root = InternedAbsolutePath::new(&root, ctx.interner())?;
+
+    ctx.global_state()
+        .package_id_to_path()
+        .insert(key.package, root);
+
+    Ok(root)
+}
+
+/// Fetch a git, http or registry source and return the package root.
+///
+/// The `locked` source from the lockfile is fetched instead of the requested one if exists,
+/// then what the fetch pinned is recorded to the lockfile.
+///
+/// Nothing is fetched if the dependencies are vendored, the copies in the vendor directory are used.
+async fn fetch_source(
+    ctx: &BuildContext,
+    source: &PackageSource,
+    locked: Option<PackageSource>,
+    name: &str,
+) -> HoneResult<Utf8PathBuf> {
+    if let Some(vendor) = ctx.global_state().vendor() {
+        let (root, pinned) = vendor
+            .replace(source, locked.as_ref())
+            .wrap_err_with(|| format!("failed to resolve the vendored package `{}`", name))?;
+
+        ctx.global_state()
+            .lock_state()
+            .record(source.clone(), pinned.clone())
+            .wrap_err_with(|| format!("failed to lock the package `{}`", name))?;
+
+        return Ok(Utf8PathBuf::from_path_buf(root)
+            .map_err(|root| eyre::eyre!("the package root {:?} is not valid utf-8", root))?);
+    }
+
+    let fetched = ctx
+        .global_state()
+        .package_cache()
+        .fetch(
+            locked.as_ref().unwrap_or(source),
+            ctx.global_state().cas_store(),
+        )
+        .await
+        .wrap_err_with(|| format!("failed to fetch the package `{}`", name))?;
+
+    ctx.global_state()
+        .lock_state()
+        .record(source.clone(), fetched.pinned.clone())
+        .wrap_err_with(|| format!("failed to lock the package `{}`", name))?;
+
+    Ok(Utf8PathBuf::from_path_buf(fetched.root)
+        .map_err(|root| eyre::eyre!("the package root {:?} is not valid utf-8", root))?)
+}
+
+/// Apply the `patches` to the fetched `root` of the `source`, returns the root of the patched copy.
+async fn patch_source(
+    ctx: &BuildContext,
+    source: &PackageSource,
+    root: &Utf8Path,
+    patches: &[Patch],
+    alias: &str,
+) -> HoneResult<Utf8PathBuf> {
+    // fetch_source has just recorded the pin
+    let pinned = ctx
+        .global_state()
+        .lock_state()
+        .pinned(source)
+        .wrap_err_with(|| format!("failed to lock the dependency `@{}`", alias))?
+        .unwrap_or_else(|| source.clone());
+
+    let patched = apply_patches(
+        ctx.global_state().package_cache(),
+        root.as_std_path(),
+        &pinned,
+        patches,
+    )
+    .await
+    .wrap_err_with(|| format!("failed to patch the dependency `@{}`", alias))?;
+
+    Ok(Utf8PathBuf::from_path_buf(patched)
+        .map_err(|root| eyre::eyre!("the package root {:?} is not valid utf-8", root))?)
+}
+
+/// Resolve a dependency of the package at `requirer_root` into its own [ResolvePackage] node.
+///
+/// Returns the id of the dependency like `com.example:name@1.0.0` and its node.
+async fn resolve_dependency<'c>(
+    raw_ctx: &'c ZakoComputeContext<'c>,
+    requirer_ctx: &BuildContext,
+    requirer_root: &Utf8Path,
+    alias: &str,
+    source: &PackageSource,
+) -> HoneResult<(String, ResolvePackage)> {
+    let ctx = raw_ctx.context();
+    let interner = ctx.interner();
+
+    source
+        .validate()
+        .wrap_err_with(|| format!("the dependency `@{}` is invalid", alias))?;
+
+    let overridden = ctx.global_state().overrides().get(alias).cloned();
+
+    let root = match (overridden, source) {
+        (Some(overridden), _) => overridden.path.canonicalize_utf8().wrap_err_with(|| {
+            format!(
+                "the override of the dependency `@{}` is not found at `{}`",
+                alias, overridden.path
+            )
+        })?,
+        (None, PackageSource::Path { path }) => requirer_root
+            .join(path)
+            .canonicalize_utf8()
+            .wrap_err_with(|| format!("the dependency `@{}` is not found at `{}`", alias, path))?,
+        (None, _) => {
+            let locked = ctx
+                .global_state()
+                .lock_state()
+                .pinned(source)
+                .wrap_err_with(|| format!("failed to lock the dependency `@{}`", alias))?;
+
+            let root = fetch_source(ctx, source, locked, alias).await?;
+
+            match ctx.global_state().patches().get(alias) {
+                Some(patches) => patch_source(ctx, source, &root, patches, alias).await?,
+                None => root,
+            }
+        }
+    };
+
+    // the id is declared by the manifest of the dependency
+    let (manifest_path, format) = manifest::find(&root)
+        .ok_or_else(|| eyre::eyre!("the dependency `@{}` has no manifest in {:?}", alias, root))?;
+    let (_, result) = file::read_text(raw_ctx, manifest_path.clone()).await?;
+    let parsed = raw_ctx
+        .request_with_context(
+            ZakoKey::ParseManifest(ParseManifest {
+                blob_handle: result.content.clone(),
+                format,
+                path: InternedAbsolutePath::new(&manifest_path, interner)?,
+            }),
+            requirer_ctx,
+        )
+        .await
+        .wrap_err_with(|| {
+            format!(
+                "failed to parse the manifest of the dependency `@{}`",
+                alias
+            )
+        })?;
+    let manifest = match parsed.value().as_ref() {
+        ZakoValue::ParseManifest(parsed) => &parsed.project,
+        _ => {
+            return Err(eyre::eyre!("expected parse manifest result").into());
+        }
+    };
+
+    let id = format!(
+        "{}:{}@{}",
+        manifest.group, manifest.artifact, manifest.version
+    );
+    let package = InternedPackageId::try_parse(&id, interner)
+        .wrap_err_with(|| format!("the dependency `@{}` has an invalid id `{}`", alias, id))?;
+    let interned_root = InternedAbsolutePath::new(&root, interner)?;
+
+    ctx.global_state()
+        .package_id_to_path()
+        .entry(package)
+        .or_insert(interned_root);
+
+    let node = ResolvePackage {
+        package,
+        source: source.clone(),
+        root: Some(interned_root),
+    };
+
+    let result = raw_ctx
+        .request_with_context(ZakoKey::ResolvePackage(node.clone()), requirer_ctx)
+        .await
+        .wrap_err_with(|| format!("failed to resolve the dependency `@{}`", alias))?;
+
+    match &*result.into_value() {
+        ZakoValue::ResolvePackage(_) => Ok((id, node)),
+        _ => Err(eyre::eyre!("expected resolve package result").into()),
+    }
+}
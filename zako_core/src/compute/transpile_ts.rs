@@ -18,14 +18,9 @@ pub async fn transpile_ts<'c>(
     ctx: &'c ZakoComputeContext<'c>,
     key: &TranspileTs,
 ) -> HoneResult<(HashPair, TranspileTsResult)> {
-    let code = key.code.clone();
-    let code = ctx
-        .context()
-        .cas_store()
-        .read(
-            code.digest(),
-            &BlobRange::new(0, None).map_err(|err| HoneError::Other(err.into()))?,
-        )
+    let code = key
+        .code
+        .read(ctx.context().cas_store(), BlobRange::full())
         .await
         .wrap_err_with(|| {
             format!(
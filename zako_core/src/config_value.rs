@@ -115,6 +115,7 @@ impl Blake3Hash for ConfigOperation {
 )]
 #[ts(export, export_to = "config_type.d.ts")]
 #[ts(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum ConfigType {
     Label,
     Boolean,
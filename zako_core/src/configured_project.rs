@@ -1,8 +1,11 @@
 use ::std::{
+    collections::BTreeMap,
     hash::{Hash, Hasher},
     sync::Arc,
 };
 
+use ::smol_str::SmolStr;
+
 use ::zako_digest::blake3::Blake3Hash;
 
 use crate::{
@@ -10,6 +13,7 @@ use crate::{
     global_state::GlobalState,
     intern::{InternedAbsolutePath, Interner},
     package::{Package, PackageResolveError, ResolvedPackage},
+    package_id::InternedPackageId,
     package_source::{InternedPackageSource, PackageSource},
 };
 
@@ -17,4 +21,9 @@ use crate::{
 pub struct ConfiguredPackage {
     pub source: InternedPackageSource,
     pub package: ResolvedPackage,
+    /// The packages that the dependencies resolve to, the key is the name in the manifest.
+    ///
+    /// The versions are unified for the root package of the build only,
+    /// see [crate::node::resolve_package::ResolvePackageResult::graph] for the others.
+    pub dependencies: BTreeMap<SmolStr, InternedPackageId>,
 }
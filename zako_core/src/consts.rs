@@ -7,6 +7,12 @@ pub static LIBRARY_FILE_SUFFIX: &str = ".ts";
 /// definition of project.see [crate] documents for details.
 pub static PACKAGE_MANIFEST_FILE_NAME: &str = "zako.toml";
 
+/// definition of project in json, see [crate::manifest].
+pub static PACKAGE_JSON_MANIFEST_FILE_NAME: &str = "zako.json";
+
+/// The lockfile next to the manifest, see [crate::lockfile].
+pub static PACKAGE_LOCKFILE_NAME: &str = "zako.lock";
+
 /// project file name.see [crate] documents for details.
 pub static PACKAGE_SCRIPT_FILE_NAME: &str = "zako.ts";
 
@@ -48,7 +54,7 @@ pub const fn default_false() -> bool {
 /// see [crate] documents for details.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FileType {
-    /// [PACKAGE_MANIFEST_FILE_NAME]
+    /// [PACKAGE_MANIFEST_FILE_NAME] or [PACKAGE_JSON_MANIFEST_FILE_NAME]
     PackageManifest,
     /// [PACKAGE_SCRIPT_FILE_NAME]
     PackageScript,
@@ -65,3 +71,15 @@ pub enum FileType {
     /// [LIBRARY_FILE_SUFFIX]
     Library,
 }
+
+/// The directory next to the workspace manifest that holds the vendored dependencies, see [crate::vendor].
+pub static VENDOR_DIR_NAME: &str = "vendor";
+
+/// The source replacement config in the [VENDOR_DIR_NAME] directory, see [crate::vendor::VendorConfig].
+pub static VENDOR_CONFIG_FILE_NAME: &str = "vendor.toml";
+
+/// The user config of the local dependency overrides in the config directory, see [crate::overrides].
+pub static USER_OVERRIDES_FILE_NAME: &str = "overrides.toml";
+
+/// The environment variable of the token that `zako publish` sends to a http registry.
+pub static REGISTRY_TOKEN_ENV: &str = "ZAKO_REGISTRY_TOKEN";
@@ -0,0 +1,188 @@
+//! Errors that point into a source file, they are rendered by [miette] like
+//!
+//! ```text
+//!   × the package config key `1bad` is not a valid xid_loose_ident
+//!    ╭─[/project/zako.toml:3:9]
+//!  2 │
+//!  3 │ [config.1bad]
+//!    ·         ────
+//!    ╰────
+//! ```
+use std::fmt::{self, Display};
+use std::ops::Range;
+use std::path::PathBuf;
+
+use miette::{
+    GraphicalReportHandler, GraphicalTheme, LabeledSpan, NamedSource, SourceCode, SourceOffset,
+};
+
+/// Where a [Diagnostic] points in the source file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Starts from 1.
+    pub line: usize,
+    /// Starts from 1, counted in bytes like [miette::SpanContents::column].
+    pub column: usize,
+    /// The count of chars that are underlined in the first line, at least 1.
+    pub width: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub file: PathBuf,
+    /// The content of the file, the [Diagnostic::span] is in it.
+    pub source: Option<String>,
+    /// The byte span that the diagnostic points to.
+    pub span: Option<Range<usize>>,
+    /// The text of the underline.
+    pub label: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>, file: impl Into<PathBuf>) -> Self {
+        Self {
+            message: message.into(),
+            file: file.into(),
+            source: None,
+            span: None,
+            label: None,
+        }
+    }
+
+    /// Point to the byte `span` of the `source`.
+    pub fn with_span(mut self, source: impl Into<String>, span: Range<usize>) -> Self {
+        self.source = Some(source.into());
+        self.span = Some(span);
+        self
+    }
+
+    /// Point to the 1-based `line` and `column`, like the ones of [serde_json::Error].
+    pub fn with_line_column(self, source: impl Into<String>, line: usize, column: usize) -> Self {
+        let source = source.into();
+        let offset = SourceOffset::from_location(&source, line, column).offset();
+
+        self.with_span(source, offset..offset)
+    }
+
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn get_location(&self) -> Option<Location> {
+        let (source, span) = (self.source.as_ref()?, self.span.clone()?);
+        let contents = source.read_span(&span.into(), 0, 0).ok()?;
+        let underlined = String::from_utf8_lossy(contents.data());
+
+        Some(Location {
+            line: contents.line() + 1,
+            column: contents.column() + 1,
+            width: underlined
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .chars()
+                .count()
+                .max(1),
+        })
+    }
+}
+
+/// The [miette::Diagnostic] of a [Diagnostic], its [Display] is only the message.
+struct Report<'a> {
+    diagnostic: &'a Diagnostic,
+    source: Option<NamedSource<String>>,
+}
+
+impl Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.diagnostic.message)
+    }
+}
+
+impl fmt::Debug for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.diagnostic, f)
+    }
+}
+
+impl std::error::Error for Report<'_> {}
+
+impl miette::Diagnostic for Report<'_> {
+    fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+        // the file is shown with the snippet if there is one
+        match self.source {
+            Some(_) => None,
+            None => Some(Box::new(format!("in {}", self.diagnostic.file.display()))),
+        }
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.source.as_ref().map(|source| source as &dyn SourceCode)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        let span = self.diagnostic.span.clone()?;
+
+        Some(Box::new(std::iter::once(LabeledSpan::new_with_span(
+            self.diagnostic.label.clone(),
+            span,
+        ))))
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = self
+            .source
+            .as_ref()
+            .filter(|_| self.span.is_some())
+            .map(|source| NamedSource::new(self.file.display().to_string(), source.clone()));
+
+        let report = Report {
+            diagnostic: self,
+            source,
+        };
+
+        let mut rendered = String::new();
+        GraphicalReportHandler::new_themed(GraphicalTheme::unicode_nocolor())
+            .with_links(false)
+            .render_report(&mut rendered, &report)?;
+
+        write!(f, "{}", rendered.trim_end())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// The [Diagnostic]s of one failure, like all the schema violations of a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+    pub fn get_diagnostics(&self) -> &[Diagnostic] {
+        &self.0
+    }
+}
+
+impl From<Diagnostic> for Diagnostics {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Self(vec![diagnostic])
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, diagnostic) in self.0.iter().enumerate() {
+            if index != 0 {
+                write!(f, "\n\n")?;
+            }
+            write!(f, "{}", diagnostic)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
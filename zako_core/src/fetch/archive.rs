@@ -0,0 +1,532 @@
+//! Download the archives of [crate::package_source::PackageSource::Http] and unpack them.
+//!
+//! - The archive is verified against the [Integrity] and stored in the cas.
+//! - The content is unpacked once into [PackageCache::get_archives_path], by the blake3 of the archive.
+//! - The unpacking ignores the owners, the times and the umask,
+//!   the same archive always gives the same tree.
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use futures::TryStreamExt;
+use sha2::Digest as _;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tracing::info;
+
+use crate::cas_store::CasStore;
+use crate::fetch::{FetchError, PackageCache, commit_staged};
+
+/// The hash of an archive, written like `sha256:<hex>` or `blake3:<hex>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Integrity {
+    Blake3(String),
+    Sha256(String),
+}
+
+impl FromStr for Integrity {
+    type Err = FetchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || FetchError::InvalidIntegrity(s.to_string());
+
+        let (algorithm, hex) = s.split_once(':').ok_or_else(invalid)?;
+
+        if hex.len() != 64 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+
+        let hex = hex.to_ascii_lowercase();
+
+        match algorithm {
+            "blake3" => Ok(Integrity::Blake3(hex)),
+            "sha256" => Ok(Integrity::Sha256(hex)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for Integrity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Integrity::Blake3(hex) => write!(f, "blake3:{}", hex),
+            Integrity::Sha256(hex) => write!(f, "sha256:{}", hex),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    TarGz,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Guess the format from the extension of the url path.
+    pub fn from_url(url: &str) -> Option<Self> {
+        let path = url.split(['?', '#']).next().unwrap_or(url);
+
+        if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if path.ends_with(".tar.zst") || path.ends_with(".tzst") {
+            Some(ArchiveFormat::TarZst)
+        } else if path.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// An archive unpacked in the [PackageCache].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnpackedArchive {
+    pub url: String,
+    /// The blake3 of the archive, it pins the content.
+    pub integrity: Integrity,
+    /// The root of the archive, before the strip prefix.
+    pub path: PathBuf,
+}
+
+/// The relative path of an entry, the `.` and the leading `/` are dropped, `..` is refused.
+fn entry_path(path: &str) -> std::io::Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+
+    for component in path.split(['/', '\\']) {
+        match component {
+            "" | "." => continue,
+            ".." => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("the archive entry `{}` escapes the archive", path),
+                ));
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    Ok(normalized)
+}
+
+/// Refuse the symlink whose target is outside of the archive.
+fn check_symlink(path: &Path, target: &str) -> std::io::Result<()> {
+    let escapes = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "the symlink {:?} to `{}` points outside of the archive",
+                path, target
+            ),
+        )
+    };
+
+    if target.starts_with('/') || target.starts_with('\\') {
+        return Err(escapes());
+    }
+
+    let mut depth = path.components().count().saturating_sub(1);
+
+    for component in target.split(['/', '\\']) {
+        match component {
+            "" | "." => {}
+            ".." => depth = depth.checked_sub(1).ok_or_else(escapes)?,
+            _ => depth += 1,
+        }
+    }
+
+    Ok(())
+}
+
+/// Refuse the symlink that is under another symlink of the archive or whose target goes through one,
+/// like `t -> .` followed by `s -> t/t/t/../../..`, [check_symlink] can not see those.
+fn check_symlink_chain(
+    symlinks: &HashSet<&Path>,
+    path: &Path,
+    target: &str,
+) -> std::io::Result<()> {
+    let through = |link: &Path| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "the symlink {:?} to `{}` goes through the symlink {:?}",
+                path, target, link
+            ),
+        )
+    };
+
+    if let Some(link) = path.ancestors().skip(1).find(|dir| symlinks.contains(dir)) {
+        return Err(through(link));
+    }
+
+    let components = target
+        .split(['/', '\\'])
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect::<Vec<_>>();
+    let mut resolved = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    // the last component may be another symlink, it is checked on its own
+    for (index, component) in components.iter().enumerate() {
+        if *component == ".." {
+            resolved.pop();
+            continue;
+        }
+
+        resolved.push(component);
+
+        if index + 1 < components.len() && symlinks.contains(resolved.as_path()) {
+            return Err(through(&resolved));
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the entries under the root, the symlinks are created last so no write goes through one.
+struct Unpacker<'a> {
+    root: &'a Path,
+    symlinks: Vec<(PathBuf, String)>,
+}
+
+impl Unpacker<'_> {
+    fn create_parent(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = self.root.join(path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        Ok(())
+    }
+
+    fn directory(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(self.root.join(path))
+    }
+
+    fn file(&self, path: &Path, mode: u32, data: &mut dyn Read) -> std::io::Result<()> {
+        if path.as_os_str().is_empty() {
+            return Ok(());
+        }
+
+        self.create_parent(path)?;
+
+        let mut file = std::fs::File::create(self.root.join(path))?;
+        std::io::copy(data, &mut file)?;
+        file.flush()?;
+        file.set_modified(SystemTime::UNIX_EPOCH)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = if mode & 0o111 != 0 { 0o755 } else { 0o644 };
+            file.set_permissions(std::fs::Permissions::from_mode(mode))?;
+        }
+        #[cfg(not(unix))]
+        let _ = mode;
+
+        Ok(())
+    }
+
+    fn hard_link(&self, path: &Path, target: &str) -> std::io::Result<()> {
+        let target = entry_path(target)?;
+
+        self.create_parent(path)?;
+        std::fs::copy(self.root.join(target), self.root.join(path))?;
+
+        Ok(())
+    }
+
+    fn symlink(&mut self, path: PathBuf, target: String) -> std::io::Result<()> {
+        check_symlink(&path, &target)?;
+        self.symlinks.push((path, target));
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        let paths = self
+            .symlinks
+            .iter()
+            .map(|(path, _)| path.as_path())
+            .collect::<HashSet<_>>();
+
+        for (path, target) in &self.symlinks {
+            check_symlink_chain(&paths, path, target)?;
+        }
+
+        for (path, target) in std::mem::take(&mut self.symlinks) {
+            self.create_parent(&path)?;
+
+            let link = self.root.join(&path);
+            if link.symlink_metadata().is_ok() {
+                std::fs::remove_file(&link)?;
+            }
+
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&target, &link)?;
+            #[cfg(not(unix))]
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("the symlink {:?} to `{}` is not supported", link, target),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn unpack_tar(reader: impl Read, root: &Path) -> std::io::Result<()> {
+    let mut tar = tar::Archive::new(reader);
+    let mut unpacker = Unpacker {
+        root,
+        symlinks: Vec::new(),
+    };
+
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let name = utf8(&entry.path()?)?;
+        let path = entry_path(&name)?;
+        let link = || match entry.link_name() {
+            Ok(Some(link)) => utf8(&link),
+            Ok(None) => Ok(String::new()),
+            Err(err) => Err(err),
+        };
+
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => unpacker.directory(&path)?,
+            // the old archives mark the directories by the trailing slash only
+            tar::EntryType::Regular if name.ends_with('/') => unpacker.directory(&path)?,
+            tar::EntryType::Symlink => unpacker.symlink(path, link()?)?,
+            tar::EntryType::Link => unpacker.hard_link(&path, &link()?)?,
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                let mode = entry.header().mode()?;
+                unpacker.file(&path, mode, &mut entry)?
+            }
+            // the devices and the fifos are never unpacked
+            _ => {}
+        }
+    }
+
+    unpacker.finish()
+}
+
+fn utf8(path: &Path) -> std::io::Result<String> {
+    path.to_str().map(str::to_string).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("the archive entry {:?} is not valid utf-8", path),
+        )
+    })
+}
+
+fn unpack_zip(reader: impl Read + Seek, root: &Path) -> std::io::Result<()> {
+    let mut zip = zip::ZipArchive::new(reader).map_err(std::io::Error::other)?;
+    let mut unpacker = Unpacker {
+        root,
+        symlinks: Vec::new(),
+    };
+
+    for index in 0..zip.len() {
+        let mut entry = zip.by_index(index).map_err(std::io::Error::other)?;
+        let path = entry_path(entry.name())?;
+
+        if entry.is_dir() {
+            unpacker.directory(&path)?;
+        } else if entry.is_symlink() {
+            let mut target = String::new();
+            entry.read_to_string(&mut target)?;
+            unpacker.symlink(path, target)?;
+        } else {
+            let mode = entry.unix_mode().unwrap_or(0o644);
+            unpacker.file(&path, mode, &mut entry)?;
+        }
+    }
+
+    unpacker.finish()
+}
+
+/// Unpack the archive into the `root`, which should not exist or be empty.
+pub fn unpack_archive(archive: &Path, format: ArchiveFormat, root: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(root)?;
+
+    let file = std::io::BufReader::new(std::fs::File::open(archive)?);
+
+    match format {
+        ArchiveFormat::TarGz => unpack_tar(flate2::read::MultiGzDecoder::new(file), root),
+        ArchiveFormat::TarZst => unpack_tar(zstd::stream::read::Decoder::new(file)?, root),
+        ArchiveFormat::Zip => unpack_zip(file, root),
+    }
+}
+
+/// Write the `reader` into the `target` file, returns the blake3 and the sha256 hex.
+pub(crate) async fn copy_hashed(
+    reader: &mut (dyn AsyncRead + Send + Unpin),
+    target: &Path,
+) -> Result<(String, String), FetchError> {
+    let io_error = |err| FetchError::Io(err, target.to_path_buf());
+
+    let mut file = tokio::fs::File::create(target).await.map_err(io_error)?;
+    let mut blake3 = ::blake3::Hasher::new();
+    let mut sha256 = sha2::Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let count = reader.read(&mut buffer).await.map_err(io_error)?;
+        if count == 0 {
+            break;
+        }
+
+        blake3.update(&buffer[..count]);
+        sha256.update(&buffer[..count]);
+        file.write_all(&buffer[..count]).await.map_err(io_error)?;
+    }
+
+    file.flush().await.map_err(io_error)?;
+
+    Ok((
+        blake3.finalize().to_hex().to_string(),
+        hex::encode(sha256.finalize()),
+    ))
+}
+
+/// Download the `url` into the `target` file, returns the blake3 and the sha256 hex.
+async fn download(url: &str, target: &Path) -> Result<(String, String), FetchError> {
+    let http_error = |err| FetchError::Http(url.to_string(), err);
+
+    // the archive is hashed as served, it must not be decoded on the way
+    let client = reqwest::Client::builder()
+        .no_gzip()
+        .no_brotli()
+        .no_deflate()
+        .build()
+        .map_err(http_error)?;
+
+    let response = client.get(url).send().await.map_err(http_error)?;
+
+    if !response.status().is_success() {
+        return Err(FetchError::HttpStatus {
+            url: url.to_string(),
+            status: response.status().as_u16(),
+        });
+    }
+
+    let stream = response.bytes_stream().map_err(std::io::Error::other);
+    let mut reader = tokio_util::io::StreamReader::new(stream);
+
+    copy_hashed(&mut reader, target).await
+}
+
+/// Verify the downloaded archive against the `expected` integrity, returns the blake3 hex of it.
+pub(crate) fn verify_integrity(
+    source: &str,
+    expected: Option<&Integrity>,
+    (blake3, sha256): (String, String),
+) -> Result<String, FetchError> {
+    let actual = match expected {
+        Some(Integrity::Sha256(_)) => Integrity::Sha256(sha256),
+        _ => Integrity::Blake3(blake3.clone()),
+    };
+
+    if let Some(expected) = expected
+        && *expected != actual
+    {
+        return Err(FetchError::IntegrityMismatch {
+            url: source.to_string(),
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        });
+    }
+
+    Ok(blake3)
+}
+
+/// The blake3 hex and the path of the unpacked archive of the `integrity` in the cache, if any.
+pub(crate) fn find_unpacked(
+    cache: &PackageCache,
+    integrity: Option<&Integrity>,
+) -> Option<(String, PathBuf)> {
+    let Some(Integrity::Blake3(hex)) = integrity else {
+        return None;
+    };
+
+    let path = cache.get_archives_path().join(hex);
+
+    path.exists().then(|| (hex.clone(), path))
+}
+
+/// Store the verified archive at `staged` in the `cas` and unpack it into the cache, returns the unpacked root.
+pub(crate) async fn unpack_staged(
+    cache: &PackageCache,
+    cas: &CasStore,
+    source: &str,
+    staged: &Path,
+    format: ArchiveFormat,
+    blake3: &str,
+) -> Result<PathBuf, FetchError> {
+    cas.put_file(staged).await?;
+
+    let path = cache.get_archives_path().join(blake3);
+
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let unpacked = cache.new_staging_path().await?;
+    let archive = staged.to_path_buf();
+    let root = unpacked.clone();
+
+    let result = tokio::task::spawn_blocking(move || unpack_archive(&archive, format, &root))
+        .await
+        .map_err(|err| FetchError::Unpack(source.to_string(), std::io::Error::other(err)))
+        .and_then(|result| result.map_err(|err| FetchError::Unpack(source.to_string(), err)));
+
+    if let Err(err) = result {
+        let _ = tokio::fs::remove_dir_all(&unpacked).await;
+        return Err(err);
+    }
+
+    commit_staged(&unpacked, &path).await?;
+
+    Ok(path)
+}
+
+/// Fetch the archive at the `url` and unpack it, it is verified against the `integrity` if any.
+///
+/// An archive with a blake3 integrity that is unpacked already needs no network.
+pub async fn fetch_archive(
+    cache: &PackageCache,
+    cas: &CasStore,
+    url: &str,
+    integrity: Option<&Integrity>,
+) -> Result<UnpackedArchive, FetchError> {
+    if let Some((blake3, path)) = find_unpacked(cache, integrity) {
+        return Ok(UnpackedArchive {
+            url: url.to_string(),
+            integrity: Integrity::Blake3(blake3),
+            path,
+        });
+    }
+
+    let format = ArchiveFormat::from_url(url)
+        .ok_or_else(|| FetchError::UnknownArchiveFormat(url.to_string()))?;
+
+    info!("download {}", url);
+
+    let staged = cache.new_staging_path().await?;
+
+    let result = async {
+        let hashes = download(url, &staged).await?;
+        let blake3 = verify_integrity(url, integrity, hashes)?;
+        let path = unpack_staged(cache, cas, url, &staged, format, &blake3).await?;
+
+        Ok(UnpackedArchive {
+            url: url.to_string(),
+            integrity: Integrity::Blake3(blake3),
+            path,
+        })
+    }
+    .await;
+
+    let _ = tokio::fs::remove_file(&staged).await;
+
+    result
+}
@@ -0,0 +1,225 @@
+//! Fetch git repositories with the `git` program, so every transport and credential helper of the user works.
+//!
+//! - A repository is mirrored once into [PackageCache::get_git_db_path] and updated by later fetches.
+//! - A commit is checked out once into [PackageCache::get_git_checkouts_path], without the `.git` directory.
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use tracing::{debug, info};
+
+use crate::fetch::{FetchError, PackageCache, commit_staged};
+
+/// The program to run, it is looked up in the `PATH`.
+pub static GIT_PROGRAM: &str = "git";
+
+/// A commit of a repository checked out in the [PackageCache].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitCheckout {
+    pub repo: String,
+    /// The full hex of the commit.
+    pub commit: String,
+    pub path: PathBuf,
+}
+
+fn is_commit_id(reference: &str) -> bool {
+    matches!(reference.len(), 40 | 64) && reference.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Run git and return the trimmed stdout.
+async fn git<I, S>(args: I) -> Result<String, FetchError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = Command::new(GIT_PROGRAM);
+    command
+        .args(args)
+        // never wait for a password in the middle of a build
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .kill_on_drop(true);
+
+    let described = format!("{:?}", command.as_std());
+
+    debug!("run {}", described);
+
+    let output = command
+        .output()
+        .await
+        .map_err(|err| FetchError::Io(err, PathBuf::from(GIT_PROGRAM)))?;
+
+    if !output.status.success() {
+        return Err(FetchError::Command {
+            command: described,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn git_dir(db: &Path) -> String {
+    format!("--git-dir={}", db.display())
+}
+
+/// The mirror of the `repo`, created or updated.
+async fn update_db(cache: &PackageCache, repo: &str, db: &Path) -> Result<(), FetchError> {
+    if db.exists() {
+        info!("fetch git repository {}", repo);
+        git([
+            git_dir(db).as_str(),
+            "fetch",
+            "--quiet",
+            "--prune",
+            "origin",
+        ])
+        .await?;
+        return Ok(());
+    }
+
+    info!("clone git repository {}", repo);
+
+    let staged = cache.new_staging_path().await?;
+    let result = git([
+        OsStr::new("clone"),
+        OsStr::new("--quiet"),
+        OsStr::new("--mirror"),
+        OsStr::new("--"),
+        OsStr::new(repo),
+        staged.as_os_str(),
+    ])
+    .await;
+
+    if let Err(err) = result {
+        let _ = tokio::fs::remove_dir_all(&staged).await;
+        return Err(err);
+    }
+
+    commit_staged(&staged, db).await
+}
+
+/// Resolve the `reference` to a commit in the mirror, `None` if it does not exist.
+async fn resolve_commit(db: &Path, reference: &str) -> Option<String> {
+    git([
+        git_dir(db).as_str(),
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        "--end-of-options",
+        &format!("{}^{{commit}}", reference),
+    ])
+    .await
+    .ok()
+}
+
+async fn checkout_commit(
+    cache: &PackageCache,
+    db: &Path,
+    commit: &str,
+    target: &Path,
+) -> Result<(), FetchError> {
+    let staged = cache.new_staging_path().await?;
+
+    let result = async {
+        git([
+            OsStr::new("clone"),
+            OsStr::new("--quiet"),
+            OsStr::new("--no-checkout"),
+            OsStr::new("--"),
+            db.as_os_str(),
+            staged.as_os_str(),
+        ])
+        .await?;
+
+        git([
+            OsStr::new("-C"),
+            staged.as_os_str(),
+            OsStr::new("-c"),
+            OsStr::new("advice.detachedHead=false"),
+            OsStr::new("checkout"),
+            OsStr::new("--quiet"),
+            OsStr::new("--detach"),
+            OsStr::new(commit),
+        ])
+        .await?;
+
+        // the checkout is addressed by the commit, the history is in the mirror already
+        let dot_git = staged.join(".git");
+        tokio::fs::remove_dir_all(&dot_git)
+            .await
+            .map_err(|err| FetchError::Io(err, dot_git))
+    }
+    .await;
+
+    if let Err(err) = result {
+        let _ = tokio::fs::remove_dir_all(&staged).await;
+        return Err(err);
+    }
+
+    commit_staged(&staged, target).await
+}
+
+/// Fetch the `checkout` of the `repo`, it can be a branch, a tag or a commit, `None` means the default branch.
+///
+/// A checkout of a commit id that is in the cache already needs no network.
+pub async fn fetch_git(
+    cache: &PackageCache,
+    repo: &str,
+    checkout: Option<&str>,
+) -> Result<GitCheckout, FetchError> {
+    let checkouts = cache.get_git_checkouts_path();
+
+    if let Some(commit) = checkout.filter(|reference| is_commit_id(reference)) {
+        let commit = commit.to_ascii_lowercase();
+        let path = checkouts.join(&commit);
+
+        if path.exists() {
+            return Ok(GitCheckout {
+                repo: repo.to_string(),
+                commit,
+                path,
+            });
+        }
+    }
+
+    let db = cache.get_git_db_path().join(format!(
+        "{}.git",
+        &blake3::hash(repo.as_bytes()).to_hex()[..32]
+    ));
+
+    let reference = checkout.unwrap_or("HEAD");
+
+    // a pinned commit that is mirrored already needs no fetch
+    let mirrored = if is_commit_id(reference) && db.exists() {
+        resolve_commit(&db, reference).await
+    } else {
+        None
+    };
+
+    let commit = match mirrored {
+        Some(commit) => commit,
+        None => {
+            update_db(cache, repo, &db).await?;
+
+            resolve_commit(&db, reference)
+                .await
+                .ok_or_else(|| FetchError::ReferenceNotFound {
+                    repo: repo.to_string(),
+                    reference: reference.to_string(),
+                })?
+        }
+    };
+
+    let path = checkouts.join(&commit);
+
+    if !path.exists() {
+        info!("check out {} of {}", commit, repo);
+        checkout_commit(cache, &db, &commit, &path).await?;
+    }
+
+    Ok(GitCheckout {
+        repo: repo.to_string(),
+        commit,
+        path,
+    })
+}
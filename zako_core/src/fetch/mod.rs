@@ -0,0 +1,265 @@
+//! Fetch the packages that are not on the local disk into the [PackageCache].
+pub mod archive;
+pub mod git;
+pub mod patch;
+pub mod registry;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::cas_store::{CasStore, CasStoreError};
+use crate::fetch::archive::Integrity;
+use crate::package_source::PackageSource;
+use crate::registry::{Registry, RegistryError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum FetchError {
+    #[error("io error at {1:?}: {0}")]
+    Io(#[source] std::io::Error, PathBuf),
+    #[error("`{command}` failed: {stderr}")]
+    Command { command: String, stderr: String },
+    #[error("the reference `{reference}` is not found in the git repository `{repo}`")]
+    ReferenceNotFound { repo: String, reference: String },
+    #[error("the package source {0:?} can not be fetched")]
+    Unsupported(PackageSource),
+    #[error("failed to download `{0}`: {1}")]
+    Http(String, #[source] reqwest::Error),
+    #[error("failed to download `{url}`: the server responds {status}")]
+    HttpStatus { url: String, status: u16 },
+    #[error("`{0}` is not an integrity like `sha256:<hex>` or `blake3:<hex>`")]
+    InvalidIntegrity(String),
+    #[error("the archive `{url}` is {actual} but {expected} is expected")]
+    IntegrityMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("the format of the archive `{0}` is unknown, use `.tar.gz`, `.tar.zst` or `.zip`")]
+    UnknownArchiveFormat(String),
+    #[error("failed to unpack the archive `{0}`: {1}")]
+    Unpack(String, #[source] std::io::Error),
+    #[error("the strip prefix `{prefix}` is not a directory of the archive `{url}`")]
+    StripPrefixNotFound { url: String, prefix: String },
+    #[error("failed to store the archive: {0}")]
+    Cas(#[from] CasStoreError),
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+    #[error("the registry package `{0}` can not be fetched without a registry")]
+    NoRegistry(String),
+    #[error("failed to apply the patch {patch:?}: {stderr}")]
+    Patch { patch: PathBuf, stderr: String },
+}
+
+/// The shared cache of the fetched packages, usually [crate::resource::heuristics::determine_package_cache_path].
+///
+/// The fetched content is addressed by what pins it, like the commit of a git repository,
+/// so the builds of different projects share it.
+#[derive(Debug, Clone)]
+pub struct PackageCache {
+    root: PathBuf,
+    registry: Option<Arc<dyn Registry>>,
+}
+
+/// A package that is fetched into the [PackageCache].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedPackage {
+    /// The package root, it holds the manifest.
+    pub root: PathBuf,
+    /// The source that pins the fetched content, like a git source with the resolved commit.
+    pub pinned: PackageSource,
+}
+
+impl PackageCache {
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            registry: None,
+        }
+    }
+
+    /// Fetch the [PackageSource::Registry] packages from the `registry`.
+    pub fn with_registry(mut self, registry: Arc<dyn Registry>) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    pub fn get_root(&self) -> &PathBuf {
+        &self.root
+    }
+
+    pub fn get_registry(&self) -> Option<&dyn Registry> {
+        self.registry.as_deref()
+    }
+
+    /// The bare mirrors of the git repositories.
+    pub fn get_git_db_path(&self) -> PathBuf {
+        self.root.join("git").join("db")
+    }
+
+    /// The checkouts of the git repositories, by the commit.
+    pub fn get_git_checkouts_path(&self) -> PathBuf {
+        self.root.join("git").join("checkouts")
+    }
+
+    /// The unpacked archives, by the blake3 of the archive.
+    pub fn get_archives_path(&self) -> PathBuf {
+        self.root.join("archives")
+    }
+
+    /// The patched copies of the packages, see [patch].
+    pub fn get_patched_path(&self) -> PathBuf {
+        self.root.join("patched")
+    }
+
+    /// The directory that holds the fetches in progress, a fetch is renamed into place once complete.
+    pub fn get_staging_path(&self) -> PathBuf {
+        self.root.join("staging")
+    }
+
+    /// Create the staging directory and return a new path in it.
+    pub(crate) async fn new_staging_path(&self) -> Result<PathBuf, FetchError> {
+        let staging = self.get_staging_path();
+
+        tokio::fs::create_dir_all(&staging)
+            .await
+            .map_err(|err| FetchError::Io(err, staging.clone()))?;
+
+        Ok(staging.join(format!("tmp_{}", uuid::Uuid::new_v4())))
+    }
+
+    /// Fetch the package, the content that is fetched already is reused.
+    ///
+    /// The downloaded archives are stored in the `cas` as well.
+    pub async fn fetch(
+        &self,
+        source: &PackageSource,
+        cas: &CasStore,
+    ) -> Result<FetchedPackage, FetchError> {
+        match source {
+            PackageSource::Git { repo, checkout } => {
+                let checkout = git::fetch_git(self, repo, checkout.as_deref()).await?;
+
+                Ok(FetchedPackage {
+                    root: checkout.path,
+                    pinned: PackageSource::Git {
+                        repo: repo.clone(),
+                        checkout: Some(checkout.commit.into()),
+                    },
+                })
+            }
+            PackageSource::Http {
+                url,
+                integrity,
+                strip_prefix,
+            } => {
+                let expected = integrity
+                    .as_deref()
+                    .map(str::parse::<Integrity>)
+                    .transpose()?;
+
+                let archive = archive::fetch_archive(self, cas, url, expected.as_ref()).await?;
+
+                let root = match strip_prefix {
+                    Some(prefix) => {
+                        let root = archive.path.join(prefix.as_str());
+
+                        if prefix.split(['/', '\\']).any(|part| part == "..") || !root.is_dir() {
+                            return Err(FetchError::StripPrefixNotFound {
+                                url: url.to_string(),
+                                prefix: prefix.to_string(),
+                            });
+                        }
+
+                        root
+                    }
+                    None => archive.path,
+                };
+
+                Ok(FetchedPackage {
+                    root,
+                    pinned: PackageSource::Http {
+                        url: url.clone(),
+                        integrity: Some(archive.integrity.to_string().into()),
+                        strip_prefix: strip_prefix.clone(),
+                    },
+                })
+            }
+            PackageSource::Registry { package } => {
+                let registry = self
+                    .get_registry()
+                    .ok_or_else(|| FetchError::NoRegistry(package.clone()))?;
+
+                let fetched = registry::fetch_registry(self, cas, registry, package).await?;
+
+                Ok(FetchedPackage {
+                    root: fetched.path,
+                    pinned: PackageSource::Registry {
+                        package: format!("{}@{}", fetched.package, fetched.entry.version),
+                    },
+                })
+            }
+            PackageSource::Path { .. } => Err(FetchError::Unsupported(source.clone())),
+        }
+    }
+}
+
+/// Move the `staged` directory to the `target`, a concurrent fetch may have placed the same content there.
+pub(crate) async fn commit_staged(staged: &Path, target: &Path) -> Result<(), FetchError> {
+    if let Some(parent) = target.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|err| FetchError::Io(err, parent.to_path_buf()))?;
+    }
+
+    if let Err(err) = tokio::fs::rename(staged, target).await {
+        let _ = tokio::fs::remove_dir_all(staged).await;
+
+        if !target.exists() {
+            return Err(FetchError::Io(err, target.to_path_buf()));
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy the directory `from` into `to`, the `.git` directories are skipped.
+pub(crate) fn copy_dir(from: &Path, to: &Path) -> Result<(), FetchError> {
+    std::fs::create_dir_all(to).map_err(|err| FetchError::Io(err, to.to_path_buf()))?;
+
+    let entries = std::fs::read_dir(from).map_err(|err| FetchError::Io(err, from.to_path_buf()))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|err| FetchError::Io(err, from.to_path_buf()))?;
+        let source = entry.path();
+        let target = to.join(entry.file_name());
+        let file_type = entry
+            .file_type()
+            .map_err(|err| FetchError::Io(err, source.clone()))?;
+
+        if file_type.is_dir() {
+            if entry.file_name() != ".git" {
+                copy_dir(&source, &target)?;
+            }
+        } else if file_type.is_symlink() {
+            copy_symlink(&source, &target)?;
+        } else {
+            std::fs::copy(&source, &target).map_err(|err| FetchError::Io(err, source.clone()))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> Result<(), FetchError> {
+    let link = std::fs::read_link(from).map_err(|err| FetchError::Io(err, from.to_path_buf()))?;
+
+    std::os::unix::fs::symlink(link, to).map_err(|err| FetchError::Io(err, to.to_path_buf()))
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, to: &Path) -> Result<(), FetchError> {
+    std::fs::copy(from, to)
+        .map(|_| ())
+        .map_err(|err| FetchError::Io(err, from.to_path_buf()))
+}
@@ -0,0 +1,186 @@
+//! Apply the patch files of [crate::package::Package::patches] to the fetched packages.
+//!
+//! The fetched package is never changed, the patches are applied to a copy in
+//! [PackageCache::get_patched_path] that is addressed by the pinned source and the patches,
+//! so the patched content has its own digest and is reused by the later builds.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use smol_str::SmolStr;
+use tokio::process::Command;
+use tracing::info;
+
+use crate::fetch::git::GIT_PROGRAM;
+use crate::fetch::{FetchError, PackageCache, commit_staged, copy_dir};
+use crate::lockfile::LockedPatch;
+use crate::package::Package;
+use crate::package_source::PackageSource;
+
+/// A patch file in the unified diff format like the output of `git diff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    pub path: PathBuf,
+    pub content: Vec<u8>,
+}
+
+impl Patch {
+    pub fn load(path: &Path) -> Result<Self, FetchError> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            content: std::fs::read(path).map_err(|err| FetchError::Io(err, path.to_path_buf()))?,
+        })
+    }
+}
+
+/// The patches of a build, the key is the alias of the dependency.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Patches {
+    pub entries: BTreeMap<SmolStr, Vec<Patch>>,
+    /// The patch files as they are declared, relative to the workspace root.
+    pub declared: BTreeMap<SmolStr, Vec<SmolStr>>,
+}
+
+impl Patches {
+    /// Read the patch files of the workspace root `package` at `root`.
+    pub fn load(root: &Path, package: &Package) -> Result<Self, FetchError> {
+        let mut patches = Self::default();
+
+        for (alias, files) in package.patches.iter().flatten() {
+            let loaded = files
+                .iter()
+                .map(|file| Patch::load(&root.join(file.as_str())))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            patches.entries.insert(alias.clone(), loaded);
+            patches.declared.insert(alias.clone(), files.clone());
+        }
+
+        Ok(patches)
+    }
+
+    /// The patches of the dependency `alias`, `None` if it has none.
+    pub fn get(&self, alias: &str) -> Option<&[Patch]> {
+        self.entries
+            .get(alias)
+            .map(Vec::as_slice)
+            .filter(|patches| !patches.is_empty())
+    }
+
+    /// The entries of the lockfile, see [crate::lockfile::LockState::record_local_changes].
+    pub fn to_locked(&self) -> Vec<LockedPatch> {
+        self.entries
+            .iter()
+            .filter(|(_, patches)| !patches.is_empty())
+            .map(|(alias, patches)| LockedPatch {
+                dependency: alias.to_string(),
+                files: self
+                    .declared
+                    .get(alias)
+                    .into_iter()
+                    .flatten()
+                    .map(ToString::to_string)
+                    .collect(),
+                digest: digest(patches),
+            })
+            .collect()
+    }
+}
+
+/// The `blake3:<hex>` of the ordered patches, it changes if any patch or the order changes.
+pub fn digest(patches: &[Patch]) -> String {
+    let mut hasher = blake3::Hasher::new();
+
+    for patch in patches {
+        hasher.update(&(patch.content.len() as u64).to_le_bytes());
+        hasher.update(&patch.content);
+    }
+
+    format!("blake3:{}", hasher.finalize().to_hex())
+}
+
+/// Apply the `patch` to the directory `root` with `git apply`.
+async fn apply(root: &Path, patch: &Patch) -> Result<(), FetchError> {
+    let patch_path = root.with_extension("patch");
+
+    tokio::fs::write(&patch_path, &patch.content)
+        .await
+        .map_err(|err| FetchError::Io(err, patch_path.clone()))?;
+
+    let output = Command::new(GIT_PROGRAM)
+        .arg("apply")
+        .arg("--whitespace=nowarn")
+        .arg(&patch_path)
+        .current_dir(root)
+        // apply to the directory even if the cache is inside a repository
+        .env(
+            "GIT_CEILING_DIRECTORIES",
+            root.parent().unwrap_or(root).as_os_str(),
+        )
+        .kill_on_drop(true)
+        .output()
+        .await;
+
+    let _ = tokio::fs::remove_file(&patch_path).await;
+
+    let output = output.map_err(|err| FetchError::Io(err, PathBuf::from(GIT_PROGRAM)))?;
+
+    if !output.status.success() {
+        return Err(FetchError::Patch {
+            patch: patch.path.clone(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Apply the `patches` in order to a copy of the fetched package at `root`, returns the root of the copy.
+///
+/// The `pinned` source is what the `root` was fetched from, a copy with the same patches is reused.
+pub async fn apply_patches(
+    cache: &PackageCache,
+    root: &Path,
+    pinned: &PackageSource,
+    patches: &[Patch],
+) -> Result<PathBuf, FetchError> {
+    let key = format!(
+        "{}\n{}",
+        serde_json::to_string(pinned).unwrap_or_default(),
+        digest(patches)
+    );
+    let target = cache
+        .get_patched_path()
+        .join(&blake3::hash(key.as_bytes()).to_hex()[..32]);
+
+    if target.exists() {
+        return Ok(target);
+    }
+
+    info!("apply {} patches to {}", patches.len(), root.display());
+
+    let staged = cache.new_staging_path().await?;
+
+    let result = async {
+        let (from, to) = (root.to_path_buf(), staged.clone());
+
+        tokio::task::spawn_blocking(move || copy_dir(&from, &to))
+            .await
+            .map_err(|err| FetchError::Io(std::io::Error::other(err), staged.clone()))??;
+
+        for patch in patches {
+            apply(&staged, patch).await?;
+        }
+
+        Ok(())
+    }
+    .await;
+
+    if let Err(err) = result {
+        let _ = tokio::fs::remove_dir_all(&staged).await;
+        return Err(err);
+    }
+
+    commit_staged(&staged, &target).await?;
+
+    Ok(target)
+}
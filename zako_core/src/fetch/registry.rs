@@ -0,0 +1,65 @@
+//! Fetch the packages of a [Registry], the archives are unpacked like [crate::fetch::archive].
+use std::path::PathBuf;
+
+use tracing::info;
+
+use crate::cas_store::CasStore;
+use crate::fetch::archive::{
+    ArchiveFormat, Integrity, copy_hashed, find_unpacked, unpack_staged, verify_integrity,
+};
+use crate::fetch::{FetchError, PackageCache};
+use crate::registry::{IndexEntry, Registry, package_name, parse_requirement};
+
+/// A version of a registry package unpacked in the [PackageCache].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryPackage {
+    /// Like `com.example:name`.
+    pub package: String,
+    pub entry: IndexEntry,
+    pub path: PathBuf,
+}
+
+/// Fetch the highest version that matches the `package` requirement like `com.example:name@^1`.
+///
+/// A version that is unpacked already needs no archive, the index is always read.
+pub async fn fetch_registry(
+    cache: &PackageCache,
+    cas: &CasStore,
+    registry: &dyn Registry,
+    package: &str,
+) -> Result<RegistryPackage, FetchError> {
+    let req = parse_requirement(package)?;
+    let entry = registry.resolve(&req).await?;
+    let name = package_name(&req.name);
+    let integrity = entry.integrity.parse::<Integrity>()?;
+    let source = format!("{}@{}", name, entry.version);
+
+    if let Some((_, path)) = find_unpacked(cache, Some(&integrity)) {
+        return Ok(RegistryPackage {
+            package: name,
+            entry,
+            path,
+        });
+    }
+
+    info!("download {} from {:?}", source, registry);
+
+    let staged = cache.new_staging_path().await?;
+
+    let result = async {
+        let mut archive = registry.open_archive(&req.name, &entry.version).await?;
+        let hashes = copy_hashed(&mut archive, &staged).await?;
+        let blake3 = verify_integrity(&source, Some(&integrity), hashes)?;
+
+        unpack_staged(cache, cas, &source, &staged, ArchiveFormat::TarZst, &blake3).await
+    }
+    .await;
+
+    let _ = tokio::fs::remove_file(&staged).await;
+
+    Ok(RegistryPackage {
+        package: name,
+        entry,
+        path: result?,
+    })
+}
@@ -1,5 +1,7 @@
 use std::{fmt::Debug, sync::Arc};
 
+use parking_lot::RwLock;
+
 use sysinfo::System;
 use tokio::runtime::{Builder, Runtime};
 use tracing::info;
@@ -7,11 +9,20 @@ use tracing::info;
 use zako_shared::ConcurrentMap;
 
 use crate::{
+    cas::LocalStore,
     cas_store::{CasStore, CasStoreOptions},
+    fetch::{PackageCache, patch::Patches},
     intern::{InternedAbsolutePath, InternedString, Interner},
     local_cas::LocalCas,
+    lockfile::LockState,
+    overrides::Overrides,
     package_id::InternedPackageId,
-    resource::heuristics::{determine_local_cas_path, determine_tokio_thread_stack_size},
+    registry::Registry,
+    resource::heuristics::{
+        determine_local_cas_path, determine_package_cache_path, determine_tokio_thread_stack_size,
+    },
+    tiered_cas::CasTier,
+    vendor::Vendor,
     worker::{
         oxc_worker::OxcTranspilerWorker,
         v8worker::V8Worker,
@@ -46,6 +57,11 @@ pub struct GlobalState {
     tokio_runtime: Runtime,
     system: Arc<System>,
     cas_store: Arc<CasStore>,
+    package_cache: PackageCache,
+    lock_state: LockState,
+    vendor: RwLock<Option<Arc<Vendor>>>,
+    patches: RwLock<Arc<Patches>>,
+    overrides: RwLock<Arc<Overrides>>,
     oxc_workers_pool: Arc<WorkerPool<OxcTranspilerWorker>>,
     v8_workers_pool: Arc<WorkerPool<V8Worker>>,
     common_interneds: CommonInternedStrings,
@@ -57,6 +73,11 @@ impl Debug for GlobalState {
             .field("resource_pool", &self.resource_pool)
             .field("package_id_to_path", &self.package_id_to_path)
             .field("cas_store", &self.cas_store)
+            .field("package_cache", &self.package_cache)
+            .field("lock_state", &self.lock_state)
+            .field("vendor", &self.vendor)
+            .field("patches", &self.patches)
+            .field("overrides", &self.overrides)
             .field("oxc_workers_pool", &self.oxc_workers_pool)
             .field("v8_workers_pool", &self.v8_workers_pool)
             .finish()
@@ -64,11 +85,42 @@ impl Debug for GlobalState {
 }
 
 impl GlobalState {
+    /// Use a [LocalCas] in the user's cache directory, see [determine_local_cas_path].
     #[must_use]
     pub fn new(
         system: System,
         resource_pool: ResourcePool,
         cas_store_options: CasStoreOptions,
+        remote_cas_tiers: Vec<CasTier>,
+        registry: Option<Arc<dyn Registry>>,
+        oxc_workers_config: PoolConfig,
+        v8_workers_config: PoolConfig,
+    ) -> Result<Arc<Self>, GlobalStateError> {
+        let local_cas = Box::new(LocalCas::new(determine_local_cas_path(&system)));
+
+        Self::new_with_local_cas(
+            system,
+            resource_pool,
+            local_cas,
+            cas_store_options,
+            remote_cas_tiers,
+            registry,
+            oxc_workers_config,
+            v8_workers_config,
+        )
+    }
+
+    /// Like [GlobalState::new] but use the given `local_cas`,
+    /// like a [crate::memory_cas::MemoryCas] for tests and ephemeral CI.
+    #[must_use]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_local_cas(
+        system: System,
+        resource_pool: ResourcePool,
+        local_cas: Box<dyn LocalStore>,
+        cas_store_options: CasStoreOptions,
+        remote_cas_tiers: Vec<CasTier>,
+        registry: Option<Arc<dyn Registry>>,
         oxc_workers_config: PoolConfig,
         v8_workers_config: PoolConfig,
     ) -> Result<Arc<Self>, GlobalStateError> {
@@ -81,6 +133,12 @@ impl GlobalState {
                 .get_or_intern(crate::consts::DEFAULT_CONFIGURATION_MOUNT_POINT)?,
         };
 
+        let mut package_cache = PackageCache::new(determine_package_cache_path(&system));
+
+        if let Some(registry) = registry {
+            package_cache = package_cache.with_registry(registry);
+        }
+
         let this = Self {
             interner,
             resource_pool: Arc::new(resource_pool),
@@ -90,10 +148,15 @@ impl GlobalState {
                 .thread_name("zako-tokio-worker")
                 .thread_stack_size(determine_tokio_thread_stack_size(&system))
                 .build()?,
+            package_cache,
+            lock_state: LockState::default(),
+            vendor: RwLock::new(None),
+            patches: RwLock::new(Arc::default()),
+            overrides: RwLock::new(Arc::default()),
             system: system.clone(),
             cas_store: Arc::new(CasStore::new(
-                Box::new(LocalCas::new(determine_local_cas_path(&system))),
-                None,
+                local_cas,
+                remote_cas_tiers,
                 cas_store_options,
             )),
             oxc_workers_pool: Arc::new(WorkerPool::new(oxc_workers_config)),
@@ -101,10 +164,7 @@ impl GlobalState {
             common_interneds,
         };
 
-        info!(
-            "use local cas path {:?}",
-            this.cas_store.get_local_cas().get_root()
-        );
+        info!("use local cas {:?}", this.cas_store.get_local_cas());
 
         let this = Arc::new(this);
 
@@ -144,6 +204,53 @@ impl GlobalState {
         &self.cas_store
     }
 
+    /// Where the git and the archive packages are fetched to.
+    #[must_use]
+    #[inline]
+    pub fn package_cache(&self) -> &PackageCache {
+        &self.package_cache
+    }
+
+    /// The `zako.lock` of the build, it is empty and unlocked until [LockState::reset].
+    #[must_use]
+    #[inline]
+    pub fn lock_state(&self) -> &LockState {
+        &self.lock_state
+    }
+
+    /// The vendor directory that replaces the fetched sources, `None` until [GlobalState::set_vendor].
+    #[must_use]
+    #[inline]
+    pub fn vendor(&self) -> Option<Arc<Vendor>> {
+        self.vendor.read().clone()
+    }
+
+    pub fn set_vendor(&self, vendor: Option<Vendor>) {
+        *self.vendor.write() = vendor.map(Arc::new);
+    }
+
+    /// The patches of the fetched dependencies, empty until [GlobalState::set_patches].
+    #[must_use]
+    #[inline]
+    pub fn patches(&self) -> Arc<Patches> {
+        self.patches.read().clone()
+    }
+
+    pub fn set_patches(&self, patches: Patches) {
+        *self.patches.write() = Arc::new(patches);
+    }
+
+    /// The local directories that replace the dependencies, empty until [GlobalState::set_overrides].
+    #[must_use]
+    #[inline]
+    pub fn overrides(&self) -> Arc<Overrides> {
+        self.overrides.read().clone()
+    }
+
+    pub fn set_overrides(&self, overrides: Overrides) {
+        *self.overrides.write() = Arc::new(overrides);
+    }
+
     #[must_use]
     #[inline]
     pub fn oxc_workers_pool(&self) -> &WorkerPool<OxcTranspilerWorker> {
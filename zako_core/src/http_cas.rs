@@ -0,0 +1,216 @@
+//! A [Cas] backed by a plain HTTP/1.1 cache, like nginx with WebDAV or the HTTP api of bazel-remote.
+//!
+//! A blob lives at `<base url>/cas/<blake3 hex>`:
+//!
+//! - `HEAD` checks the existence and the length.
+//! - `GET` fetches the blob, a [BlobRange] is mapped to the `Range` header.
+//! - `PUT` uploads the blob.
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use reqwest::header::{AUTHORIZATION, CONTENT_LENGTH, RANGE};
+use reqwest::{Client, StatusCode};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use url::Url;
+use zako_digest::Digest;
+
+use crate::blob_range::BlobRange;
+use crate::cas::{Cas, CasError};
+
+/// The path segment under the base url that holds the blobs.
+pub static HTTP_CAS_PATH_PREFIX: &str = "cas/";
+
+#[derive(Debug, Clone)]
+pub struct HttpCasOptions {
+    /// The timeout of connecting, a transfer itself is not limited.
+    pub connect_timeout: Duration,
+    /// Sent as `Authorization: Bearer <token>` if exists.
+    pub bearer_token: Option<String>,
+}
+
+impl Default for HttpCasOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            bearer_token: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HttpCas {
+    client: Client,
+    base: Url,
+    options: HttpCasOptions,
+}
+
+fn http_error(err: impl std::fmt::Display) -> CasError {
+    CasError::Internal(format!("http cas error: {}", err))
+}
+
+impl HttpCas {
+    /// The `base` should be a `http://` or `https://` url.
+    pub fn new(mut base: Url, options: HttpCasOptions) -> Result<Self, CasError> {
+        if base.scheme() != "http" && base.scheme() != "https" {
+            return Err(CasError::Internal(format!(
+                "http cas requires a http or https url, got {}",
+                base
+            )));
+        }
+
+        // make `join` append to the path instead of replacing the last segment
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+
+        let client = Client::builder()
+            .connect_timeout(options.connect_timeout)
+            .build()
+            .map_err(http_error)?;
+
+        Ok(Self {
+            client,
+            base,
+            options,
+        })
+    }
+
+    pub fn get_base_url(&self) -> &Url {
+        &self.base
+    }
+
+    pub fn url_for_digest(&self, digest: &Digest) -> Result<Url, CasError> {
+        self.base
+            .join(&format!("{}{}", HTTP_CAS_PATH_PREFIX, digest.hex_blake3()))
+            .map_err(http_error)
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match self.options.bearer_token.as_ref() {
+            Some(token) => request.header(AUTHORIZATION, format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+}
+
+#[async_trait]
+impl Cas for HttpCas {
+    async fn store(
+        &self,
+        digest: &Digest,
+        data: Box<dyn AsyncRead + Send + Unpin + 'static>,
+    ) -> Result<(), CasError> {
+        let url = self.url_for_digest(digest)?;
+
+        // the length is known, so the body is streamed without chunked encoding
+        let body = reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(data));
+
+        let response = self
+            .authorize(self.client.put(url.clone()))
+            .header(CONTENT_LENGTH, digest.size_bytes)
+            .body(body)
+            .send()
+            .await
+            .map_err(http_error)?;
+
+        if !response.status().is_success() {
+            return Err(http_error(format!(
+                "PUT {} returns {}",
+                url,
+                response.status()
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn check(&self, digest: &Digest) -> Option<u64> {
+        let url = self.url_for_digest(digest).ok()?;
+
+        let response = self.authorize(self.client.head(url)).send().await.ok()?;
+
+        if !response.status().is_success() {
+            return None;
+        }
+
+        Some(
+            response
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|length| length.to_str().ok())
+                .and_then(|length| length.parse().ok())
+                .unwrap_or(digest.size_bytes),
+        )
+    }
+
+    async fn contains(&self, digest: &Digest) -> bool {
+        self.check(digest).await.is_some()
+    }
+
+    async fn fetch(
+        &self,
+        digest: &Digest,
+        range: &BlobRange,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, CasError> {
+        if range.is_out_of_span_length(digest.size_bytes) && digest.size_bytes != 0 {
+            return Err(CasError::RequestedIndexOutOfRange {
+                requested_range: *range,
+                blob_digest: *digest,
+                blob_length: digest.size_bytes,
+            });
+        }
+
+        let url = self.url_for_digest(digest)?;
+        let partial = range.start() != 0 || range.length().is_some();
+
+        let mut request = self.authorize(self.client.get(url.clone()));
+
+        if partial {
+            let header = match range.end() {
+                Some(end) => format!("bytes={}-{}", range.start(), end - 1),
+                None => format!("bytes={}-", range.start()),
+            };
+            request = request.header(RANGE, header);
+        }
+
+        let response = request.send().await.map_err(http_error)?;
+
+        let skip = match response.status() {
+            StatusCode::OK => range.start(),
+            StatusCode::PARTIAL_CONTENT => 0,
+            StatusCode::NOT_FOUND => {
+                return Err(CasError::NotFound(*digest, PathBuf::from(url.as_str())));
+            }
+            StatusCode::RANGE_NOT_SATISFIABLE => {
+                return Err(CasError::RequestedIndexOutOfRange {
+                    requested_range: *range,
+                    blob_digest: *digest,
+                    blob_length: digest.size_bytes,
+                });
+            }
+            status => return Err(http_error(format!("GET {} returns {}", url, status))),
+        };
+
+        let stream = response.bytes_stream().map_err(std::io::Error::other);
+        let mut reader = tokio_util::io::StreamReader::new(stream);
+
+        // the server ignored the range header
+        if skip != 0 {
+            tokio::io::copy(&mut (&mut reader).take(skip), &mut tokio::io::sink())
+                .await
+                .map_err(|err| CasError::Io(err, None))?;
+        }
+
+        Ok(match range.length() {
+            Some(length) => Box::pin(reader.take(length)),
+            None => Box::pin(reader),
+        })
+    }
+
+    async fn get_local_path(&self, _digest: &Digest) -> Option<PathBuf> {
+        None
+    }
+}
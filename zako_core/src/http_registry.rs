@@ -0,0 +1,254 @@
+//! A [Registry] served by any static HTTP server, the files are fetched with `GET` under the base url.
+//!
+//! Publishing uploads the files with `PUT`, like to a WebDAV server or an object storage.
+//! The uploads are conditional, so the server needs to send the `ETag` of the index
+//! and check `If-Match`/`If-None-Match`: an archive is never replaced,
+//! the index is only replaced if it is not changed meanwhile.
+use std::pin::Pin;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use reqwest::header::{AUTHORIZATION, ETAG, HeaderValue, IF_MATCH, IF_NONE_MATCH};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use tokio::io::AsyncRead;
+use url::Url;
+use zako_id::Name;
+
+use crate::registry::{
+    IndexUpdate, PackageIndex, Registry, RegistryError, archive_path, index_path, package_name,
+};
+
+/// How many times [HttpRegistry::update_index] tries when the index is changed meanwhile.
+pub static INDEX_UPDATE_ATTEMPTS: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct HttpRegistryOptions {
+    /// The timeout of connecting, a transfer itself is not limited.
+    pub connect_timeout: Duration,
+    /// Sent as `Authorization: Bearer <token>` if exists.
+    pub bearer_token: Option<String>,
+}
+
+impl Default for HttpRegistryOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            bearer_token: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HttpRegistry {
+    client: Client,
+    base: Url,
+    options: HttpRegistryOptions,
+}
+
+impl HttpRegistry {
+    /// The `base` should be a `http://` or `https://` url.
+    pub fn new(mut base: Url, options: HttpRegistryOptions) -> Result<Self, RegistryError> {
+        if base.scheme() != "http" && base.scheme() != "https" {
+            return Err(RegistryError::Http {
+                url: base.to_string(),
+                message: "http registry requires a http or https url".into(),
+            });
+        }
+
+        // keep the last segment of the base when joining
+        if !base.path().ends_with('/') {
+            base.set_path(&format!("{}/", base.path()));
+        }
+
+        let client = Client::builder()
+            .connect_timeout(options.connect_timeout)
+            // the archives are hashed as served
+            .no_gzip()
+            .no_brotli()
+            .no_deflate()
+            .build()
+            .map_err(|err| RegistryError::Http {
+                url: base.to_string(),
+                message: err.to_string(),
+            })?;
+
+        Ok(Self {
+            client,
+            base,
+            options,
+        })
+    }
+
+    pub fn get_base(&self) -> &Url {
+        &self.base
+    }
+
+    fn url(&self, path: &str) -> Result<Url, RegistryError> {
+        self.base.join(path).map_err(|err| RegistryError::Http {
+            url: format!("{}{}", self.base, path),
+            message: err.to_string(),
+        })
+    }
+
+    async fn send(
+        &self,
+        mut request: RequestBuilder,
+        url: &Url,
+    ) -> Result<Response, RegistryError> {
+        if let Some(token) = self.options.bearer_token.as_ref() {
+            request = request.header(AUTHORIZATION, format!("Bearer {}", token));
+        }
+
+        request.send().await.map_err(|err| RegistryError::Http {
+            url: url.to_string(),
+            message: err.to_string(),
+        })
+    }
+
+    async fn get(&self, url: &Url) -> Result<Response, RegistryError> {
+        self.send(self.client.get(url.clone()), url).await
+    }
+
+    /// The index and its `ETag`, `None` if the registry does not have it.
+    async fn get_index(
+        &self,
+        url: &Url,
+    ) -> Result<Option<(PackageIndex, HeaderValue)>, RegistryError> {
+        let response = self.get(url).await?;
+
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => return Ok(None),
+            status => return Err(unexpected_status(url, status)),
+        }
+
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .cloned()
+            .ok_or_else(|| RegistryError::Http {
+                url: url.to_string(),
+                message: "the server does not send the `ETag` of the index".into(),
+            })?;
+
+        let content = response.bytes().await.map_err(|err| RegistryError::Http {
+            url: url.to_string(),
+            message: err.to_string(),
+        })?;
+
+        let index = serde_json::from_slice(&content)
+            .map_err(|err| RegistryError::InvalidIndex(url.to_string(), err))?;
+
+        Ok(Some((index, etag)))
+    }
+}
+
+fn unexpected_status(url: &Url, status: StatusCode) -> RegistryError {
+    RegistryError::Http {
+        url: url.to_string(),
+        message: format!("the server responds {}", status),
+    }
+}
+
+#[async_trait]
+impl Registry for HttpRegistry {
+    async fn index(&self, name: &Name) -> Result<Option<PackageIndex>, RegistryError> {
+        let url = self.url(&index_path(name))?;
+        let response = self.get(&url).await?;
+
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => return Ok(None),
+            status => return Err(unexpected_status(&url, status)),
+        }
+
+        let content = response.bytes().await.map_err(|err| RegistryError::Http {
+            url: url.to_string(),
+            message: err.to_string(),
+        })?;
+
+        serde_json::from_slice(&content)
+            .map(Some)
+            .map_err(|err| RegistryError::InvalidIndex(url.to_string(), err))
+    }
+
+    async fn open_archive(
+        &self,
+        name: &Name,
+        version: &semver::Version,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, RegistryError> {
+        let url = self.url(&archive_path(name, version))?;
+        let response = self.get(&url).await?;
+
+        match response.status() {
+            StatusCode::OK => {}
+            StatusCode::NOT_FOUND => {
+                return Err(RegistryError::ArchiveNotFound(
+                    package_name(name),
+                    version.clone(),
+                ));
+            }
+            status => return Err(unexpected_status(&url, status)),
+        }
+
+        let stream = response.bytes_stream().map_err(std::io::Error::other);
+
+        Ok(Box::pin(tokio_util::io::StreamReader::new(stream)))
+    }
+
+    async fn put_archive(
+        &self,
+        name: &Name,
+        version: &semver::Version,
+        archive: Vec<u8>,
+    ) -> Result<(), RegistryError> {
+        let url = self.url(&archive_path(name, version))?;
+        let request = self
+            .client
+            .put(url.clone())
+            .header(IF_NONE_MATCH, "*")
+            .body(archive);
+
+        // an archive is never replaced
+        match self.send(request, &url).await?.status() {
+            StatusCode::PRECONDITION_FAILED => Err(RegistryError::VersionExists(
+                package_name(name),
+                version.clone(),
+            )),
+            status if status.is_success() => Ok(()),
+            status => Err(unexpected_status(&url, status)),
+        }
+    }
+
+    async fn update_index(
+        &self,
+        name: &Name,
+        update: &IndexUpdate<'_>,
+    ) -> Result<(), RegistryError> {
+        let url = self.url(&index_path(name))?;
+
+        for _ in 0..INDEX_UPDATE_ATTEMPTS {
+            let (current, etag) = self.get_index(&url).await?.unzip();
+
+            let index = update(current)?;
+            let content = serde_json::to_vec_pretty(&index)
+                .map_err(|err| RegistryError::InvalidIndex(url.to_string(), err))?;
+
+            // only replace the index that is read above
+            let request = match etag {
+                Some(etag) => self.client.put(url.clone()).header(IF_MATCH, etag),
+                None => self.client.put(url.clone()).header(IF_NONE_MATCH, "*"),
+            };
+            let response = self.send(request.body(content), &url).await?;
+
+            match response.status() {
+                StatusCode::PRECONDITION_FAILED => continue,
+                status if status.is_success() => return Ok(()),
+                status => return Err(unexpected_status(&url, status)),
+            }
+        }
+
+        Err(RegistryError::IndexChanged(package_name(name)))
+    }
+}
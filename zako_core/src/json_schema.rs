@@ -0,0 +1,297 @@
+//! A validator for the subset of JSON Schema that `zako_json/project_schema.json` uses.
+//!
+//! The keywords `type`, `enum`, `const`, `pattern`, `properties`, `required`,
+//! `additionalProperties`, `items`, `allOf`, `anyOf`, `oneOf` and the local `$ref` like
+//! `#/$defs/name` are checked, the others like `description` are ignored.
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+/// A value that does not match the schema.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("at `{}`: {message}", display_path(path))]
+pub struct SchemaViolation {
+    /// The JSON pointer of the value like `/dependencies/foo/repo`, it is empty for the root.
+    pub path: String,
+    pub message: String,
+    /// Whether the name of the property at the path is wrong instead of its value.
+    pub is_key: bool,
+}
+
+fn display_path(path: &str) -> &str {
+    if path.is_empty() { "/" } else { path }
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonSchema {
+    root: Value,
+    /// The compiled `pattern`s of the schema by their source.
+    patterns: HashMap<String, Result<Regex, regex::Error>>,
+}
+
+struct Validation<'s> {
+    root: &'s Value,
+    patterns: &'s HashMap<String, Result<Regex, regex::Error>>,
+    path: Vec<String>,
+    violations: Vec<SchemaViolation>,
+}
+
+impl JsonSchema {
+    pub fn new(root: Value) -> Self {
+        let mut patterns = HashMap::new();
+        collect_patterns(&root, &mut patterns);
+
+        Self { root, patterns }
+    }
+
+    pub fn parse(content: &str) -> Result<Self, serde_json::Error> {
+        Ok(Self::new(serde_json::from_str(content)?))
+    }
+
+    /// All the violations of the `instance`, it is valid if nothing is returned.
+    pub fn validate(&self, instance: &Value) -> Vec<SchemaViolation> {
+        let mut validation = Validation {
+            root: &self.root,
+            patterns: &self.patterns,
+            path: Vec::new(),
+            violations: Vec::new(),
+        };
+
+        validation.check(&self.root, instance);
+
+        validation.violations
+    }
+}
+
+/// Compile every `pattern` once, so validating does not compile them again.
+fn collect_patterns(schema: &Value, patterns: &mut HashMap<String, Result<Regex, regex::Error>>) {
+    match schema {
+        Value::Object(object) => {
+            if let Some(Value::String(pattern)) = object.get("pattern") {
+                patterns
+                    .entry(pattern.clone())
+                    .or_insert_with(|| Regex::new(pattern));
+            }
+
+            object
+                .values()
+                .for_each(|value| collect_patterns(value, patterns));
+        }
+        Value::Array(array) => array
+            .iter()
+            .for_each(|value| collect_patterns(value, patterns)),
+        _ => {}
+    }
+}
+
+fn type_matches(name: &str, instance: &Value) -> bool {
+    match name {
+        "null" => instance.is_null(),
+        "boolean" => instance.is_boolean(),
+        "integer" => instance.is_i64() || instance.is_u64(),
+        "number" => instance.is_number(),
+        "string" => instance.is_string(),
+        "array" => instance.is_array(),
+        "object" => instance.is_object(),
+        _ => false,
+    }
+}
+
+fn type_name(instance: &Value) -> &'static str {
+    match instance {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Escape a property name as a JSON pointer token.
+pub fn escape_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+impl<'s> Validation<'s> {
+    fn report(&mut self, message: String) {
+        self.report_at(message, false);
+    }
+
+    fn report_at(&mut self, message: String, is_key: bool) {
+        let path = self
+            .path
+            .iter()
+            .map(|token| format!("/{}", token))
+            .collect::<String>();
+
+        self.violations.push(SchemaViolation {
+            path,
+            message,
+            is_key,
+        });
+    }
+
+    fn check(&mut self, schema: &'s Value, instance: &Value) {
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => {
+                self.report("no value is allowed here".to_string());
+                return;
+            }
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        if let Some(Value::String(reference)) = schema.get("$ref") {
+            match reference
+                .strip_prefix('#')
+                .and_then(|pointer| self.root.pointer(pointer))
+            {
+                Some(target) => self.check(target, instance),
+                None => self.report(format!("the schema `{}` is not found", reference)),
+            }
+        }
+
+        if let Some(expected) = schema.get("type") {
+            let names: Vec<&str> = match expected {
+                Value::String(name) => vec![name.as_str()],
+                Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+
+            if !names.is_empty() && !names.iter().any(|name| type_matches(name, instance)) {
+                self.report(format!(
+                    "expected {}, found {}",
+                    names.join(" or "),
+                    type_name(instance)
+                ));
+                return;
+            }
+        }
+
+        if let Some(Value::Array(allowed)) = schema.get("enum")
+            && !allowed.contains(instance)
+        {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            self.report(format!(
+                "expected one of {}, found {}",
+                allowed.join(", "),
+                instance
+            ));
+        }
+
+        if let Some(expected) = schema.get("const")
+            && expected != instance
+        {
+            self.report(format!("expected {}, found {}", expected, instance));
+        }
+
+        if let (Some(Value::String(pattern)), Value::String(value)) =
+            (schema.get("pattern"), instance)
+        {
+            match self.patterns.get(pattern) {
+                Some(Ok(regex)) if regex.is_match(value) => {}
+                Some(Ok(_)) => self.report(format!("`{}` does not match `{}`", value, pattern)),
+                Some(Err(err)) => {
+                    self.report(format!("the pattern `{}` is invalid: {}", pattern, err))
+                }
+                None => self.report(format!("the pattern `{}` is not compiled", pattern)),
+            }
+        }
+
+        if let Value::Object(object) = instance {
+            self.check_object(schema, object);
+        }
+
+        if let (Some(items), Value::Array(array)) = (schema.get("items"), instance) {
+            for (index, item) in array.iter().enumerate() {
+                self.path.push(index.to_string());
+                self.check(items, item);
+                self.path.pop();
+            }
+        }
+
+        if let Some(Value::Array(schemas)) = schema.get("allOf") {
+            for schema in schemas {
+                self.check(schema, instance);
+            }
+        }
+
+        if let Some(Value::Array(schemas)) = schema.get("anyOf") {
+            self.check_alternatives(schemas, instance, false);
+        }
+
+        if let Some(Value::Array(schemas)) = schema.get("oneOf") {
+            self.check_alternatives(schemas, instance, true);
+        }
+    }
+
+    fn check_object(&mut self, schema: &'s Map<String, Value>, object: &Map<String, Value>) {
+        let properties = schema.get("properties").and_then(Value::as_object);
+
+        if let Some(Value::Array(required)) = schema.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    self.report(format!("the property `{}` is required", name));
+                }
+            }
+        }
+
+        for (name, value) in object {
+            self.path.push(escape_token(name));
+
+            match properties.and_then(|properties| properties.get(name)) {
+                Some(property) => self.check(property, value),
+                None => match schema.get("additionalProperties") {
+                    Some(Value::Bool(false)) => {
+                        self.report_at(format!("the property `{}` is not allowed", name), true)
+                    }
+                    Some(additional) => self.check(additional, value),
+                    None => {}
+                },
+            }
+
+            self.path.pop();
+        }
+    }
+
+    /// Check `anyOf` or `oneOf`.
+    ///
+    /// If no schema matches, the violations of the closest one are reported,
+    /// it is likely the one the author meant.
+    fn check_alternatives(&mut self, schemas: &'s [Value], instance: &Value, exactly_one: bool) {
+        let mut closest: Option<Vec<SchemaViolation>> = None;
+        let mut matched = 0;
+
+        for schema in schemas {
+            let mut validation = Validation {
+                root: self.root,
+                patterns: self.patterns,
+                path: self.path.clone(),
+                violations: Vec::new(),
+            };
+            validation.check(schema, instance);
+
+            if validation.violations.is_empty() {
+                matched += 1;
+            } else if closest
+                .as_ref()
+                .is_none_or(|closest| validation.violations.len() < closest.len())
+            {
+                closest = Some(validation.violations);
+            }
+        }
+
+        if matched == 0 {
+            self.violations.extend(closest.unwrap_or_default());
+        } else if exactly_one && matched > 1 {
+            self.report(format!(
+                "the value matches {} schemas but only one is allowed",
+                matched
+            ));
+        }
+    }
+}
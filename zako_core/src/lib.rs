@@ -33,14 +33,21 @@ use crate::{
     node::{node_key::ZakoKey, node_value::ZakoValue},
 };
 pub mod access_control;
+pub mod action_cache;
+pub mod action_cache_server;
 pub mod author;
 pub mod blob_handle;
 pub mod blob_range;
 pub mod build_constants;
 pub mod builtin;
 pub mod cas;
+pub mod cas_gc;
+pub mod cas_lease;
 pub mod cas_server;
 pub mod cas_store;
+pub mod cas_token;
+pub mod cas_upload;
+pub mod cas_verify;
 pub mod compute;
 pub mod computer;
 pub mod config;
@@ -48,31 +55,49 @@ pub mod config_value;
 pub mod configured_project;
 pub mod consts;
 pub mod context;
+pub mod diagnostic;
 pub mod engine;
 pub mod error;
 pub mod extension;
+pub mod fetch;
 pub mod file_finder;
 pub mod fs;
 pub mod global_state;
+pub mod http_cas;
+pub mod http_registry;
 pub mod id;
 pub mod intern;
+pub mod json_schema;
 pub mod link;
+pub mod local_action_cache;
 pub mod local_cas;
+pub mod local_registry;
+pub mod lockfile;
 mod make_builtin;
+pub mod manifest;
+pub mod memory_cas;
 pub mod module_loader;
 pub mod node;
+pub mod overrides;
 pub mod package;
+pub mod package_archive;
+pub mod package_graph;
 pub mod package_id;
 pub mod package_source;
 pub mod path;
 pub mod pattern;
 pub mod persistent;
+pub mod reapi_cas;
+pub mod registry;
+pub mod remote_action_cache;
 pub mod resource;
 pub mod sandbox;
+pub mod single_flight;
 pub mod socket_address;
 pub mod target;
 #[cfg(test)]
 pub mod tests;
+pub mod tiered_cas;
 pub mod tool;
 pub mod transformer;
 pub mod transport_server;
@@ -81,8 +106,10 @@ pub mod v8error;
 pub mod v8platform;
 pub mod v8snapshot;
 pub mod v8utils;
+pub mod vendor;
 pub mod version_extractor;
 pub mod worker;
+pub mod workspace;
 
 pub type HoneEngine = hone::engine::Engine<BuildContext, ZakoKey, ZakoValue>;
 pub type HoneComputer = crate::computer::Computer;
@@ -107,6 +134,35 @@ pub mod protobuf {
     pub mod range {
         tonic::include_proto!("zako.v1.range");
     }
+
+    pub mod action_cache {
+        tonic::include_proto!("zako.v1.action_cache");
+    }
+
+    /// The subset of the Bazel Remote Execution API, see [crate::reapi_cas].
+    pub mod reapi {
+        pub mod build {
+            pub mod bazel {
+                pub mod remote {
+                    pub mod execution {
+                        pub mod v2 {
+                            tonic::include_proto!("build.bazel.remote.execution.v2");
+                        }
+                    }
+                }
+            }
+        }
+
+        pub mod google {
+            pub mod rpc {
+                tonic::include_proto!("google.rpc");
+            }
+
+            pub mod bytestream {
+                tonic::include_proto!("google.bytestream");
+            }
+        }
+    }
 }
 
 pub use camino;
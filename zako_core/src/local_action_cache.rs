@@ -0,0 +1,117 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use hone::redb::{self, ReadableDatabase, TableDefinition};
+use prost::Message;
+use zako_digest::Digest;
+
+use crate::action_cache::{ActionCache, ActionCacheError, ActionResult};
+use crate::protobuf::action_cache as proto;
+
+/// The key is the blake3 hash of the action digest, the value is an encoded [proto::ActionResult].
+static ACTION_RESULT_TABLE: TableDefinition<&[u8], &[u8]> =
+    TableDefinition::new("zako_action_results");
+
+/// An [ActionCache] stored in a local redb database.
+#[derive(Debug)]
+pub struct LocalActionCache {
+    path: PathBuf,
+    database: Arc<redb::Database>,
+}
+
+impl LocalActionCache {
+    pub fn open(path: &Path) -> Result<Self, ActionCacheError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let database = redb::Database::create(path).map_err(storage)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            database: Arc::new(database),
+        })
+    }
+
+    pub fn get_path(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+fn get_sync(
+    database: &redb::Database,
+    action_digest: &Digest,
+) -> Result<Option<ActionResult>, ActionCacheError> {
+    let txn = database.begin_read().map_err(storage)?;
+
+    let table = match txn.open_table(ACTION_RESULT_TABLE) {
+        Ok(table) => table,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+        Err(err) => return Err(storage(err)),
+    };
+
+    let Some(value) = table
+        .get(action_digest.blake3.as_bytes().as_slice())
+        .map_err(storage)?
+    else {
+        return Ok(None);
+    };
+
+    let result = proto::ActionResult::decode(value.value())?;
+
+    Ok(Some(result.try_into()?))
+}
+
+fn put_sync(
+    database: &redb::Database,
+    action_digest: &Digest,
+    result: ActionResult,
+) -> Result<(), ActionCacheError> {
+    let encoded = proto::ActionResult::from(result).encode_to_vec();
+
+    let txn = database.begin_write().map_err(storage)?;
+    {
+        let mut table = txn.open_table(ACTION_RESULT_TABLE).map_err(storage)?;
+        table
+            .insert(
+                action_digest.blake3.as_bytes().as_slice(),
+                encoded.as_slice(),
+            )
+            .map_err(storage)?;
+    }
+    txn.commit().map_err(storage)?;
+
+    Ok(())
+}
+
+fn storage(err: impl std::fmt::Display) -> ActionCacheError {
+    ActionCacheError::Storage(err.to_string())
+}
+
+#[async_trait]
+impl ActionCache for LocalActionCache {
+    async fn get(&self, action_digest: &Digest) -> Result<Option<ActionResult>, ActionCacheError> {
+        let database = self.database.clone();
+        let action_digest = *action_digest;
+
+        // redb blocks on the file system
+        tokio::task::spawn_blocking(move || get_sync(&database, &action_digest))
+            .await
+            .map_err(storage)?
+    }
+
+    async fn put(
+        &self,
+        action_digest: &Digest,
+        result: &ActionResult,
+    ) -> Result<(), ActionCacheError> {
+        let database = self.database.clone();
+        let action_digest = *action_digest;
+        let result = result.clone();
+
+        tokio::task::spawn_blocking(move || put_sync(&database, &action_digest, result))
+            .await
+            .map_err(storage)?
+    }
+}
@@ -1,15 +1,52 @@
 use crate::blob_range::BlobRange;
-use crate::cas::{Cas, CasError};
+use crate::cas::{Cas, CasError, LocalStore, StoredBlob};
 use async_trait::async_trait;
 use camino::Utf8Path;
 use eyre::Context;
 use memmap2::MmapOptions;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
-use std::time::SystemTime;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use zako_digest::Digest;
 
+/// The size of the buffer used by [LocalCas::store_reader].
+pub static STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// See [LocalCas::get_quarantine_path].
+pub static QUARANTINE_DIR_NAME: &str = "quarantine";
+
+/// See [LocalCas::get_lease_path].
+pub static LEASE_DIR_NAME: &str = "leases";
+
+/// See [LocalCas::get_staging_path].
+pub static STAGING_DIR_NAME: &str = "staging";
+
+/// A blob that is read is touched again only if it is not touched for this long, see [touch_stale].
+pub static TOUCH_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Refresh the modification time of an existing blob, the garbage collection keeps the recently used blobs.
+async fn touch(path: &Path) {
+    let path = path.to_path_buf();
+
+    let _ = tokio::task::spawn_blocking(move || {
+        std::fs::File::open(path).and_then(|file| file.set_modified(SystemTime::now()))
+    })
+    .await;
+}
+
+/// [touch] the blob that is read unless it is touched within [TOUCH_INTERVAL].
+async fn touch_stale(path: &Path, metadata: &std::fs::Metadata) {
+    let touched = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+
+    if touched
+        .elapsed()
+        .is_ok_and(|elapsed| elapsed > TOUCH_INTERVAL)
+    {
+        touch(path).await;
+    }
+}
+
 #[derive(Debug)]
 pub struct LocalCas {
     root: PathBuf,
@@ -24,6 +61,37 @@ impl LocalCas {
         &self.root
     }
 
+    /// The directory that holds the corrupted blobs moved out by [crate::cas_verify].
+    pub fn get_quarantine_path(&self) -> PathBuf {
+        self.root.join(QUARANTINE_DIR_NAME)
+    }
+
+    /// The directory that holds the lease files of [crate::cas_lease].
+    pub fn get_lease_path(&self) -> PathBuf {
+        self.root.join(LEASE_DIR_NAME)
+    }
+
+    /// The directory that holds the blobs being written, a blob is renamed into place once complete.
+    ///
+    /// It is on the same file system as the blobs, so the rename is atomic.
+    pub fn get_staging_path(&self) -> PathBuf {
+        self.root.join(STAGING_DIR_NAME)
+    }
+
+    /// Create the staging directory and return a new path in it.
+    async fn new_staging_file(&self) -> Result<PathBuf, CasError> {
+        let staging = self.get_staging_path();
+
+        tokio::fs::create_dir_all(&staging)
+            .await
+            .map_err(|err| CasError::Io(err, Some(staging.clone())))?;
+
+        Ok(staging.join(format!("tmp_{}", uuid::Uuid::new_v4())))
+    }
+
+    /// The canonical layout of a blob is `<root>/<first two hex chars>/<the rest hex chars>`.
+    ///
+    /// Every read and write of the blob should use this path.
     pub fn get_path_for_digest(&self, digest: &Digest) -> PathBuf {
         let hex = digest.get_hash().to_hex();
         self.root.join(&hex[0..2]).join(&hex[2..])
@@ -82,8 +150,7 @@ impl LocalCas {
         let target_path = self.get_path_for_digest(&digest);
 
         if target_path.exists() {
-            let file = std::fs::File::open(source_path)?;
-            file.set_modified(SystemTime::now())?;
+            touch(&target_path).await;
             return Ok(digest);
         }
 
@@ -91,19 +158,118 @@ impl LocalCas {
             std::fs::create_dir_all(parent)?;
         }
 
+        // link into the staging directory first, a concurrent reader never sees a half-cloned blob
+        let staged_path = self
+            .new_staging_file()
+            .await
+            .map_err(|err| std::io::Error::other(err.to_string()))?;
+
         crate::link::ref_or_hard_link_file(
             source_path,
-            &Utf8Path::from_path(target_path.as_path()).ok_or(std::io::Error::new(
+            Utf8Path::from_path(staged_path.as_path()).ok_or(std::io::Error::new(
                 std::io::ErrorKind::InvalidFilename,
                 format!(
-                    "target path {:?} is invalid: contains non-utf8 characters",
-                    &source_path
+                    "staging path {:?} is invalid: contains non-utf8 characters",
+                    staged_path
                 ),
             ))?,
         )?;
 
+        // another ingestion of the same content may win the race, the rename replaces it with the same content
+        if let Err(err) = std::fs::rename(&staged_path, &target_path) {
+            let _ = std::fs::remove_file(&staged_path);
+            return Err(err);
+        }
+
         Ok(digest)
     }
+
+    /// Store the data whose digest is unknown yet.
+    ///
+    /// The data is hashed while being written to a file in the staging directory, then the file is renamed into place.
+    /// The content is kept in memory only if it is not bigger than `inline_limit`.
+    pub async fn store_reader(
+        &self,
+        mut data: impl AsyncRead + Unpin,
+        inline_limit: usize,
+    ) -> Result<StoredBlob, CasError> {
+        let temp_path = self.new_staging_file().await?;
+
+        let result = self
+            .write_and_hash(&mut data, &temp_path, inline_limit)
+            .await;
+
+        let (digest, inline) = match result {
+            Ok(result) => result,
+            Err(err) => {
+                let _ = tokio::fs::remove_file(&temp_path).await;
+                return Err(err);
+            }
+        };
+
+        let target_path = self.get_path_for_digest(&digest);
+
+        if target_path.exists() {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            touch(&target_path).await;
+            return Ok(StoredBlob { digest, inline });
+        }
+
+        if let Some(parent) = target_path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|err| CasError::Io(err, Some(parent.to_path_buf())))?;
+        }
+
+        if let Err(err) = tokio::fs::rename(&temp_path, &target_path).await {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(CasError::Io(err, Some(target_path)));
+        }
+
+        Ok(StoredBlob { digest, inline })
+    }
+
+    async fn write_and_hash(
+        &self,
+        data: &mut (impl AsyncRead + Unpin),
+        temp_path: &Path,
+        inline_limit: usize,
+    ) -> Result<(Digest, Option<Vec<u8>>), CasError> {
+        let io_error = |err| CasError::Io(err, Some(temp_path.to_path_buf()));
+
+        let mut file = tokio::fs::File::create(temp_path).await.map_err(io_error)?;
+
+        let mut hasher = blake3::Hasher::new();
+        let mut buffer = vec![0u8; STREAM_BUFFER_SIZE];
+        let mut inline = Some(Vec::new());
+        let mut size: u64 = 0;
+
+        loop {
+            let read = data.read(&mut buffer).await.map_err(io_error)?;
+
+            if read == 0 {
+                break;
+            }
+
+            let chunk = &buffer[..read];
+
+            hasher.update(chunk);
+            file.write_all(chunk).await.map_err(io_error)?;
+            size += read as u64;
+
+            if let Some(content) = inline.as_mut() {
+                if content.len() + read <= inline_limit {
+                    content.extend_from_slice(chunk);
+                } else {
+                    inline = None;
+                }
+            }
+        }
+
+        file.sync_all().await.map_err(io_error)?;
+
+        Ok((Digest::new(size, hasher.finalize().into()), inline))
+    }
 }
 
 #[async_trait]
@@ -116,6 +282,7 @@ impl Cas for LocalCas {
         let target_path = self.get_path_for_digest(digest);
 
         if target_path.exists() {
+            touch(&target_path).await;
             return Ok(());
         }
 
@@ -125,33 +292,31 @@ impl Cas for LocalCas {
                 .map_err(|err| CasError::Io(err.into(), Some(parent.to_path_buf())))?;
         }
 
-        let temp_name = format!("tmp_{}", uuid::Uuid::new_v4());
-        let temp_path = target_path.join(temp_name);
-
-        let mut file = tokio::fs::File::create(&temp_path)
-            .await
-            .map_err(|err| CasError::Io(err.into(), Some(temp_path.clone())))?;
+        let temp_path = self.new_staging_file().await?;
 
-        tokio::io::copy(&mut data, &mut file)
-            .await
-            .map_err(|err| CasError::Io(err, Some(temp_path.clone())))?;
+        let written = async {
+            let mut file = tokio::fs::File::create(&temp_path).await?;
+            tokio::io::copy(&mut data, &mut file).await?;
+            file.sync_all().await
+        }
+        .await;
 
-        file.sync_all()
-            .await
-            .map_err(|err| CasError::Io(err.into(), Some(temp_path.clone())))?;
+        if let Err(err) = written {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(CasError::Io(err, Some(temp_path)));
+        }
 
         // rename应该是原子的。
-        tokio::fs::rename(&temp_path, &target_path)
-            .await
-            .map_err(|err| CasError::Io(err.into(), Some(target_path.clone())))?;
+        if let Err(err) = tokio::fs::rename(&temp_path, &target_path).await {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(CasError::Io(err, Some(target_path)));
+        }
 
         Ok(())
     }
 
     async fn check(&self, digest: &Digest) -> Option<u64> {
-        let hex = digest.blake3.to_hex();
-
-        let path = self.root.join(&hex[0..2]).join(&hex[2..4]).join(&hex[4..]);
+        let path = self.get_path_for_digest(digest);
 
         tokio::fs::metadata(path).await.ok().map(|meta| meta.len())
     }
@@ -177,11 +342,13 @@ impl Cas for LocalCas {
             }
         })?;
 
-        let file_size = file
+        let metadata = file
             .metadata()
             .await
-            .map_err(|e| CasError::Io(e, Some(path.clone())))?
-            .len();
+            .map_err(|e| CasError::Io(e, Some(path.clone())))?;
+        let file_size = metadata.len();
+
+        touch_stale(&path, &metadata).await;
 
         if range.is_out_of_span_length(file_size) {
             return Err(CasError::RequestedIndexOutOfRange {
@@ -207,10 +374,25 @@ impl Cas for LocalCas {
     async fn get_local_path(&self, digest: &Digest) -> Option<PathBuf> {
         let path = self.get_path_for_digest(digest);
 
-        if self.contains(digest).await {
-            Some(path)
-        } else {
-            None
-        }
+        // the caller reads the blob by the path
+        let metadata = tokio::fs::metadata(&path).await.ok()?;
+        touch_stale(&path, &metadata).await;
+
+        Some(path)
+    }
+}
+
+#[async_trait]
+impl LocalStore for LocalCas {
+    async fn store_reader(
+        &self,
+        data: &mut (dyn AsyncRead + Send + Unpin),
+        inline_limit: usize,
+    ) -> Result<StoredBlob, CasError> {
+        LocalCas::store_reader(self, data, inline_limit).await
+    }
+
+    async fn input_file(&self, path: &Utf8Path, is_symlink: bool) -> std::io::Result<Digest> {
+        LocalCas::input_file(self, path, is_symlink).await
     }
 }
@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use tokio::io::AsyncRead;
+use zako_id::Name;
+
+use crate::registry::{
+    IndexUpdate, PackageIndex, Registry, RegistryError, archive_path, index_path,
+};
+
+/// How long [LocalRegistry::update_index] waits for the other publishes of the package.
+pub static INDEX_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A [Registry] in a directory, like a shared network drive.
+#[derive(Debug, Clone)]
+pub struct LocalRegistry {
+    root: PathBuf,
+}
+
+impl LocalRegistry {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn get_root(&self) -> &PathBuf {
+        &self.root
+    }
+
+    pub fn get_index_path(&self, name: &Name) -> PathBuf {
+        self.root.join(index_path(name))
+    }
+
+    pub fn get_archive_path(&self, name: &Name, version: &semver::Version) -> PathBuf {
+        self.root.join(archive_path(name, version))
+    }
+}
+
+/// The lock file of an index, removed when dropped.
+struct IndexLock {
+    path: PathBuf,
+}
+
+impl IndexLock {
+    /// Create the lock file next to the index, wait while another publish holds it.
+    async fn acquire(index: &Path) -> Result<Self, RegistryError> {
+        let path = index.with_extension("json.lock");
+        let io_error = |err| RegistryError::Io(err, path.clone());
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await.map_err(io_error)?;
+        }
+
+        let started = Instant::now();
+
+        loop {
+            match tokio::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .await
+            {
+                Ok(_) => return Ok(Self { path }),
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if started.elapsed() > INDEX_LOCK_TIMEOUT {
+                        return Err(RegistryError::IndexLocked(path));
+                    }
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+                Err(err) => return Err(io_error(err)),
+            }
+        }
+    }
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Write the file aside and move it into place, the readers never see a partial file.
+///
+/// If `create_new` is set, an existing file is kept and [std::io::ErrorKind::AlreadyExists] is returned.
+async fn write_atomically(
+    path: &Path,
+    content: &[u8],
+    create_new: bool,
+) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let staged = path.with_extension(format!("tmp_{}", uuid::Uuid::new_v4()));
+
+    if let Err(err) = tokio::fs::write(&staged, content).await {
+        let _ = tokio::fs::remove_file(&staged).await;
+        return Err(err);
+    }
+
+    // a hard link never replaces the target, a rename does
+    let moved = if create_new {
+        tokio::fs::hard_link(&staged, path).await
+    } else {
+        tokio::fs::rename(&staged, path).await
+    };
+
+    let _ = tokio::fs::remove_file(&staged).await;
+
+    moved
+}
+
+#[async_trait]
+impl Registry for LocalRegistry {
+    async fn index(&self, name: &Name) -> Result<Option<PackageIndex>, RegistryError> {
+        let path = self.get_index_path(name);
+
+        let content = match tokio::fs::read(&path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(RegistryError::Io(err, path)),
+        };
+
+        serde_json::from_slice(&content)
+            .map(Some)
+            .map_err(|err| RegistryError::InvalidIndex(path.display().to_string(), err))
+    }
+
+    async fn open_archive(
+        &self,
+        name: &Name,
+        version: &semver::Version,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, RegistryError> {
+        let path = self.get_archive_path(name, version);
+
+        match tokio::fs::File::open(&path).await {
+            Ok(file) => Ok(Box::pin(file)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                Err(RegistryError::ArchiveNotFound(
+                    crate::registry::package_name(name),
+                    version.clone(),
+                ))
+            }
+            Err(err) => Err(RegistryError::Io(err, path)),
+        }
+    }
+
+    async fn put_archive(
+        &self,
+        name: &Name,
+        version: &semver::Version,
+        archive: Vec<u8>,
+    ) -> Result<(), RegistryError> {
+        let path = self.get_archive_path(name, version);
+
+        match write_atomically(&path, &archive, true).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => Err(
+                RegistryError::VersionExists(crate::registry::package_name(name), version.clone()),
+            ),
+            Err(err) => Err(RegistryError::Io(err, path)),
+        }
+    }
+
+    async fn update_index(
+        &self,
+        name: &Name,
+        update: &IndexUpdate<'_>,
+    ) -> Result<(), RegistryError> {
+        let path = self.get_index_path(name);
+        let _lock = IndexLock::acquire(&path).await?;
+
+        let index = update(self.index(name).await?)?;
+        let content = serde_json::to_vec_pretty(&index)
+            .map_err(|err| RegistryError::InvalidIndex(path.display().to_string(), err))?;
+
+        write_atomically(&path, &content, false)
+            .await
+            .map_err(|err| RegistryError::Io(err, path))
+    }
+}
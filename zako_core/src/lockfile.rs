@@ -0,0 +1,370 @@
+//! The `zako.lock` next to the manifest, it pins every fetched dependency so builds are reproducible.
+//!
+//! The file is written deterministically: the entries are sorted and no timestamp is recorded,
+//! so it can be checked in and diffed.
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::cas_store::CasStore;
+use crate::fetch::{FetchError, PackageCache};
+use crate::manifest;
+use crate::package::{Package, PackageResolveError};
+use crate::package_source::PackageSource;
+
+/// The version of the lockfile format that is written.
+pub static LOCKFILE_VERSION: u32 = 1;
+
+/// The comment at the top of the written lockfile.
+pub static LOCKFILE_HEADER: &str = "# This file is generated by zako, do not edit it by hand.\n";
+
+#[derive(Debug, thiserror::Error)]
+pub enum LockfileError {
+    #[error("io error at {1:?}: {0}")]
+    Io(#[source] std::io::Error, PathBuf),
+    #[error("the lockfile {0:?} is invalid: {1}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+    #[error("failed to serialize the lockfile: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    #[error(
+        "the lockfile version {0} is not supported, the supported version is {LOCKFILE_VERSION}"
+    )]
+    UnsupportedVersion(u32),
+    #[error("the lockfile needs to be updated for {0:?} but `--locked` is set")]
+    Outdated(PackageSource),
+    #[error(
+        "the patches or the overrides in the lockfile need to be updated but `--locked` is set"
+    )]
+    OutdatedLocalChanges,
+    #[error(transparent)]
+    Fetch(#[from] FetchError),
+    #[error("the package {0:?} has no manifest")]
+    NoManifest(PathBuf),
+    #[error("failed to load the manifest of the package {0:?}: {1}")]
+    Manifest(PathBuf, #[source] Box<PackageResolveError>),
+}
+
+/// A dependency and what it resolved to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    /// The source in the manifest, like a git branch or a version requirement.
+    pub requested: PackageSource,
+    /// The source that pins the content, see [crate::fetch::FetchedPackage::pinned].
+    pub pinned: PackageSource,
+}
+
+/// The patches that are applied to a dependency, see [crate::fetch::patch].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct LockedPatch {
+    /// The alias of the dependency.
+    pub dependency: String,
+    /// The patch files in the order they are applied, relative to the workspace root.
+    pub files: Vec<String>,
+    /// The digest of the patches, see [crate::fetch::patch::digest].
+    pub digest: String,
+}
+
+/// A dependency that is replaced by a local directory, see [crate::overrides].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct LockedOverride {
+    /// The alias of the dependency.
+    pub dependency: String,
+    /// The directory as it is declared.
+    pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub version: u32,
+    #[serde(rename = "package", default)]
+    pub packages: Vec<LockedPackage>,
+    #[serde(rename = "patch", default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<LockedPatch>,
+    #[serde(rename = "override", default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<LockedOverride>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            packages: Vec::new(),
+            patches: Vec::new(),
+            overrides: Vec::new(),
+        }
+    }
+}
+
+fn sort_key(source: &PackageSource) -> String {
+    serde_json::to_string(source).unwrap_or_default()
+}
+
+impl Lockfile {
+    pub fn parse(path: &Path, content: &str) -> Result<Self, LockfileError> {
+        let lockfile: Self =
+            toml::from_str(content).map_err(|err| LockfileError::Parse(path.to_path_buf(), err))?;
+
+        if lockfile.version != LOCKFILE_VERSION {
+            return Err(LockfileError::UnsupportedVersion(lockfile.version));
+        }
+
+        Ok(lockfile)
+    }
+
+    /// Read the lockfile, an empty one if it does not exist.
+    pub fn load(path: &Path) -> Result<Self, LockfileError> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Self::parse(path, &content),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(LockfileError::Io(err, path.to_path_buf())),
+        }
+    }
+
+    /// The content to write, the same packages always give the same content.
+    pub fn to_toml(&self) -> Result<String, LockfileError> {
+        let mut sorted = self.clone();
+        sorted
+            .packages
+            .sort_by_cached_key(|package| sort_key(&package.requested));
+        sorted.patches.sort();
+        sorted.overrides.sort();
+
+        Ok(format!("{}{}", LOCKFILE_HEADER, toml::to_string(&sorted)?))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), LockfileError> {
+        std::fs::write(path, self.to_toml()?)
+            .map_err(|err| LockfileError::Io(err, path.to_path_buf()))
+    }
+
+    pub fn get(&self, requested: &PackageSource) -> Option<&PackageSource> {
+        self.packages
+            .iter()
+            .find(|package| &package.requested == requested)
+            .map(|package| &package.pinned)
+    }
+
+    /// Pin the `requested` source, returns whether the lockfile is changed.
+    pub fn insert(&mut self, requested: PackageSource, pinned: PackageSource) -> bool {
+        match self
+            .packages
+            .iter_mut()
+            .find(|package| package.requested == requested)
+        {
+            Some(package) if package.pinned == pinned => false,
+            Some(package) => {
+                package.pinned = pinned;
+                true
+            }
+            None => {
+                self.packages.push(LockedPackage { requested, pinned });
+                true
+            }
+        }
+    }
+
+    /// Returns the removed pin if exists.
+    pub fn remove(&mut self, requested: &PackageSource) -> Option<PackageSource> {
+        let index = self
+            .packages
+            .iter()
+            .position(|package| &package.requested == requested)?;
+
+        Some(self.packages.remove(index).pinned)
+    }
+}
+
+/// The [Lockfile] of a build, shared by the computations of [crate::global_state::GlobalState].
+#[derive(Debug, Default)]
+pub struct LockState {
+    lockfile: Mutex<Lockfile>,
+    /// Fail instead of changing the lockfile.
+    locked: AtomicBool,
+    changed: AtomicBool,
+}
+
+impl LockState {
+    pub fn new(lockfile: Lockfile, locked: bool) -> Self {
+        Self {
+            lockfile: Mutex::new(lockfile),
+            locked: AtomicBool::new(locked),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    /// Start a build with the `lockfile`.
+    pub fn reset(&self, lockfile: Lockfile, locked: bool) {
+        *self.lockfile.lock() = lockfile;
+        self.locked.store(locked, Ordering::SeqCst);
+        self.changed.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::SeqCst)
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.changed.load(Ordering::SeqCst)
+    }
+
+    /// The pinned source of the `requested` one.
+    ///
+    /// If it is not pinned and the state is locked, [LockfileError::Outdated] is returned.
+    pub fn pinned(
+        &self,
+        requested: &PackageSource,
+    ) -> Result<Option<PackageSource>, LockfileError> {
+        match self.lockfile.lock().get(requested) {
+            Some(pinned) => Ok(Some(pinned.clone())),
+            None if self.is_locked() => Err(LockfileError::Outdated(requested.clone())),
+            None => Ok(None),
+        }
+    }
+
+    pub fn record(
+        &self,
+        requested: PackageSource,
+        pinned: PackageSource,
+    ) -> Result<(), LockfileError> {
+        let mut lockfile = self.lockfile.lock();
+
+        if lockfile.get(&requested) == Some(&pinned) {
+            return Ok(());
+        }
+
+        if self.is_locked() {
+            return Err(LockfileError::Outdated(requested));
+        }
+
+        lockfile.insert(requested, pinned);
+        self.changed.store(true, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Record the patches and the overrides of the build, they replace the recorded ones.
+    pub fn record_local_changes(
+        &self,
+        mut patches: Vec<LockedPatch>,
+        mut overrides: Vec<LockedOverride>,
+    ) -> Result<(), LockfileError> {
+        let mut lockfile = self.lockfile.lock();
+
+        patches.sort();
+        overrides.sort();
+
+        let mut recorded_patches = lockfile.patches.clone();
+        let mut recorded_overrides = lockfile.overrides.clone();
+        recorded_patches.sort();
+        recorded_overrides.sort();
+
+        if recorded_patches == patches && recorded_overrides == overrides {
+            return Ok(());
+        }
+
+        if self.is_locked() {
+            return Err(LockfileError::OutdatedLocalChanges);
+        }
+
+        lockfile.patches = patches;
+        lockfile.overrides = overrides;
+        self.changed.store(true, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> Lockfile {
+        self.lockfile.lock().clone()
+    }
+}
+
+fn load_manifest(root: &Path) -> Result<Package, LockfileError> {
+    let utf8_root = camino::Utf8Path::from_path(root)
+        .ok_or_else(|| LockfileError::NoManifest(root.to_path_buf()))?;
+    let (path, _) =
+        manifest::find(utf8_root).ok_or_else(|| LockfileError::NoManifest(root.to_path_buf()))?;
+
+    Package::load(path.as_std_path())
+        .map_err(|err| LockfileError::Manifest(root.to_path_buf(), Box::new(err)))
+}
+
+/// Walk the dependency graph from the `dependencies`, each with the root of the package
+/// that declares it, and pin every fetched package of it.
+///
+/// The `refreshed` sources and the dependencies they bring in are fetched again ignoring
+/// their pins, like a git branch that moved, the others keep their pins. The path sources are
+/// not pinned, they are relative to the package that declares them. The pins that the graph
+/// does not reach anymore are removed.
+///
+/// Returns whether the lockfile is changed.
+pub async fn update(
+    lockfile: &mut Lockfile,
+    cache: &PackageCache,
+    cas: &CasStore,
+    dependencies: impl IntoIterator<Item = (PathBuf, PackageSource)>,
+    refreshed: &[PackageSource],
+) -> Result<bool, LockfileError> {
+    let dependencies: Vec<(PathBuf, PackageSource)> = dependencies.into_iter().collect();
+    let mut seen_sources = BTreeSet::new();
+    let mut seen_roots = BTreeSet::new();
+    let mut changed = false;
+
+    // the refreshed part of the graph is walked first, so a shared dependency is refreshed too
+    for refresh in [true, false] {
+        let mut queue: Vec<(PathBuf, PackageSource)> = dependencies
+            .iter()
+            .filter(|(_, source)| !refresh || refreshed.contains(source))
+            .cloned()
+            .collect();
+
+        while let Some((parent, source)) = queue.pop() {
+            let root = match &source {
+                PackageSource::Path { path } => parent.join(path),
+                _ => {
+                    if !seen_sources.insert(sort_key(&source)) {
+                        continue;
+                    }
+
+                    let pinned = if refresh {
+                        None
+                    } else {
+                        lockfile.get(&source).cloned()
+                    };
+                    let fetched = cache.fetch(pinned.as_ref().unwrap_or(&source), cas).await?;
+                    changed |= lockfile.insert(source, fetched.pinned);
+
+                    fetched.root
+                }
+            };
+
+            if !seen_roots.insert(root.clone()) {
+                continue;
+            }
+
+            let package = load_manifest(&root)?;
+            queue.extend(
+                package
+                    .dependencies
+                    .into_iter()
+                    .flatten()
+                    .map(|(_, dependency)| (root.clone(), dependency)),
+            );
+        }
+    }
+
+    let unreferenced: Vec<PackageSource> = lockfile
+        .packages
+        .iter()
+        .map(|package| package.requested.clone())
+        .filter(|requested| !seen_sources.contains(&sort_key(requested)))
+        .collect();
+
+    for requested in unreferenced {
+        changed |= lockfile.remove(&requested).is_some();
+    }
+
+    Ok(changed)
+}
@@ -0,0 +1,471 @@
+//! The manifest of a package, [PACKAGE_MANIFEST_FILE_NAME] or [PACKAGE_JSON_MANIFEST_FILE_NAME].
+//!
+//! Both formats describe the same [Package], they are checked against [PROJECT_SCHEMA]
+//! before they are deserialized so a mistake is reported with the JSON pointer of the value.
+//!
+//! [ManifestSource] keeps the content of the file, its errors are [Diagnostic]s that show
+//! the line of the mistake.
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use oxc_allocator::Allocator;
+use oxc_ast::ast::{Expression, ObjectPropertyKind, PropertyKey};
+use oxc_parser::Parser;
+use oxc_span::{GetSpan, SourceType, Span};
+use serde_json::Value;
+use toml::de::{DeTable, DeValue};
+
+use crate::config_value::{ConfigDefault, ConfigType};
+use crate::consts::{PACKAGE_JSON_MANIFEST_FILE_NAME, PACKAGE_MANIFEST_FILE_NAME};
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::id::Label;
+use crate::intern::Interner;
+use crate::json_schema::{JsonSchema, SchemaViolation, escape_token};
+use crate::package::{Package, PackageResolveError};
+
+/// The JSON schema of the manifest, see `zako_json/project_schema.json`.
+pub static PROJECT_SCHEMA: &str = include_str!("../../zako_json/project_schema.json");
+
+/// [PROJECT_SCHEMA] parsed once with its patterns compiled.
+static PARSED_PROJECT_SCHEMA: LazyLock<Result<JsonSchema, String>> =
+    LazyLock::new(|| JsonSchema::parse(PROJECT_SCHEMA).map_err(|err| err.to_string()));
+
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error("invalid toml: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("failed to serialize the manifest as toml: {0}")]
+    SerializeToml(#[from] toml::ser::Error),
+    #[error("the manifest does not match the schema:{}", display_violations(.0))]
+    Schema(Vec<SchemaViolation>),
+    #[error("the bundled manifest schema is invalid: {0}")]
+    InvalidSchema(String),
+}
+
+fn display_violations(violations: &[SchemaViolation]) -> String {
+    violations
+        .iter()
+        .map(|violation| format!("\n  {}", violation))
+        .collect()
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, rkyv::Deserialize, rkyv::Serialize, rkyv::Archive,
+)]
+pub enum ManifestFormat {
+    Toml,
+    Json,
+}
+
+impl ManifestFormat {
+    /// All formats, in the order they are looked up in a package root.
+    pub const ALL: [ManifestFormat; 2] = [ManifestFormat::Toml, ManifestFormat::Json];
+
+    /// The format of a file by its extension, like `zako.json`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+
+    /// The manifest file name of the format in a package root.
+    pub fn get_file_name(self) -> &'static str {
+        match self {
+            Self::Toml => PACKAGE_MANIFEST_FILE_NAME,
+            Self::Json => PACKAGE_JSON_MANIFEST_FILE_NAME,
+        }
+    }
+
+    pub fn is_manifest_file_name(name: &str) -> bool {
+        Self::ALL
+            .iter()
+            .any(|format| format.get_file_name() == name)
+    }
+}
+
+/// The manifest of the package at `root`, [PACKAGE_MANIFEST_FILE_NAME] wins if both exist.
+pub fn find(root: &Utf8Path) -> Option<(Utf8PathBuf, ManifestFormat)> {
+    ManifestFormat::ALL
+        .into_iter()
+        .map(|format| (root.join(format.get_file_name()), format))
+        .find(|(path, _)| path.is_file())
+}
+
+/// Check the manifest against [PROJECT_SCHEMA].
+pub fn validate_schema(manifest: &Value) -> Result<(), ManifestError> {
+    let schema = PARSED_PROJECT_SCHEMA
+        .as_ref()
+        .map_err(|err| ManifestError::InvalidSchema(err.clone()))?;
+    let violations = schema.validate(manifest);
+
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ManifestError::Schema(violations))
+    }
+}
+
+/// Parse the manifest, it is checked against the schema but not [Package::validate]d.
+pub fn parse(content: &[u8], format: ManifestFormat) -> Result<Package, ManifestError> {
+    match format {
+        ManifestFormat::Toml => {
+            validate_schema(&toml::from_slice::<Value>(content)?)?;
+            Ok(toml::from_slice(content)?)
+        }
+        ManifestFormat::Json => {
+            validate_schema(&serde_json::from_slice::<Value>(content)?)?;
+            Ok(serde_json::from_slice(content)?)
+        }
+    }
+}
+
+/// Remove the `null`s of the absent optional fields, the schema does not allow them.
+fn remove_nulls(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.retain(|_, value| !value.is_null());
+            object.values_mut().for_each(remove_nulls);
+        }
+        Value::Array(array) => array.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+pub fn to_string(package: &Package, format: ManifestFormat) -> Result<String, ManifestError> {
+    match format {
+        ManifestFormat::Toml => Ok(toml::to_string_pretty(package)?),
+        ManifestFormat::Json => {
+            let mut value = serde_json::to_value(package)?;
+            remove_nulls(&mut value);
+
+            Ok(format!("{}\n", serde_json::to_string_pretty(&value)?))
+        }
+    }
+}
+
+/// Convert a manifest between the formats.
+pub fn convert(
+    content: &[u8],
+    from: ManifestFormat,
+    to: ManifestFormat,
+) -> Result<String, ManifestError> {
+    to_string(&parse(content, from)?, to)
+}
+
+/// Where an error points in the manifest, by the JSON pointer of a property like `/config/debug`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestPointer {
+    /// The name of the property.
+    Key(String),
+    /// The value of the property.
+    Value(String),
+}
+
+impl ManifestPointer {
+    fn tokens(&self) -> Vec<String> {
+        let (Self::Key(pointer) | Self::Value(pointer)) = self;
+
+        pointer
+            .split('/')
+            .skip(1)
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect()
+    }
+}
+
+fn pointer(tokens: &[&str]) -> String {
+    tokens
+        .iter()
+        .map(|token| format!("/{}", escape_token(token)))
+        .collect()
+}
+
+/// The byte spans of a property in the manifest, the root has no key.
+struct PropertySpan {
+    key: Option<Range<usize>>,
+    value: Range<usize>,
+}
+
+/// A manifest file with its content, so its errors can show where they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestSource {
+    path: PathBuf,
+    content: String,
+    format: ManifestFormat,
+}
+
+impl ManifestSource {
+    pub fn new(path: impl Into<PathBuf>, content: String, format: ManifestFormat) -> Self {
+        Self {
+            path: path.into(),
+            content,
+            format,
+        }
+    }
+
+    /// Read the manifest file, the format is decided by its extension.
+    pub fn load(path: &Path) -> Result<Self, PackageResolveError> {
+        let format = ManifestFormat::from_path(path)
+            .ok_or_else(|| PackageResolveError::UnknownManifestFormat(path.to_path_buf()))?;
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| PackageResolveError::ManifestIoError(err, path.to_path_buf()))?;
+
+        Ok(Self::new(path, content, format))
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_content(&self) -> &str {
+        &self.content
+    }
+
+    pub fn get_format(&self) -> ManifestFormat {
+        self.format
+    }
+
+    /// The byte span that the `pointer` points to.
+    ///
+    /// A [ManifestPointer::Key] falls back to the value if the key is not written,
+    /// like the root or an item of an array.
+    pub fn locate(&self, pointer: &ManifestPointer) -> Option<Range<usize>> {
+        let tokens = pointer.tokens();
+        let span = match self.format {
+            ManifestFormat::Toml => locate_toml(&self.content, &tokens),
+            ManifestFormat::Json => locate_json(&self.content, &tokens),
+        }?;
+
+        match pointer {
+            ManifestPointer::Key(_) => Some(span.key.unwrap_or(span.value)),
+            ManifestPointer::Value(_) => Some(span.value),
+        }
+    }
+
+    /// A diagnostic that points to the `pointer`, or to the whole file if it is not found.
+    pub fn diagnose(&self, message: impl Into<String>, pointer: &ManifestPointer) -> Diagnostic {
+        let diagnostic = Diagnostic::new(message, self.path.clone());
+
+        match self.locate(pointer) {
+            Some(span) => diagnostic.with_span(self.content.clone(), span),
+            None => diagnostic,
+        }
+    }
+
+    fn toml_diagnostic(&self, err: &toml::de::Error) -> Diagnostic {
+        let diagnostic = Diagnostic::new(err.message().trim(), self.path.clone());
+
+        match err.span() {
+            Some(span) => diagnostic.with_span(self.content.clone(), span),
+            None => diagnostic,
+        }
+    }
+
+    fn json_diagnostic(&self, err: &serde_json::Error) -> Diagnostic {
+        let message = err.to_string();
+        let suffix = format!(" at line {} column {}", err.line(), err.column());
+        let message = message.strip_suffix(&suffix).unwrap_or(&message);
+
+        Diagnostic::new(message, self.path.clone()).with_line_column(
+            self.content.clone(),
+            err.line(),
+            err.column(),
+        )
+    }
+
+    /// Parse the manifest and check it against [PROJECT_SCHEMA], it is not validated.
+    pub fn parse(&self) -> Result<Package, Diagnostics> {
+        let value = match self.format {
+            ManifestFormat::Toml => {
+                toml::from_str::<Value>(&self.content).map_err(|err| self.toml_diagnostic(&err))?
+            }
+            ManifestFormat::Json => serde_json::from_str::<Value>(&self.content)
+                .map_err(|err| self.json_diagnostic(&err))?,
+        };
+
+        if let Err(err) = validate_schema(&value) {
+            return Err(match err {
+                ManifestError::Schema(violations) => Diagnostics(
+                    violations
+                        .into_iter()
+                        .map(|violation| {
+                            let pointer = if violation.is_key {
+                                ManifestPointer::Key(violation.path)
+                            } else {
+                                ManifestPointer::Value(violation.path)
+                            };
+                            self.diagnose(violation.message, &pointer)
+                        })
+                        .collect(),
+                ),
+                err => Diagnostic::new(err.to_string(), self.path.clone()).into(),
+            });
+        }
+
+        Ok(match self.format {
+            ManifestFormat::Toml => {
+                toml::from_str(&self.content).map_err(|err| self.toml_diagnostic(&err))?
+            }
+            ManifestFormat::Json => {
+                serde_json::from_str(&self.content).map_err(|err| self.json_diagnostic(&err))?
+            }
+        })
+    }
+
+    /// [Package::validate] the `package` parsed from this manifest.
+    pub fn validate(&self, package: &Package) -> Result<(), Diagnostic> {
+        let Err(err) = package.validate() else {
+            return Ok(());
+        };
+
+        let pointer = match &err {
+            PackageResolveError::InvalidConfigKey(key) => {
+                Some(ManifestPointer::Key(pointer(&["config", key])))
+            }
+            PackageResolveError::InvalidDependencyKey(key) => {
+                Some(ManifestPointer::Key(pointer(&["dependencies", key])))
+            }
+            PackageResolveError::InvalidDependencySource(alias, _) => {
+                Some(ManifestPointer::Value(pointer(&["dependencies", alias])))
+            }
+            PackageResolveError::InvalidPatch(alias, _) => {
+                Some(ManifestPointer::Value(pointer(&["patches", alias])))
+            }
+            PackageResolveError::InvalidOverrideKey(key) => {
+                Some(ManifestPointer::Key(pointer(&["overrides", key])))
+            }
+            PackageResolveError::InvalidWorkspacePattern(pattern) => package
+                .workspaces
+                .iter()
+                .flatten()
+                .position(|workspace| workspace == pattern)
+                .map(|index| ManifestPointer::Value(pointer(&["workspaces", &index.to_string()]))),
+            _ => None,
+        };
+
+        let message = err.to_string();
+
+        Err(match pointer {
+            Some(pointer) => self.diagnose(message, &pointer),
+            None => Diagnostic::new(message, self.path.clone()),
+        })
+    }
+
+    /// Check the defaults of the `label` configs, the config keys are checked by [Package::validate].
+    pub fn validate_labels(
+        &self,
+        package: &Package,
+        interner: &Interner,
+    ) -> Result<(), Diagnostic> {
+        for (key, value) in package.config.iter().flatten() {
+            if let (ConfigType::Label, ConfigDefault::String(default)) =
+                (&value.r#type, &value.default)
+                && let Err(err) = Label::try_parse(default, interner)
+            {
+                return Err(self.diagnose(
+                    format!(
+                        "the default of the config `{}` is not a valid label: {}",
+                        key, err
+                    ),
+                    &ManifestPointer::Value(pointer(&["config", key, "default"])),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn locate_toml(content: &str, tokens: &[String]) -> Option<PropertySpan> {
+    let root = DeTable::parse(content).ok()?;
+    let span = root.span();
+    let root = toml::Spanned::new(span, DeValue::Table(root.into_inner()));
+
+    locate_toml_value(&root, tokens, None)
+}
+
+fn locate_toml_value(
+    value: &toml::Spanned<DeValue<'_>>,
+    tokens: &[String],
+    key: Option<Range<usize>>,
+) -> Option<PropertySpan> {
+    let Some((token, rest)) = tokens.split_first() else {
+        return Some(PropertySpan {
+            key,
+            value: value.span(),
+        });
+    };
+
+    match value.get_ref() {
+        DeValue::Table(table) => {
+            let (key, value) = table
+                .iter()
+                .find(|(key, _)| key.get_ref().as_ref() == token)?;
+
+            locate_toml_value(value, rest, Some(key.span()))
+        }
+        DeValue::Array(array) => {
+            locate_toml_value(array.get(token.parse::<usize>().ok()?)?, rest, None)
+        }
+        _ => None,
+    }
+}
+
+/// JSON is a JavaScript expression, so the [oxc_parser] keeps the spans that [serde_json] does not.
+fn locate_json(content: &str, tokens: &[String]) -> Option<PropertySpan> {
+    let allocator = Allocator::default();
+    let root = Parser::new(&allocator, content, SourceType::mjs())
+        .parse_expression()
+        .ok()?;
+
+    locate_json_value(&root, tokens, None)
+}
+
+fn span_range(span: Span) -> Range<usize> {
+    span.start as usize..span.end as usize
+}
+
+fn locate_json_value(
+    value: &Expression<'_>,
+    tokens: &[String],
+    key: Option<Range<usize>>,
+) -> Option<PropertySpan> {
+    let Some((token, rest)) = tokens.split_first() else {
+        return Some(PropertySpan {
+            key,
+            value: span_range(value.span()),
+        });
+    };
+
+    match value.without_parentheses() {
+        Expression::ObjectExpression(object) => {
+            // the last one wins like serde_json
+            let property = object.properties.iter().rev().find_map(|property| {
+                let ObjectPropertyKind::ObjectProperty(property) = property else {
+                    return None;
+                };
+
+                match &property.key {
+                    PropertyKey::StringLiteral(name) if name.value.as_str() == token => {
+                        Some(property)
+                    }
+                    _ => None,
+                }
+            })?;
+
+            locate_json_value(&property.value, rest, Some(span_range(property.key.span())))
+        }
+        Expression::ArrayExpression(array) => {
+            let element = array.elements.get(token.parse::<usize>().ok()?)?;
+
+            locate_json_value(element.as_expression()?, rest, None)
+        }
+        _ => None,
+    }
+}
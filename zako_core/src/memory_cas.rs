@@ -0,0 +1,244 @@
+//! A [Cas] that keeps everything in memory, for tests and short-lived CI containers.
+//!
+//! Nothing is written to the disk, the blobs are gone with the process.
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use camino::Utf8Path;
+use parking_lot::Mutex;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use zako_digest::Digest;
+use zako_digest::blake3::Hash;
+
+use crate::blob_range::BlobRange;
+use crate::cas::{Cas, CasError, LocalStore, StoredBlob};
+
+#[derive(Debug)]
+struct MemoryEntry {
+    data: Bytes,
+    /// The tick of the last use, the key of [MemoryCasInner::recency].
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct MemoryCasInner {
+    blobs: HashMap<Hash, MemoryEntry>,
+    /// From the tick of the last use to the blob, the least recently used one comes first.
+    recency: BTreeMap<u64, Hash>,
+    tick: u64,
+    used_bytes: u64,
+}
+
+impl MemoryCasInner {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn get(&mut self, hash: &Hash) -> Option<Bytes> {
+        let tick = self.next_tick();
+        let entry = self.blobs.get_mut(hash)?;
+
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(tick, *hash);
+        entry.last_used = tick;
+
+        Some(entry.data.clone())
+    }
+
+    fn insert(&mut self, hash: Hash, data: Bytes, limit: Option<u64>) {
+        if self.get(&hash).is_some() {
+            return;
+        }
+
+        let size = data.len() as u64;
+
+        if let Some(limit) = limit {
+            while self.used_bytes + size > limit {
+                let Some((_, evicted)) = self.recency.pop_first() else {
+                    break;
+                };
+
+                if let Some(entry) = self.blobs.remove(&evicted) {
+                    self.used_bytes -= entry.data.len() as u64;
+                }
+            }
+        }
+
+        let tick = self.next_tick();
+        self.recency.insert(tick, hash);
+        self.blobs.insert(
+            hash,
+            MemoryEntry {
+                data,
+                last_used: tick,
+            },
+        );
+        self.used_bytes += size;
+    }
+}
+
+/// An in-memory [Cas], the least recently used blobs are evicted once the byte limit is exceeded.
+#[derive(Debug, Default)]
+pub struct MemoryCas {
+    limit: Option<u64>,
+    inner: Mutex<MemoryCasInner>,
+}
+
+impl MemoryCas {
+    /// `limit` is the max total bytes of the blobs, `None` means unlimited.
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            limit,
+            inner: Mutex::new(MemoryCasInner::default()),
+        }
+    }
+
+    pub fn get_limit(&self) -> Option<u64> {
+        self.limit
+    }
+
+    /// The total bytes of the blobs that are kept.
+    pub fn get_used_bytes(&self) -> u64 {
+        self.inner.lock().used_bytes
+    }
+
+    /// The count of the blobs that are kept.
+    pub fn len(&self) -> usize {
+        self.inner.lock().blobs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Keep the bytes, the digest is trusted.
+    pub fn insert(&self, digest: &Digest, data: Bytes) -> Result<(), CasError> {
+        if let Some(limit) = self.limit
+            && data.len() as u64 > limit
+        {
+            return Err(CasError::Internal(format!(
+                "blob {} of {} bytes exceeds the memory cas limit of {} bytes",
+                digest.hex_blake3(),
+                data.len(),
+                limit
+            )));
+        }
+
+        self.inner.lock().insert(digest.blake3, data, self.limit);
+
+        Ok(())
+    }
+
+    fn insert_hashed(&self, data: Vec<u8>, inline_limit: usize) -> Result<StoredBlob, CasError> {
+        let digest = Digest::new(data.len() as u64, blake3::hash(&data).into());
+        let inline = (data.len() <= inline_limit).then(|| data.clone());
+
+        self.insert(&digest, Bytes::from(data))?;
+
+        Ok(StoredBlob { digest, inline })
+    }
+}
+
+#[async_trait]
+impl Cas for MemoryCas {
+    async fn store(
+        &self,
+        digest: &Digest,
+        mut data: Box<dyn AsyncRead + Send + Unpin + 'static>,
+    ) -> Result<(), CasError> {
+        let mut bytes = Vec::with_capacity(digest.size_bytes.try_into().unwrap_or(0));
+
+        data.read_to_end(&mut bytes)
+            .await
+            .map_err(|err| CasError::Io(err, None))?;
+
+        self.insert(digest, Bytes::from(bytes))
+    }
+
+    async fn check(&self, digest: &Digest) -> Option<u64> {
+        self.inner
+            .lock()
+            .blobs
+            .get(&digest.blake3)
+            .map(|entry| entry.data.len() as u64)
+    }
+
+    async fn contains(&self, digest: &Digest) -> bool {
+        self.inner.lock().blobs.contains_key(&digest.blake3)
+    }
+
+    async fn fetch(
+        &self,
+        digest: &Digest,
+        range: &BlobRange,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, CasError> {
+        let data = self
+            .inner
+            .lock()
+            .get(&digest.blake3)
+            .ok_or_else(|| CasError::NotFound(*digest, PathBuf::from("<memory cas>")))?;
+
+        let length = data.len() as u64;
+
+        if range.is_out_of_span_length(length) {
+            return Err(CasError::RequestedIndexOutOfRange {
+                requested_range: *range,
+                blob_digest: *digest,
+                blob_length: length,
+            });
+        }
+
+        let end = range.end().unwrap_or(length);
+
+        Ok(Box::pin(std::io::Cursor::new(
+            data.slice(range.start() as usize..end as usize),
+        )))
+    }
+
+    async fn get_local_path(&self, _digest: &Digest) -> Option<PathBuf> {
+        None
+    }
+}
+
+#[async_trait]
+impl LocalStore for MemoryCas {
+    async fn store_reader(
+        &self,
+        data: &mut (dyn AsyncRead + Send + Unpin),
+        inline_limit: usize,
+    ) -> Result<StoredBlob, CasError> {
+        let mut bytes = Vec::new();
+
+        data.read_to_end(&mut bytes)
+            .await
+            .map_err(|err| CasError::Io(err, None))?;
+
+        self.insert_hashed(bytes, inline_limit)
+    }
+
+    async fn input_file(&self, path: &Utf8Path, is_symlink: bool) -> std::io::Result<Digest> {
+        let data = if is_symlink {
+            tokio::fs::read_link(path)
+                .await?
+                .to_str()
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "link is not a valid utf-8 string",
+                    )
+                })?
+                .as_bytes()
+                .to_vec()
+        } else {
+            tokio::fs::read(path).await?
+        };
+
+        self.insert_hashed(data, 0)
+            .map(|stored| stored.digest)
+            .map_err(std::io::Error::other)
+    }
+}
@@ -1,8 +1,14 @@
-use crate::{blob_handle::BlobHandle, package::Package};
+use crate::{
+    blob_handle::BlobHandle, intern::InternedAbsolutePath, manifest::ManifestFormat,
+    package::Package,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, rkyv::Deserialize, rkyv::Serialize, rkyv::Archive)]
 pub struct ParseManifest {
     pub blob_handle: BlobHandle,
+    pub format: ManifestFormat,
+    /// The manifest file, the errors point to it.
+    pub path: InternedAbsolutePath,
 }
 
 #[derive(Debug, Clone, rkyv::Deserialize, rkyv::Serialize, rkyv::Archive)]
@@ -1,16 +1,31 @@
+use std::collections::BTreeMap;
+
+use smol_str::SmolStr;
+
 use crate::{
-    configured_project::ConfiguredPackage, intern::InternedAbsolutePath,
-    package_id::InternedPackageId, package_source::PackageSource,
+    configured_project::ConfiguredPackage,
+    intern::InternedAbsolutePath,
+    package_graph::{GraphPackage, PackageGraph},
+    package_id::InternedPackageId,
+    package_source::PackageSource,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, rkyv::Deserialize, rkyv::Serialize, rkyv::Archive)]
 pub struct ResolvePackage {
     pub package: InternedPackageId,
     pub source: PackageSource,
+    /// The root of a dependency, the root package of the build has none.
     pub root: Option<InternedAbsolutePath>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, rkyv::Deserialize, rkyv::Serialize, rkyv::Archive)]
 pub struct ResolvePackageResult {
     pub package: ConfiguredPackage,
+    /// The direct dependencies of the package, the versions are not unified.
+    pub edges: GraphPackage,
+    /// The nodes of the direct dependencies, the key is the name in the manifest.
+    pub dependencies: BTreeMap<SmolStr, ResolvePackage>,
+    /// The unified graph of every package that the root package of the build depends on,
+    /// only the root has it.
+    pub graph: Option<PackageGraph>,
 }
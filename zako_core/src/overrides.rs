@@ -0,0 +1,139 @@
+//! The local directories that replace the dependencies, like a checkout that is being debugged.
+//!
+//! The overrides are declared by the `overrides` of the workspace root manifest or by the user
+//! config at [crate::resource::heuristics::determine_user_overrides_path], the user config wins.
+//! An overridden dependency is resolved like a path dependency and is never fetched.
+//!
+//! Only the overrides of the manifest are recorded in the lockfile, the user config is not shared.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+use smol_str::SmolStr;
+
+use crate::lockfile::LockedOverride;
+use crate::package::Package;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OverridesError {
+    #[error("io error at {1:?}: {0}")]
+    Io(#[source] std::io::Error, PathBuf),
+    #[error("the overrides config {0:?} is invalid: {1}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+    #[error("the overrides key `{0}` in {1:?} is not a valid xid_loose_ident")]
+    InvalidKey(String, PathBuf),
+    #[error("the override path {0:?} is not valid utf-8")]
+    NonUtf8Path(PathBuf),
+}
+
+/// A local directory that replaces a dependency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyOverride {
+    /// The absolute directory that replaces the dependency.
+    pub path: Utf8PathBuf,
+    /// The path as it is declared, it may be relative to the declaring file.
+    pub declared: SmolStr,
+    /// The manifest or the user config that declares the override.
+    pub origin: PathBuf,
+}
+
+/// The user config, like `[overrides] my-lib = "../my-lib"`.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct UserOverridesConfig {
+    #[serde(default)]
+    overrides: BTreeMap<SmolStr, SmolStr>,
+}
+
+/// The overrides of a build, the key is the alias of the dependency.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overrides {
+    pub entries: BTreeMap<SmolStr, DependencyOverride>,
+}
+
+impl Overrides {
+    fn from_declared<'a>(
+        base: &Utf8Path,
+        origin: &Path,
+        declared: impl IntoIterator<Item = (&'a SmolStr, &'a SmolStr)>,
+    ) -> Result<Self, OverridesError> {
+        let mut entries = BTreeMap::new();
+
+        for (alias, path) in declared {
+            if !crate::id::is_loose_ident(alias) {
+                return Err(OverridesError::InvalidKey(
+                    alias.to_string(),
+                    origin.to_path_buf(),
+                ));
+            }
+
+            entries.insert(
+                alias.clone(),
+                DependencyOverride {
+                    path: base.join(path.as_str()),
+                    declared: path.clone(),
+                    origin: origin.to_path_buf(),
+                },
+            );
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// The overrides of the workspace root `package`, the paths are relative to the `root`.
+    pub fn from_manifest(
+        root: &Utf8Path,
+        manifest_path: &Path,
+        package: &Package,
+    ) -> Result<Self, OverridesError> {
+        Self::from_declared(root, manifest_path, package.overrides.iter().flatten())
+    }
+
+    /// Read the user config at `path`, empty if it does not exist.
+    ///
+    /// The relative paths are relative to the directory of the config.
+    pub fn load_user(path: &Path) -> Result<Self, OverridesError> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(OverridesError::Io(err, path.to_path_buf())),
+        };
+
+        let config: UserOverridesConfig = toml::from_str(&content)
+            .map_err(|err| OverridesError::Parse(path.to_path_buf(), err))?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        let base = Utf8Path::from_path(base)
+            .ok_or_else(|| OverridesError::NonUtf8Path(base.to_path_buf()))?;
+
+        Self::from_declared(base, path, config.overrides.iter())
+    }
+
+    /// Add the `other` overrides, they replace the overrides of the same dependencies.
+    pub fn merge(&mut self, other: Overrides) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn get(&self, alias: &str) -> Option<&DependencyOverride> {
+        self.entries.get(alias)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&SmolStr, &DependencyOverride)> {
+        self.entries.iter()
+    }
+
+    /// The entries of the lockfile, see [crate::lockfile::LockState::record_local_changes].
+    pub fn to_locked(&self) -> Vec<LockedOverride> {
+        self.entries
+            .iter()
+            .map(|(alias, entry)| LockedOverride {
+                dependency: alias.to_string(),
+                path: entry.declared.to_string(),
+            })
+            .collect()
+    }
+}
@@ -2,6 +2,7 @@ use std::collections::{BTreeMap, HashMap};
 use std::default;
 
 use crate::intern::{Internable, Uninternable};
+use crate::manifest::ManifestSource;
 use crate::pattern::{InternedPattern, Pattern, PatternError, PatternGroup};
 use crate::{
     author::{Author, InternedAuthor},
@@ -47,6 +48,20 @@ pub enum PackageResolveError {
     ConfigError(#[from] crate::config::ConfigError),
     #[error("interner error: {0}")]
     InternerError(#[from] ::zako_interner::InternerError),
+    #[error("the workspace pattern `{0}` is not relative to the package root")]
+    InvalidWorkspacePattern(String),
+    #[error("failed to read the manifest {1:?}: {0}")]
+    ManifestIoError(#[source] std::io::Error, std::path::PathBuf),
+    #[error("{0}")]
+    InvalidManifest(#[from] crate::diagnostic::Diagnostics),
+    #[error("the dependency `{0}` is invalid: {1}")]
+    InvalidDependencySource(String, #[source] eyre::Report),
+    #[error("the manifest {0:?} is neither a `.toml` nor a `.json` file")]
+    UnknownManifestFormat(std::path::PathBuf),
+    #[error("the patches of the dependency `{0}` are invalid: {1}")]
+    InvalidPatch(String, String),
+    #[error("the package overrides key `{0}` is not a valid xid_loose_ident")]
+    InvalidOverrideKey(String),
     #[error("other error: {0}")]
     OtherError(#[from] eyre::Report),
 }
@@ -84,6 +99,19 @@ pub struct Package {
     pub mount_config: Option<SmolStr>,
     /// The key will be checked by [crate::id::is_loose_ident]
     pub config: Option<BTreeMap<SmolStr, ConfigValue>>,
+    /// The patterns of the member package directories, relative to the package root,
+    /// like `packages/*`. See [crate::workspace].
+    pub workspaces: Option<Vec<SmolStr>>,
+    /// The patch files that are applied in order to the fetched dependencies, the key is the alias
+    /// of the dependency and the paths are relative to the package root. See [crate::fetch::patch].
+    ///
+    /// Only the patches of the workspace root are applied.
+    pub patches: Option<BTreeMap<SmolStr, Vec<SmolStr>>>,
+    /// The local directories that replace the dependencies while debugging, the key is the alias
+    /// of the dependency and the paths are relative to the package root. See [crate::overrides].
+    ///
+    /// Only the overrides of the workspace root are used.
+    pub overrides: Option<BTreeMap<SmolStr, SmolStr>>,
 }
 
 #[cfg(not(feature = "v8snapshot"))]
@@ -104,11 +132,21 @@ impl Default for Package {
             dependencies: Default::default(),
             mount_config: Default::default(),
             config: Default::default(),
+            workspaces: Default::default(),
+            patches: Default::default(),
+            overrides: Default::default(),
         }
     }
 }
 
 impl Package {
+    /// Read and parse the manifest file, the format is decided by its extension.
+    ///
+    /// It is checked against the schema but not validated.
+    pub fn load(path: &std::path::Path) -> Result<Self, PackageResolveError> {
+        Ok(ManifestSource::load(path)?.parse()?)
+    }
+
     #[must_use]
     pub fn validate(&self) -> Result<(), PackageResolveError> {
         if let Some(wrong_config_key) = self.config.as_ref().and_then(|cfg| {
@@ -133,6 +171,59 @@ impl Package {
             ));
         }
 
+        for (alias, source) in self.dependencies.iter().flatten() {
+            source.validate().map_err(|err| {
+                PackageResolveError::InvalidDependencySource(alias.to_string(), err)
+            })?;
+        }
+
+        for (alias, files) in self.patches.iter().flatten() {
+            let invalid =
+                |message: String| PackageResolveError::InvalidPatch(alias.to_string(), message);
+
+            match self.dependencies.as_ref().and_then(|deps| deps.get(alias)) {
+                None => return Err(invalid("it is not a dependency".to_string())),
+                Some(PackageSource::Path { .. }) => {
+                    return Err(invalid(
+                        "it is a path dependency, edit it instead".to_string(),
+                    ));
+                }
+                Some(_) => {}
+            }
+
+            for file in files {
+                PackageSource::Path {
+                    path: file.to_string(),
+                }
+                .validate()
+                .map_err(|err| invalid(err.to_string()))?;
+            }
+        }
+
+        if let Some(wrong_override_key) = self
+            .overrides
+            .iter()
+            .flatten()
+            .map(|(key, _)| key)
+            .find(|key| !crate::id::is_loose_ident(key))
+        {
+            return Err(PackageResolveError::InvalidOverrideKey(
+                wrong_override_key.to_string(),
+            ));
+        }
+
+        for pattern in self.workspaces.iter().flatten() {
+            let is_relative = !pattern.starts_with(['/', '\\'])
+                && !pattern.contains(':')
+                && !pattern.split(['/', '\\']).any(|part| part == "..");
+
+            if !is_relative {
+                return Err(PackageResolveError::InvalidWorkspacePattern(
+                    pattern.to_string(),
+                ));
+            }
+        }
+
         Ok(())
     }
 }
@@ -153,6 +244,9 @@ impl Blake3Hash for Package {
         self.dependencies.hash_into_blake3(hasher);
         self.mount_config.hash_into_blake3(hasher);
         self.config.hash_into_blake3(hasher);
+        self.workspaces.hash_into_blake3(hasher);
+        self.patches.hash_into_blake3(hasher);
+        self.overrides.hash_into_blake3(hasher);
     }
 }
 
@@ -0,0 +1,206 @@
+//! The archive of a package that is published to a [Registry], like `zako package` and `zako publish`.
+//!
+//! The archive is reproducible: the entries are sorted by their paths and no modification time,
+//! owner or permission except the executable bit is recorded, so the same files always give
+//! the same archive and the same integrity.
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::PathBuf;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::fetch::archive::Integrity;
+use crate::manifest;
+use crate::package::{Package, PackageResolveError};
+use crate::package_source::PackageSource;
+use crate::path::NeutralPath;
+use crate::pattern::PatternError;
+use crate::registry::{
+    ARCHIVE_EXTENSION, IndexDependency, IndexEntry, Registry, RegistryError, package_name,
+    parse_requirement,
+};
+
+/// The zstd level of the archives, changing it changes the integrity of every archive.
+pub static PACKAGE_ARCHIVE_ZSTD_LEVEL: i32 = 19;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PackageArchiveError {
+    #[error("io error at {1:?}: {0}")]
+    Io(#[source] std::io::Error, PathBuf),
+    #[error("the package {0:?} has no manifest")]
+    NoManifest(Utf8PathBuf),
+    #[error("failed to load the manifest: {0}")]
+    Manifest(#[from] Box<PackageResolveError>),
+    #[error("failed to select the files: {0}")]
+    Pattern(#[from] PatternError),
+    #[error("the file {0:?} is not a valid path in the archive")]
+    InvalidPath(PathBuf),
+    #[error("the version `{0}` is not a semantic version: {1}")]
+    InvalidVersion(String, #[source] semver::Error),
+    #[error(
+        "the dependency `@{0}` is a path, it can not be published, use a registry, git or http source"
+    )]
+    PathDependency(String),
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+}
+
+/// The archive of a package, see [create].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackageArchive {
+    pub package: Package,
+    /// The paths in the archive like `src/BUILD.ts`, sorted.
+    pub files: Vec<String>,
+    /// The `.tar.zst` archive, its root is the package root.
+    pub data: Vec<u8>,
+}
+
+impl PackageArchive {
+    pub fn get_integrity(&self) -> Integrity {
+        Integrity::Blake3(blake3::hash(&self.data).to_hex().to_string())
+    }
+
+    /// Like `example-1.0.0.tar.zst`.
+    pub fn get_file_name(&self) -> String {
+        format!(
+            "{}-{}.{}",
+            self.package.artifact, self.package.version, ARCHIVE_EXTENSION
+        )
+    }
+
+    pub fn get_version(&self) -> Result<semver::Version, PackageArchiveError> {
+        semver::Version::parse(self.package.version.trim()).map_err(|err| {
+            PackageArchiveError::InvalidVersion(self.package.version.to_string(), err)
+        })
+    }
+
+    /// The version in the registry index, the registry dependencies are listed in it.
+    ///
+    /// The path dependencies are refused, the archive does not hold them.
+    pub fn get_index_entry(&self) -> Result<IndexEntry, PackageArchiveError> {
+        let mut dependencies = Vec::new();
+
+        for (alias, source) in self.package.dependencies.iter().flatten() {
+            match source {
+                PackageSource::Registry { package } => {
+                    let req = parse_requirement(package)?;
+
+                    dependencies.push(IndexDependency {
+                        package: package_name(&req.name),
+                        req: req.version,
+                    });
+                }
+                PackageSource::Path { .. } => {
+                    return Err(PackageArchiveError::PathDependency(alias.to_string()));
+                }
+                PackageSource::Git { .. } | PackageSource::Http { .. } => {}
+            }
+        }
+
+        Ok(IndexEntry {
+            version: self.get_version()?,
+            integrity: self.get_integrity().to_string(),
+            dependencies,
+            yanked: false,
+        })
+    }
+}
+
+/// The files of the package in the `root`: the manifest, the configure script and
+/// the files selected by `builds`, `rules` and `toolchains`.
+///
+/// The key is the path in the archive.
+pub fn select_files(
+    root: &Utf8Path,
+    manifest_path: &Utf8Path,
+    package: &Package,
+) -> Result<BTreeMap<String, PathBuf>, PackageArchiveError> {
+    let mut selected = vec![manifest_path.as_std_path().to_path_buf()];
+
+    if let Some(script) = package.configure_script.as_ref() {
+        selected.push(root.join(script.as_str()).into_std_path_buf());
+    }
+
+    for pattern in [&package.builds, &package.rules, &package.toolchains]
+        .into_iter()
+        .flatten()
+    {
+        selected.extend(pattern.select_files(root.as_std_path())?);
+    }
+
+    let mut files = BTreeMap::new();
+
+    for path in selected {
+        let relative = path
+            .strip_prefix(root)
+            .ok()
+            .and_then(Utf8Path::from_path)
+            .and_then(|relative| NeutralPath::from_path(relative).ok())
+            .filter(|relative| !relative.to_string().starts_with(".."))
+            .ok_or_else(|| PackageArchiveError::InvalidPath(path.clone()))?;
+
+        files.insert(relative.to_string(), path);
+    }
+
+    Ok(files)
+}
+
+/// Create the archive of the package in the `root`.
+pub fn create(root: &Utf8Path) -> Result<PackageArchive, PackageArchiveError> {
+    let (manifest_path, _) =
+        manifest::find(root).ok_or_else(|| PackageArchiveError::NoManifest(root.to_path_buf()))?;
+    let package = Package::load(manifest_path.as_std_path()).map_err(Box::new)?;
+    package.validate().map_err(Box::new)?;
+
+    let files = select_files(root, &manifest_path, &package)?;
+
+    let mut tar = tar::Builder::new(Vec::new());
+
+    for (name, path) in files.iter() {
+        let io_error = |err| PackageArchiveError::Io(err, path.clone());
+
+        let file = std::fs::File::open(path).map_err(io_error)?;
+        let size = file.metadata().map_err(io_error)?.len();
+
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(size);
+        header.set_mode(if is_executable::is_executable(path) {
+            0o755
+        } else {
+            0o644
+        });
+        header.set_mtime(0);
+
+        tar.append_data(&mut header, name, file.take(size))
+            .map_err(io_error)?;
+    }
+
+    let io_error = |err| PackageArchiveError::Io(err, root.as_std_path().to_path_buf());
+
+    let tar = tar.into_inner().map_err(io_error)?;
+    let data = zstd::encode_all(tar.as_slice(), PACKAGE_ARCHIVE_ZSTD_LEVEL).map_err(io_error)?;
+
+    Ok(PackageArchive {
+        package,
+        files: files.into_keys().collect(),
+        data,
+    })
+}
+
+/// Upload the archive to the `registry` and add it to the index, returns the added version.
+pub async fn publish(
+    registry: &dyn Registry,
+    archive: &PackageArchive,
+) -> Result<IndexEntry, PackageArchiveError> {
+    let entry = archive.get_index_entry()?;
+    let name = parse_requirement(&format!(
+        "{}:{}",
+        archive.package.group, archive.package.artifact
+    ))?
+    .name;
+
+    crate::registry::publish(registry, &name, entry.clone(), archive.data.clone()).await?;
+
+    Ok(entry)
+}
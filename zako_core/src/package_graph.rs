@@ -0,0 +1,411 @@
+//! The dependency graph of a package, see [PackageGraph].
+//!
+//! The versions of a package are unified under the semver compatibility rules:
+//! `1.2.0` and `1.5.0` are compatible so only one of them is kept, `1.5.0` and `2.0.0` are not
+//! so both of them can be in the graph. The packages are identified by `group:artifact@version`.
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Display;
+
+use smol_str::SmolStr;
+use zako_digest::blake3::Blake3Hash;
+
+use crate::package_source::PackageSource;
+use crate::registry::{RegistryError, parse_requirement};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PackageGraphError {
+    #[error("the version of `{0}` is not a semver version: {1}")]
+    InvalidVersion(String, #[source] semver::Error),
+    #[error("the requirement `{0}` is not a semver requirement: {1}")]
+    InvalidRequirement(String, #[source] semver::Error),
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+    #[error("{0}")]
+    Conflict(DependencyConflict),
+}
+
+/// A package that requires a version, see [DependencyConflict].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requirer {
+    /// The packages from the root to the requirer.
+    pub path: Vec<String>,
+    /// The name of the dependency in the manifest of the requirer.
+    pub alias: SmolStr,
+    pub req: String,
+}
+
+impl Display for Requirer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} requires `@{}` as `{}`",
+            self.path.join(" -> "),
+            self.alias,
+            self.req
+        )
+    }
+}
+
+/// No version of a package satisfies all of its compatible requirements.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencyConflict {
+    /// Like `com.example:name`.
+    pub package: String,
+    /// The versions in the graph that are compatible with each other.
+    pub candidates: Vec<String>,
+    pub requirers: Vec<Requirer>,
+}
+
+impl Display for DependencyConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "no version of `{}` in [{}] satisfies all of its requirers:",
+            self.package,
+            self.candidates.join(", ")
+        )?;
+
+        for requirer in self.requirers.iter() {
+            write!(f, "\n  {}", requirer)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A dependency edge of the [PackageGraph].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, rkyv::Deserialize, rkyv::Serialize, rkyv::Archive)]
+pub struct GraphDependency {
+    /// The package it resolves to, like `com.example:name@1.2.0`.
+    pub package: String,
+    /// The semver requirement, see [dependency_requirement].
+    pub req: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, rkyv::Deserialize, rkyv::Serialize, rkyv::Archive)]
+pub struct GraphPackage {
+    pub source: PackageSource,
+    /// The key is the name of the dependency in the manifest.
+    pub dependencies: BTreeMap<SmolStr, GraphDependency>,
+}
+
+/// The packages that a root package depends on, directly or not.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, rkyv::Deserialize, rkyv::Serialize, rkyv::Archive)]
+pub struct PackageGraph {
+    root: String,
+    packages: BTreeMap<String, GraphPackage>,
+}
+
+impl Blake3Hash for GraphDependency {
+    fn hash_into_blake3(&self, hasher: &mut blake3::Hasher) {
+        self.package.hash_into_blake3(hasher);
+        self.req.hash_into_blake3(hasher);
+    }
+}
+
+impl Blake3Hash for GraphPackage {
+    fn hash_into_blake3(&self, hasher: &mut blake3::Hasher) {
+        self.source.hash_into_blake3(hasher);
+        self.dependencies.hash_into_blake3(hasher);
+    }
+}
+
+impl Blake3Hash for PackageGraph {
+    fn hash_into_blake3(&self, hasher: &mut blake3::Hasher) {
+        self.root.hash_into_blake3(hasher);
+        self.packages.hash_into_blake3(hasher);
+    }
+}
+
+/// Split `group:artifact@version` into `group:artifact` and `version`.
+pub fn split_package_id(id: &str) -> (&str, &str) {
+    id.rsplit_once('@').unwrap_or((id, ""))
+}
+
+/// The versions with the same compatibility are unified,
+/// it is the first non-zero part of the version like `1`, `0.2` or `0.0.3`.
+pub fn compatibility(version: &semver::Version) -> String {
+    if version.major > 0 {
+        version.major.to_string()
+    } else if version.minor > 0 {
+        format!("0.{}", version.minor)
+    } else {
+        format!("0.0.{}", version.patch)
+    }
+}
+
+/// The requirement of a dependency that resolved to the `version`.
+///
+/// It is the requirement of a [PackageSource::Registry], any other source is pinned to a version
+/// so any compatible version that is not lower satisfies it.
+pub fn dependency_requirement(
+    source: &PackageSource,
+    version: &str,
+) -> Result<String, PackageGraphError> {
+    match source {
+        PackageSource::Registry { package } => Ok(parse_requirement(package)?.version.to_string()),
+        _ => Ok(format!("^{}", version)),
+    }
+}
+
+fn parse_version(id: &str) -> Result<semver::Version, PackageGraphError> {
+    split_package_id(id)
+        .1
+        .parse()
+        .map_err(|err| PackageGraphError::InvalidVersion(id.to_string(), err))
+}
+
+fn parse_req(req: &str) -> Result<semver::VersionReq, PackageGraphError> {
+    req.parse()
+        .map_err(|err| PackageGraphError::InvalidRequirement(req.to_string(), err))
+}
+
+/// `group:artifact` and [compatibility] of a package id.
+type Class = (String, String);
+
+fn class_of(id: &str) -> Result<Class, PackageGraphError> {
+    Ok((
+        split_package_id(id).0.to_string(),
+        compatibility(&parse_version(id)?),
+    ))
+}
+
+impl PackageGraph {
+    /// A graph of the `root` package only, its dependencies are added by [PackageGraph::merge].
+    pub fn new(root: String, package: GraphPackage) -> Self {
+        Self {
+            packages: BTreeMap::from([(root.clone(), package)]),
+            root,
+        }
+    }
+
+    pub fn get_root(&self) -> &str {
+        &self.root
+    }
+
+    pub fn get_packages(&self) -> &BTreeMap<String, GraphPackage> {
+        &self.packages
+    }
+
+    pub fn get(&self, id: &str) -> Option<&GraphPackage> {
+        self.packages.get(id)
+    }
+
+    /// Add the packages of the graph of a dependency, the existing packages are kept.
+    pub fn merge(&mut self, other: PackageGraph) {
+        for (id, package) in other.packages {
+            self.packages.entry(id).or_insert(package);
+        }
+    }
+
+    /// The packages reachable from the root, following the `chosen` packages.
+    fn reachable(&self, chosen: &BTreeMap<String, String>) -> BTreeSet<String> {
+        let mut live = BTreeSet::new();
+        let mut queue = VecDeque::from([self.root.clone()]);
+
+        while let Some(id) = queue.pop_front() {
+            if !live.insert(id.clone()) {
+                continue;
+            }
+
+            if let Some(package) = self.packages.get(&id) {
+                for dependency in package.dependencies.values() {
+                    let target = chosen
+                        .get(&dependency.package)
+                        .unwrap_or(&dependency.package);
+                    queue.push_back(target.clone());
+                }
+            }
+        }
+
+        live
+    }
+
+    /// The shortest path of the packages from the root to the `target`.
+    fn path_to(&self, target: &str, chosen: &BTreeMap<String, String>) -> Vec<String> {
+        let mut previous: BTreeMap<String, String> = BTreeMap::new();
+        let mut queue = VecDeque::from([self.root.clone()]);
+        let mut seen = BTreeSet::from([self.root.clone()]);
+
+        while let Some(id) = queue.pop_front() {
+            if id == target {
+                break;
+            }
+
+            if let Some(package) = self.packages.get(&id) {
+                for dependency in package.dependencies.values() {
+                    let next = chosen
+                        .get(&dependency.package)
+                        .unwrap_or(&dependency.package);
+
+                    if seen.insert(next.clone()) {
+                        previous.insert(next.clone(), id.clone());
+                        queue.push_back(next.clone());
+                    }
+                }
+            }
+        }
+
+        let mut path = vec![target.to_string()];
+        while let Some(parent) = previous.get(path.last().map(String::as_str).unwrap_or_default()) {
+            path.push(parent.clone());
+        }
+        path.reverse();
+        path
+    }
+
+    /// Choose a version for every class that the `live` packages require.
+    ///
+    /// The highest candidate that satisfies every requirement is chosen,
+    /// the highest one is chosen if there is none and the class is returned as conflicted.
+    fn choose(
+        &self,
+        live: &BTreeSet<String>,
+    ) -> Result<(BTreeMap<String, String>, BTreeSet<Class>), PackageGraphError> {
+        let mut candidates: BTreeMap<Class, Vec<(semver::Version, String)>> = BTreeMap::new();
+
+        for id in self.packages.keys().filter(|id| **id != self.root) {
+            candidates
+                .entry(class_of(id)?)
+                .or_default()
+                .push((parse_version(id)?, id.clone()));
+        }
+
+        let mut requirements: BTreeMap<Class, Vec<semver::VersionReq>> = BTreeMap::new();
+
+        for id in live.iter() {
+            let Some(package) = self.packages.get(id) else {
+                continue;
+            };
+
+            for dependency in package.dependencies.values() {
+                requirements
+                    .entry(class_of(&dependency.package)?)
+                    .or_default()
+                    .push(parse_req(&dependency.req)?);
+            }
+        }
+
+        let mut chosen = BTreeMap::new();
+        let mut conflicted = BTreeSet::new();
+
+        for (class, versions) in candidates.iter() {
+            let reqs = requirements
+                .get(class)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+
+            let satisfying = versions
+                .iter()
+                .filter(|(version, _)| reqs.iter().all(|req| req.matches(version)))
+                .max_by(|a, b| a.0.cmp(&b.0));
+
+            let selected = match satisfying {
+                Some(selected) => selected,
+                None => {
+                    conflicted.insert(class.clone());
+                    match versions.iter().max_by(|a, b| a.0.cmp(&b.0)) {
+                        Some(selected) => selected,
+                        None => continue,
+                    }
+                }
+            };
+
+            for (_, id) in versions.iter() {
+                chosen.insert(id.clone(), selected.1.clone());
+            }
+        }
+
+        Ok((chosen, conflicted))
+    }
+
+    fn conflict(
+        &self,
+        class: &Class,
+        live: &BTreeSet<String>,
+        chosen: &BTreeMap<String, String>,
+    ) -> Result<DependencyConflict, PackageGraphError> {
+        let mut candidates = Vec::new();
+        for id in self.packages.keys().filter(|id| **id != self.root) {
+            if class_of(id)? == *class {
+                candidates.push(split_package_id(id).1.to_string());
+            }
+        }
+
+        let mut requirers = Vec::new();
+        for id in live.iter() {
+            let Some(package) = self.packages.get(id) else {
+                continue;
+            };
+
+            for (alias, dependency) in package.dependencies.iter() {
+                if class_of(&dependency.package)? == *class {
+                    requirers.push(Requirer {
+                        path: self.path_to(id, chosen),
+                        alias: alias.clone(),
+                        req: dependency.req.clone(),
+                    });
+                }
+            }
+        }
+
+        // the nearest requirers first
+        requirers.sort_by(|a, b| (a.path.len(), &a.path).cmp(&(b.path.len(), &b.path)));
+
+        Ok(DependencyConflict {
+            package: class.0.clone(),
+            candidates,
+            requirers,
+        })
+    }
+
+    /// Unify the compatible versions of every package, then remove the packages that are not used.
+    ///
+    /// Every dependency edge is pointed to the chosen version.
+    pub fn unify(&mut self) -> Result<(), PackageGraphError> {
+        let mut live = self.reachable(&BTreeMap::new());
+
+        // choosing a version changes what is reachable, repeat until it does not
+        let mut round = 0;
+        let (chosen, conflicted) = loop {
+            let (chosen, conflicted) = self.choose(&live)?;
+            let next = self.reachable(&chosen);
+            round += 1;
+
+            if next == live || round > self.packages.len() {
+                live = next;
+                break (chosen, conflicted);
+            }
+
+            live = next;
+        };
+
+        for class in conflicted.iter() {
+            let required = live.iter().any(|id| {
+                self.packages.get(id).is_some_and(|package| {
+                    package.dependencies.values().any(|dependency| {
+                        class_of(&dependency.package).ok().as_ref() == Some(class)
+                    })
+                })
+            });
+
+            if required {
+                return Err(PackageGraphError::Conflict(
+                    self.conflict(class, &live, &chosen)?,
+                ));
+            }
+        }
+
+        self.packages.retain(|id, _| live.contains(id));
+
+        for package in self.packages.values_mut() {
+            for dependency in package.dependencies.values_mut() {
+                if let Some(target) = chosen.get(&dependency.package) {
+                    dependency.package = target.clone();
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
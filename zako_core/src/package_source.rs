@@ -5,6 +5,7 @@ use ts_rs::TS;
 use zako_digest::blake3::Blake3Hash;
 
 use crate::{
+    fetch::archive::Integrity,
     intern::{Internable, InternedAbsolutePath, Interner, Uninternable},
     package_id::{InternedPackageId, PackageIdParseError},
     path::{NeutralPath, PathError, interned::InternedNeutralPath},
@@ -55,9 +56,17 @@ pub enum PackageSource {
         checkout: Option<SmolStr>,
     },
     /// 来源于HTTP下载
+    ///
+    /// The url should end with `.tar.gz`, `.tar.zst` or `.zip`.
     Http {
         #[ts(as = "::std::string::String")]
         url: SmolStr,
+        /// The hash of the archive like `sha256:<hex>` or `blake3:<hex>`, see [crate::fetch::archive::Integrity].
+        #[ts(as = "::std::option::Option<::std::string::String>")]
+        integrity: Option<SmolStr>,
+        /// The directory in the archive that is the package root, like `project-1.0.0`.
+        #[ts(as = "::std::option::Option<::std::string::String>")]
+        strip_prefix: Option<SmolStr>,
     },
     /// 来源于本地路径
     ///
@@ -78,6 +87,25 @@ impl PackageSource {
                 }
                 Ok(())
             }
+            PackageSource::Http {
+                integrity,
+                strip_prefix,
+                ..
+            } => {
+                if let Some(integrity) = integrity {
+                    integrity.parse::<Integrity>()?;
+                }
+                if let Some(prefix) = strip_prefix {
+                    let p = NeutralPath::from_path(prefix.as_str())?;
+                    if !p.is_in_dir(NeutralPath::dot()) {
+                        return Err(eyre::eyre!(
+                            "the strip prefix `{}` is not relative to the archive root",
+                            prefix
+                        ));
+                    }
+                }
+                Ok(())
+            }
             _ => Ok(()),
         }
     }
@@ -91,7 +119,15 @@ impl Blake3Hash for PackageSource {
                 repo.hash_into_blake3(hasher);
                 checkout.hash_into_blake3(hasher);
             }
-            PackageSource::Http { url } => url.hash_into_blake3(hasher),
+            PackageSource::Http {
+                url,
+                integrity,
+                strip_prefix,
+            } => {
+                url.hash_into_blake3(hasher);
+                integrity.hash_into_blake3(hasher);
+                strip_prefix.hash_into_blake3(hasher);
+            }
             PackageSource::Path { path } => path.hash_into_blake3(hasher),
         }
     }
@@ -111,6 +147,8 @@ pub enum InternedPackageSource {
     },
     Http {
         url: SmolStr,
+        integrity: Option<SmolStr>,
+        strip_prefix: Option<SmolStr>,
     },
     Path {
         path: InternedNeutralPath,
@@ -133,7 +171,15 @@ impl Internable for PackageSource {
             PackageSource::Git { repo, checkout } => {
                 Ok(InternedPackageSource::Git { repo, checkout })
             }
-            PackageSource::Http { url } => Ok(InternedPackageSource::Http { url }),
+            PackageSource::Http {
+                url,
+                integrity,
+                strip_prefix,
+            } => Ok(InternedPackageSource::Http {
+                url,
+                integrity,
+                strip_prefix,
+            }),
             PackageSource::Path { path } => {
                 let path = NeutralPath::from_path(path.as_str())?;
 
@@ -163,7 +209,15 @@ impl Uninternable for InternedPackageSource {
                 repo: repo.clone(),
                 checkout: checkout.clone(),
             }),
-            InternedPackageSource::Http { url } => Ok(PackageSource::Http { url: url.clone() }),
+            InternedPackageSource::Http {
+                url,
+                integrity,
+                strip_prefix,
+            } => Ok(PackageSource::Http {
+                url: url.clone(),
+                integrity: integrity.clone(),
+                strip_prefix: strip_prefix.clone(),
+            }),
             InternedPackageSource::Path { path } => Ok(PackageSource::Path {
                 path: interner.resolve(path)?.into(),
             }),
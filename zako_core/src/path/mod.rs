@@ -367,8 +367,17 @@ impl NeutralPath {
     }
 
     pub fn get_relative_path_to(&self, to: &NeutralPath) -> Option<NeutralPath> {
-        let from_parts: StackString = self.0.split('/').collect();
-        let to_parts: StackString = to.0.split('/').collect();
+        // `.` is the root, it has no part
+        fn parts(path: &NeutralPath) -> StackString<'_> {
+            if path.0 == "." {
+                StackString::new()
+            } else {
+                path.0.split('/').collect()
+            }
+        }
+
+        let from_parts = parts(self);
+        let to_parts = parts(to);
 
         let mut common_length = 0;
         let max_common_length = std::cmp::min(from_parts.len(), to_parts.len());
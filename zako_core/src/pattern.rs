@@ -59,6 +59,51 @@ pub struct Pattern {
     pub ignore_hidden_files: bool,
 }
 
+impl Pattern {
+    /// The files under the `root` that match the patterns, sorted.
+    ///
+    /// The patterns are globs relative to the `root` like `src/**/*.ts`, nothing matches if there is no pattern.
+    pub fn select_files(&self, root: &Path) -> Result<Vec<PathBuf>, PatternError> {
+        if self.patterns.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut overrides = ignore::overrides::OverrideBuilder::new(root);
+
+        for pattern in &self.patterns {
+            overrides
+                .add(pattern)
+                .map_err(|err| eyre::eyre!("the pattern `{}` is invalid: {}", pattern, err))?;
+        }
+
+        let overrides = overrides
+            .build()
+            .map_err(|err| eyre::eyre!("the patterns are invalid: {}", err))?;
+
+        let mut walker = ignore::WalkBuilder::new(root);
+
+        walker.standard_filters(self.following_ignore_files);
+        walker.hidden(self.ignore_hidden_files);
+        walker.overrides(overrides);
+        // the repository of the package is never a part of it
+        walker.filter_entry(|entry| entry.file_name() != ".git");
+
+        let mut files = Vec::new();
+
+        for entry in walker.build() {
+            let entry = entry.map_err(|err| eyre::eyre!(err))?;
+
+            if entry.path().is_file() {
+                files.push(entry.into_path());
+            }
+        }
+
+        files.sort();
+
+        Ok(files)
+    }
+}
+
 impl Blake3Hash for Pattern {
     fn hash_into_blake3(&self, hasher: &mut blake3::Hasher) {
         for pattern in &self.patterns {
@@ -0,0 +1,880 @@
+//! A [Cas] that talks to a Bazel Remote Execution API v2 cache, like bazel-remote or Buildbarn.
+//!
+//! The `ContentAddressableStorage` and `ByteStream` services hold the blobs.
+//!
+//! Most REAPI servers only accept SHA-256 digests while zako addresses blobs by blake3.
+//! With [ReapiDigestFunction::Sha256] the SHA-256 of a blob is computed when it is stored and
+//! published to the `ActionCache` of the server under [index_action_digest], so every machine
+//! that shares the server finds the SHA-256 of the blobs that the others stored.
+//! The [Sha256Index] only caches the published mappings locally, once the blob is read and hashed.
+//! Servers that support [ReapiDigestFunction::Blake3] need no index.
+
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use hone::redb::{self, ReadableDatabase, TableDefinition};
+use sha2::Digest as _;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::sync::{OnceCell, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::{Channel, Endpoint};
+use tracing::warn;
+use zako_digest::Digest;
+use zako_digest::blake3::Hash;
+use zako_shared::ConcurrentMap;
+
+use crate::blob_range::BlobRange;
+use crate::cas::{Cas, CasError};
+use crate::protobuf::reapi::build::bazel::remote::execution::v2::{
+    ActionResult, BatchUpdateBlobsRequest, FindMissingBlobsRequest, GetActionResultRequest,
+    OutputFile, UpdateActionResultRequest, action_cache_client::ActionCacheClient,
+    batch_update_blobs_request,
+    content_addressable_storage_client::ContentAddressableStorageClient, digest_function,
+};
+use crate::protobuf::reapi::google::bytestream::{
+    ReadRequest, WriteRequest, byte_stream_client::ByteStreamClient,
+};
+
+pub type ReapiDigest = crate::protobuf::reapi::build::bazel::remote::execution::v2::Digest;
+
+const SHA256_INDEX_TABLE: TableDefinition<&[u8], &[u8]> =
+    TableDefinition::new("zako_reapi_sha256_index");
+
+/// The count of `ByteStream.Write` chunks that are read ahead of the upload.
+pub static WRITE_CHUNKS: usize = 4;
+
+/// The path of the only output file of an index entry in the `ActionCache`.
+pub static SHA256_INDEX_OUTPUT_PATH: &str = "blob";
+
+/// The key of the SHA-256 of the blob in the `ActionCache`, it is the SHA-256 digest of
+/// `zako-sha256-index/<blake3>/<size>`. The output file of the entry is the blob.
+pub fn index_action_digest(digest: &Digest) -> ReapiDigest {
+    let key = format!(
+        "zako-sha256-index/{}/{}",
+        digest.blake3.to_hex(),
+        digest.size_bytes
+    );
+
+    ReapiDigest {
+        hash: hex::encode(sha2::Sha256::digest(key.as_bytes())),
+        size_bytes: key.len() as i64,
+    }
+}
+
+/// The digest function that is used to address blobs on the REAPI server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReapiDigestFunction {
+    Sha256,
+    Blake3,
+}
+
+impl ReapiDigestFunction {
+    fn as_proto(&self) -> i32 {
+        match self {
+            ReapiDigestFunction::Sha256 => digest_function::Value::Sha256 as i32,
+            ReapiDigestFunction::Blake3 => digest_function::Value::Blake3 as i32,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReapiCasOptions {
+    /// The `instance_name` of every request, empty for most servers.
+    pub instance_name: String,
+    pub digest_function: ReapiDigestFunction,
+    /// Blobs not bigger than it are uploaded by `BatchUpdateBlobs`, others by `ByteStream.Write`.
+    pub max_batch_blob_size: u64,
+    /// The size of every `ByteStream.Write` chunk.
+    pub chunk_size: usize,
+    /// Where the [Sha256Index] persists, `None` keeps it in memory only.
+    pub sha256_index_path: Option<PathBuf>,
+}
+
+impl Default for ReapiCasOptions {
+    fn default() -> Self {
+        Self {
+            instance_name: String::new(),
+            digest_function: ReapiDigestFunction::Sha256,
+            max_batch_blob_size: 1024 * 1024,
+            chunk_size: 64 * 1024,
+            sha256_index_path: None,
+        }
+    }
+}
+
+/// Caches the blake3 hash of a blob to its SHA-256 hash, the `ActionCache` of the server
+/// is the source of truth.
+#[derive(Debug)]
+pub struct Sha256Index {
+    memory: ConcurrentMap<Hash, [u8; 32]>,
+    database: Option<redb::Database>,
+}
+
+impl Sha256Index {
+    pub fn in_memory() -> Self {
+        Self {
+            memory: ConcurrentMap::default(),
+            database: None,
+        }
+    }
+
+    pub fn open(path: &std::path::Path) -> Result<Self, CasError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|err| CasError::Io(err, Some(parent.to_path_buf())))?;
+        }
+
+        let database = redb::Database::create(path).map_err(|err| {
+            CasError::Internal(format!("failed to open sha256 index {:?}: {}", path, err))
+        })?;
+
+        Ok(Self {
+            memory: ConcurrentMap::default(),
+            database: Some(database),
+        })
+    }
+
+    pub fn get(&self, blake3: &Hash) -> Result<Option<[u8; 32]>, CasError> {
+        if let Some(sha256) = self.memory.get(blake3) {
+            return Ok(Some(*sha256));
+        }
+
+        let Some(database) = self.database.as_ref() else {
+            return Ok(None);
+        };
+
+        let internal = |err: &dyn std::fmt::Display| {
+            CasError::Internal(format!("failed to read sha256 index: {}", err))
+        };
+
+        let txn = database.begin_read().map_err(|err| internal(&err))?;
+
+        let table = match txn.open_table(SHA256_INDEX_TABLE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(err) => return Err(internal(&err)),
+        };
+
+        let Some(value) = table
+            .get(blake3.as_bytes().as_slice())
+            .map_err(|err| internal(&err))?
+        else {
+            return Ok(None);
+        };
+
+        let sha256: [u8; 32] = value
+            .value()
+            .try_into()
+            .map_err(|_| internal(&"stored sha256 is not 32 bytes"))?;
+
+        self.memory.insert(*blake3, sha256);
+
+        Ok(Some(sha256))
+    }
+
+    pub fn insert(&self, blake3: Hash, sha256: [u8; 32]) -> Result<(), CasError> {
+        if self.memory.insert(blake3, sha256).is_some() {
+            return Ok(());
+        }
+
+        let Some(database) = self.database.as_ref() else {
+            return Ok(());
+        };
+
+        let internal = |err: &dyn std::fmt::Display| {
+            CasError::Internal(format!("failed to write sha256 index: {}", err))
+        };
+
+        let txn = database.begin_write().map_err(|err| internal(&err))?;
+        {
+            let mut table = txn
+                .open_table(SHA256_INDEX_TABLE)
+                .map_err(|err| internal(&err))?;
+            table
+                .insert(blake3.as_bytes().as_slice(), sha256.as_slice())
+                .map_err(|err| internal(&err))?;
+        }
+        txn.commit().map_err(|err| internal(&err))?;
+
+        Ok(())
+    }
+}
+
+/// A [Cas] backed by a REAPI v2 server.
+#[derive(Debug)]
+pub struct ReapiCas {
+    /// Where the channel connects to, `None` for the cas that is created with a channel.
+    endpoint: Option<Endpoint>,
+    channel: OnceCell<Channel>,
+    options: ReapiCasOptions,
+    index: Arc<Sha256Index>,
+}
+
+impl ReapiCas {
+    pub fn new(channel: Channel, options: ReapiCasOptions) -> Result<Self, CasError> {
+        Self::with_channel(None, OnceCell::new_with(Some(channel)), options)
+    }
+
+    fn with_channel(
+        endpoint: Option<Endpoint>,
+        channel: OnceCell<Channel>,
+        options: ReapiCasOptions,
+    ) -> Result<Self, CasError> {
+        let index = match options.sha256_index_path.as_ref() {
+            Some(path) => Sha256Index::open(path)?,
+            None => Sha256Index::in_memory(),
+        };
+
+        Ok(Self {
+            endpoint,
+            channel,
+            options,
+            index: Arc::new(index),
+        })
+    }
+
+    /// Like [ReapiCas::connect] but connect on the first request, it needs no runtime.
+    pub fn lazy(endpoint: &str, options: ReapiCasOptions) -> Result<Self, CasError> {
+        let endpoint = if let Some(rest) = endpoint.strip_prefix("grpc://") {
+            format!("http://{}", rest)
+        } else if let Some(rest) = endpoint.strip_prefix("grpcs://") {
+            format!("https://{}", rest)
+        } else {
+            endpoint.to_string()
+        };
+
+        let endpoint = Endpoint::from_shared(endpoint.clone())
+            .map_err(|err| CasError::Internal(format!("invalid endpoint {}: {}", endpoint, err)))?;
+
+        Self::with_channel(Some(endpoint), OnceCell::new(), options)
+    }
+
+    /// Connect to a server like `grpc://localhost:9092`.
+    pub async fn connect(endpoint: &str, options: ReapiCasOptions) -> Result<Self, CasError> {
+        let cas = Self::lazy(endpoint, options)?;
+        cas.channel().await?;
+        Ok(cas)
+    }
+
+    async fn channel(&self) -> Result<Channel, CasError> {
+        self.channel
+            .get_or_try_init(|| async {
+                let endpoint = self.endpoint.as_ref().ok_or_else(|| {
+                    CasError::Internal("the reapi cas has no endpoint to connect".to_string())
+                })?;
+
+                endpoint.connect().await.map_err(|err| {
+                    CasError::Internal(format!("failed to connect to {}: {}", endpoint.uri(), err))
+                })
+            })
+            .await
+            .cloned()
+    }
+
+    pub fn sha256_index(&self) -> &Sha256Index {
+        &self.index
+    }
+
+    /// The digest of the blob on the server, and whether the blob is known to match it.
+    ///
+    /// The SHA-256 that is not cached locally is looked up in the `ActionCache`,
+    /// a blob that no one has published is [CasError::NotFound].
+    /// The looked up SHA-256 is not verified, the blob is hashed by [ReapiCas::verified_read] once.
+    async fn to_reapi_digest(&self, digest: &Digest) -> Result<(ReapiDigest, bool), CasError> {
+        let (hash, verified) = match self.options.digest_function {
+            ReapiDigestFunction::Blake3 => (digest.blake3.to_hex().to_string(), true),
+            ReapiDigestFunction::Sha256 => match self.index.get(&digest.blake3)? {
+                Some(sha256) => (hex::encode(sha256), true),
+                None => (hex::encode(self.lookup_sha256(digest).await?), false),
+            },
+        };
+
+        Ok((
+            ReapiDigest {
+                hash,
+                size_bytes: to_i64(digest.size_bytes)?,
+            },
+            verified,
+        ))
+    }
+
+    async fn lookup_sha256(&self, digest: &Digest) -> Result<[u8; 32], CasError> {
+        let result = ActionCacheClient::new(self.channel().await?)
+            .get_action_result(GetActionResultRequest {
+                instance_name: self.options.instance_name.clone(),
+                action_digest: Some(index_action_digest(digest)),
+                digest_function: digest_function::Value::Sha256 as i32,
+            })
+            .await
+            .map_err(|status| match status.code() {
+                tonic::Code::NotFound => CasError::NotFound(
+                    *digest,
+                    PathBuf::from("<sha256 of the blob is not published>"),
+                ),
+                _ => CasError::Internal(format!(
+                    "failed to look up the sha256 of blob {:?}: {}",
+                    digest.blake3, status
+                )),
+            })?
+            .into_inner();
+
+        let invalid = |reason: &str| {
+            CasError::Internal(format!(
+                "the published sha256 of blob {:?} is invalid: {}",
+                digest.blake3, reason
+            ))
+        };
+
+        let published = result
+            .output_files
+            .into_iter()
+            .find(|file| file.path == SHA256_INDEX_OUTPUT_PATH)
+            .and_then(|file| file.digest)
+            .ok_or_else(|| invalid("it has no output file"))?;
+
+        if published.size_bytes != to_i64(digest.size_bytes)? {
+            return Err(invalid("the size does not match"));
+        }
+
+        let sha256: [u8; 32] = hex::decode(&published.hash)
+            .ok()
+            .and_then(|hash| hash.try_into().ok())
+            .ok_or_else(|| invalid("it is not a sha256"))?;
+
+        Ok(sha256)
+    }
+
+    /// Read the `range` of the blob from the `ByteStream`.
+    async fn read(
+        &self,
+        digest: &Digest,
+        reapi_digest: &ReapiDigest,
+        range: &BlobRange,
+    ) -> Result<impl Stream<Item = std::io::Result<Bytes>> + Send + 'static, CasError> {
+        let stream = ByteStreamClient::new(self.channel().await?)
+            .read(ReadRequest {
+                resource_name: self.read_resource_name(reapi_digest),
+                read_offset: to_i64(range.start())?,
+                read_limit: to_i64(range.length().unwrap_or(0))?,
+            })
+            .await
+            .map_err(|status| status_to_cas_error(status, Some(digest)))?
+            .into_inner();
+
+        Ok(stream.map(|response| match response {
+            Ok(response) => Ok(response.data),
+            Err(status) => Err(std::io::Error::other(status)),
+        }))
+    }
+
+    /// Read the whole blob by the SHA-256 from the `ActionCache` and hash it while reading,
+    /// the stream fails at the end if it is not the blob of the `digest`.
+    ///
+    /// The SHA-256 is cached in the [Sha256Index] once the blob matches.
+    async fn verified_read(
+        &self,
+        digest: &Digest,
+        reapi_digest: &ReapiDigest,
+    ) -> Result<impl Stream<Item = std::io::Result<Bytes>> + Send + 'static, CasError> {
+        let stream = self.read(digest, reapi_digest, &BlobRange::full()).await?;
+        let sha256: Option<[u8; 32]> = hex::decode(&reapi_digest.hash)
+            .ok()
+            .and_then(|hash| hash.try_into().ok());
+        let index = self.index.clone();
+        let digest = *digest;
+
+        Ok(async_stream::try_stream! {
+            let mut hasher = ::blake3::Hasher::new();
+            let mut size = 0u64;
+
+            for await chunk in stream {
+                let chunk = chunk?;
+                hasher.update(&chunk);
+                size += chunk.len() as u64;
+                yield chunk;
+            }
+
+            let actual = Digest::new(size, hasher.finalize().into());
+
+            if actual != digest {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "the published sha256 of blob {:?} names another blob {:?}",
+                        digest.blake3, actual.blake3
+                    ),
+                ))?;
+            }
+
+            if let Some(sha256) = sha256 {
+                index.insert(digest.blake3, sha256).map_err(std::io::Error::other)?;
+            }
+        })
+    }
+
+    /// Publish the SHA-256 of the stored blob so the other machines can find it.
+    async fn publish_sha256(
+        &self,
+        digest: &Digest,
+        reapi_digest: ReapiDigest,
+    ) -> Result<(), CasError> {
+        ActionCacheClient::new(self.channel().await?)
+            .update_action_result(UpdateActionResultRequest {
+                instance_name: self.options.instance_name.clone(),
+                action_digest: Some(index_action_digest(digest)),
+                action_result: Some(ActionResult {
+                    output_files: vec![OutputFile {
+                        path: SHA256_INDEX_OUTPUT_PATH.to_string(),
+                        digest: Some(reapi_digest),
+                        is_executable: false,
+                    }],
+                    exit_code: 0,
+                }),
+                digest_function: digest_function::Value::Sha256 as i32,
+            })
+            .await
+            .map_err(|status| {
+                CasError::Internal(format!(
+                    "failed to publish the sha256 of blob {:?}: {}",
+                    digest.blake3, status
+                ))
+            })?;
+
+        Ok(())
+    }
+
+    fn read_resource_name(&self, digest: &ReapiDigest) -> String {
+        let blobs = format!("blobs/{}/{}", digest.hash, digest.size_bytes);
+
+        if self.options.instance_name.is_empty() {
+            blobs
+        } else {
+            format!("{}/{}", self.options.instance_name, blobs)
+        }
+    }
+
+    fn write_resource_name(&self, digest: &ReapiDigest) -> String {
+        let uploads = format!(
+            "uploads/{}/blobs/{}/{}",
+            uuid::Uuid::new_v4(),
+            digest.hash,
+            digest.size_bytes
+        );
+
+        if self.options.instance_name.is_empty() {
+            uploads
+        } else {
+            format!("{}/{}", self.options.instance_name, uploads)
+        }
+    }
+
+    async fn is_missing(&self, digest: &ReapiDigest) -> Result<bool, CasError> {
+        let response = ContentAddressableStorageClient::new(self.channel().await?)
+            .find_missing_blobs(FindMissingBlobsRequest {
+                instance_name: self.options.instance_name.clone(),
+                blob_digests: vec![digest.clone()],
+                digest_function: self.options.digest_function.as_proto(),
+            })
+            .await
+            .map_err(|status| status_to_cas_error(status, None))?;
+
+        Ok(!response.into_inner().missing_blob_digests.is_empty())
+    }
+
+    async fn batch_update(&self, digest: ReapiDigest, data: Vec<u8>) -> Result<(), CasError> {
+        let response = ContentAddressableStorageClient::new(self.channel().await?)
+            .batch_update_blobs(BatchUpdateBlobsRequest {
+                instance_name: self.options.instance_name.clone(),
+                requests: vec![batch_update_blobs_request::Request {
+                    digest: Some(digest),
+                    data,
+                    compressor: 0,
+                }],
+                digest_function: self.options.digest_function.as_proto(),
+            })
+            .await
+            .map_err(|status| status_to_cas_error(status, None))?;
+
+        for response in response.into_inner().responses {
+            if let Some(status) = response.status
+                && status.code != tonic::Code::Ok as i32
+            {
+                return Err(CasError::Internal(format!(
+                    "remote rejected blob {:?}: {}",
+                    response.digest, status.message
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the blob from the `data` in chunks of [ReapiCasOptions::chunk_size],
+    /// only a few chunks are in memory at a time.
+    async fn byte_stream_write(
+        &self,
+        digest: ReapiDigest,
+        mut data: Box<dyn AsyncRead + Send + Unpin + 'static>,
+    ) -> Result<(), CasError> {
+        let resource_name = self.write_resource_name(&digest);
+        let chunk_size = self.options.chunk_size.max(1);
+        let total = digest.size_bytes;
+        let hash = digest.hash.clone();
+
+        let (sender, receiver) = mpsc::channel::<WriteRequest>(WRITE_CHUNKS);
+
+        // the reading task stops early once the write fails and drops the receiver
+        let reading = tokio::spawn(async move {
+            let mut offset = 0i64;
+
+            loop {
+                let length = usize::try_from(total - offset)
+                    .unwrap_or(usize::MAX)
+                    .min(chunk_size);
+                let chunk = read_chunk(&mut data, length)
+                    .await
+                    .map_err(|err| CasError::Io(err, None))?;
+
+                if chunk.len() != length {
+                    return Err(CasError::Internal(format!(
+                        "blob {:?} has {} bytes but the digest says {}",
+                        hash,
+                        offset + chunk.len() as i64,
+                        total
+                    )));
+                }
+
+                let write_offset = offset;
+                offset += chunk.len() as i64;
+                let finish_write = offset == total;
+
+                if finish_write
+                    && !read_chunk(&mut data, 1)
+                        .await
+                        .map_err(|err| CasError::Io(err, None))?
+                        .is_empty()
+                {
+                    return Err(CasError::Internal(format!(
+                        "blob {:?} has more bytes than the digest says {}",
+                        hash, total
+                    )));
+                }
+
+                let request = WriteRequest {
+                    resource_name: if write_offset == 0 {
+                        resource_name.clone()
+                    } else {
+                        String::new()
+                    },
+                    write_offset,
+                    finish_write,
+                    data: chunk,
+                };
+
+                if sender.send(request).await.is_err() || finish_write {
+                    return Ok(());
+                }
+            }
+        });
+
+        let response = ByteStreamClient::new(self.channel().await?)
+            .write(ReceiverStream::new(receiver))
+            .await;
+
+        reading.await.map_err(|err| {
+            CasError::Internal(format!("failed to read blob {:?}: {}", digest.hash, err))
+        })??;
+
+        let response = response
+            .map_err(|status| status_to_cas_error(status, None))?
+            .into_inner();
+
+        if response.committed_size != digest.size_bytes {
+            return Err(CasError::Internal(format!(
+                "remote committed {} bytes of {:?}",
+                response.committed_size, digest
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Store the small blob by `BatchUpdateBlobs`, which needs the whole blob in memory anyway.
+    async fn store_batch(
+        &self,
+        digest: &Digest,
+        data: Box<dyn AsyncRead + Send + Unpin + 'static>,
+    ) -> Result<ReapiDigest, CasError> {
+        let mut bytes = Vec::with_capacity(digest.size_bytes.try_into().unwrap_or(0));
+        data.take(digest.size_bytes.saturating_add(1))
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(|err| CasError::Io(err, None))?;
+
+        // the sha256 is indexed for the digest, so the blob has to be the one of the digest
+        let actual = Digest::new(bytes.len() as u64, ::blake3::hash(&bytes).into());
+
+        if actual != *digest {
+            return Err(CasError::Internal(format!(
+                "blob {:?} of {} bytes is stored as {:?} of {} bytes",
+                actual.blake3, actual.size_bytes, digest.blake3, digest.size_bytes
+            )));
+        }
+
+        let reapi_digest = match self.options.digest_function {
+            ReapiDigestFunction::Blake3 => self.to_reapi_digest(digest).await?.0,
+            ReapiDigestFunction::Sha256 => {
+                let sha256: [u8; 32] = sha2::Sha256::digest(&bytes).into();
+                self.index.insert(digest.blake3, sha256)?;
+
+                ReapiDigest {
+                    hash: hex::encode(sha256),
+                    size_bytes: to_i64(digest.size_bytes)?,
+                }
+            }
+        };
+
+        if self.is_missing(&reapi_digest).await? {
+            self.batch_update(reapi_digest.clone(), bytes).await?;
+        }
+
+        Ok(reapi_digest)
+    }
+
+    /// Store the big blob by `ByteStream.Write` without buffering it in memory.
+    ///
+    /// The SHA-256 is a part of the resource name, the blob whose SHA-256 is not indexed yet
+    /// is spooled to a temporary file to hash it first.
+    async fn store_stream(
+        &self,
+        digest: &Digest,
+        data: Box<dyn AsyncRead + Send + Unpin + 'static>,
+    ) -> Result<ReapiDigest, CasError> {
+        let indexed = match self.options.digest_function {
+            ReapiDigestFunction::Blake3 => Some(self.to_reapi_digest(digest).await?.0),
+            ReapiDigestFunction::Sha256 => self
+                .index
+                .get(&digest.blake3)?
+                .map(|sha256| -> Result<ReapiDigest, CasError> {
+                    Ok(ReapiDigest {
+                        hash: hex::encode(sha256),
+                        size_bytes: to_i64(digest.size_bytes)?,
+                    })
+                })
+                .transpose()?,
+        };
+
+        let (reapi_digest, data, spooled) = match indexed {
+            Some(reapi_digest) => (reapi_digest, data, None),
+            None => {
+                let (path, sha256) = self.spool(digest, data).await?;
+
+                let file = match tokio::fs::File::open(&path).await {
+                    Ok(file) => file,
+                    Err(err) => {
+                        let _ = tokio::fs::remove_file(&path).await;
+                        return Err(CasError::Io(err, Some(path)));
+                    }
+                };
+
+                self.index.insert(digest.blake3, sha256)?;
+
+                (
+                    ReapiDigest {
+                        hash: hex::encode(sha256),
+                        size_bytes: to_i64(digest.size_bytes)?,
+                    },
+                    Box::new(file) as Box<dyn AsyncRead + Send + Unpin + 'static>,
+                    Some(path),
+                )
+            }
+        };
+
+        let result = match self.is_missing(&reapi_digest).await {
+            Ok(true) => self.byte_stream_write(reapi_digest.clone(), data).await,
+            Ok(false) => Ok(()),
+            Err(err) => Err(err),
+        };
+
+        if let Some(path) = spooled {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+
+        result.map(|()| reapi_digest)
+    }
+
+    /// Copy the `data` into a temporary file while hashing it, for the blob that is too big to
+    /// buffer but whose SHA-256 has to be known before it is written.
+    async fn spool(
+        &self,
+        digest: &Digest,
+        mut data: Box<dyn AsyncRead + Send + Unpin + 'static>,
+    ) -> Result<(PathBuf, [u8; 32]), CasError> {
+        let path = std::env::temp_dir().join(format!("zako-reapi-tmp_{}", uuid::Uuid::new_v4()));
+        let io_error = |err: std::io::Error| CasError::Io(err, Some(path.clone()));
+
+        let result = async {
+            let mut file = tokio::fs::File::create(&path).await.map_err(io_error)?;
+            let mut hasher = sha2::Sha256::new();
+            let mut blake3 = ::blake3::Hasher::new();
+            let mut size = 0u64;
+            let mut buffer = vec![0u8; self.options.chunk_size.max(1)];
+
+            loop {
+                let read = data
+                    .read(&mut buffer)
+                    .await
+                    .map_err(|err| CasError::Io(err, None))?;
+
+                if read == 0 {
+                    break;
+                }
+
+                hasher.update(&buffer[..read]);
+                blake3.update(&buffer[..read]);
+                size += read as u64;
+                file.write_all(&buffer[..read]).await.map_err(io_error)?;
+            }
+
+            file.flush().await.map_err(io_error)?;
+
+            // the sha256 is indexed for the digest, so the blob has to be the one of the digest
+            let actual = Digest::new(size, blake3.finalize().into());
+
+            if actual != *digest {
+                return Err(CasError::Internal(format!(
+                    "blob {:?} of {} bytes is stored as {:?} of {} bytes",
+                    actual.blake3, actual.size_bytes, digest.blake3, digest.size_bytes
+                )));
+            }
+
+            Ok(hasher.finalize().into())
+        }
+        .await;
+
+        match result {
+            Ok(sha256) => Ok((path, sha256)),
+            Err(err) => {
+                let _ = tokio::fs::remove_file(&path).await;
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Read at most `length` bytes, fewer only at the end of the `data`.
+async fn read_chunk(data: &mut (impl AsyncRead + Unpin), length: usize) -> std::io::Result<Bytes> {
+    let mut chunk = BytesMut::zeroed(length);
+    let mut filled = 0;
+
+    while filled < length {
+        let read = data.read(&mut chunk[filled..]).await?;
+
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+
+    chunk.truncate(filled);
+    Ok(chunk.freeze())
+}
+
+fn to_i64(value: u64) -> Result<i64, CasError> {
+    i64::try_from(value).map_err(|_| CasError::Internal(format!("{} overflows i64", value)))
+}
+
+fn status_to_cas_error(status: tonic::Status, not_found: Option<&Digest>) -> CasError {
+    match (status.code(), not_found) {
+        (tonic::Code::NotFound, Some(digest)) => {
+            CasError::NotFound(*digest, PathBuf::from(status.message()))
+        }
+        _ => CasError::Internal(format!("remote error: {}", status)),
+    }
+}
+
+#[async_trait]
+impl Cas for ReapiCas {
+    async fn store(
+        &self,
+        digest: &Digest,
+        data: Box<dyn AsyncRead + Send + Unpin + 'static>,
+    ) -> Result<(), CasError> {
+        let reapi_digest = if digest.size_bytes <= self.options.max_batch_blob_size {
+            self.store_batch(digest, data).await?
+        } else {
+            self.store_stream(digest, data).await?
+        };
+
+        // the blob may be uploaded by a client that does not publish the sha256
+        if self.options.digest_function == ReapiDigestFunction::Sha256 {
+            self.publish_sha256(digest, reapi_digest).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn check(&self, digest: &Digest) -> Option<u64> {
+        let reapi_digest = match self.to_reapi_digest(digest).await {
+            Ok((reapi_digest, _)) => reapi_digest,
+            Err(CasError::NotFound(..)) => return None,
+            Err(err) => {
+                warn!("failed to check blob {:?}: {}", digest.blake3, err);
+                return None;
+            }
+        };
+
+        match self.is_missing(&reapi_digest).await {
+            Ok(missing) => (!missing).then_some(digest.size_bytes),
+            Err(err) => {
+                warn!("failed to check blob {:?}: {}", digest.blake3, err);
+                None
+            }
+        }
+    }
+
+    async fn contains(&self, digest: &Digest) -> bool {
+        self.check(digest).await.is_some()
+    }
+
+    async fn fetch(
+        &self,
+        digest: &Digest,
+        range: &BlobRange,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, CasError> {
+        let (reapi_digest, verified) = self.to_reapi_digest(digest).await?;
+
+        if range.is_out_of_span_length(digest.size_bytes) && digest.size_bytes != 0 {
+            return Err(CasError::RequestedIndexOutOfRange {
+                requested_range: *range,
+                blob_digest: *digest,
+                blob_length: digest.size_bytes,
+            });
+        }
+
+        if !verified {
+            let mut stream = Box::pin(self.verified_read(digest, &reapi_digest).await?);
+
+            if range.start() == 0 && range.length().is_none() {
+                return Ok(Box::pin(tokio_util::io::StreamReader::new(stream)));
+            }
+
+            // a range can not be hashed alone, the whole blob is read once to verify it
+            while let Some(chunk) = stream.next().await {
+                chunk.map_err(|err| CasError::Io(err, None))?;
+            }
+        }
+
+        let stream = self.read(digest, &reapi_digest, range).await?;
+
+        Ok(Box::pin(tokio_util::io::StreamReader::new(stream)))
+    }
+
+    async fn get_local_path(&self, _digest: &Digest) -> Option<PathBuf> {
+        None
+    }
+}
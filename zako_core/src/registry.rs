@@ -0,0 +1,282 @@
+//! The registry of the packages, see [Registry].
+//!
+//! A registry is a tree of files, served from a directory ([crate::local_registry::LocalRegistry])
+//! or over HTTP ([crate::http_registry::HttpRegistry]):
+//!
+//! - `index/<group>/<artifact>.json` is the [PackageIndex] of the package.
+//! - `archives/<group>/<artifact>/<version>.tar.zst` is the archive of a version, its root is the package root.
+//!
+//! The versions are added by [publish], like `zako publish`.
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use zako_id::{AnyId, Name, ParseIdError, UniqueIdReq};
+
+/// The directory of the index files.
+pub static INDEX_DIR_NAME: &str = "index";
+
+/// The directory of the archives.
+pub static ARCHIVES_DIR_NAME: &str = "archives";
+
+/// The extension of an archive in the registry.
+pub static ARCHIVE_EXTENSION: &str = "tar.zst";
+
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryError {
+    #[error("io error at {1:?}: {0}")]
+    Io(#[source] std::io::Error, PathBuf),
+    #[error("registry request to `{url}` failed: {message}")]
+    Http { url: String, message: String },
+    #[error("the index `{0}` is invalid: {1}")]
+    InvalidIndex(String, #[source] serde_json::Error),
+    #[error("`{0}` is not a package requirement like `com.example:name@^1`: {1}")]
+    InvalidRequirement(String, #[source] ParseIdError),
+    #[error("the package `{0}` is not found in the registry")]
+    PackageNotFound(String),
+    #[error("the archive of `{0}@{1}` is not found in the registry")]
+    ArchiveNotFound(String, semver::Version),
+    #[error("the version `{1}` of `{0}` is in the registry already")]
+    VersionExists(String, semver::Version),
+    #[error("the index of `{0}` keeps being changed by other publishes, try again later")]
+    IndexChanged(String),
+    #[error(
+        "the index at {0:?} is locked by another publish, remove the lock file if nobody is publishing"
+    )]
+    IndexLocked(PathBuf),
+    #[error("no version of `{package}` matches `{req}`, the available versions are [{available}]")]
+    NoMatchingVersion {
+        package: String,
+        req: semver::VersionReq,
+        available: String,
+    },
+}
+
+/// A dependency of a version in the [PackageIndex].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexDependency {
+    /// Like `com.example:name`.
+    pub package: String,
+    pub req: semver::VersionReq,
+}
+
+/// A version in the [PackageIndex].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub version: semver::Version,
+    /// The integrity of the archive, see [crate::fetch::archive::Integrity].
+    pub integrity: String,
+    #[serde(default)]
+    pub dependencies: Vec<IndexDependency>,
+    /// A yanked version is only used when it is required exactly.
+    #[serde(default)]
+    pub yanked: bool,
+}
+
+/// The versions of a package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageIndex {
+    pub group: String,
+    pub artifact: String,
+    pub versions: Vec<IndexEntry>,
+}
+
+impl PackageIndex {
+    /// The highest version that matches the `req`.
+    pub fn select(&self, req: &semver::VersionReq) -> Option<&IndexEntry> {
+        let exact = req.comparators.len() == 1 && req.comparators[0].op == semver::Op::Exact;
+
+        self.versions
+            .iter()
+            .filter(|entry| exact || !entry.yanked)
+            .filter(|entry| req.matches(&entry.version))
+            .max_by(|a, b| a.version.cmp(&b.version))
+    }
+}
+
+/// `group:artifact` of the name.
+pub fn package_name(name: &Name) -> String {
+    format!("{}:{}", group_path(name), name.name)
+}
+
+fn group_path(name: &Name) -> String {
+    name.group
+        .parts
+        .iter()
+        .map(|part| part.as_str())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// The path of the index file relative to the registry root.
+pub fn index_path(name: &Name) -> String {
+    format!("{}/{}/{}.json", INDEX_DIR_NAME, group_path(name), name.name)
+}
+
+/// The path of the archive relative to the registry root.
+pub fn archive_path(name: &Name, version: &semver::Version) -> String {
+    format!(
+        "{}/{}/{}/{}.{}",
+        ARCHIVES_DIR_NAME,
+        group_path(name),
+        name.name,
+        version,
+        ARCHIVE_EXTENSION
+    )
+}
+
+/// Parse a requirement like `com.example:name@^1`, no version means any version.
+pub fn parse_requirement(package: &str) -> Result<UniqueIdReq, RegistryError> {
+    format!("pkg:{}", package)
+        .parse::<AnyId>()
+        .map(UniqueIdReq::from)
+        .map_err(|err| RegistryError::InvalidRequirement(package.to_string(), err))
+}
+
+/// Makes the new index of a package from the current one, see [Registry::update_index].
+pub type IndexUpdate<'a> =
+    dyn Fn(Option<PackageIndex>) -> Result<PackageIndex, RegistryError> + Send + Sync + 'a;
+
+/// A source of the packages that are not in the project, so teams can host their own packages.
+#[async_trait]
+pub trait Registry: Send + Sync + 'static + std::fmt::Debug {
+    /// The index of the package, `None` if the registry does not have it.
+    async fn index(&self, name: &Name) -> Result<Option<PackageIndex>, RegistryError>;
+
+    /// Open the archive of the version.
+    async fn open_archive(
+        &self,
+        name: &Name,
+        version: &semver::Version,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, RegistryError>;
+
+    /// Store the archive of the version, see [publish].
+    ///
+    /// An archive is never replaced, [RegistryError::VersionExists] is returned if it is stored already.
+    async fn put_archive(
+        &self,
+        name: &Name,
+        version: &semver::Version,
+        archive: Vec<u8>,
+    ) -> Result<(), RegistryError>;
+
+    /// Replace the index of the package with what `update` makes of the current one, see [publish].
+    ///
+    /// The index is not changed by others in between, the `update` may be called again if it is.
+    async fn update_index(
+        &self,
+        name: &Name,
+        update: &IndexUpdate<'_>,
+    ) -> Result<(), RegistryError>;
+
+    /// The highest version that matches the requirement.
+    async fn resolve(&self, req: &UniqueIdReq) -> Result<IndexEntry, RegistryError> {
+        let package = package_name(&req.name);
+
+        let index = self
+            .index(&req.name)
+            .await?
+            .ok_or_else(|| RegistryError::PackageNotFound(package.clone()))?;
+
+        index
+            .select(&req.version)
+            .cloned()
+            .ok_or_else(|| RegistryError::NoMatchingVersion {
+                package,
+                req: req.version.clone(),
+                available: index
+                    .versions
+                    .iter()
+                    .map(|entry| entry.version.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+    }
+}
+
+/// Add the version `entry` of the package to the registry.
+///
+/// The archive is stored before the index is updated, so the index never names a missing archive.
+/// A version is never replaced, yank it in the index instead.
+///
+/// The same archive can be published again, like after a publish that fails to update the index.
+pub async fn publish(
+    registry: &dyn Registry,
+    name: &Name,
+    entry: IndexEntry,
+    archive: Vec<u8>,
+) -> Result<(), RegistryError> {
+    let package = package_name(name);
+    let exists = |index: &PackageIndex| {
+        index
+            .versions
+            .iter()
+            .any(|existing| existing.version == entry.version)
+    };
+
+    if let Some(index) = registry.index(name).await?
+        && exists(&index)
+    {
+        return Err(RegistryError::VersionExists(package, entry.version));
+    }
+
+    let digest = blake3::hash(&archive);
+
+    // only one of the racing publishes stores its archive, the index names that archive only
+    match registry.put_archive(name, &entry.version, archive).await {
+        Ok(()) => {}
+        Err(RegistryError::VersionExists(..))
+            if stored_digest(registry, name, &entry.version).await? == digest => {}
+        Err(err) => return Err(err),
+    }
+
+    // another publish may add the version after the check above
+    registry
+        .update_index(name, &|index| {
+            let mut index = index.unwrap_or_else(|| PackageIndex {
+                group: group_path(name),
+                artifact: name.name.to_string(),
+                versions: Vec::new(),
+            });
+
+            if exists(&index) {
+                return Err(RegistryError::VersionExists(
+                    package.clone(),
+                    entry.version.clone(),
+                ));
+            }
+
+            index.versions.push(entry.clone());
+            index.versions.sort_by(|a, b| a.version.cmp(&b.version));
+
+            Ok(index)
+        })
+        .await
+}
+
+/// The blake3 of the archive of the version in the registry.
+async fn stored_digest(
+    registry: &dyn Registry,
+    name: &Name,
+    version: &semver::Version,
+) -> Result<blake3::Hash, RegistryError> {
+    let path = archive_path(name, version);
+    let mut archive = registry.open_archive(name, version).await?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+
+    loop {
+        let read = archive
+            .read(&mut buffer)
+            .await
+            .map_err(|err| RegistryError::Io(err, PathBuf::from(&path)))?;
+
+        if read == 0 {
+            return Ok(hasher.finalize());
+        }
+
+        hasher.update(&buffer[..read]);
+    }
+}
@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request};
+use zako_digest::Digest;
+
+use crate::action_cache::{ActionCache, ActionCacheError, ActionResult};
+use crate::cas_token::{AUTH_TOKEN_METADATA_KEY, AUTH_TOKEN_SCHEME};
+use crate::protobuf::action_cache::{
+    GetActionResultRequest, UpdateActionResultRequest, action_cache_client::ActionCacheClient,
+};
+
+/// An [ActionCache] backed by a remote [crate::action_cache_server::ActionCacheServer].
+#[derive(Debug, Clone)]
+pub struct RemoteActionCache {
+    client: ActionCacheClient<Channel>,
+    token: Option<String>,
+}
+
+impl RemoteActionCache {
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            client: ActionCacheClient::new(channel),
+            token: None,
+        }
+    }
+
+    pub async fn connect(endpoint: &str) -> Result<Self, ActionCacheError> {
+        let channel = Endpoint::from_shared(endpoint.to_string())
+            .map_err(|err| ActionCacheError::Storage(format!("invalid endpoint: {}", err)))?
+            .connect()
+            .await
+            .map_err(|err| ActionCacheError::Storage(format!("failed to connect: {}", err)))?;
+
+        Ok(Self::new(channel))
+    }
+
+    /// Attach the token to every request, it is required when the server is behind a
+    /// [crate::cas_token::TokenInterceptor].
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    fn request<T>(&self, message: T) -> Result<Request<T>, ActionCacheError> {
+        let mut request = Request::new(message);
+
+        if let Some(token) = self.token.as_ref() {
+            let value = MetadataValue::try_from(format!("{}{}", AUTH_TOKEN_SCHEME, token))
+                .map_err(|err| ActionCacheError::Storage(format!("invalid token: {}", err)))?;
+            request
+                .metadata_mut()
+                .insert(AUTH_TOKEN_METADATA_KEY, value);
+        }
+
+        Ok(request)
+    }
+}
+
+#[async_trait]
+impl ActionCache for RemoteActionCache {
+    async fn get(&self, action_digest: &Digest) -> Result<Option<ActionResult>, ActionCacheError> {
+        let request = self.request(GetActionResultRequest {
+            action_digest: Some((*action_digest).into()),
+        })?;
+
+        match self.client.clone().get_action_result(request).await {
+            Ok(response) => Ok(Some(response.into_inner().try_into()?)),
+            Err(status) if status.code() == Code::NotFound => Ok(None),
+            Err(status) => Err(status.into()),
+        }
+    }
+
+    async fn put(
+        &self,
+        action_digest: &Digest,
+        result: &ActionResult,
+    ) -> Result<(), ActionCacheError> {
+        let request = self.request(UpdateActionResultRequest {
+            action_digest: Some((*action_digest).into()),
+            action_result: Some(result.clone().into()),
+        })?;
+
+        self.client.clone().update_action_result(request).await?;
+
+        Ok(())
+    }
+}
@@ -64,6 +64,25 @@ pub fn determine_local_cas_path(_: &System) -> PathBuf {
     ))
 }
 
+pub fn determine_package_cache_path(_: &System) -> PathBuf {
+    PathBuf::from(format!(
+        "{}/{}/{}",
+        ::dirs::cache_dir()
+            .unwrap_or(PathBuf::from("~/"))
+            .to_string_lossy(),
+        "zako",
+        "packages"
+    ))
+}
+
+/// The user config of the local dependency overrides, see [crate::overrides].
+pub fn determine_user_overrides_path(_: &System) -> PathBuf {
+    ::dirs::config_dir()
+        .unwrap_or(PathBuf::from("~/"))
+        .join("zako")
+        .join(crate::consts::USER_OVERRIDES_FILE_NAME)
+}
+
 /// Determines the CPU capacity for the resource pool.
 /// Returns the number of logical CPU cores.
 pub fn determine_cpu_capacity(system: &System) -> u64 {
@@ -0,0 +1,93 @@
+use std::hash::Hash;
+use std::sync::Arc;
+
+use tokio::sync::watch;
+use zako_shared::ConcurrentMap;
+
+/// The result of a flight, the error is shared by every caller.
+type Landing<T, E> = Option<Result<T, Arc<E>>>;
+
+/// Coordinate the concurrent operations on the same key, like storing or fetching one blob.
+///
+/// Only the first caller of a key runs its operation, the others wait for it and get its result.
+/// If the first caller is cancelled, a waiting caller runs its own operation instead.
+#[derive(Debug)]
+pub struct SingleFlight<K: Eq + Hash + Clone, T: Clone, E> {
+    flights: ConcurrentMap<K, watch::Receiver<Landing<T, E>>>,
+}
+
+impl<K: Eq + Hash + Clone, T: Clone, E> Default for SingleFlight<K, T, E> {
+    fn default() -> Self {
+        Self {
+            flights: ConcurrentMap::default(),
+        }
+    }
+}
+
+/// Removes the flight once its operation finishes, even if the caller is cancelled.
+struct FlightGuard<'a, K: Eq + Hash + Clone, T: Clone, E> {
+    flights: &'a ConcurrentMap<K, watch::Receiver<Landing<T, E>>>,
+    key: K,
+    sender: watch::Sender<Landing<T, E>>,
+}
+
+impl<K: Eq + Hash + Clone, T: Clone, E> Drop for FlightGuard<'_, K, T, E> {
+    fn drop(&mut self) {
+        // a later flight of the key may be running already
+        self.flights.remove_if(&self.key, |_, flight| {
+            flight.same_channel(&self.sender.subscribe())
+        });
+    }
+}
+
+impl<K: Eq + Hash + Clone, T: Clone, E> SingleFlight<K, T, E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The count of the keys that have a running operation.
+    pub fn in_flight(&self) -> usize {
+        self.flights.len()
+    }
+
+    pub async fn run(
+        &self,
+        key: K,
+        operation: impl Future<Output = Result<T, E>>,
+    ) -> Result<T, Arc<E>> {
+        loop {
+            let sender = match self.flights.entry(key.clone()) {
+                dashmap::Entry::Occupied(entry) => {
+                    let mut flight = entry.get().clone();
+                    drop(entry);
+
+                    // the sender is dropped without a result if the running caller is cancelled
+                    match flight
+                        .wait_for(Option::is_some)
+                        .await
+                        .map(|landed| landed.clone())
+                    {
+                        Ok(Some(result)) => return result,
+                        _ => continue,
+                    }
+                }
+                dashmap::Entry::Vacant(entry) => {
+                    let (sender, flight) = watch::channel(None);
+                    entry.insert(flight);
+                    sender
+                }
+            };
+
+            let guard = FlightGuard {
+                flights: &self.flights,
+                key,
+                sender,
+            };
+
+            let result = operation.await.map_err(Arc::new);
+            guard.sender.send_replace(Some(result.clone()));
+
+            return result;
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use tonic::transport::Server;
+use tonic::transport::server::TcpIncoming;
+
+use crate::action_cache::*;
+use crate::action_cache_server::ActionCacheServer;
+use crate::blob_handle::BlobHandle;
+use crate::cas_token::{TokenInterceptor, TokenStore};
+use crate::local_action_cache::LocalActionCache;
+use crate::path::NeutralPath;
+use crate::protobuf::action_cache::action_cache_server::ActionCacheServer as ActionCacheService;
+use crate::remote_action_cache::RemoteActionCache;
+use crate::tests::digest_of;
+
+fn temp_database() -> std::path::PathBuf {
+    std::env::temp_dir()
+        .join(format!("zako-action-cache-test-{}", uuid::Uuid::new_v4()))
+        .join("action_cache.redb")
+}
+
+fn sample_result() -> ActionResult {
+    ActionResult {
+        output_files: vec![OutputFile {
+            path: NeutralPath::try_from("out/main.o").unwrap(),
+            digest: digest_of(b"object file"),
+            is_executable: false,
+        }],
+        output_directories: vec![OutputDirectory {
+            path: NeutralPath::try_from("out/gen").unwrap(),
+            tree_digest: digest_of(b"tree"),
+        }],
+        exit_code: 0,
+        stdout: Some(BlobHandle::new_referenced(digest_of(b"stdout"))),
+        stderr: None,
+    }
+}
+
+#[tokio::test]
+async fn test_local_action_cache_round_trip() {
+    let path = temp_database();
+    let cache = LocalActionCache::open(&path).unwrap();
+    let action = digest_of(b"cc -c main.c");
+
+    assert_eq!(cache.get(&action).await.unwrap(), None);
+
+    cache.put(&action, &sample_result()).await.unwrap();
+    assert_eq!(cache.get(&action).await.unwrap(), Some(sample_result()));
+
+    drop(cache);
+    let reopened = LocalActionCache::open(&path).unwrap();
+    assert_eq!(reopened.get(&action).await.unwrap(), Some(sample_result()));
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[tokio::test]
+async fn test_action_result_referenced_digests() {
+    let result = sample_result();
+
+    assert_eq!(result.referenced_digests().count(), 3);
+    assert!(
+        result
+            .referenced_digests()
+            .any(|digest| *digest == digest_of(b"stdout"))
+    );
+}
+
+#[tokio::test]
+async fn test_remote_action_cache_round_trip() {
+    let path = temp_database();
+    let local: Arc<dyn ActionCache> = Arc::new(LocalActionCache::open(&path).unwrap());
+    let tokens = Arc::new(TokenStore::new(std::time::Duration::from_secs(60)));
+    let token = tokens.issue();
+
+    let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let address = incoming.local_addr().unwrap();
+    tokio::spawn(async move {
+        Server::builder()
+            .add_service(ActionCacheService::with_interceptor(
+                ActionCacheServer::new(local),
+                TokenInterceptor::new(tokens),
+            ))
+            .serve_with_incoming(incoming)
+            .await
+            .unwrap();
+    });
+
+    let endpoint = format!("http://{}", address);
+    let action = digest_of(b"link main.o");
+
+    let anonymous = RemoteActionCache::connect(&endpoint).await.unwrap();
+    assert!(matches!(
+        anonymous.get(&action).await,
+        Err(ActionCacheError::Remote(_))
+    ));
+
+    let cache = RemoteActionCache::connect(&endpoint)
+        .await
+        .unwrap()
+        .with_token(token);
+
+    assert_eq!(cache.get(&action).await.unwrap(), None);
+
+    cache.put(&action, &sample_result()).await.unwrap();
+    assert_eq!(cache.get(&action).await.unwrap(), Some(sample_result()));
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,197 @@
+use std::time::{Duration, SystemTime};
+
+use zako_digest::Digest;
+
+use crate::blob_range::BlobRange;
+use crate::cas::Cas;
+use crate::cas_gc::{GcOptions, collect_local_cas};
+use crate::cas_lease::{BlobLease, DEFAULT_LEASE_TTL, read_active_leases};
+use crate::cas_store::{CasStore, CasStoreOptions};
+use crate::cas_upload::UploadQueueOptions;
+use crate::local_cas::LocalCas;
+use crate::tests::{digest_of, temp_root};
+
+async fn put_old(cas: &LocalCas, data: &[u8]) -> Digest {
+    let digest = digest_of(data);
+    cas.store(&digest, Box::new(std::io::Cursor::new(data.to_vec())))
+        .await
+        .unwrap();
+
+    let old = SystemTime::now() - Duration::from_secs(60 * 60);
+    std::fs::File::open(cas.get_path_for_digest(&digest))
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+
+    digest
+}
+
+#[test]
+fn test_lease_file_lifecycle() {
+    let dir = temp_root("cas-lease");
+    let digest = digest_of(b"pinned");
+
+    let lease = BlobLease::new(&dir, DEFAULT_LEASE_TTL);
+    assert!(!lease.get_path().exists());
+
+    lease.pin(&digest).unwrap();
+    lease.pin(&digest).unwrap();
+    assert!(lease.is_pinned(&digest));
+
+    let active = read_active_leases(&dir, false).unwrap();
+    assert!(active.contains(&digest.blake3));
+    assert_eq!(active.len(), 1);
+
+    let path = lease.get_path().to_path_buf();
+    drop(lease);
+
+    assert!(!path.exists());
+    assert!(read_active_leases(&dir, false).unwrap().is_empty());
+}
+
+#[test]
+fn test_stale_leases_are_ignored() {
+    let dir = temp_root("cas-lease");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let digest = digest_of(b"stale");
+
+    // the owner has exited
+    let dead = dir.join("dead.lease");
+    std::fs::write(
+        &dead,
+        format!(
+            "# zako blob lease\npid {}\nttl 3600\n{}\n",
+            u32::MAX - 1,
+            digest.hex_blake3()
+        ),
+    )
+    .unwrap();
+
+    // the lease is expired
+    let expired = BlobLease::new(&dir, Duration::ZERO);
+    expired.pin(&digest).unwrap();
+    std::fs::File::open(expired.get_path())
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(10))
+        .unwrap();
+
+    assert!(read_active_leases(&dir, false).unwrap().is_empty());
+    assert!(dead.exists());
+
+    assert!(read_active_leases(&dir, true).unwrap().is_empty());
+    assert!(!dead.exists());
+    assert!(!expired.get_path().exists());
+}
+
+#[tokio::test]
+async fn test_gc_keeps_pinned_and_recent_blobs() {
+    let cas = LocalCas::new(temp_root("cas-lease"));
+
+    let old = put_old(&cas, b"old and unused").await;
+    let pinned = put_old(&cas, b"old but pinned").await;
+
+    let recent = digest_of(b"recent");
+    cas.store(&recent, Box::new(std::io::Cursor::new(b"recent".to_vec())))
+        .await
+        .unwrap();
+
+    let lease = BlobLease::new(&cas.get_lease_path(), DEFAULT_LEASE_TTL);
+    lease.pin(&pinned).unwrap();
+
+    let options = GcOptions {
+        max_age: Duration::from_secs(60),
+        dry_run: true,
+    };
+
+    let report = collect_local_cas(&cas, &options).unwrap();
+    assert_eq!((report.scanned, report.removed, report.pinned), (3, 1, 1));
+    assert!(cas.contains(&old).await);
+
+    let report = collect_local_cas(
+        &cas,
+        &GcOptions {
+            dry_run: false,
+            ..options
+        },
+    )
+    .unwrap();
+    assert_eq!((report.scanned, report.removed, report.pinned), (3, 1, 1));
+
+    assert!(!cas.contains(&old).await);
+    assert!(cas.contains(&pinned).await);
+    assert!(cas.contains(&recent).await);
+}
+
+#[tokio::test]
+async fn test_gc_keeps_recently_read_blobs() {
+    let cas = LocalCas::new(temp_root("cas-lease"));
+
+    let fetched = put_old(&cas, b"fetched by a build").await;
+    let linked = put_old(&cas, b"linked by a build").await;
+    let unused = put_old(&cas, b"unused").await;
+
+    cas.fetch(&fetched, &BlobRange::full()).await.unwrap();
+    cas.get_local_path(&linked).await.unwrap();
+
+    let report = collect_local_cas(
+        &cas,
+        &GcOptions {
+            max_age: Duration::from_secs(60),
+            dry_run: false,
+        },
+    )
+    .unwrap();
+    assert_eq!((report.scanned, report.removed), (3, 1));
+
+    assert!(cas.contains(&fetched).await);
+    assert!(cas.contains(&linked).await);
+    assert!(!cas.contains(&unused).await);
+}
+
+#[tokio::test]
+async fn test_cas_store_pins_touched_blobs() {
+    let root = temp_root("cas-lease");
+    let cas = LocalCas::new(root.clone());
+    let existing = put_old(&cas, b"read by the build").await;
+
+    let store = CasStore::new(
+        Box::new(LocalCas::new(root.clone())),
+        Vec::new(),
+        CasStoreOptions {
+            max_cache_capacity: 1024 * 1024,
+            max_cache_ttl: Duration::from_secs(60),
+            max_cache_tti: Duration::from_secs(60),
+            upload_queue: UploadQueueOptions::default(),
+            max_inlined_blob_size: 0,
+            lease_dir: Some(cas.get_lease_path()),
+        },
+    );
+
+    let stored = store
+        .put_bytes(b"stored by the build".to_vec())
+        .await
+        .unwrap();
+    store.read(&existing, &BlobRange::full()).await.unwrap();
+
+    let active = read_active_leases(&cas.get_lease_path(), false).unwrap();
+    assert!(active.contains(&stored.digest().blake3));
+    assert!(active.contains(&existing.blake3));
+
+    let report = collect_local_cas(
+        &cas,
+        &GcOptions {
+            max_age: Duration::ZERO,
+            dry_run: false,
+        },
+    )
+    .unwrap();
+    assert_eq!(report.removed, 0);
+
+    drop(store);
+    assert!(
+        read_active_leases(&cas.get_lease_path(), false)
+            .unwrap()
+            .is_empty()
+    );
+}
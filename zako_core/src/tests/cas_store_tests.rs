@@ -0,0 +1,391 @@
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use zako_digest::Digest;
+
+use crate::blob_range::BlobRange;
+use crate::cas::{Cas, CasError};
+use crate::cas_store::*;
+use crate::cas_upload::UploadQueueOptions;
+use crate::local_cas::LocalCas;
+use crate::memory_cas::MemoryCas;
+use crate::single_flight::SingleFlight;
+use crate::tests::{digest_of, temp_root};
+use crate::tiered_cas::{CasTier, TierAccess};
+
+fn store(root: &Path) -> CasStore {
+    CasStore::new(
+        Box::new(LocalCas::new(root.to_path_buf())),
+        Vec::new(),
+        CasStoreOptions {
+            max_cache_capacity: 1024 * 1024,
+            max_cache_ttl: Duration::from_secs(60),
+            max_cache_tti: Duration::from_secs(60),
+            upload_queue: UploadQueueOptions::default(),
+            max_inlined_blob_size: DEFAULT_MAX_INLINED_BLOB_SIZE,
+            lease_dir: None,
+        },
+    )
+}
+
+fn leftover_temp_files(root: &Path) -> usize {
+    let staging = LocalCas::new(root.to_path_buf()).get_staging_path();
+
+    // nothing is staged outside the staging directory
+    let in_root = std::fs::read_dir(root)
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with("tmp_")
+        })
+        .count();
+
+    in_root + std::fs::read_dir(staging).map_or(0, |entries| entries.count())
+}
+
+#[tokio::test]
+async fn test_put_reader_streams_big_blob() {
+    let root = temp_root("cas-store");
+    let store = store(&root);
+
+    let data: Vec<u8> = (0..=255u8).cycle().take(300 * 1024).collect();
+    let handle = store
+        .put_reader(std::io::Cursor::new(data.clone()))
+        .await
+        .unwrap();
+
+    assert_eq!(*handle.digest(), digest_of(&data));
+    assert_eq!(
+        std::fs::read(LocalCas::new(root.clone()).get_path_for_digest(handle.digest())).unwrap(),
+        data
+    );
+    assert_eq!(
+        store
+            .read(handle.digest(), &BlobRange::new(1024, Some(16)).unwrap())
+            .await
+            .unwrap(),
+        data[1024..1040]
+    );
+    assert_eq!(leftover_temp_files(&root), 0);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[tokio::test]
+async fn test_put_file_twice() {
+    let root = temp_root("cas-store");
+    let store = store(&root);
+
+    let source = root.join("source.txt");
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(&source, b"generated output").unwrap();
+
+    let first = store.put_file(&source).await.unwrap();
+    let second = store.put_file(&source).await.unwrap();
+
+    assert_eq!(first, second);
+    assert_eq!(*first.digest(), digest_of(b"generated output"));
+    assert_eq!(
+        store
+            .read(first.digest(), &BlobRange::full())
+            .await
+            .unwrap(),
+        b"generated output"
+    );
+    assert_eq!(leftover_temp_files(&root), 0);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[tokio::test]
+async fn test_put_bytes_inlines_small_blob() {
+    let root = temp_root("cas-store");
+    let store = store(&root);
+
+    let handle = store.put_bytes(b"tiny snippet".to_vec()).await.unwrap();
+
+    assert!(handle.is_inlined());
+    assert_eq!(*handle.digest(), digest_of(b"tiny snippet"));
+    assert!(!store.get_local_cas().contains(handle.digest()).await);
+    assert_eq!(
+        handle.read(&store, BlobRange::full()).await.unwrap(),
+        b"tiny snippet"
+    );
+    assert_eq!(
+        handle
+            .read(&store, BlobRange::new(5, Some(7)).unwrap())
+            .await
+            .unwrap(),
+        b"snippet"
+    );
+    assert!(
+        handle
+            .read(&store, BlobRange::new(5, Some(100)).unwrap())
+            .await
+            .is_err()
+    );
+
+    // nothing is written for the inlined blob until it is persisted
+    assert!(!root.exists());
+
+    let persisted = store.persist(&handle).await.unwrap();
+
+    assert!(!persisted.is_inlined());
+    assert_eq!(persisted, handle);
+
+    let mut fetched = Vec::new();
+    store
+        .get_local_cas()
+        .fetch(handle.digest(), &BlobRange::full())
+        .await
+        .unwrap()
+        .read_to_end(&mut fetched)
+        .await
+        .unwrap();
+    assert_eq!(fetched, b"tiny snippet");
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+fn store_with_remote(root: &Path, remote: Arc<MemoryCas>) -> CasStore {
+    CasStore::new(
+        Box::new(LocalCas::new(root.to_path_buf())),
+        vec![CasTier::new("remote", remote, TierAccess::ReadWrite)],
+        CasStoreOptions {
+            max_cache_capacity: 1024 * 1024,
+            max_cache_ttl: Duration::from_secs(60),
+            max_cache_tti: Duration::from_secs(60),
+            upload_queue: UploadQueueOptions::default(),
+            max_inlined_blob_size: DEFAULT_MAX_INLINED_BLOB_SIZE,
+            lease_dir: None,
+        },
+    )
+}
+
+#[tokio::test]
+async fn test_persisted_inlined_blob_is_uploaded() {
+    let root = temp_root("cas-store");
+    let remote = Arc::new(MemoryCas::new(None));
+    let store = store_with_remote(&root, remote.clone());
+
+    let handle = store.put_bytes(b"tiny snippet".to_vec()).await.unwrap();
+    assert!(handle.is_inlined());
+
+    store.persist(&handle).await.unwrap();
+    let report = store.flush_uploads().await;
+
+    assert_eq!(report.uploaded, 1);
+    assert!(report.failures.is_empty());
+
+    let mut fetched = Vec::new();
+    remote
+        .fetch(handle.digest(), &BlobRange::full())
+        .await
+        .unwrap()
+        .read_to_end(&mut fetched)
+        .await
+        .unwrap();
+    assert_eq!(fetched, b"tiny snippet");
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[tokio::test]
+async fn test_blobs_on_the_remote_are_not_uploaded_again() {
+    let root = temp_root("cas-store");
+    let remote = Arc::new(MemoryCas::new(None));
+    let store = store_with_remote(&root, remote.clone());
+    let data: Vec<u8> = (0..=255u8).cycle().take(64 * 1024).collect();
+
+    store.put_bytes(data.clone()).await.unwrap();
+    let report = store.flush_uploads().await;
+    assert_eq!((report.uploaded, report.present), (1, 0));
+
+    store.put_bytes(data.clone()).await.unwrap();
+    let report = store.flush_uploads().await;
+    assert_eq!((report.uploaded, report.present), (0, 1));
+
+    store.put_reader(data.as_slice()).await.unwrap();
+    let report = store.flush_uploads().await;
+    assert_eq!((report.uploaded, report.present), (0, 1));
+
+    // a blob stored by an earlier build is uploaded to a remote that does not have it
+    let other = Arc::new(MemoryCas::new(None));
+    let store = store_with_remote(&root, other.clone());
+
+    store.put_bytes(data.clone()).await.unwrap();
+    let report = store.flush_uploads().await;
+    assert_eq!((report.uploaded, report.present), (1, 0));
+    assert!(other.contains(&digest_of(&data)).await);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[tokio::test]
+async fn test_put_bytes_stores_big_blob() {
+    let root = temp_root("cas-store");
+    let store = store(&root);
+
+    let data = vec![7u8; DEFAULT_MAX_INLINED_BLOB_SIZE + 1];
+    let handle = store.put_bytes(data.clone()).await.unwrap();
+
+    assert!(!handle.is_inlined());
+    assert!(store.get_local_cas().contains(handle.digest()).await);
+    assert_eq!(handle.read(&store, BlobRange::full()).await.unwrap(), data);
+    assert_eq!(leftover_temp_files(&root), 0);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+/// Counts the fetches and slows them down, so the concurrent callers overlap.
+#[derive(Debug)]
+struct SlowCas {
+    inner: MemoryCas,
+    fetches: AtomicUsize,
+}
+
+#[async_trait]
+impl Cas for SlowCas {
+    async fn store(
+        &self,
+        digest: &Digest,
+        data: Box<dyn AsyncRead + Send + Unpin + 'static>,
+    ) -> Result<(), CasError> {
+        self.inner.store(digest, data).await
+    }
+
+    async fn check(&self, digest: &Digest) -> Option<u64> {
+        self.inner.check(digest).await
+    }
+
+    async fn contains(&self, digest: &Digest) -> bool {
+        self.inner.contains(digest).await
+    }
+
+    async fn fetch(
+        &self,
+        digest: &Digest,
+        range: &BlobRange,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, CasError> {
+        self.fetches.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        self.inner.fetch(digest, range).await
+    }
+
+    async fn get_local_path(&self, _digest: &Digest) -> Option<PathBuf> {
+        None
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_reads_fetch_the_remote_once() {
+    let root = temp_root("cas-store");
+
+    let remote = Arc::new(SlowCas {
+        inner: MemoryCas::new(None),
+        fetches: AtomicUsize::new(0),
+    });
+    let data = vec![3u8; 128 * 1024];
+    let digest = digest_of(&data);
+    remote
+        .store(&digest, Box::new(std::io::Cursor::new(data.clone())))
+        .await
+        .unwrap();
+
+    let store = Arc::new(CasStore::new(
+        Box::new(LocalCas::new(root.clone())),
+        vec![CasTier::new("remote", remote.clone(), TierAccess::ReadOnly)],
+        CasStoreOptions {
+            max_cache_capacity: 1024 * 1024,
+            max_cache_ttl: Duration::from_secs(60),
+            max_cache_tti: Duration::from_secs(60),
+            upload_queue: UploadQueueOptions::default(),
+            max_inlined_blob_size: DEFAULT_MAX_INLINED_BLOB_SIZE,
+            lease_dir: None,
+        },
+    ));
+
+    let readers = (0..8).map(|_| {
+        let store = store.clone();
+        tokio::spawn(async move { store.read(&digest, &BlobRange::full()).await.unwrap() })
+    });
+
+    for reader in readers {
+        assert_eq!(reader.await.unwrap(), data);
+    }
+
+    assert_eq!(remote.fetches.load(Ordering::SeqCst), 1);
+    assert_eq!(leftover_temp_files(&root), 0);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_single_flight_shares_one_result() {
+    let flights = Arc::new(SingleFlight::<u32, u32, String>::new());
+    let runs = Arc::new(AtomicUsize::new(0));
+
+    let tasks = (0..8)
+        .map(|_| {
+            let flights = flights.clone();
+            let runs = runs.clone();
+
+            tokio::spawn(async move {
+                flights
+                    .run(7, async {
+                        runs.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        Err("unavailable".to_string())
+                    })
+                    .await
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for task in tasks {
+        assert_eq!(
+            task.await.unwrap(),
+            Err(Arc::new("unavailable".to_string()))
+        );
+    }
+
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+    assert_eq!(flights.in_flight(), 0);
+
+    // a new flight runs again once the last one landed
+    assert_eq!(flights.run(7, async { Ok(1) }).await, Ok(1));
+}
+
+#[tokio::test]
+async fn test_single_flight_survives_a_cancelled_caller() {
+    let flights = Arc::new(SingleFlight::<u32, u32, String>::new());
+
+    let first = tokio::spawn({
+        let flights = flights.clone();
+        async move { flights.run(7, std::future::pending()).await }
+    });
+
+    while flights.in_flight() == 0 {
+        tokio::task::yield_now().await;
+    }
+
+    let second = tokio::spawn({
+        let flights = flights.clone();
+        async move { flights.run(7, async { Ok(2) }).await }
+    });
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    first.abort();
+
+    assert_eq!(second.await.unwrap(), Ok(2));
+    assert_eq!(flights.in_flight(), 0);
+}
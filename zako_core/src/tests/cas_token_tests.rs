@@ -0,0 +1,217 @@
+use std::time::Duration;
+
+use tonic::Request;
+use tonic::service::Interceptor;
+
+use crate::cas_token::*;
+
+#[test]
+fn test_issued_token_is_valid() {
+    let store = TokenStore::new(Duration::from_secs(60));
+    let token = store.issue();
+
+    assert!(store.validate(&token));
+    assert!(!store.validate("not-a-token"));
+    assert!(store.revoke(&token));
+    assert!(!store.validate(&token));
+}
+
+#[test]
+fn test_expired_token_is_rejected_and_swept() {
+    let store = TokenStore::new(Duration::ZERO);
+    let first = store.issue();
+    let _second = store.issue();
+
+    assert!(!store.validate(&first));
+    assert_eq!(store.issued_count(), 1);
+    assert_eq!(store.sweep_expired(), 1);
+    assert_eq!(store.issued_count(), 0);
+}
+
+#[test]
+fn test_static_token_never_expires() {
+    let store = TokenStore::new(Duration::ZERO).with_static_tokens(StaticTokenConfig {
+        tokens: vec!["pre-shared".to_string()],
+    });
+
+    assert!(store.validate("pre-shared"));
+    assert_eq!(store.sweep_expired(), 0);
+    assert!(!store.revoke("pre-shared"));
+    assert!(store.validate("pre-shared"));
+}
+
+#[test]
+fn test_interceptor_checks_metadata() {
+    let store = std::sync::Arc::new(TokenStore::new(Duration::from_secs(60)));
+    let token = store.issue();
+    let mut interceptor = TokenInterceptor::new(store);
+
+    assert!(interceptor.call(Request::new(())).is_err());
+
+    let mut request = Request::new(());
+    request.metadata_mut().insert(
+        AUTH_TOKEN_METADATA_KEY,
+        format!("{}{}", AUTH_TOKEN_SCHEME, token).parse().unwrap(),
+    );
+    assert!(interceptor.call(request).is_ok());
+
+    let mut request = Request::new(());
+    request
+        .metadata_mut()
+        .insert(AUTH_TOKEN_METADATA_KEY, "Bearer wrong".parse().unwrap());
+    assert!(interceptor.call(request).is_err());
+}
+
+#[tokio::test]
+async fn test_transport_details_need_a_credential() {
+    use crate::cas_server::{CasServer, CasServerOptions};
+    use crate::memory_cas::MemoryCas;
+    use crate::protobuf::cas::GetTransportDetailsRequest;
+    use crate::protobuf::cas::content_addressable_storage_server::ContentAddressableStorage;
+    use crate::protobuf::net::Protocol;
+
+    let store = std::sync::Arc::new(TokenStore::new(Duration::from_secs(60)).with_static_tokens(
+        StaticTokenConfig {
+            tokens: vec!["pre-shared".to_string()],
+        },
+    ));
+    let mut options = CasServerOptions::new_default(
+        std::sync::Arc::new(MemoryCas::new(None)),
+        "127.0.0.1:9092"
+            .parse::<std::net::SocketAddr>()
+            .unwrap()
+            .into(),
+    );
+    options.tokens = store.clone();
+    let server = CasServer::new(options);
+
+    let request = || {
+        Request::new(GetTransportDetailsRequest {
+            supported_protocols: vec![Protocol::Grpc as i32],
+        })
+    };
+
+    let status = server.get_transport_details(request()).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    assert_eq!(store.issued_count(), 0);
+
+    let mut authenticated = request();
+    authenticated.metadata_mut().insert(
+        AUTH_TOKEN_METADATA_KEY,
+        "Bearer pre-shared".parse().unwrap(),
+    );
+    let details = server
+        .get_transport_details(authenticated)
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert!(store.validate(&details.auth_token));
+
+    // an issued token can not get another one
+    let mut chained = request();
+    chained.metadata_mut().insert(
+        AUTH_TOKEN_METADATA_KEY,
+        format!("{}{}", AUTH_TOKEN_SCHEME, details.auth_token)
+            .parse()
+            .unwrap(),
+    );
+    let status = server.get_transport_details(chained).await.unwrap_err();
+    assert_eq!(status.code(), tonic::Code::Unauthenticated);
+}
+
+#[tokio::test]
+async fn test_expired_token_chain_is_refused() {
+    use crate::cas_server::{CasServer, CasServerOptions};
+    use crate::memory_cas::MemoryCas;
+    use crate::protobuf::cas::GetTransportDetailsRequest;
+    use crate::protobuf::cas::content_addressable_storage_server::ContentAddressableStorage;
+    use crate::protobuf::net::Protocol;
+
+    let store = std::sync::Arc::new(
+        TokenStore::new(Duration::from_millis(50)).with_static_tokens(StaticTokenConfig {
+            tokens: vec!["pre-shared".to_string()],
+        }),
+    );
+    let mut options = CasServerOptions::new_default(
+        std::sync::Arc::new(MemoryCas::new(None)),
+        "127.0.0.1:9092"
+            .parse::<std::net::SocketAddr>()
+            .unwrap()
+            .into(),
+    );
+    options.tokens = store.clone();
+    let server = CasServer::new(options);
+
+    let request = |token: &str| {
+        let mut request = Request::new(GetTransportDetailsRequest {
+            supported_protocols: vec![Protocol::Grpc as i32],
+        });
+        request.metadata_mut().insert(
+            AUTH_TOKEN_METADATA_KEY,
+            format!("{}{}", AUTH_TOKEN_SCHEME, token).parse().unwrap(),
+        );
+        request
+    };
+
+    let mut token = server
+        .get_transport_details(request("pre-shared"))
+        .await
+        .unwrap()
+        .into_inner()
+        .auth_token;
+
+    // renewing the token before it expires does not extend its lifetime
+    for _ in 0..3 {
+        if let Ok(details) = server.get_transport_details(request(&token)).await {
+            token = details.into_inner().auth_token;
+        }
+        tokio::time::sleep(Duration::from_millis(30)).await;
+    }
+
+    let mut interceptor = TokenInterceptor::new(store.clone());
+    let mut transport = Request::new(());
+    transport.metadata_mut().insert(
+        AUTH_TOKEN_METADATA_KEY,
+        format!("{}{}", AUTH_TOKEN_SCHEME, token).parse().unwrap(),
+    );
+    assert!(interceptor.call(transport).is_err());
+    assert_eq!(
+        server
+            .get_transport_details(request(&token))
+            .await
+            .unwrap_err()
+            .code(),
+        tonic::Code::Unauthenticated
+    );
+}
+
+#[tokio::test]
+async fn test_transport_details_without_static_tokens() {
+    use crate::cas_server::{CasServer, CasServerOptions};
+    use crate::memory_cas::MemoryCas;
+    use crate::protobuf::cas::GetTransportDetailsRequest;
+    use crate::protobuf::cas::content_addressable_storage_server::ContentAddressableStorage;
+    use crate::protobuf::net::Protocol;
+
+    let store = std::sync::Arc::new(TokenStore::new(Duration::from_secs(60)));
+    let mut options = CasServerOptions::new_default(
+        std::sync::Arc::new(MemoryCas::new(None)),
+        "127.0.0.1:9092"
+            .parse::<std::net::SocketAddr>()
+            .unwrap()
+            .into(),
+    );
+    options.tokens = store.clone();
+    let server = CasServer::new(options);
+
+    let details = server
+        .get_transport_details(Request::new(GetTransportDetailsRequest {
+            supported_protocols: vec![Protocol::Grpc as i32],
+        }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert!(store.validate(&details.auth_token));
+}
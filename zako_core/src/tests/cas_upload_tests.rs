@@ -0,0 +1,173 @@
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use zako_digest::Digest;
+use zako_shared::ConcurrentMap;
+
+use crate::blob_range::BlobRange;
+use crate::cas::{Cas, CasError};
+use crate::cas_upload::*;
+use crate::local_cas::LocalCas;
+use crate::tests::digest_of;
+
+/// A remote that fails the first `failures` stores.
+#[derive(Debug, Default)]
+struct FlakyCas {
+    failures: AtomicUsize,
+    stores: AtomicUsize,
+    blobs: ConcurrentMap<zako_digest::blake3::Hash, Vec<u8>>,
+}
+
+#[async_trait]
+impl Cas for FlakyCas {
+    async fn store(
+        &self,
+        digest: &Digest,
+        mut data: Box<dyn AsyncRead + Send + Unpin + 'static>,
+    ) -> Result<(), CasError> {
+        self.stores.fetch_add(1, Ordering::SeqCst);
+
+        if self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok()
+        {
+            return Err(CasError::Internal("remote is unavailable".to_string()));
+        }
+
+        let mut bytes = Vec::new();
+        data.read_to_end(&mut bytes).await.unwrap();
+        self.blobs.insert(digest.blake3, bytes);
+        Ok(())
+    }
+
+    async fn check(&self, digest: &Digest) -> Option<u64> {
+        self.blobs.get(&digest.blake3).map(|blob| blob.len() as u64)
+    }
+
+    async fn contains(&self, digest: &Digest) -> bool {
+        self.blobs.contains_key(&digest.blake3)
+    }
+
+    async fn fetch(
+        &self,
+        digest: &Digest,
+        _range: &BlobRange,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, CasError> {
+        Err(CasError::NotFound(*digest, PathBuf::new()))
+    }
+
+    async fn get_local_path(&self, _digest: &Digest) -> Option<PathBuf> {
+        None
+    }
+}
+
+fn options() -> UploadQueueOptions {
+    UploadQueueOptions {
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(4),
+        ..Default::default()
+    }
+}
+
+fn queue(remote: Arc<FlakyCas>, options: UploadQueueOptions) -> (UploadQueue, PathBuf) {
+    let root = std::env::temp_dir().join(format!("zako-upload-test-{}", uuid::Uuid::new_v4()));
+    let local = Arc::new(LocalCas::new(root.clone()));
+    (UploadQueue::new(remote, local, options), root)
+}
+
+#[tokio::test]
+async fn test_upload_queue_deduplicates_in_flight_digests() {
+    let remote = Arc::new(FlakyCas::default());
+    let (queue, _) = queue(remote.clone(), options());
+
+    let data = b"the same output twice".to_vec();
+    let digest = digest_of(&data);
+
+    queue.enqueue(digest, Some(data.clone().into()));
+    queue.enqueue(digest, Some(data.clone().into()));
+
+    let report = queue.flush().await;
+
+    assert_eq!(report.uploaded, 1);
+    assert_eq!(report.deduplicated, 1);
+    assert_eq!(remote.stores.load(Ordering::SeqCst), 1);
+    assert_eq!(*remote.blobs.get(&digest.blake3).unwrap(), data);
+    assert_eq!(queue.pending(), 0);
+}
+
+#[tokio::test]
+async fn test_upload_queue_retries_with_backoff() {
+    let remote = Arc::new(FlakyCas {
+        failures: AtomicUsize::new(2),
+        ..Default::default()
+    });
+    let (queue, _) = queue(remote.clone(), options());
+
+    let data = b"flaky network".to_vec();
+    queue.enqueue(digest_of(&data), Some(data.into()));
+
+    let report = queue.flush().await;
+
+    assert_eq!(report.uploaded, 1);
+    assert_eq!(report.retried, 2);
+    assert!(report.failures.is_empty());
+}
+
+#[tokio::test]
+async fn test_upload_queue_reports_failures() {
+    let remote = Arc::new(FlakyCas {
+        failures: AtomicUsize::new(usize::MAX),
+        ..Default::default()
+    });
+    let (queue, _) = queue(remote.clone(), options());
+
+    let data = b"never reaches the remote".to_vec();
+    let digest = digest_of(&data);
+    queue.enqueue(digest, Some(data.into()));
+
+    let report = queue.flush().await;
+
+    assert_eq!(report.uploaded, 0);
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].digest, digest);
+    assert_eq!(remote.stores.load(Ordering::SeqCst), 4);
+
+    // the report is reset after flushing
+    assert!(queue.flush().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_upload_queue_reads_local_cas_over_budget() {
+    let remote = Arc::new(FlakyCas::default());
+    let (queue, root) = queue(
+        remote.clone(),
+        UploadQueueOptions {
+            max_pending_bytes: 0,
+            ..options()
+        },
+    );
+
+    let data = b"too big to keep in memory".to_vec();
+    let digest = digest_of(&data);
+
+    let path = LocalCas::new(root.clone()).get_path_for_digest(&digest);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, &data).unwrap();
+
+    queue.enqueue(digest, Some(data.clone().into()));
+
+    let report = queue.flush().await;
+
+    assert_eq!(report.uploaded, 1);
+    assert_eq!(*remote.blobs.get(&digest.blake3).unwrap(), data);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+
+use zako_digest::Digest;
+
+use crate::cas::Cas;
+use crate::cas_verify::*;
+use crate::local_cas::LocalCas;
+use crate::tests::digest_of;
+
+fn temp_cas() -> LocalCas {
+    LocalCas::new(std::env::temp_dir().join(format!("zako-verify-test-{}", uuid::Uuid::new_v4())))
+}
+
+fn write_blob(cas: &LocalCas, digest: &Digest, content: &[u8]) -> PathBuf {
+    let path = cas.get_path_for_digest(digest);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn test_check_uses_canonical_layout() {
+    let cas = temp_cas();
+    let digest = digest_of(b"canonical");
+
+    assert_eq!(cas.check(&digest).await, None);
+
+    write_blob(&cas, &digest, b"canonical");
+
+    assert_eq!(cas.check(&digest).await, Some(9));
+    assert!(cas.contains(&digest).await);
+
+    std::fs::remove_dir_all(cas.get_root()).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_verify_finds_and_quarantines_corrupted_blobs() {
+    let cas = temp_cas();
+
+    write_blob(&cas, &digest_of(b"good"), b"good");
+    let bad = write_blob(&cas, &digest_of(b"original"), b"bit rot");
+    std::fs::write(cas.get_root().join("stray.txt"), b"?").unwrap();
+
+    let report = verify_local_cas(&cas, &VerifyOptions::default())
+        .await
+        .unwrap();
+
+    assert_eq!(report.checked, 2);
+    assert_eq!(report.corrupted.len(), 1);
+    assert_eq!(report.corrupted[0].path, bad);
+    assert_eq!(
+        report.corrupted[0].actual.as_deref(),
+        Some(blake3::hash(b"bit rot").to_hex().as_str())
+    );
+    assert_eq!(report.unrecognized, vec![cas.get_root().join("stray.txt")]);
+    assert!(bad.exists());
+
+    let report = verify_local_cas(
+        &cas,
+        &VerifyOptions {
+            repair: true,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let quarantined_to = report.corrupted[0].quarantined_to.clone().unwrap();
+    assert!(!bad.exists());
+    assert!(quarantined_to.starts_with(cas.get_quarantine_path()));
+    assert_eq!(std::fs::read(quarantined_to).unwrap(), b"bit rot");
+
+    let report = verify_local_cas(&cas, &VerifyOptions::default())
+        .await
+        .unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.checked, 1);
+
+    std::fs::remove_dir_all(cas.get_root()).unwrap();
+}
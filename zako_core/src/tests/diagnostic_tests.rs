@@ -0,0 +1,45 @@
+use crate::diagnostic::{Diagnostic, Diagnostics};
+
+#[test]
+fn test_render_diagnostic() {
+    let content = "group = \"a\"\n\n[config.1bad]\n";
+    let span = content.find("1bad").unwrap();
+    let diagnostic = Diagnostic::new("the key is invalid", "/project/zako.toml")
+        .with_span(content, span..span + 4)
+        .with_label("here");
+
+    let rendered = diagnostic.to_string();
+
+    assert!(rendered.contains("the key is invalid"), "{}", rendered);
+    assert!(rendered.contains("/project/zako.toml:3:9"), "{}", rendered);
+    assert!(rendered.contains("3 │ [config.1bad]"), "{}", rendered);
+    assert!(rendered.contains("here"), "{}", rendered);
+}
+
+#[test]
+fn test_render_diagnostics() {
+    let diagnostics = Diagnostics(vec![
+        Diagnostic::new("first", "zako.json"),
+        Diagnostic::new("second", "zako.json"),
+    ]);
+
+    let rendered = diagnostics.to_string();
+    let (first, second) = rendered.split_once("\n\n").unwrap();
+
+    assert!(first.contains("first") && first.contains("in zako.json"));
+    assert!(second.contains("second") && second.contains("in zako.json"));
+}
+
+#[test]
+fn test_location_of_multibyte_span() {
+    let content = "a = \"é\"\nb = \"\u{4e2d}\u{6587}\"\n";
+    let start = content.find('\u{4e2d}').unwrap();
+    let location = Diagnostic::new("", "zako.toml")
+        .with_span(content, start..content.len())
+        .get_location()
+        .unwrap();
+
+    assert_eq!(location.line, 2);
+    assert_eq!(location.column, 6);
+    assert_eq!(location.width, 3);
+}
@@ -0,0 +1,395 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use zako_digest::Digest;
+
+use crate::fetch::archive::{ArchiveFormat, Integrity, fetch_archive};
+use crate::fetch::{FetchError, PackageCache};
+use crate::package_source::PackageSource;
+use crate::tests::http_stand_in::HttpStandIn;
+use crate::tests::{memory_store, temp_root};
+
+pub enum Entry {
+    File(&'static str, u32, &'static [u8]),
+    Directory(&'static str),
+    Symlink(&'static str, &'static str),
+}
+
+/// The entries of every test archive.
+pub fn package_entries() -> Vec<Entry> {
+    vec![
+        Entry::Directory("pkg-1.0/"),
+        Entry::File(
+            "pkg-1.0/zako.toml",
+            0o600,
+            b"group = \"test\"\nartifact = \"http\"\nversion = \"1.0.0\"\n",
+        ),
+        Entry::File("pkg-1.0/bin/run", 0o700, b"#!/bin/sh\n"),
+        Entry::Symlink("pkg-1.0/run", "bin/run"),
+    ]
+}
+
+fn tar_header(path: &str, mode: u32, kind: u8, size: usize, link: &str) -> [u8; 512] {
+    let mut header = [0u8; 512];
+    header[..path.len()].copy_from_slice(path.as_bytes());
+    header[100..108].copy_from_slice(format!("{:07o}\0", mode).as_bytes());
+    header[124..136].copy_from_slice(format!("{:011o}\0", size).as_bytes());
+    header[156] = kind;
+    header[157..157 + link.len()].copy_from_slice(link.as_bytes());
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|byte| u32::from(*byte)).sum();
+    header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+
+    header
+}
+
+pub fn tar(entries: &[Entry]) -> Vec<u8> {
+    let mut tar = Vec::new();
+
+    for entry in entries {
+        match entry {
+            Entry::File(path, mode, data) => {
+                tar.extend(tar_header(path, *mode, b'0', data.len(), ""));
+                tar.extend(*data);
+                tar.resize(tar.len().div_ceil(512) * 512, 0);
+            }
+            Entry::Directory(path) => tar.extend(tar_header(path, 0o755, b'5', 0, "")),
+            Entry::Symlink(path, target) => tar.extend(tar_header(path, 0o777, b'2', 0, target)),
+        }
+    }
+
+    tar.extend([0u8; 1024]);
+    tar
+}
+
+fn tar_gz(entries: &[Entry]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&tar(entries)).unwrap();
+    encoder.finish().unwrap()
+}
+
+pub fn tar_zst(entries: &[Entry]) -> Vec<u8> {
+    zstd::encode_all(tar(entries).as_slice(), 0).unwrap()
+}
+
+fn zip(entries: &[Entry]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+
+    for entry in entries {
+        match entry {
+            Entry::File(path, mode, data) => {
+                let options = zip::write::SimpleFileOptions::default().unix_permissions(*mode);
+                zip.start_file(*path, options).unwrap();
+                zip.write_all(data).unwrap();
+            }
+            Entry::Directory(path) => zip
+                .add_directory(*path, zip::write::SimpleFileOptions::default())
+                .unwrap(),
+            Entry::Symlink(path, target) => zip
+                .add_symlink(*path, *target, zip::write::SimpleFileOptions::default())
+                .unwrap(),
+        }
+    }
+
+    zip.finish().unwrap().into_inner()
+}
+
+fn sha256(data: &[u8]) -> String {
+    use sha2::Digest as _;
+    format!("sha256:{}", hex::encode(sha2::Sha256::digest(data)))
+}
+
+fn blake3(data: &[u8]) -> String {
+    format!("blake3:{}", ::blake3::hash(data).to_hex())
+}
+
+/// Serve the `files` and return the base url.
+async fn serve(files: Vec<(&str, Vec<u8>)>) -> (HttpStandIn, String) {
+    let server = HttpStandIn::default();
+    for (path, content) in files {
+        server.files.insert(path.to_string(), content);
+    }
+    let url = server.clone().start().await;
+    (server, url)
+}
+
+fn list_tree(root: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut tree = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path
+                .strip_prefix(root)
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+            let metadata = path.symlink_metadata().unwrap();
+
+            if metadata.is_symlink() {
+                let target = std::fs::read_link(&path).unwrap();
+                tree.push((name, target.to_string_lossy().as_bytes().to_vec()));
+            } else if metadata.is_dir() {
+                pending.push(path);
+            } else {
+                tree.push((name, std::fs::read(&path).unwrap()));
+            }
+        }
+    }
+
+    tree.sort();
+    tree
+}
+
+#[test]
+fn test_archive_format_and_integrity() {
+    assert_eq!(
+        ArchiveFormat::from_url("https://example.com/a.tar.gz?token=1"),
+        Some(ArchiveFormat::TarGz)
+    );
+    assert_eq!(
+        ArchiveFormat::from_url("https://example.com/a.tar.zst"),
+        Some(ArchiveFormat::TarZst)
+    );
+    assert_eq!(
+        ArchiveFormat::from_url("https://example.com/a.zip"),
+        Some(ArchiveFormat::Zip)
+    );
+    assert_eq!(ArchiveFormat::from_url("https://example.com/a.rar"), None);
+
+    let hex = "AB".repeat(32);
+    let integrity: Integrity = format!("sha256:{}", hex).parse().unwrap();
+    assert_eq!(integrity, Integrity::Sha256("ab".repeat(32)));
+    assert_eq!(integrity.to_string(), format!("sha256:{}", "ab".repeat(32)));
+
+    assert!("md5:00".parse::<Integrity>().is_err());
+    assert!(
+        format!("blake3:{}", "zz".repeat(32))
+            .parse::<Integrity>()
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_fetch_tar_gz_with_strip_prefix() {
+    let root = temp_root("fetch-archive");
+    let archive = tar_gz(&package_entries());
+    let (_server, base) = serve(vec![("/pkg-1.0.tar.gz", archive.clone())]).await;
+
+    let cache = PackageCache::new(root.clone());
+    let cas = memory_store();
+
+    let fetched = cache
+        .fetch(
+            &PackageSource::Http {
+                url: format!("{}/pkg-1.0.tar.gz", base).into(),
+                integrity: Some(sha256(&archive).into()),
+                strip_prefix: Some("pkg-1.0".into()),
+            },
+            &cas,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        fetched.root,
+        cache
+            .get_archives_path()
+            .join(::blake3::hash(&archive).to_hex().as_str())
+            .join("pkg-1.0")
+    );
+    assert!(
+        std::fs::read_to_string(fetched.root.join("zako.toml"))
+            .unwrap()
+            .contains("1.0.0")
+    );
+    assert_eq!(
+        std::fs::read_link(fetched.root.join("run")).unwrap(),
+        PathBuf::from("bin/run")
+    );
+
+    // the recorded integrity is the blake3 of the archive
+    let PackageSource::Http { integrity, .. } = &fetched.pinned else {
+        panic!("not an http source: {:?}", fetched.pinned);
+    };
+    assert_eq!(integrity.as_deref(), Some(blake3(&archive).as_str()));
+
+    let digest = Digest::new(archive.len() as u64, ::blake3::hash(&archive).into());
+    assert!(cas.get_local_cas().contains(&digest).await);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = |path: &str| {
+            std::fs::metadata(fetched.root.join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(mode("bin/run"), 0o755);
+        assert_eq!(mode("zako.toml"), 0o644);
+    }
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_every_format_unpacks_the_same_tree() {
+    let root = temp_root("fetch-archive");
+    let entries = package_entries();
+    let (_server, base) = serve(vec![
+        ("/pkg.tar.gz", tar_gz(&entries)),
+        ("/pkg.tar.zst", tar_zst(&entries)),
+        ("/pkg.zip", zip(&entries)),
+    ])
+    .await;
+
+    let cache = PackageCache::new(root.clone());
+    let cas = memory_store();
+
+    let mut trees = Vec::new();
+    for name in ["pkg.tar.gz", "pkg.tar.zst", "pkg.zip"] {
+        let unpacked = fetch_archive(&cache, &cas, &format!("{}/{}", base, name), None)
+            .await
+            .unwrap();
+        trees.push(list_tree(&unpacked.path));
+    }
+
+    assert_eq!(trees[0].len(), 3);
+    assert_eq!(trees[0], trees[1]);
+    assert_eq!(trees[0], trees[2]);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_integrity_mismatch_and_offline_reuse() {
+    let root = temp_root("fetch-archive");
+    let archive = tar_zst(&package_entries());
+    let (server, base) = serve(vec![("/pkg.tar.zst", archive.clone())]).await;
+    let url = format!("{}/pkg.tar.zst", base);
+
+    let cache = PackageCache::new(root.clone());
+    let cas = memory_store();
+
+    let wrong: Integrity = blake3(b"something else").parse().unwrap();
+    let result = fetch_archive(&cache, &cas, &url, Some(&wrong)).await;
+    assert!(matches!(result, Err(FetchError::IntegrityMismatch { .. })));
+    assert!(!cache.get_archives_path().exists());
+
+    let pinned: Integrity = blake3(&archive).parse().unwrap();
+    let unpacked = fetch_archive(&cache, &cas, &url, Some(&pinned))
+        .await
+        .unwrap();
+
+    // the server is gone but the pinned archive is unpacked already
+    server.files.clear();
+    let again = fetch_archive(&cache, &cas, &url, Some(&pinned))
+        .await
+        .unwrap();
+    assert_eq!(again, unpacked);
+
+    let result = fetch_archive(&cache, &cas, &url, None).await;
+    assert!(matches!(
+        result,
+        Err(FetchError::HttpStatus { status: 404, .. })
+    ));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_refuse_entries_outside_of_the_archive() {
+    let root = temp_root("fetch-archive");
+    let (_server, base) = serve(vec![
+        (
+            "/escape.tar.gz",
+            tar_gz(&[Entry::File("pkg/../../evil", 0o644, b"evil")]),
+        ),
+        (
+            "/link.tar.gz",
+            tar_gz(&[Entry::Symlink("pkg/link", "../../etc/passwd")]),
+        ),
+        // every link stays inside alone, but the chain escapes
+        (
+            "/chain.tar.gz",
+            tar_gz(&[
+                Entry::Symlink("pkg/t", "."),
+                Entry::Symlink("pkg/s", "t/t/t/../../.."),
+            ]),
+        ),
+        (
+            "/chain.zip",
+            zip(&[
+                Entry::Symlink("pkg/s", "t/t/t/../../.."),
+                Entry::Symlink("pkg/t", "."),
+            ]),
+        ),
+        (
+            "/nested.tar.gz",
+            tar_gz(&[
+                Entry::Symlink("pkg/t", "."),
+                Entry::Symlink("pkg/t/t/t/s", "../../.."),
+            ]),
+        ),
+        ("/pkg.rar", Vec::new()),
+    ])
+    .await;
+
+    let cache = PackageCache::new(root.join("cache"));
+    let cas = memory_store();
+
+    for name in [
+        "escape.tar.gz",
+        "link.tar.gz",
+        "chain.tar.gz",
+        "chain.zip",
+        "nested.tar.gz",
+    ] {
+        let result = fetch_archive(&cache, &cas, &format!("{}/{}", base, name), None).await;
+        assert!(matches!(result, Err(FetchError::Unpack(..))), "{}", name);
+    }
+
+    assert!(!root.join("evil").exists());
+    assert!(!cache.get_archives_path().exists());
+
+    let result = fetch_archive(&cache, &cas, &format!("{}/pkg.rar", base), None).await;
+    assert!(matches!(result, Err(FetchError::UnknownArchiveFormat(_))));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_refuse_corrupted_tar_headers() {
+    let root = temp_root("fetch-archive");
+
+    // the checksum of the first header does not match anymore
+    let mut corrupted = tar(&package_entries());
+    corrupted[0] = b'x';
+
+    let (_server, base) = serve(vec![(
+        "/pkg.tar.zst",
+        zstd::encode_all(corrupted.as_slice(), 0).unwrap(),
+    )])
+    .await;
+
+    let cache = PackageCache::new(root.join("cache"));
+    let result = fetch_archive(
+        &cache,
+        &memory_store(),
+        &format!("{}/pkg.tar.zst", base),
+        None,
+    )
+    .await;
+
+    assert!(matches!(result, Err(FetchError::Unpack(..))));
+    assert!(!cache.get_archives_path().exists());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
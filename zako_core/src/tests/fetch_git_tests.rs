@@ -0,0 +1,119 @@
+use std::path::Path;
+
+use crate::fetch::git::fetch_git;
+use crate::fetch::{FetchError, PackageCache};
+use crate::package_source::PackageSource;
+use crate::tests::{Origin, git, memory_store, temp_root};
+
+fn manifest(path: &Path) -> String {
+    std::fs::read_to_string(path.join("zako.toml")).unwrap()
+}
+
+#[tokio::test]
+async fn test_fetch_default_branch() {
+    let root = temp_root("fetch-git");
+    let origin = Origin::new(&root);
+    let commit = origin.commit("1.0.0");
+
+    let cache = PackageCache::new(root.join("cache"));
+    let fetched = cache
+        .fetch(
+            &PackageSource::Git {
+                repo: origin.url().into(),
+                checkout: None,
+            },
+            &memory_store(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        fetched.pinned,
+        PackageSource::Git {
+            repo: origin.url().into(),
+            checkout: Some(commit.clone().into()),
+        }
+    );
+    assert_eq!(fetched.root, cache.get_git_checkouts_path().join(&commit));
+    assert!(manifest(&fetched.root).contains("1.0.0"));
+    assert!(!fetched.root.join(".git").exists());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_fetch_follows_branch_and_tag() {
+    let root = temp_root("fetch-git");
+    let origin = Origin::new(&root);
+    let first = origin.commit("1.0.0");
+    git(&origin.work, &["tag", "v1"]);
+    git(&origin.work, &["push", "--quiet", "origin", "v1"]);
+
+    let cache = PackageCache::new(root.join("cache"));
+    let url = origin.url();
+
+    let fetched = fetch_git(&cache, &url, Some("main")).await.unwrap();
+    assert_eq!(fetched.commit, first);
+
+    let second = origin.commit("2.0.0");
+
+    // the branch is fetched again and moves on, the tag stays
+    let fetched = fetch_git(&cache, &url, Some("main")).await.unwrap();
+    assert_eq!(fetched.commit, second);
+    assert!(manifest(&fetched.path).contains("2.0.0"));
+
+    let tagged = fetch_git(&cache, &url, Some("v1")).await.unwrap();
+    assert_eq!(tagged.commit, first);
+    assert!(manifest(&tagged.path).contains("1.0.0"));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_pinned_commit_needs_no_origin() {
+    let root = temp_root("fetch-git");
+    let origin = Origin::new(&root);
+    let first = origin.commit("1.0.0");
+    let second = origin.commit("2.0.0");
+
+    let cache = PackageCache::new(root.join("cache"));
+    let url = origin.url();
+
+    let fetched = fetch_git(&cache, &url, Some(&second)).await.unwrap();
+    assert_eq!(fetched.commit, second);
+
+    std::fs::remove_dir_all(&origin.bare).unwrap();
+
+    // checked out already
+    let again = fetch_git(&cache, &url, Some(&second)).await.unwrap();
+    assert_eq!(again, fetched);
+
+    // mirrored but not checked out yet
+    let older = fetch_git(&cache, &url, Some(&first)).await.unwrap();
+    assert!(manifest(&older.path).contains("1.0.0"));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_missing_reference() {
+    let root = temp_root("fetch-git");
+    let origin = Origin::new(&root);
+    origin.commit("1.0.0");
+
+    let cache = PackageCache::new(root.join("cache"));
+    let result = fetch_git(&cache, &origin.url(), Some("no-such-branch")).await;
+
+    assert!(matches!(
+        result,
+        Err(FetchError::ReferenceNotFound { reference, .. }) if reference == "no-such-branch"
+    ));
+    assert!(
+        std::fs::read_dir(cache.get_staging_path())
+            .map(|entries| entries.count())
+            .unwrap_or(0)
+            == 0
+    );
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
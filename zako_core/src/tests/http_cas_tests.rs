@@ -0,0 +1,110 @@
+use tokio::io::AsyncReadExt;
+use url::Url;
+use zako_digest::Digest;
+
+use crate::blob_range::BlobRange;
+use crate::cas::{Cas, CasError};
+use crate::http_cas::*;
+use crate::tests::digest_of;
+use crate::tests::http_stand_in::HttpStandIn;
+
+async fn read_all(cas: &HttpCas, digest: &Digest, range: &BlobRange) -> Vec<u8> {
+    let mut reader = cas.fetch(digest, range).await.unwrap();
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await.unwrap();
+    data
+}
+
+async fn start(server: HttpStandIn) -> HttpCas {
+    let base = server.start().await;
+    HttpCas::new(
+        Url::parse(&format!("{}/team-cache", base)).unwrap(),
+        HttpCasOptions::default(),
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_http_cas_round_trip() {
+    let server = HttpStandIn::default();
+    let cas = start(server.clone()).await;
+
+    let data = b"plain old http".to_vec();
+    let digest = digest_of(&data);
+
+    assert!(!cas.contains(&digest).await);
+    assert!(matches!(
+        cas.fetch(&digest, &BlobRange::full()).await,
+        Err(CasError::NotFound(..))
+    ));
+
+    cas.store(&digest, Box::new(std::io::Cursor::new(data.clone())))
+        .await
+        .unwrap();
+
+    assert!(
+        server
+            .files
+            .contains_key(&format!("/team-cache/cas/{}", digest.blake3.to_hex()))
+    );
+    assert_eq!(cas.check(&digest).await, Some(data.len() as u64));
+    assert_eq!(read_all(&cas, &digest, &BlobRange::full()).await, data);
+}
+
+#[tokio::test]
+async fn test_http_cas_range_request() {
+    let cas = start(HttpStandIn::default()).await;
+
+    let data = b"0123456789".to_vec();
+    let digest = digest_of(&data);
+
+    cas.store(&digest, Box::new(std::io::Cursor::new(data)))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        read_all(&cas, &digest, &BlobRange::new(2, Some(3)).unwrap()).await,
+        b"234"
+    );
+    assert_eq!(
+        read_all(&cas, &digest, &BlobRange::new(7, None).unwrap()).await,
+        b"789"
+    );
+    assert!(matches!(
+        cas.fetch(&digest, &BlobRange::new(8, Some(5)).unwrap())
+            .await,
+        Err(CasError::RequestedIndexOutOfRange { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_http_cas_server_ignores_range() {
+    let cas = start(HttpStandIn {
+        ignore_range: true,
+        ..Default::default()
+    })
+    .await;
+
+    let data = b"0123456789".to_vec();
+    let digest = digest_of(&data);
+
+    cas.store(&digest, Box::new(std::io::Cursor::new(data)))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        read_all(&cas, &digest, &BlobRange::new(4, Some(2)).unwrap()).await,
+        b"45"
+    );
+}
+
+#[test]
+fn test_http_cas_rejects_other_schemes() {
+    assert!(
+        HttpCas::new(
+            Url::parse("grpc://localhost:9092").unwrap(),
+            HttpCasOptions::default()
+        )
+        .is_err()
+    );
+}
@@ -0,0 +1,152 @@
+//! A tiny HTTP/1.1 server for tests.
+//!
+//! It serves `GET`/`HEAD`/`PUT` on an in-memory map from the request path to the content,
+//! `GET` supports a single `Range: bytes=<start>-[<end>]`.
+//! The `ETag` is the blake3 of the content, `PUT` checks `If-Match` and `If-None-Match: *`.
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use zako_shared::ConcurrentMap;
+
+#[derive(Debug, Clone, Default)]
+pub struct HttpStandIn {
+    pub files: Arc<ConcurrentMap<String, Vec<u8>>>,
+    /// Ignore the `Range` header like some simple servers do.
+    pub ignore_range: bool,
+}
+
+impl HttpStandIn {
+    /// Serve in a background task, returns the base url like `http://127.0.0.1:1234`.
+    pub async fn start(self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address: SocketAddr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let server = self.clone();
+                tokio::spawn(async move {
+                    let _ = server.handle(stream).await;
+                });
+            }
+        });
+
+        format!("http://{}", address)
+    }
+
+    async fn handle(&self, stream: TcpStream) -> std::io::Result<()> {
+        let mut stream = BufReader::new(stream);
+
+        loop {
+            let mut request_line = String::new();
+            if stream.read_line(&mut request_line).await? == 0 {
+                return Ok(());
+            }
+
+            let mut parts = request_line.split_whitespace();
+            let method = parts.next().unwrap_or_default().to_string();
+            let path = parts.next().unwrap_or_default().to_string();
+
+            let mut content_length = 0usize;
+            let mut range = None;
+            let mut if_match = None;
+            let mut if_none_match = None;
+
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await?;
+                let line = line.trim_end();
+
+                if line.is_empty() {
+                    break;
+                }
+
+                let Some((name, value)) = line.split_once(':') else {
+                    continue;
+                };
+                let value = value.trim();
+
+                match name.to_ascii_lowercase().as_str() {
+                    "content-length" => content_length = value.parse().unwrap_or(0),
+                    "range" => range = value.strip_prefix("bytes=").map(str::to_string),
+                    "if-match" => if_match = Some(value.to_string()),
+                    "if-none-match" => if_none_match = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+
+            let mut etag = None;
+
+            let (status, body) = match method.as_str() {
+                "PUT" => {
+                    let mut body = vec![0u8; content_length];
+                    stream.read_exact(&mut body).await?;
+
+                    let entry = self.files.entry(path);
+                    let current = match &entry {
+                        dashmap::Entry::Occupied(entry) => Some(etag_of(entry.get())),
+                        dashmap::Entry::Vacant(_) => None,
+                    };
+
+                    let matched = if_match.is_none_or(|expected| current == Some(expected))
+                        && (if_none_match.as_deref() != Some("*") || current.is_none());
+
+                    if matched {
+                        entry.insert(body);
+                        ("201 Created", Vec::new())
+                    } else {
+                        ("412 Precondition Failed", Vec::new())
+                    }
+                }
+                "GET" | "HEAD" => match self.files.get(&path) {
+                    None => ("404 Not Found", Vec::new()),
+                    Some(content) => match range.filter(|_| !self.ignore_range) {
+                        None => {
+                            etag = Some(etag_of(&content));
+                            ("200 OK", content.clone())
+                        }
+                        Some(range) => {
+                            let (start, end) = range.split_once('-').unwrap();
+                            let start: usize = start.parse().unwrap();
+                            let end: usize = if end.is_empty() {
+                                content.len()
+                            } else {
+                                end.parse::<usize>().unwrap() + 1
+                            };
+
+                            if start >= content.len() || end > content.len() {
+                                ("416 Range Not Satisfiable", Vec::new())
+                            } else {
+                                ("206 Partial Content", content[start..end].to_vec())
+                            }
+                        }
+                    },
+                },
+                _ => ("405 Method Not Allowed", Vec::new()),
+            };
+
+            let etag = etag
+                .map(|etag| format!("etag: {}\r\n", etag))
+                .unwrap_or_default();
+            let head = format!(
+                "HTTP/1.1 {}\r\ncontent-length: {}\r\n{}\r\n",
+                status,
+                body.len(),
+                etag
+            );
+            stream.get_mut().write_all(head.as_bytes()).await?;
+
+            if method != "HEAD" {
+                stream.get_mut().write_all(&body).await?;
+            }
+
+            stream.get_mut().flush().await?;
+        }
+    }
+}
+
+fn etag_of(content: &[u8]) -> String {
+    format!("\"{}\"", blake3::hash(content).to_hex())
+}
@@ -0,0 +1,256 @@
+use std::path::PathBuf;
+
+use crate::fetch::PackageCache;
+use crate::lockfile::{LockState, Lockfile, LockfileError, update};
+use crate::package_source::PackageSource;
+use crate::tests::{Origin, memory_store, temp_root};
+
+fn git(repo: &str, checkout: Option<&str>) -> PackageSource {
+    PackageSource::Git {
+        repo: repo.into(),
+        checkout: checkout.map(Into::into),
+    }
+}
+
+fn registry(package: &str) -> PackageSource {
+    PackageSource::Registry {
+        package: package.to_string(),
+    }
+}
+
+#[test]
+fn test_lockfile_is_deterministic() {
+    let mut first = Lockfile::default();
+    assert!(first.insert(registry("test.pkgs:b@^1"), registry("test.pkgs:b@1.2.0")));
+    assert!(first.insert(
+        git("https://example.com/a.git", None),
+        git("https://example.com/a.git", Some("0123"))
+    ));
+    assert!(first.insert(
+        PackageSource::Http {
+            url: "https://example.com/c.tar.gz".into(),
+            integrity: None,
+            strip_prefix: Some("c-1.0".into()),
+        },
+        PackageSource::Http {
+            url: "https://example.com/c.tar.gz".into(),
+            integrity: Some("blake3:00".into()),
+            strip_prefix: Some("c-1.0".into()),
+        },
+    ));
+
+    let mut second = Lockfile::default();
+    for package in first.packages.iter().rev() {
+        second.insert(package.requested.clone(), package.pinned.clone());
+    }
+
+    let content = first.to_toml().unwrap();
+    assert_eq!(content, second.to_toml().unwrap());
+    assert!(content.starts_with("# "));
+
+    let path = PathBuf::from("zako.lock");
+    let parsed = Lockfile::parse(&path, &content).unwrap();
+    assert_eq!(parsed.packages.len(), 3);
+    assert_eq!(
+        parsed.get(&registry("test.pkgs:b@^1")),
+        Some(&registry("test.pkgs:b@1.2.0"))
+    );
+    assert_eq!(parsed.to_toml().unwrap(), content);
+
+    assert!(matches!(
+        Lockfile::parse(&path, "version = 2\n"),
+        Err(LockfileError::UnsupportedVersion(2))
+    ));
+}
+
+#[test]
+fn test_insert_and_remove() {
+    let mut lockfile = Lockfile::default();
+    let requested = registry("test.pkgs:lib");
+
+    assert!(lockfile.insert(requested.clone(), registry("test.pkgs:lib@1.0.0")));
+    assert!(!lockfile.insert(requested.clone(), registry("test.pkgs:lib@1.0.0")));
+    assert!(lockfile.insert(requested.clone(), registry("test.pkgs:lib@1.1.0")));
+    assert_eq!(lockfile.packages.len(), 1);
+
+    assert_eq!(
+        lockfile.remove(&requested),
+        Some(registry("test.pkgs:lib@1.1.0"))
+    );
+    assert_eq!(lockfile.remove(&requested), None);
+
+    let missing = temp_root("lockfile").join("zako.lock");
+    assert_eq!(Lockfile::load(&missing).unwrap(), Lockfile::default());
+}
+
+#[test]
+fn test_locked_state() {
+    let requested = registry("test.pkgs:lib");
+    let pinned = registry("test.pkgs:lib@1.0.0");
+
+    let state = LockState::default();
+    assert_eq!(state.pinned(&requested).unwrap(), None);
+    state.record(requested.clone(), pinned.clone()).unwrap();
+    assert!(state.is_changed());
+    assert_eq!(state.pinned(&requested).unwrap(), Some(pinned.clone()));
+
+    // the pinned package is fetched again, nothing changes
+    state.reset(state.snapshot(), true);
+    state.record(requested.clone(), pinned.clone()).unwrap();
+    assert!(!state.is_changed());
+
+    let other = registry("test.pkgs:other");
+    assert!(matches!(
+        state.pinned(&other),
+        Err(LockfileError::Outdated(_))
+    ));
+    assert!(matches!(
+        state.record(requested, registry("test.pkgs:lib@1.1.0")),
+        Err(LockfileError::Outdated(_))
+    ));
+    assert!(!state.is_changed());
+}
+
+#[tokio::test]
+async fn test_update_moves_the_pin() {
+    let root = temp_root("lockfile");
+    let origin = Origin::new(&root);
+    let first = origin.commit("1.0.0");
+
+    let cache = PackageCache::new(root.join("cache"));
+    let cas = memory_store();
+    let requested = git(&origin.url(), Some("main"));
+    let dependencies = [(root.clone(), requested.clone())];
+    let refreshed = [requested.clone()];
+
+    let mut lockfile = Lockfile::default();
+    assert!(
+        update(
+            &mut lockfile,
+            &cache,
+            &cas,
+            dependencies.clone(),
+            &refreshed
+        )
+        .await
+        .unwrap()
+    );
+    assert_eq!(
+        lockfile.get(&requested),
+        Some(&git(&origin.url(), Some(&first)))
+    );
+
+    // the pinned commit is kept until the branch moves
+    assert!(
+        !update(
+            &mut lockfile,
+            &cache,
+            &cas,
+            dependencies.clone(),
+            &refreshed
+        )
+        .await
+        .unwrap()
+    );
+
+    let second = origin.commit("1.1.0");
+    let path = root.join("zako.lock");
+    lockfile.write(&path).unwrap();
+
+    let mut lockfile = Lockfile::load(&path).unwrap();
+    assert!(
+        update(
+            &mut lockfile,
+            &cache,
+            &cas,
+            dependencies.clone(),
+            &refreshed
+        )
+        .await
+        .unwrap()
+    );
+    assert_eq!(
+        lockfile.get(&requested),
+        Some(&git(&origin.url(), Some(&second)))
+    );
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_update_walks_the_graph() {
+    let root = temp_root("lockfile");
+    let lib = Origin::new(&root.join("lib"));
+    let app = Origin::new(&root.join("app"));
+
+    let first = lib.commit("1.0.0");
+    let lib_source = git(&lib.url(), Some("main"));
+    let app_commit = app.commit_manifest(&format!(
+        "group = \"test\"\nartifact = \"app\"\nversion = \"1.0.0\"\n\n[dependencies.lib]\nrepo = \"{}\"\ncheckout = \"main\"\n",
+        lib.url()
+    ));
+    let app_source = git(&app.url(), Some("main"));
+
+    // the workspace reaches the app through a path dependency
+    let member = root.join("workspace").join("member");
+    std::fs::create_dir_all(&member).unwrap();
+    std::fs::write(
+        member.join("zako.toml"),
+        format!(
+            "group = \"test\"\nartifact = \"member\"\nversion = \"1.0.0\"\n\n[dependencies.app]\nrepo = \"{}\"\ncheckout = \"main\"\n",
+            app.url()
+        ),
+    )
+    .unwrap();
+    let member_source = PackageSource::Path {
+        path: "member".to_string(),
+    };
+    let dependencies = [(root.join("workspace"), member_source.clone())];
+
+    let cache = PackageCache::new(root.join("cache"));
+    let cas = memory_store();
+
+    let mut lockfile = Lockfile::default();
+    lockfile.insert(registry("test.pkgs:gone"), registry("test.pkgs:gone@1.0.0"));
+
+    assert!(
+        update(&mut lockfile, &cache, &cas, dependencies.clone(), &[])
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        lockfile.get(&app_source),
+        Some(&git(&app.url(), Some(&app_commit)))
+    );
+    assert_eq!(
+        lockfile.get(&lib_source),
+        Some(&git(&lib.url(), Some(&first)))
+    );
+    assert_eq!(lockfile.get(&registry("test.pkgs:gone")), None);
+    assert_eq!(lockfile.packages.len(), 2);
+
+    // the transitive pin is kept unless a dependency that brings it in is refreshed
+    let second = lib.commit("1.1.0");
+    assert!(
+        !update(&mut lockfile, &cache, &cas, dependencies.clone(), &[])
+            .await
+            .unwrap()
+    );
+    assert!(
+        update(
+            &mut lockfile,
+            &cache,
+            &cas,
+            dependencies.clone(),
+            &[member_source]
+        )
+        .await
+        .unwrap()
+    );
+    assert_eq!(
+        lockfile.get(&lib_source),
+        Some(&git(&lib.url(), Some(&second)))
+    );
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,227 @@
+use crate::config_value::{ConfigDefault, ConfigType};
+use crate::diagnostic::Diagnostic;
+use crate::manifest::{self, ManifestError, ManifestFormat, ManifestSource};
+use crate::package_source::PackageSource;
+use crate::tests::TEST_INTERNER;
+
+static EXAMPLE_JSON: &str = include_str!("../../../tests/new_project/zako.json");
+
+static EXAMPLE_TOML: &str = r#"
+group = "com.example"
+artifact = "example"
+version = "1.0.0"
+mount_config = "config"
+
+[builds]
+patterns = ["src/**"]
+
+[dependencies.git]
+repo = "https://example.com/git.git"
+checkout = "main"
+
+[dependencies.local]
+path = "../local"
+
+[config.debug]
+type = "boolean"
+default = true
+"#;
+
+fn violation_paths(content: &str, format: ManifestFormat) -> Vec<String> {
+    match manifest::parse(content.as_bytes(), format) {
+        Err(ManifestError::Schema(violations)) => violations
+            .into_iter()
+            .map(|violation| violation.path)
+            .collect(),
+        other => panic!("expected a schema error, found {:?}", other),
+    }
+}
+
+#[test]
+fn test_parse_json_manifest() {
+    let package = manifest::parse(EXAMPLE_JSON.as_bytes(), ManifestFormat::Json).unwrap();
+
+    assert_eq!(package.group, "fra.moe");
+    assert_eq!(
+        package.authors.as_ref().unwrap()[0].email(),
+        "me@kawayi.moe"
+    );
+
+    let debug = &package.config.as_ref().unwrap()["debug"];
+    assert_eq!(debug.r#type, ConfigType::Boolean);
+    assert_eq!(debug.default, ConfigDefault::Boolean(false));
+}
+
+#[test]
+fn test_schema_violation_points_to_the_value() {
+    let content = r#"{
+        "group": "com.example",
+        "artifact": "example",
+        "version": "1.0.0",
+        "dependencies": { "foo": { "repo": 1 } }
+    }"#;
+
+    assert_eq!(
+        violation_paths(content, ManifestFormat::Json),
+        vec!["/dependencies/foo/repo"]
+    );
+
+    let content = r#"
+group = "com.example"
+artifact = "example"
+version = "one"
+options = {}
+"#;
+
+    assert_eq!(
+        violation_paths(content, ManifestFormat::Toml),
+        vec!["/options", "/version"]
+    );
+}
+
+#[test]
+fn test_convert_round_trip() {
+    let toml = manifest::parse(EXAMPLE_TOML.as_bytes(), ManifestFormat::Toml).unwrap();
+    assert_eq!(
+        toml.dependencies.as_ref().unwrap()["local"],
+        PackageSource::Path {
+            path: "../local".to_string()
+        }
+    );
+
+    let json = manifest::convert(
+        EXAMPLE_TOML.as_bytes(),
+        ManifestFormat::Toml,
+        ManifestFormat::Json,
+    )
+    .unwrap();
+    assert_eq!(
+        manifest::parse(json.as_bytes(), ManifestFormat::Json).unwrap(),
+        toml
+    );
+
+    let back =
+        manifest::convert(json.as_bytes(), ManifestFormat::Json, ManifestFormat::Toml).unwrap();
+    assert_eq!(
+        manifest::parse(back.as_bytes(), ManifestFormat::Toml).unwrap(),
+        toml
+    );
+}
+
+#[test]
+fn test_find_manifest() {
+    let root = std::env::temp_dir().join(format!("zako-manifest-test-{}", uuid::Uuid::new_v4()));
+    let root = camino::Utf8PathBuf::from_path_buf(root).unwrap();
+    std::fs::create_dir_all(&root).unwrap();
+
+    assert_eq!(manifest::find(&root), None);
+
+    std::fs::write(root.join("zako.json"), EXAMPLE_JSON).unwrap();
+    assert_eq!(
+        manifest::find(&root),
+        Some((root.join("zako.json"), ManifestFormat::Json))
+    );
+
+    std::fs::write(root.join("zako.toml"), EXAMPLE_TOML).unwrap();
+    assert_eq!(
+        manifest::find(&root),
+        Some((root.join("zako.toml"), ManifestFormat::Toml))
+    );
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+fn location(diagnostic: &Diagnostic) -> (usize, usize, usize) {
+    let location = diagnostic.get_location().unwrap();
+    (location.line, location.column, location.width)
+}
+
+#[test]
+fn test_parse_errors_point_to_the_line() {
+    let toml = ManifestSource::new(
+        "zako.toml",
+        "group = \"a\"\nversion = \n".into(),
+        ManifestFormat::Toml,
+    );
+    let diagnostics = toml.parse().unwrap_err();
+    assert_eq!(location(&diagnostics.get_diagnostics()[0]).0, 2);
+
+    let json = ManifestSource::new(
+        "zako.json",
+        "{\n  \"group\": \"a\",\n  \"artifact\": \"b\",\n  \"version\": \"1.0.0\",\n  \"workspaces\": [\"x\", 1]\n}".into(),
+        ManifestFormat::Json,
+    );
+    let diagnostics = json.parse().unwrap_err();
+    let diagnostic = &diagnostics.get_diagnostics()[0];
+    assert_eq!(location(diagnostic), (5, 23, 1));
+    assert!(
+        diagnostic
+            .to_string()
+            .contains("5 │   \"workspaces\": [\"x\", 1]")
+    );
+}
+
+#[test]
+fn test_validate_errors_point_to_the_key() {
+    let content = r#"group = "com.example"
+artifact = "example"
+version = "1.0.0"
+
+[config.1bad]
+type = "boolean"
+default = true
+"#;
+    let source = ManifestSource::new("zako.toml", content.into(), ManifestFormat::Toml);
+    let package = source.parse().unwrap();
+    let diagnostic = source.validate(&package).unwrap_err();
+
+    assert!(diagnostic.message.contains("`1bad`"));
+    assert_eq!(location(&diagnostic), (5, 9, 4));
+
+    let content = r#"{
+    "group": "com.example",
+    "artifact": "example",
+    "version": "1.0.0",
+    "dependencies": {
+        "fine": { "path": "fine" },
+        "outside": { "path": "../../outside" }
+    }
+}"#;
+    let source = ManifestSource::new("zako.json", content.into(), ManifestFormat::Json);
+    let package = source.parse().unwrap();
+    let diagnostic = source.validate(&package).unwrap_err();
+
+    assert!(diagnostic.message.contains("`outside`"));
+    assert_eq!(location(&diagnostic), (7, 20, 27));
+}
+
+#[test]
+fn test_validate_labels() {
+    let content = r#"group = "com.example"
+artifact = "example"
+version = "1.0.0"
+
+[config.debug]
+type = "boolean"
+default = true
+
+[config.toolchain]
+type = "label"
+default = "not a label"
+"#;
+    let source = ManifestSource::new("zako.toml", content.into(), ManifestFormat::Toml);
+    let package = source.parse().unwrap();
+    source.validate(&package).unwrap();
+    let diagnostic = source
+        .validate_labels(&package, &TEST_INTERNER)
+        .unwrap_err();
+
+    assert!(diagnostic.message.contains("config `toolchain`"));
+    assert_eq!(location(&diagnostic), (11, 11, 13));
+
+    // the config keys are idents, not labels
+    let source = ManifestSource::new("zako.json", EXAMPLE_JSON.to_string(), ManifestFormat::Json);
+    let package = source.parse().unwrap();
+    source.validate(&package).unwrap();
+    source.validate_labels(&package, &TEST_INTERNER).unwrap();
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use camino::Utf8PathBuf;
+use tokio::io::AsyncReadExt;
+use zako_digest::Digest;
+
+use crate::blob_range::BlobRange;
+use crate::cas::{Cas, CasError, LocalStore};
+use crate::cas_store::{CasStore, CasStoreOptions, DEFAULT_MAX_INLINED_BLOB_SIZE};
+use crate::cas_upload::UploadQueueOptions;
+use crate::memory_cas::MemoryCas;
+use crate::tests::digest_of;
+
+async fn put(cas: &MemoryCas, data: &[u8]) -> Digest {
+    let digest = digest_of(data);
+    cas.store(&digest, Box::new(std::io::Cursor::new(data.to_vec())))
+        .await
+        .unwrap();
+    digest
+}
+
+#[tokio::test]
+async fn test_fetch_range() {
+    let cas = MemoryCas::new(None);
+    let digest = put(&cas, b"hello memory cas").await;
+
+    assert_eq!(cas.check(&digest).await, Some(16));
+
+    let mut data = Vec::new();
+    cas.fetch(&digest, &BlobRange::new(6, Some(6)).unwrap())
+        .await
+        .unwrap()
+        .read_to_end(&mut data)
+        .await
+        .unwrap();
+    assert_eq!(data, b"memory");
+
+    assert!(matches!(
+        cas.fetch(&digest, &BlobRange::new(10, Some(10)).unwrap())
+            .await,
+        Err(CasError::RequestedIndexOutOfRange { .. })
+    ));
+    assert!(matches!(
+        cas.fetch(&digest_of(b"missing"), &BlobRange::full()).await,
+        Err(CasError::NotFound(..))
+    ));
+}
+
+#[tokio::test]
+async fn test_evicts_least_recently_used() {
+    let cas = MemoryCas::new(Some(30));
+
+    let first = put(&cas, &[1u8; 10]).await;
+    let second = put(&cas, &[2u8; 10]).await;
+    let third = put(&cas, &[3u8; 10]).await;
+
+    // refresh the first one, so the second one is the least recently used
+    cas.fetch(&first, &BlobRange::full()).await.unwrap();
+
+    let fourth = put(&cas, &[4u8; 10]).await;
+
+    assert!(cas.contains(&first).await);
+    assert!(!cas.contains(&second).await);
+    assert!(cas.contains(&third).await);
+    assert!(cas.contains(&fourth).await);
+    assert_eq!(cas.get_used_bytes(), 30);
+    assert_eq!(cas.len(), 3);
+
+    let too_big = [5u8; 31];
+    assert!(
+        cas.store(
+            &digest_of(&too_big),
+            Box::new(std::io::Cursor::new(too_big.to_vec()))
+        )
+        .await
+        .is_err()
+    );
+    assert_eq!(cas.len(), 3);
+}
+
+#[tokio::test]
+async fn test_input_file() {
+    let dir = std::env::temp_dir().join(format!("zako-memory-cas-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let path = Utf8PathBuf::from_path_buf(dir.join("input.txt")).unwrap();
+    std::fs::write(&path, b"input content").unwrap();
+
+    let cas = MemoryCas::new(None);
+    let digest = cas.input_file(&path, false).await.unwrap();
+
+    assert_eq!(digest, digest_of(b"input content"));
+    assert!(cas.contains(&digest).await);
+}
+
+#[tokio::test]
+async fn test_cas_store_with_memory_cas() {
+    let store = CasStore::new(
+        Box::new(MemoryCas::new(Some(1024 * 1024))),
+        Vec::new(),
+        CasStoreOptions {
+            max_cache_capacity: 1024 * 1024,
+            max_cache_ttl: Duration::from_secs(60),
+            max_cache_tti: Duration::from_secs(60),
+            upload_queue: UploadQueueOptions::default(),
+            max_inlined_blob_size: DEFAULT_MAX_INLINED_BLOB_SIZE,
+            lease_dir: None,
+        },
+    );
+
+    let data: Vec<u8> = (0..=255u8).cycle().take(128 * 1024).collect();
+    let handle = store
+        .put_reader(std::io::Cursor::new(data.clone()))
+        .await
+        .unwrap();
+
+    assert!(store.get_local_cas().contains(handle.digest()).await);
+    assert_eq!(
+        store.get_local_cas().get_local_path(handle.digest()).await,
+        None
+    );
+    assert_eq!(
+        store
+            .read(handle.digest(), &BlobRange::full())
+            .await
+            .unwrap(),
+        data
+    );
+}
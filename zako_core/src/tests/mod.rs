@@ -1,15 +1,137 @@
 use static_init::dynamic;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 use std::sync::Arc;
+use std::time::Duration;
+
+use zako_digest::Digest;
+
+use crate::cas_store::{CasStore, CasStoreOptions, DEFAULT_MAX_INLINED_BLOB_SIZE};
+use crate::cas_upload::UploadQueueOptions;
+use crate::memory_cas::MemoryCas;
 
 #[dynamic(lazy)]
 pub static TEST_INTERNER: Arc<::zako_interner::ThreadedInterner> =
     Arc::new(::zako_interner::ThreadedInterner::new().unwrap());
 
+/// A unique directory in the temp directory for the test `name`, it is not created.
+pub fn temp_root(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("zako-{}-test-{}", name, uuid::Uuid::new_v4()))
+}
+
+pub fn digest_of(data: &[u8]) -> Digest {
+    Digest::new(data.len() as u64, blake3::hash(data).into())
+}
+
+/// A [CasStore] that keeps everything in memory.
+pub fn memory_store() -> CasStore {
+    CasStore::new(
+        Box::new(MemoryCas::new(None)),
+        Vec::new(),
+        CasStoreOptions {
+            max_cache_capacity: 1024 * 1024,
+            max_cache_ttl: Duration::from_secs(60),
+            max_cache_tti: Duration::from_secs(60),
+            upload_queue: UploadQueueOptions::default(),
+            max_inlined_blob_size: DEFAULT_MAX_INLINED_BLOB_SIZE,
+            lease_dir: None,
+        },
+    )
+}
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// A bare origin and a work tree that pushes to it.
+pub struct Origin {
+    bare: PathBuf,
+    work: PathBuf,
+}
+
+impl Origin {
+    pub fn new(root: &Path) -> Self {
+        let bare = root.join("origin.git");
+        let work = root.join("work");
+        std::fs::create_dir_all(&bare).unwrap();
+        std::fs::create_dir_all(&work).unwrap();
+
+        git(&bare, &["init", "--quiet", "--bare", "-b", "main"]);
+        git(&work, &["init", "--quiet", "-b", "main"]);
+        git(&work, &["remote", "add", "origin", bare.to_str().unwrap()]);
+
+        Self { bare, work }
+    }
+
+    pub fn url(&self) -> String {
+        format!("file://{}", self.bare.display())
+    }
+
+    /// Commit the manifest with the `version` and push it, return the commit.
+    pub fn commit(&self, version: &str) -> String {
+        self.commit_manifest(&format!(
+            "group = \"test\"\nartifact = \"git\"\nversion = \"{}\"\n",
+            version
+        ))
+    }
+
+    /// Commit the `manifest` and push it, return the commit.
+    pub fn commit_manifest(&self, manifest: &str) -> String {
+        std::fs::write(self.work.join("zako.toml"), manifest).unwrap();
+
+        git(&self.work, &["add", "zako.toml"]);
+        git(
+            &self.work,
+            &["commit", "--quiet", "-m", "update the manifest"],
+        );
+        git(&self.work, &["push", "--quiet", "origin", "HEAD:main"]);
+
+        git(&self.work, &["rev-parse", "HEAD"])
+    }
+}
+
+pub mod action_cache_tests;
 pub mod author_tests;
 pub mod blob_range_tests;
+pub mod cas_lease_tests;
+pub mod cas_store_tests;
+pub mod cas_token_tests;
+pub mod cas_upload_tests;
+pub mod cas_verify_tests;
 pub mod config_value_tests;
+pub mod diagnostic_tests;
+pub mod fetch_archive_tests;
+pub mod fetch_git_tests;
+pub mod http_cas_tests;
+pub mod http_stand_in;
 pub mod id_tests;
 pub mod intern_tests;
+pub mod lockfile_tests;
+pub mod manifest_tests;
+pub mod memory_cas_tests;
 pub mod neutral_path_tests;
+pub mod package_archive_tests;
+pub mod package_graph_tests;
 pub mod package_tests;
+pub mod patch_tests;
+pub mod reapi_cas_tests;
+pub mod registry_tests;
+pub mod tiered_cas_tests;
+pub mod vendor_tests;
 pub mod version_extractor_tests;
+pub mod workspace_tests;
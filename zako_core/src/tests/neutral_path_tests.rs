@@ -142,4 +142,12 @@ fn test_neutral_path_is_in_dir() {
     assert!(file.is_in_dir(&dir));
     assert!(!outside.is_in_dir(&dir));
     assert!(dir.is_in_dir(&dir)); // A directory is in itself
+
+    assert!(file.is_in_dir(NeutralPath::dot()));
+    assert!(NeutralPath::dot().is_in_dir(NeutralPath::dot()));
+    assert!(
+        !NeutralPath::from_path("../a")
+            .unwrap()
+            .is_in_dir(NeutralPath::dot())
+    );
 }
@@ -0,0 +1,411 @@
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+use camino::Utf8PathBuf;
+use tokio::io::AsyncReadExt;
+
+use crate::fetch::PackageCache;
+use crate::http_registry::{HttpRegistry, HttpRegistryOptions};
+use crate::local_registry::LocalRegistry;
+use crate::package_archive::{self, PackageArchiveError};
+use crate::package_source::PackageSource;
+use crate::pattern::Pattern;
+use crate::registry::{Registry, RegistryError, parse_requirement};
+use crate::tests::http_stand_in::HttpStandIn;
+use crate::tests::{memory_store, temp_root};
+
+static MANIFEST: &str = r#"group = "test.pkgs"
+artifact = "lib"
+version = "1.0.0"
+
+[builds]
+patterns = ["src/**/*.ts"]
+
+[toolchains]
+patterns = ["*.toolchain.ts"]
+
+[dependencies.dep]
+package = "test.pkgs:dep@^0.1"
+"#;
+
+/// A package with the `manifest`, the files that are selected and some that are not.
+fn write_package(root: &Path, manifest: &str) -> Utf8PathBuf {
+    for (path, content) in [
+        ("zako.toml", manifest),
+        ("src/BUILD.ts", "export default {};\n"),
+        ("src/nested/lib.ts", "export const a = 1;\n"),
+        ("src/nested/data.json", "{}\n"),
+        ("cc.toolchain.ts", "export default {};\n"),
+        ("notes.md", "not packaged\n"),
+        (".git/HEAD", "ref: refs/heads/main\n"),
+    ] {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    Utf8PathBuf::from_path_buf(root.to_path_buf()).unwrap()
+}
+
+/// The regular files of the `archive`: the path, the mode and the content.
+fn entries(archive: &[u8]) -> Vec<(String, u32, Vec<u8>)> {
+    let tar = zstd::decode_all(archive).unwrap();
+    let mut tar = tar::Archive::new(tar.as_slice());
+
+    tar.entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            assert_eq!(entry.header().entry_type(), tar::EntryType::Regular);
+
+            let path = entry.path().unwrap().to_str().unwrap().to_string();
+            let mode = entry.header().mode().unwrap();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+
+            (path, mode, data)
+        })
+        .collect()
+}
+
+#[test]
+fn test_archive_is_reproducible() {
+    let root = temp_root("package");
+    let first = write_package(&root.join("first"), MANIFEST);
+    let second = write_package(&root.join("second"), MANIFEST);
+
+    // the modification times and the permissions of the files differ
+    let file = std::fs::File::options()
+        .write(true)
+        .open(second.join("src/BUILD.ts"))
+        .unwrap();
+    file.set_modified(std::time::SystemTime::UNIX_EPOCH)
+        .unwrap();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(
+            second.join("src/nested/lib.ts"),
+            std::fs::Permissions::from_mode(0o600),
+        )
+        .unwrap();
+    }
+
+    let archive = package_archive::create(&first).unwrap();
+
+    assert_eq!(
+        archive.files,
+        vec![
+            "cc.toolchain.ts",
+            "src/BUILD.ts",
+            "src/nested/lib.ts",
+            "zako.toml"
+        ]
+    );
+    assert_eq!(archive.get_file_name(), "lib-1.0.0.tar.zst");
+    assert_eq!(package_archive::create(&second).unwrap().data, archive.data);
+
+    assert_eq!(
+        entries(&archive.data)
+            .into_iter()
+            .map(|(path, mode, _)| {
+                assert_eq!(mode, 0o644);
+                path
+            })
+            .collect::<Vec<_>>(),
+        archive.files
+    );
+
+    let entry = archive.get_index_entry().unwrap();
+    assert_eq!(entry.integrity, archive.get_integrity().to_string());
+    assert_eq!(entry.dependencies[0].package, "test.pkgs:dep");
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_select_files_with_globs() {
+    let root = temp_root("package");
+    let package = write_package(&root, MANIFEST);
+    let pattern = Pattern {
+        patterns: vec!["src/**/*.ts".to_string(), "*.md".to_string()],
+        following_ignore_files: true,
+        ignore_hidden_files: false,
+    };
+
+    let files = pattern.select_files(package.as_std_path()).unwrap();
+
+    assert_eq!(
+        files,
+        vec![
+            package.join("notes.md").into_std_path_buf(),
+            package.join("src/BUILD.ts").into_std_path_buf(),
+            package.join("src/nested/lib.ts").into_std_path_buf(),
+        ]
+    );
+
+    let empty = Pattern {
+        patterns: Vec::new(),
+        ..pattern
+    };
+    assert!(
+        empty
+            .select_files(package.as_std_path())
+            .unwrap()
+            .is_empty()
+    );
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_archive_long_names() {
+    let root = temp_root("package");
+    let long = format!("src/{}/file.ts", "directory".repeat(15));
+    let package = write_package(&root, MANIFEST);
+    std::fs::create_dir_all(package.join(&long).parent().unwrap()).unwrap();
+    std::fs::write(package.join(&long), "content").unwrap();
+
+    let archive = package_archive::create(&package).unwrap();
+
+    assert!(entries(&archive.data).contains(&(long, 0o644, b"content".to_vec())));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_path_dependencies_are_refused() {
+    let root = temp_root("package");
+    let manifest = format!("{}\n[dependencies.local]\npath = \"local\"\n", MANIFEST);
+    let package = write_package(&root, &manifest);
+
+    let archive = package_archive::create(&package).unwrap();
+
+    assert!(matches!(
+        archive.get_index_entry(),
+        Err(PackageArchiveError::PathDependency(alias)) if alias == "local"
+    ));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_publish_to_local_registry() {
+    let root = temp_root("package");
+    let package = write_package(&root.join("package"), MANIFEST);
+    let archive = package_archive::create(&package).unwrap();
+
+    let registry = Arc::new(LocalRegistry::new(root.join("registry")));
+    let entry = package_archive::publish(registry.as_ref(), &archive)
+        .await
+        .unwrap();
+    assert_eq!(entry.version, semver::Version::new(1, 0, 0));
+
+    let again = package_archive::publish(registry.as_ref(), &archive).await;
+    assert!(matches!(
+        again,
+        Err(PackageArchiveError::Registry(RegistryError::VersionExists(
+            ..
+        )))
+    ));
+
+    // the published package is fetched like any other
+    let cache = PackageCache::new(root.join("cache")).with_registry(registry.clone());
+    let fetched = cache
+        .fetch(
+            &PackageSource::Registry {
+                package: "test.pkgs:lib@^1".to_string(),
+            },
+            &memory_store(),
+        )
+        .await
+        .unwrap();
+
+    assert!(
+        fetched
+            .root
+            .join("src")
+            .join("nested")
+            .join("lib.ts")
+            .exists()
+    );
+    assert!(!fetched.root.join("notes.md").exists());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_publish_to_http_registry() {
+    let root = temp_root("package");
+    let package = write_package(&root, MANIFEST);
+    let archive = package_archive::create(&package).unwrap();
+
+    let server = HttpStandIn::default();
+    let base = server.clone().start().await;
+    let registry = HttpRegistry::new(
+        format!("{}/registry", base).parse().unwrap(),
+        HttpRegistryOptions::default(),
+    )
+    .unwrap();
+
+    package_archive::publish(&registry, &archive).await.unwrap();
+
+    assert_eq!(
+        server
+            .files
+            .get("/registry/archives/test.pkgs/lib/1.0.0.tar.zst")
+            .map(|data| data.clone()),
+        Some(archive.data.clone())
+    );
+
+    let name = parse_requirement("test.pkgs:lib").unwrap().name;
+    let index = registry.index(&name).await.unwrap().unwrap();
+    assert_eq!(index.versions.len(), 1);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_concurrent_publishes_keep_all_versions() {
+    let root = temp_root("package");
+    let versions = ["1.0.0", "1.1.0", "1.2.0", "2.0.0"];
+    let archives = versions
+        .iter()
+        .map(|version| {
+            let package = write_package(&root.join(version), &MANIFEST.replace("1.0.0", version));
+            package_archive::create(&package).unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let base = HttpStandIn::default().start().await;
+    let registries: [Arc<dyn Registry>; 2] = [
+        Arc::new(LocalRegistry::new(root.join("registry"))),
+        Arc::new(
+            HttpRegistry::new(
+                format!("{}/registry", base).parse().unwrap(),
+                HttpRegistryOptions::default(),
+            )
+            .unwrap(),
+        ),
+    ];
+
+    for registry in registries {
+        futures::future::try_join_all(
+            archives
+                .iter()
+                .map(|archive| package_archive::publish(registry.as_ref(), archive)),
+        )
+        .await
+        .unwrap();
+
+        let name = parse_requirement("test.pkgs:lib").unwrap().name;
+        let index = registry.index(&name).await.unwrap().unwrap();
+        assert_eq!(
+            index
+                .versions
+                .iter()
+                .map(|entry| entry.version.to_string())
+                .collect::<Vec<_>>(),
+            versions
+        );
+    }
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_racing_publishes_of_one_version_keep_one_archive() {
+    let root = temp_root("package");
+    let first = package_archive::create(&write_package(&root.join("first"), MANIFEST)).unwrap();
+    let second_root = write_package(&root.join("second"), MANIFEST);
+    std::fs::write(
+        second_root.join("src/BUILD.ts"),
+        "export default { a: 1 };\n",
+    )
+    .unwrap();
+    let second = package_archive::create(&second_root).unwrap();
+    assert_ne!(first.data, second.data);
+
+    let base = HttpStandIn::default().start().await;
+    let registries: [Arc<dyn Registry>; 2] = [
+        Arc::new(LocalRegistry::new(root.join("registry"))),
+        Arc::new(
+            HttpRegistry::new(
+                format!("{}/registry", base).parse().unwrap(),
+                HttpRegistryOptions::default(),
+            )
+            .unwrap(),
+        ),
+    ];
+    let name = parse_requirement("test.pkgs:lib").unwrap().name;
+    let version = semver::Version::new(1, 0, 0);
+
+    for registry in registries {
+        let (a, b) = futures::future::join(
+            package_archive::publish(registry.as_ref(), &first),
+            package_archive::publish(registry.as_ref(), &second),
+        )
+        .await;
+        assert_eq!(a.is_ok() as usize + b.is_ok() as usize, 1);
+
+        // the index names the archive that is stored
+        let index = registry.index(&name).await.unwrap().unwrap();
+        let mut stored = Vec::new();
+        registry
+            .open_archive(&name, &version)
+            .await
+            .unwrap()
+            .read_to_end(&mut stored)
+            .await
+            .unwrap();
+        assert_eq!(
+            index.versions[0].integrity,
+            format!("blake3:{}", blake3::hash(&stored).to_hex())
+        );
+    }
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_publish_again_after_the_index_update_fails() {
+    let root = temp_root("package");
+    let archive = package_archive::create(&write_package(&root.join("package"), MANIFEST)).unwrap();
+    let registry = LocalRegistry::new(root.join("registry"));
+    let name = parse_requirement("test.pkgs:lib").unwrap().name;
+    let version = semver::Version::new(1, 0, 0);
+
+    // the archive is stored but the index is not updated
+    registry
+        .put_archive(&name, &version, archive.data.clone())
+        .await
+        .unwrap();
+
+    package_archive::publish(&registry, &archive).await.unwrap();
+    assert_eq!(
+        registry.index(&name).await.unwrap().unwrap().versions.len(),
+        1
+    );
+
+    let other = package_archive::create(&write_package(
+        &root.join("other"),
+        &MANIFEST.replace("1.0.0", "1.1.0"),
+    ))
+    .unwrap();
+    registry
+        .put_archive(&name, &semver::Version::new(1, 1, 0), archive.data.clone())
+        .await
+        .unwrap();
+
+    // another archive of the version is stored already
+    assert!(matches!(
+        package_archive::publish(&registry, &other).await,
+        Err(PackageArchiveError::Registry(RegistryError::VersionExists(
+            ..
+        )))
+    ));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
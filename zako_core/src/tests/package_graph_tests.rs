@@ -0,0 +1,196 @@
+use crate::package_graph::{
+    GraphDependency, GraphPackage, PackageGraph, PackageGraphError, compatibility,
+    dependency_requirement,
+};
+use crate::package_source::PackageSource;
+
+/// The alias, the package and the requirement of each dependency.
+type Edges<'a> = &'a [(&'a str, &'a str, &'a str)];
+
+fn package(dependencies: Edges) -> GraphPackage {
+    GraphPackage {
+        source: PackageSource::Path {
+            path: "./pkg".to_string(),
+        },
+        dependencies: dependencies
+            .iter()
+            .map(|(alias, package, req)| {
+                (
+                    (*alias).into(),
+                    GraphDependency {
+                        package: package.to_string(),
+                        req: req.to_string(),
+                    },
+                )
+            })
+            .collect(),
+    }
+}
+
+/// The package that the `alias` of the package `from` resolves to.
+fn alias_of<'a>(graph: &'a PackageGraph, from: &str, alias: &str) -> Option<&'a str> {
+    graph
+        .get(from)?
+        .dependencies
+        .get(alias)
+        .map(|dependency| dependency.package.as_str())
+}
+
+/// Build a graph like the resolver does, the root is merged with the edges of each dependency.
+fn build_graph(root: (&str, Edges), packages: &[(&str, Edges)]) -> PackageGraph {
+    let mut graph = PackageGraph::new(root.0.to_string(), package(root.1));
+
+    for (id, dependencies) in packages {
+        graph.merge(PackageGraph::new(id.to_string(), package(dependencies)));
+    }
+
+    graph
+}
+
+#[test]
+fn test_compatibility() {
+    let class = |version: &str| compatibility(&version.parse().unwrap());
+
+    assert_eq!(class("1.2.3"), "1");
+    assert_eq!(class("0.2.3"), "0.2");
+    assert_eq!(class("0.0.3"), "0.0.3");
+
+    let git = PackageSource::Git {
+        repo: "https://example.com/a.git".into(),
+        checkout: None,
+    };
+    assert_eq!(dependency_requirement(&git, "1.2.0").unwrap(), "^1.2.0");
+
+    let registry = PackageSource::Registry {
+        package: "test.pkgs:a@=1.2.0".to_string(),
+    };
+    assert_eq!(
+        dependency_requirement(&registry, "1.2.0").unwrap(),
+        "=1.2.0"
+    );
+}
+
+#[test]
+fn test_unify_compatible_versions() {
+    let mut graph = build_graph(
+        (
+            "test:root@1.0.0",
+            &[
+                ("a", "test:a@1.2.0", "^1.2.0"),
+                ("b", "test:b@1.0.0", "^1.0.0"),
+                ("c", "test:c@2.0.0", "^2.0.0"),
+            ],
+        ),
+        &[
+            ("test:a@1.2.0", &[]),
+            (
+                "test:b@1.0.0",
+                &[
+                    ("a", "test:a@1.5.0", "^1.5.0"),
+                    ("c", "test:c@1.0.0", "^1.0.0"),
+                ],
+            ),
+            ("test:a@1.5.0", &[]),
+            ("test:c@2.0.0", &[]),
+            ("test:c@1.0.0", &[]),
+        ],
+    );
+
+    graph.unify().unwrap();
+
+    assert_eq!(
+        alias_of(&graph, "test:root@1.0.0", "a"),
+        Some("test:a@1.5.0")
+    );
+    assert_eq!(alias_of(&graph, "test:b@1.0.0", "a"), Some("test:a@1.5.0"));
+    assert!(graph.get("test:a@1.2.0").is_none());
+
+    // the incompatible versions are both kept
+    assert_eq!(
+        alias_of(&graph, "test:root@1.0.0", "c"),
+        Some("test:c@2.0.0")
+    );
+    assert_eq!(alias_of(&graph, "test:b@1.0.0", "c"), Some("test:c@1.0.0"));
+
+    assert_eq!(
+        graph.get_packages().keys().cloned().collect::<Vec<_>>(),
+        vec![
+            "test:a@1.5.0",
+            "test:b@1.0.0",
+            "test:c@1.0.0",
+            "test:c@2.0.0",
+            "test:root@1.0.0"
+        ]
+    );
+}
+
+#[test]
+fn test_unify_prefers_a_version_that_satisfies_all() {
+    let mut graph = build_graph(
+        (
+            "test:root@1.0.0",
+            &[("a", "test:a@1.5.0", "^1"), ("b", "test:b@1.0.0", "^1.0.0")],
+        ),
+        &[
+            ("test:a@1.5.0", &[("e", "test:e@1.1.0", "^1.1.0")]),
+            ("test:b@1.0.0", &[("a", "test:a@1.2.0", "=1.2.0")]),
+            // the requirement of the version that is not chosen is ignored
+            ("test:a@1.2.0", &[("e", "test:e@1.0.0", "=1.0.0")]),
+            ("test:e@1.1.0", &[]),
+            ("test:e@1.0.0", &[]),
+        ],
+    );
+
+    graph.unify().unwrap();
+
+    assert_eq!(
+        alias_of(&graph, "test:root@1.0.0", "a"),
+        Some("test:a@1.2.0")
+    );
+    assert_eq!(alias_of(&graph, "test:a@1.2.0", "e"), Some("test:e@1.0.0"));
+    assert!(graph.get("test:a@1.5.0").is_none());
+    assert!(graph.get("test:e@1.1.0").is_none());
+}
+
+#[test]
+fn test_conflict_reports_the_requirers() {
+    let mut graph = build_graph(
+        (
+            "test:root@1.0.0",
+            &[
+                ("a", "test:a@1.2.0", "=1.2.0"),
+                ("b", "test:b@1.0.0", "^1.0.0"),
+            ],
+        ),
+        &[
+            ("test:a@1.2.0", &[]),
+            ("test:b@1.0.0", &[("lib", "test:a@1.5.0", "^1.5.0")]),
+            ("test:a@1.5.0", &[]),
+        ],
+    );
+
+    let Err(PackageGraphError::Conflict(conflict)) = graph.unify() else {
+        panic!("the versions of `test:a` should conflict");
+    };
+
+    assert_eq!(conflict.package, "test:a");
+    assert_eq!(conflict.candidates, vec!["1.2.0", "1.5.0"]);
+    assert_eq!(conflict.requirers.len(), 2);
+    assert_eq!(
+        conflict.requirers[1].path,
+        vec!["test:root@1.0.0", "test:b@1.0.0"]
+    );
+
+    let message = conflict.to_string();
+    assert!(message.contains("test:root@1.0.0 requires `@a` as `=1.2.0`"));
+    assert!(message.contains("test:root@1.0.0 -> test:b@1.0.0 requires `@lib` as `^1.5.0`"));
+
+    let mut invalid = build_graph(
+        ("test:root@1.0.0", &[("a", "test:a@x", "^1")]),
+        &[("test:a@x", &[])],
+    );
+    assert!(matches!(
+        invalid.unify(),
+        Err(PackageGraphError::InvalidVersion(..))
+    ));
+}
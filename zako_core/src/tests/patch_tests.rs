@@ -0,0 +1,282 @@
+use std::path::{Path, PathBuf};
+
+use camino::Utf8PathBuf;
+
+use crate::fetch::patch::{Patch, Patches, apply_patches, digest};
+use crate::fetch::{FetchError, PackageCache};
+use crate::lockfile::{LockState, LockedOverride, LockedPatch, Lockfile, LockfileError};
+use crate::overrides::{Overrides, OverridesError};
+use crate::package::{Package, PackageResolveError};
+use crate::package_source::PackageSource;
+use crate::tests::{Origin, memory_store, temp_root};
+
+static BUMP_VERSION: &str = r#"diff --git a/zako.toml b/zako.toml
+--- a/zako.toml
++++ b/zako.toml
+@@ -1,3 +1,3 @@
+ group = "test"
+ artifact = "git"
+-version = "1.0.0"
++version = "1.0.0-patched"
+"#;
+
+static ADD_NOTES: &str = r#"diff --git a/notes.md b/notes.md
+new file mode 100644
+--- /dev/null
++++ b/notes.md
+@@ -0,0 +1 @@
++patched
+"#;
+
+fn patch(name: &str, content: &str) -> Patch {
+    Patch {
+        path: PathBuf::from(name),
+        content: content.as_bytes().to_vec(),
+    }
+}
+
+fn parse_package(content: &str) -> Package {
+    toml::from_str(content).unwrap()
+}
+
+#[tokio::test]
+async fn test_apply_patches_to_fetched_package() {
+    let root = temp_root("patch");
+    let origin = Origin::new(&root);
+    origin.commit("1.0.0");
+
+    let cache = PackageCache::new(root.join("cache"));
+    let fetched = cache
+        .fetch(
+            &PackageSource::Git {
+                repo: origin.url().into(),
+                checkout: None,
+            },
+            &memory_store(),
+        )
+        .await
+        .unwrap();
+
+    let patches = vec![
+        patch("bump.patch", BUMP_VERSION),
+        patch("notes.patch", ADD_NOTES),
+    ];
+    let patched = apply_patches(&cache, &fetched.root, &fetched.pinned, &patches)
+        .await
+        .unwrap();
+
+    assert_ne!(patched, fetched.root);
+    assert!(
+        std::fs::read_to_string(patched.join("zako.toml"))
+            .unwrap()
+            .contains("1.0.0-patched")
+    );
+    assert_eq!(
+        std::fs::read_to_string(patched.join("notes.md")).unwrap(),
+        "patched\n"
+    );
+
+    // the fetched package is never changed
+    assert!(!fetched.root.join("notes.md").exists());
+
+    // the same patches give the same copy
+    let again = apply_patches(&cache, &fetched.root, &fetched.pinned, &patches)
+        .await
+        .unwrap();
+    assert_eq!(again, patched);
+
+    // a patch that does not apply names the patch file
+    let failed = apply_patches(
+        &cache,
+        &fetched.root,
+        &fetched.pinned,
+        &[
+            patch("notes.patch", ADD_NOTES),
+            patch("again.patch", ADD_NOTES),
+        ],
+    )
+    .await;
+    assert!(matches!(
+        failed,
+        Err(FetchError::Patch { patch, .. }) if patch == Path::new("again.patch")
+    ));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_digest_depends_on_order() {
+    let first = [patch("a", BUMP_VERSION), patch("b", ADD_NOTES)];
+    let second = [patch("b", ADD_NOTES), patch("a", BUMP_VERSION)];
+
+    assert!(digest(&first).starts_with("blake3:"));
+    assert_eq!(digest(&first), digest(&first.clone()));
+    assert_ne!(digest(&first), digest(&second));
+}
+
+#[test]
+fn test_lockfile_records_patches_and_overrides() {
+    let root = temp_root("patch");
+    std::fs::create_dir_all(root.join("patches")).unwrap();
+    std::fs::write(root.join("patches/bump.patch"), BUMP_VERSION).unwrap();
+
+    let package = parse_package(
+        r#"group = "test"
+artifact = "app"
+version = "1.0.0"
+
+[dependencies.lib]
+repo = "https://example.com/lib.git"
+
+[patches]
+lib = ["patches/bump.patch"]
+
+[overrides]
+lib = "../lib"
+"#,
+    );
+    package.validate().unwrap();
+
+    let patches = Patches::load(&root, &package).unwrap();
+    let utf8_root = Utf8PathBuf::from_path_buf(root.clone()).unwrap();
+    let overrides =
+        Overrides::from_manifest(&utf8_root, &root.join("zako.toml"), &package).unwrap();
+
+    assert_eq!(overrides.get("lib").unwrap().path, utf8_root.join("../lib"));
+
+    let lock_state = LockState::default();
+    lock_state
+        .record_local_changes(patches.to_locked(), overrides.to_locked())
+        .unwrap();
+    assert!(lock_state.is_changed());
+
+    let lockfile = lock_state.snapshot();
+    assert_eq!(
+        lockfile.patches,
+        vec![LockedPatch {
+            dependency: "lib".to_string(),
+            files: vec!["patches/bump.patch".to_string()],
+            digest: digest(patches.get("lib").unwrap()),
+        }]
+    );
+    assert_eq!(
+        lockfile.overrides,
+        vec![LockedOverride {
+            dependency: "lib".to_string(),
+            path: "../lib".to_string(),
+        }]
+    );
+
+    let content = lockfile.to_toml().unwrap();
+    assert!(content.contains("[[patch]]"));
+    assert!(content.contains("[[override]]"));
+    assert_eq!(
+        Lockfile::parse(Path::new("zako.lock"), &content).unwrap(),
+        lockfile
+    );
+
+    // the recorded entries are kept, the changed ones are refused when locked
+    let locked = LockState::default();
+    locked.reset(lockfile, true);
+    locked
+        .record_local_changes(patches.to_locked(), overrides.to_locked())
+        .unwrap();
+    assert!(!locked.is_changed());
+    assert!(matches!(
+        locked.record_local_changes(patches.to_locked(), Vec::new()),
+        Err(LockfileError::OutdatedLocalChanges)
+    ));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_invalid_patches() {
+    let not_a_dependency = parse_package(
+        r#"group = "test"
+artifact = "app"
+version = "1.0.0"
+
+[patches]
+lib = ["lib.patch"]
+"#,
+    );
+    assert!(matches!(
+        not_a_dependency.validate(),
+        Err(PackageResolveError::InvalidPatch(alias, _)) if alias == "lib"
+    ));
+
+    let path_dependency = parse_package(
+        r#"group = "test"
+artifact = "app"
+version = "1.0.0"
+
+[dependencies.lib]
+path = "lib"
+
+[patches]
+lib = ["lib.patch"]
+"#,
+    );
+    assert!(matches!(
+        path_dependency.validate(),
+        Err(PackageResolveError::InvalidPatch(alias, _)) if alias == "lib"
+    ));
+
+    let invalid_override = parse_package(
+        r#"group = "test"
+artifact = "app"
+version = "1.0.0"
+
+[overrides]
+"not an alias" = "../lib"
+"#,
+    );
+    assert!(matches!(
+        invalid_override.validate(),
+        Err(PackageResolveError::InvalidOverrideKey(key)) if key == "not an alias"
+    ));
+}
+
+#[test]
+fn test_user_overrides_replace_manifest_overrides() {
+    let root = temp_root("patch");
+    std::fs::create_dir_all(&root).unwrap();
+
+    let path = root.join("overrides.toml");
+    assert!(Overrides::load_user(&path).unwrap().is_empty());
+
+    std::fs::write(&path, "[overrides]\nlib = \"checkouts/lib\"\n").unwrap();
+    let user = Overrides::load_user(&path).unwrap();
+
+    let package = parse_package(
+        r#"group = "test"
+artifact = "app"
+version = "1.0.0"
+
+[overrides]
+lib = "../lib"
+other = "../other"
+"#,
+    );
+    let utf8_root = Utf8PathBuf::from_path_buf(root.join("workspace")).unwrap();
+    let mut overrides =
+        Overrides::from_manifest(&utf8_root, &root.join("workspace/zako.toml"), &package).unwrap();
+    overrides.merge(user);
+
+    let lib = overrides.get("lib").unwrap();
+    assert_eq!(
+        lib.path,
+        Utf8PathBuf::from_path_buf(root.join("checkouts/lib")).unwrap()
+    );
+    assert_eq!(lib.origin, path);
+    assert_eq!(overrides.get("other").unwrap().declared, "../other");
+
+    std::fs::write(&path, "[overrides]\n\"not an alias\" = \"lib\"\n").unwrap();
+    assert!(matches!(
+        Overrides::load_user(&path),
+        Err(OverridesError::InvalidKey(..))
+    ));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
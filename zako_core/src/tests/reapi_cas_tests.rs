@@ -0,0 +1,460 @@
+use std::pin::Pin;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tokio::io::AsyncReadExt;
+use tokio_stream::{Stream, StreamExt};
+use tonic::transport::Server;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status, Streaming};
+use zako_digest::Digest;
+use zako_shared::ConcurrentMap;
+
+use crate::blob_range::BlobRange;
+use crate::cas::{Cas, CasError};
+use crate::protobuf::reapi::build::bazel::remote::execution::v2::{
+    ActionResult, BatchReadBlobsRequest, BatchReadBlobsResponse, BatchUpdateBlobsRequest,
+    BatchUpdateBlobsResponse, FindMissingBlobsRequest, FindMissingBlobsResponse,
+    GetActionResultRequest, UpdateActionResultRequest,
+    action_cache_server::{ActionCache, ActionCacheServer},
+    batch_update_blobs_request, batch_update_blobs_response,
+    content_addressable_storage_server::{
+        ContentAddressableStorage, ContentAddressableStorageServer,
+    },
+};
+use crate::protobuf::reapi::google::bytestream::{
+    ReadRequest, ReadResponse, WriteRequest, WriteResponse,
+    byte_stream_server::{ByteStream, ByteStreamServer},
+};
+use crate::reapi_cas::*;
+use crate::tests::digest_of;
+
+/// A tiny in-process REAPI server that keeps blobs and action results in memory.
+#[derive(Debug, Default, Clone)]
+struct StandInServer {
+    blobs: Arc<ConcurrentMap<String, Vec<u8>>>,
+    action_results: Arc<ConcurrentMap<String, ActionResult>>,
+}
+
+#[tonic::async_trait]
+impl ActionCache for StandInServer {
+    async fn get_action_result(
+        &self,
+        request: Request<GetActionResultRequest>,
+    ) -> Result<Response<ActionResult>, Status> {
+        let digest = request.into_inner().action_digest.unwrap();
+
+        self.action_results
+            .get(&digest.hash)
+            .map(|result| Response::new(result.clone()))
+            .ok_or_else(|| Status::not_found(digest.hash))
+    }
+
+    async fn update_action_result(
+        &self,
+        request: Request<UpdateActionResultRequest>,
+    ) -> Result<Response<ActionResult>, Status> {
+        let request = request.into_inner();
+        let result = request.action_result.unwrap();
+
+        self.action_results
+            .insert(request.action_digest.unwrap().hash, result.clone());
+
+        Ok(Response::new(result))
+    }
+}
+
+#[tonic::async_trait]
+impl ContentAddressableStorage for StandInServer {
+    async fn find_missing_blobs(
+        &self,
+        request: Request<FindMissingBlobsRequest>,
+    ) -> Result<Response<FindMissingBlobsResponse>, Status> {
+        Ok(Response::new(FindMissingBlobsResponse {
+            missing_blob_digests: request
+                .into_inner()
+                .blob_digests
+                .into_iter()
+                .filter(|digest| !self.blobs.contains_key(&digest.hash))
+                .collect(),
+        }))
+    }
+
+    async fn batch_update_blobs(
+        &self,
+        request: Request<BatchUpdateBlobsRequest>,
+    ) -> Result<Response<BatchUpdateBlobsResponse>, Status> {
+        let responses = request
+            .into_inner()
+            .requests
+            .into_iter()
+            .map(|batch_update_blobs_request::Request { digest, data, .. }| {
+                let digest = digest.unwrap();
+                self.blobs.insert(digest.hash.clone(), data);
+                batch_update_blobs_response::Response {
+                    digest: Some(digest),
+                    status: None,
+                }
+            })
+            .collect();
+
+        Ok(Response::new(BatchUpdateBlobsResponse { responses }))
+    }
+
+    async fn batch_read_blobs(
+        &self,
+        _request: Request<BatchReadBlobsRequest>,
+    ) -> Result<Response<BatchReadBlobsResponse>, Status> {
+        Err(Status::unimplemented("not used by zako"))
+    }
+}
+
+fn hash_of_resource_name(resource_name: &str) -> String {
+    let parts: Vec<&str> = resource_name.split('/').collect();
+    parts[parts.len() - 2].to_string()
+}
+
+#[tonic::async_trait]
+impl ByteStream for StandInServer {
+    type ReadStream = Pin<Box<dyn Stream<Item = Result<ReadResponse, Status>> + Send>>;
+
+    async fn read(
+        &self,
+        request: Request<ReadRequest>,
+    ) -> Result<Response<Self::ReadStream>, Status> {
+        let request = request.into_inner();
+        let data = self
+            .blobs
+            .get(&hash_of_resource_name(&request.resource_name))
+            .ok_or_else(|| Status::not_found(request.resource_name.clone()))?
+            .clone();
+
+        let start = request.read_offset as usize;
+        let end = if request.read_limit == 0 {
+            data.len()
+        } else {
+            start + request.read_limit as usize
+        };
+
+        let chunks: Vec<Result<ReadResponse, Status>> = data[start..end]
+            .chunks(3)
+            .map(|chunk| {
+                Ok(ReadResponse {
+                    data: Bytes::copy_from_slice(chunk),
+                })
+            })
+            .collect();
+
+        Ok(Response::new(Box::pin(tokio_stream::iter(chunks))))
+    }
+
+    async fn write(
+        &self,
+        request: Request<Streaming<WriteRequest>>,
+    ) -> Result<Response<WriteResponse>, Status> {
+        let mut stream = request.into_inner();
+        let mut resource_name = String::new();
+        let mut data = Vec::new();
+
+        while let Some(request) = stream.next().await {
+            let request = request?;
+            if !request.resource_name.is_empty() {
+                resource_name = request.resource_name;
+            }
+            data.extend(request.data);
+        }
+
+        let committed_size = data.len() as i64;
+        self.blobs
+            .insert(hash_of_resource_name(&resource_name), data);
+
+        Ok(Response::new(WriteResponse { committed_size }))
+    }
+}
+
+/// Start the stand-in server, `with_action_cache` is false for the servers that have no `ActionCache`.
+async fn start_server(with_action_cache: bool) -> (String, StandInServer) {
+    let server = StandInServer::default();
+    let incoming = TcpIncoming::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let address = incoming.local_addr().unwrap();
+
+    let service = server.clone();
+    tokio::spawn(async move {
+        Server::builder()
+            .add_optional_service(
+                with_action_cache.then(|| ActionCacheServer::new(service.clone())),
+            )
+            .add_service(ContentAddressableStorageServer::new(service.clone()))
+            .add_service(ByteStreamServer::new(service))
+            .serve_with_incoming(incoming)
+            .await
+            .unwrap();
+    });
+
+    (format!("grpc://{}", address), server)
+}
+
+async fn start_stand_in_server() -> (String, StandInServer) {
+    start_server(true).await
+}
+
+async fn read_all(cas: &ReapiCas, digest: &Digest, range: &BlobRange) -> Vec<u8> {
+    let mut reader = cas.fetch(digest, range).await.unwrap();
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await.unwrap();
+    data
+}
+
+#[tokio::test]
+async fn test_reapi_cas_batch_round_trip() {
+    let (endpoint, server) = start_stand_in_server().await;
+    let cas = ReapiCas::connect(&endpoint, ReapiCasOptions::default())
+        .await
+        .unwrap();
+
+    let data = b"hello remote execution api".to_vec();
+    let digest = digest_of(&data);
+
+    assert!(!cas.contains(&digest).await);
+
+    cas.store(&digest, Box::new(std::io::Cursor::new(data.clone())))
+        .await
+        .unwrap();
+
+    let sha256 = hex::encode(<sha2::Sha256 as sha2::Digest>::digest(&data));
+    assert!(server.blobs.contains_key(&sha256));
+    assert_eq!(cas.check(&digest).await, Some(data.len() as u64));
+    assert_eq!(read_all(&cas, &digest, &BlobRange::full()).await, data);
+    assert_eq!(
+        read_all(&cas, &digest, &BlobRange::new(6, Some(6)).unwrap()).await,
+        b"remote".to_vec()
+    );
+}
+
+#[tokio::test]
+async fn test_reapi_cas_byte_stream_round_trip() {
+    let (endpoint, _server) = start_stand_in_server().await;
+    let cas = ReapiCas::connect(
+        &endpoint,
+        ReapiCasOptions {
+            max_batch_blob_size: 0,
+            chunk_size: 4,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let data: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+    let digest = digest_of(&data);
+
+    cas.store(&digest, Box::new(std::io::Cursor::new(data.clone())))
+        .await
+        .unwrap();
+
+    assert_eq!(read_all(&cas, &digest, &BlobRange::full()).await, data);
+}
+
+#[tokio::test]
+async fn test_reapi_cas_blake3_needs_no_index() {
+    let (endpoint, server) = start_stand_in_server().await;
+    let cas = ReapiCas::connect(
+        &endpoint,
+        ReapiCasOptions {
+            digest_function: ReapiDigestFunction::Blake3,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+
+    let data = b"blake3 all the way".to_vec();
+    let digest = digest_of(&data);
+    server
+        .blobs
+        .insert(digest.blake3.to_hex().to_string(), data.clone());
+
+    assert!(cas.contains(&digest).await);
+    assert_eq!(read_all(&cas, &digest, &BlobRange::full()).await, data);
+}
+
+#[tokio::test]
+async fn test_reapi_cas_unknown_sha256_is_not_found() {
+    let (endpoint, _server) = start_stand_in_server().await;
+    let cas = ReapiCas::connect(&endpoint, ReapiCasOptions::default())
+        .await
+        .unwrap();
+
+    let digest = digest_of(b"never stored");
+
+    assert!(!cas.contains(&digest).await);
+    assert!(matches!(
+        cas.fetch(&digest, &BlobRange::full()).await,
+        Err(CasError::NotFound(..))
+    ));
+}
+
+#[tokio::test]
+async fn test_reapi_cas_sha256_is_shared_between_clients() {
+    let (endpoint, server) = start_stand_in_server().await;
+    let first = ReapiCas::connect(&endpoint, ReapiCasOptions::default())
+        .await
+        .unwrap();
+    let second = ReapiCas::connect(&endpoint, ReapiCasOptions::default())
+        .await
+        .unwrap();
+
+    let data = b"stored by another machine".to_vec();
+    let digest = digest_of(&data);
+
+    first
+        .store(&digest, Box::new(std::io::Cursor::new(data.clone())))
+        .await
+        .unwrap();
+    assert!(
+        server
+            .action_results
+            .contains_key(&index_action_digest(&digest).hash)
+    );
+
+    // the second client has never seen the blob
+    assert_eq!(second.sha256_index().get(&digest.blake3).unwrap(), None);
+    assert!(second.contains(&digest).await);
+    assert_eq!(read_all(&second, &digest, &BlobRange::full()).await, data);
+    assert!(second.sha256_index().get(&digest.blake3).unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_reapi_cas_without_action_cache_fails_loudly() {
+    let (endpoint, _server) = start_server(false).await;
+    let cas = ReapiCas::connect(&endpoint, ReapiCasOptions::default())
+        .await
+        .unwrap();
+
+    let data = b"no action cache".to_vec();
+    let digest = digest_of(&data);
+
+    assert!(matches!(
+        cas.store(&digest, Box::new(std::io::Cursor::new(data)))
+            .await,
+        Err(CasError::Internal(..))
+    ));
+    assert!(matches!(
+        cas.fetch(&digest_of(b"unknown"), &BlobRange::full()).await,
+        Err(CasError::Internal(..))
+    ));
+}
+
+#[tokio::test]
+async fn test_reapi_cas_byte_stream_from_the_reader() {
+    let (endpoint, server) = start_stand_in_server().await;
+
+    for digest_function in [ReapiDigestFunction::Sha256, ReapiDigestFunction::Blake3] {
+        let cas = ReapiCas::connect(
+            &endpoint,
+            ReapiCasOptions {
+                digest_function,
+                max_batch_blob_size: 0,
+                chunk_size: 7,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let data: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        let digest = digest_of(&data);
+
+        // a reader that ends early or goes on is not the blob of the digest
+        for wrong in [&data[..999], &[data.as_slice(), b"!"].concat()[..]] {
+            assert!(
+                cas.store(&digest, Box::new(std::io::Cursor::new(wrong.to_vec())))
+                    .await
+                    .is_err()
+            );
+            // unlike a real server, the stand-in keeps a partial write
+            server.blobs.clear();
+        }
+
+        cas.store(&digest, Box::new(std::io::Cursor::new(data.clone())))
+            .await
+            .unwrap();
+
+        assert_eq!(read_all(&cas, &digest, &BlobRange::full()).await, data);
+    }
+}
+
+#[tokio::test]
+async fn test_reapi_cas_refuses_a_wrong_published_sha256() {
+    let (endpoint, server) = start_stand_in_server().await;
+    let first = ReapiCas::connect(&endpoint, ReapiCasOptions::default())
+        .await
+        .unwrap();
+    let second = ReapiCas::connect(&endpoint, ReapiCasOptions::default())
+        .await
+        .unwrap();
+
+    let data = b"the blob that is stored".to_vec();
+    let other = b"another blob, same size".to_vec();
+    assert_eq!(data.len(), other.len());
+    let digest = digest_of(&data);
+    let other_digest = digest_of(&other);
+
+    first
+        .store(&other_digest, Box::new(std::io::Cursor::new(other.clone())))
+        .await
+        .unwrap();
+
+    // publish the sha256 of the other blob for the digest
+    let published = server
+        .action_results
+        .get(&index_action_digest(&other_digest).hash)
+        .unwrap()
+        .clone();
+    server
+        .action_results
+        .insert(index_action_digest(&digest).hash, published);
+
+    let mut reader = second.fetch(&digest, &BlobRange::full()).await.unwrap();
+    assert!(reader.read_to_end(&mut Vec::new()).await.is_err());
+    assert!(
+        second
+            .fetch(&digest, &BlobRange::new(0, Some(5)).unwrap())
+            .await
+            .is_err()
+    );
+    assert_eq!(second.sha256_index().get(&digest.blake3).unwrap(), None);
+
+    // the right mapping is cached once it is verified
+    assert_eq!(
+        read_all(&second, &other_digest, &BlobRange::full()).await,
+        other
+    );
+    assert!(
+        second
+            .sha256_index()
+            .get(&other_digest.blake3)
+            .unwrap()
+            .is_some()
+    );
+}
+
+#[test]
+fn test_reapi_cas_connects_lazily() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (endpoint, _server) = runtime.block_on(start_stand_in_server());
+
+    // no runtime is entered here, like the cli opening its remote caches
+    let cas = ReapiCas::lazy(&endpoint, ReapiCasOptions::default()).unwrap();
+    assert!(ReapiCas::lazy("not a url", ReapiCasOptions::default()).is_err());
+
+    let data = b"connected on the first request".to_vec();
+    let digest = digest_of(&data);
+
+    runtime.block_on(async {
+        cas.store(&digest, Box::new(std::io::Cursor::new(data.clone())))
+            .await
+            .unwrap();
+        assert_eq!(read_all(&cas, &digest, &BlobRange::full()).await, data);
+    });
+}
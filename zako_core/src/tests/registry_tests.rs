@@ -0,0 +1,244 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::fetch::{FetchError, PackageCache};
+use crate::http_registry::{HttpRegistry, HttpRegistryOptions};
+use crate::local_registry::LocalRegistry;
+use crate::package_source::PackageSource;
+use crate::registry::{
+    IndexDependency, IndexEntry, PackageIndex, Registry, RegistryError, archive_path, index_path,
+    parse_requirement,
+};
+use crate::tests::fetch_archive_tests::{package_entries, tar_zst};
+use crate::tests::http_stand_in::HttpStandIn;
+use crate::tests::{memory_store, temp_root};
+
+fn entry(version: &str, archive: &[u8], yanked: bool) -> IndexEntry {
+    IndexEntry {
+        version: version.parse().unwrap(),
+        integrity: format!("blake3:{}", ::blake3::hash(archive).to_hex()),
+        dependencies: vec![IndexDependency {
+            package: "test.pkgs:dep".to_string(),
+            req: "^0.1".parse().unwrap(),
+        }],
+        yanked,
+    }
+}
+
+/// The files of a registry with `test.pkgs:lib` 1.0.0, 1.2.0, the yanked 1.3.0 and 2.0.0.
+fn registry_files() -> Vec<(String, Vec<u8>)> {
+    let name = parse_requirement("test.pkgs:lib").unwrap().name;
+    let archive = tar_zst(&package_entries());
+    let versions = [
+        ("1.0.0", false),
+        ("1.2.0", false),
+        ("1.3.0", true),
+        ("2.0.0", false),
+    ];
+
+    let index = PackageIndex {
+        group: "test.pkgs".to_string(),
+        artifact: "lib".to_string(),
+        versions: versions
+            .iter()
+            .map(|(version, yanked)| entry(version, &archive, *yanked))
+            .collect(),
+    };
+
+    let mut files = vec![(
+        index_path(&name),
+        serde_json::to_vec_pretty(&index).unwrap(),
+    )];
+
+    for (version, _) in versions {
+        files.push((
+            archive_path(&name, &version.parse().unwrap()),
+            archive.clone(),
+        ));
+    }
+
+    files
+}
+
+fn write_files(root: &Path, files: &[(String, Vec<u8>)]) {
+    for (path, content) in files {
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+}
+
+#[test]
+fn test_select_version() {
+    let archive = b"archive";
+    let index = PackageIndex {
+        group: "test.pkgs".to_string(),
+        artifact: "lib".to_string(),
+        versions: vec![
+            entry("1.0.0", archive, false),
+            entry("1.2.0", archive, false),
+            entry("1.3.0", archive, true),
+            entry("2.0.0", archive, false),
+        ],
+    };
+
+    let select = |req: &str| {
+        index
+            .select(&req.parse().unwrap())
+            .map(|entry| entry.version.to_string())
+    };
+
+    assert_eq!(select("^1").as_deref(), Some("1.2.0"));
+    assert_eq!(select("*").as_deref(), Some("2.0.0"));
+    assert_eq!(select("=1.3.0").as_deref(), Some("1.3.0"));
+    assert_eq!(select("^3"), None);
+
+    let req = parse_requirement("test.pkgs:lib@1.2.0").unwrap();
+    assert!(req.version.matches(&"1.2.0".parse().unwrap()));
+    assert!(!req.version.matches(&"1.2.1".parse().unwrap()));
+    assert!(matches!(
+        parse_requirement("test.pkgs"),
+        Err(RegistryError::InvalidRequirement(..))
+    ));
+}
+
+#[tokio::test]
+async fn test_fetch_from_local_registry() {
+    let root = temp_root("registry");
+    write_files(&root.join("registry"), &registry_files());
+
+    let registry = Arc::new(LocalRegistry::new(root.join("registry")));
+    let cache = PackageCache::new(root.join("cache")).with_registry(registry.clone());
+    let cas = memory_store();
+
+    let fetched = cache
+        .fetch(
+            &PackageSource::Registry {
+                package: "test.pkgs:lib@^1".to_string(),
+            },
+            &cas,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        fetched.pinned,
+        PackageSource::Registry {
+            package: "test.pkgs:lib@1.2.0".to_string(),
+        }
+    );
+    assert!(fetched.root.join("pkg-1.0").join("zako.toml").exists());
+
+    let resolved = registry
+        .resolve(&parse_requirement("test.pkgs:lib@>=2").unwrap())
+        .await
+        .unwrap();
+    assert_eq!(resolved.dependencies[0].package, "test.pkgs:dep");
+
+    let missing = registry
+        .resolve(&parse_requirement("test.pkgs:missing").unwrap())
+        .await;
+    assert!(matches!(missing, Err(RegistryError::PackageNotFound(_))));
+
+    let unmatched = registry
+        .resolve(&parse_requirement("test.pkgs:lib@^3").unwrap())
+        .await;
+    assert!(matches!(
+        unmatched,
+        Err(RegistryError::NoMatchingVersion { available, .. })
+            if available == "1.0.0, 1.2.0, 1.3.0, 2.0.0"
+    ));
+
+    // no registry is configured
+    let result = PackageCache::new(root.join("cache"))
+        .fetch(
+            &PackageSource::Registry {
+                package: "test.pkgs:lib".to_string(),
+            },
+            &cas,
+        )
+        .await;
+    assert!(matches!(result, Err(FetchError::NoRegistry(_))));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_fetch_from_http_registry() {
+    let root = temp_root("registry");
+    let server = HttpStandIn::default();
+    for (path, content) in registry_files() {
+        server.files.insert(format!("/registry/{}", path), content);
+    }
+    let base = server.clone().start().await;
+
+    let registry = Arc::new(
+        HttpRegistry::new(
+            format!("{}/registry", base).parse().unwrap(),
+            HttpRegistryOptions::default(),
+        )
+        .unwrap(),
+    );
+    let cache = PackageCache::new(root.join("cache")).with_registry(registry.clone());
+    let cas = memory_store();
+
+    let fetched = cache
+        .fetch(
+            &PackageSource::Registry {
+                package: "test.pkgs:lib".to_string(),
+            },
+            &cas,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        fetched.pinned,
+        PackageSource::Registry {
+            package: "test.pkgs:lib@2.0.0".to_string(),
+        }
+    );
+    assert!(
+        fetched
+            .root
+            .join("pkg-1.0")
+            .join("bin")
+            .join("run")
+            .exists()
+    );
+
+    // an archive that does not match the index is refused
+    let name = parse_requirement("test.pkgs:other").unwrap().name;
+    let index = PackageIndex {
+        group: "test.pkgs".to_string(),
+        artifact: "other".to_string(),
+        versions: vec![entry("1.0.0", b"expected", false)],
+    };
+    server.files.insert(
+        format!("/registry/{}", index_path(&name)),
+        serde_json::to_vec(&index).unwrap(),
+    );
+    server.files.insert(
+        format!(
+            "/registry/{}",
+            archive_path(&name, &"1.0.0".parse().unwrap())
+        ),
+        tar_zst(&package_entries()),
+    );
+
+    let result = cache
+        .fetch(
+            &PackageSource::Registry {
+                package: "test.pkgs:other".to_string(),
+            },
+            &cas,
+        )
+        .await;
+    assert!(matches!(result, Err(FetchError::IntegrityMismatch { .. })));
+
+    assert!(registry.index(&name).await.unwrap().is_some());
+    let missing = parse_requirement("test.pkgs:missing").unwrap().name;
+    assert!(registry.index(&missing).await.unwrap().is_none());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
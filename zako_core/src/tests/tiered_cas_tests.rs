@@ -0,0 +1,294 @@
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use zako_digest::Digest;
+
+use crate::blob_range::BlobRange;
+use crate::cas::{Cas, CasError};
+use crate::cas_store::{CasStore, CasStoreOptions, DEFAULT_MAX_INLINED_BLOB_SIZE};
+use crate::cas_upload::UploadQueueOptions;
+use crate::local_cas::LocalCas;
+use crate::tests::{digest_of, temp_root};
+use crate::tiered_cas::{CasTier, TierAccess, TieredCas};
+
+async fn put(cas: &dyn Cas, data: &[u8]) -> Digest {
+    let digest = digest_of(data);
+    cas.store(&digest, Box::new(std::io::Cursor::new(data.to_vec())))
+        .await
+        .unwrap();
+    digest
+}
+
+async fn read(cas: &dyn Cas, digest: &Digest, range: &BlobRange) -> Vec<u8> {
+    let mut data = Vec::new();
+    cas.fetch(digest, range)
+        .await
+        .unwrap()
+        .read_to_end(&mut data)
+        .await
+        .unwrap();
+    data
+}
+
+#[tokio::test]
+async fn test_fetch_populates_faster_writable_tiers() {
+    let fast = Arc::new(LocalCas::new(temp_root("tiered-cas")));
+    let read_only = Arc::new(LocalCas::new(temp_root("tiered-cas")));
+    let slow = Arc::new(LocalCas::new(temp_root("tiered-cas")));
+
+    let data = b"hello from the slowest tier".to_vec();
+    let digest = put(slow.as_ref(), &data).await;
+
+    let tiered = TieredCas::new(vec![
+        CasTier::new("fast", fast.clone(), TierAccess::ReadWrite),
+        CasTier::new("read-only", read_only.clone(), TierAccess::ReadOnly),
+        CasTier::new("slow", slow.clone(), TierAccess::ReadWrite),
+    ]);
+
+    assert_eq!(
+        read(&tiered, &digest, &BlobRange::new(6, Some(4)).unwrap()).await,
+        b"from"
+    );
+
+    assert!(fast.contains(&digest).await);
+    assert!(!read_only.contains(&digest).await);
+
+    // served by the fast tier now
+    assert_eq!(read(&tiered, &digest, &BlobRange::full()).await, data);
+
+    let reports = tiered.reports();
+    assert_eq!((reports[0].hits, reports[0].misses), (1, 1));
+    assert_eq!((reports[1].hits, reports[1].misses), (0, 1));
+    assert_eq!((reports[2].hits, reports[2].misses), (1, 0));
+}
+
+#[tokio::test]
+async fn test_store_skips_read_only_tiers() {
+    let writable = Arc::new(LocalCas::new(temp_root("tiered-cas")));
+    let read_only = Arc::new(LocalCas::new(temp_root("tiered-cas")));
+
+    let tiered = TieredCas::new(vec![
+        CasTier::new("read-only", read_only.clone(), TierAccess::ReadOnly),
+        CasTier::new("writable", writable.clone(), TierAccess::ReadWrite),
+    ]);
+
+    let digest = put(&tiered, b"only to the writable tier").await;
+
+    assert!(writable.contains(&digest).await);
+    assert!(!read_only.contains(&digest).await);
+
+    let nothing_writable = TieredCas::new(vec![CasTier::new(
+        "read-only",
+        read_only.clone(),
+        TierAccess::ReadOnly,
+    )]);
+    assert!(!nothing_writable.is_writable());
+}
+
+#[tokio::test]
+async fn test_fetch_missing_blob_counts_misses() {
+    let tiered = TieredCas::new(vec![
+        CasTier::new(
+            "first",
+            Arc::new(LocalCas::new(temp_root("tiered-cas"))),
+            TierAccess::ReadWrite,
+        ),
+        CasTier::new(
+            "second",
+            Arc::new(LocalCas::new(temp_root("tiered-cas"))),
+            TierAccess::ReadOnly,
+        ),
+    ]);
+
+    let digest = digest_of(b"missing");
+    assert!(tiered.fetch(&digest, &BlobRange::full()).await.is_err());
+
+    for report in tiered.reports() {
+        assert_eq!((report.hits, report.misses), (0, 1));
+    }
+}
+
+#[tokio::test]
+async fn test_cas_store_reads_through_remote_tiers() {
+    let remote = Arc::new(LocalCas::new(temp_root("tiered-cas")));
+    let data: Vec<u8> = (0..=255u8).cycle().take(8 * 1024).collect();
+    let digest = put(remote.as_ref(), &data).await;
+
+    let store = CasStore::new(
+        Box::new(LocalCas::new(temp_root("tiered-cas"))),
+        vec![CasTier::new("remote", remote, TierAccess::ReadOnly)],
+        CasStoreOptions {
+            max_cache_capacity: 1024 * 1024,
+            max_cache_ttl: Duration::from_secs(60),
+            max_cache_tti: Duration::from_secs(60),
+            upload_queue: UploadQueueOptions::default(),
+            max_inlined_blob_size: DEFAULT_MAX_INLINED_BLOB_SIZE,
+            lease_dir: None,
+        },
+    );
+
+    assert!(store.get_upload_queue().is_none());
+
+    assert_eq!(
+        store
+            .read(&digest, &BlobRange::new(4096, None).unwrap())
+            .await
+            .unwrap(),
+        data[4096..]
+    );
+    assert!(store.get_local_cas().contains(&digest).await);
+
+    // the second read is served by the memory cache
+    assert_eq!(store.read(&digest, &BlobRange::full()).await.unwrap(), data);
+
+    let reports = store.tier_reports();
+    let names: Vec<&str> = reports.iter().map(|report| report.name.as_str()).collect();
+    assert_eq!(names, ["memory", "local", "remote"]);
+    assert_eq!((reports[0].hits, reports[0].misses), (1, 1));
+    assert_eq!((reports[1].hits, reports[1].misses), (0, 1));
+    assert_eq!((reports[2].hits, reports[2].misses), (1, 0));
+}
+
+/// A writable tier that fails every store and has nothing.
+#[derive(Debug)]
+struct UnavailableCas;
+
+#[async_trait]
+impl Cas for UnavailableCas {
+    async fn store(
+        &self,
+        _digest: &Digest,
+        _data: Box<dyn AsyncRead + Send + Unpin + 'static>,
+    ) -> Result<(), CasError> {
+        Err(CasError::Internal("unavailable".to_string()))
+    }
+
+    async fn check(&self, _digest: &Digest) -> Option<u64> {
+        None
+    }
+
+    async fn contains(&self, _digest: &Digest) -> bool {
+        false
+    }
+
+    async fn fetch(
+        &self,
+        digest: &Digest,
+        _range: &BlobRange,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, CasError> {
+        Err(CasError::NotFound(*digest, PathBuf::new()))
+    }
+
+    async fn get_local_path(&self, _digest: &Digest) -> Option<PathBuf> {
+        None
+    }
+}
+
+#[tokio::test]
+async fn test_store_and_populate_stream_to_every_tier() {
+    let first = Arc::new(LocalCas::new(temp_root("tiered-cas")));
+    let second = Arc::new(LocalCas::new(temp_root("tiered-cas")));
+    let data: Vec<u8> = (0..=255u8).cycle().take(1024 * 1024 + 7).collect();
+
+    let tiered = TieredCas::new(vec![
+        CasTier::new(
+            "unavailable",
+            Arc::new(UnavailableCas),
+            TierAccess::ReadWrite,
+        ),
+        CasTier::new("first", first.clone(), TierAccess::ReadWrite),
+        CasTier::new("second", second.clone(), TierAccess::ReadWrite),
+    ]);
+
+    // a failing tier fails the store but never stops the others
+    let digest = digest_of(&data);
+    assert!(
+        tiered
+            .store(&digest, Box::new(std::io::Cursor::new(data.clone())))
+            .await
+            .is_err()
+    );
+    assert_eq!(
+        read(first.as_ref(), &digest, &BlobRange::full()).await,
+        data
+    );
+    assert_eq!(
+        read(second.as_ref(), &digest, &BlobRange::full()).await,
+        data
+    );
+
+    // a hit in the slowest tier populates the faster ones
+    let slow = Arc::new(LocalCas::new(temp_root("tiered-cas")));
+    let digest = put(slow.as_ref(), &data[7..]).await;
+
+    let tiered = TieredCas::new(vec![
+        CasTier::new("first", first.clone(), TierAccess::ReadWrite),
+        CasTier::new(
+            "unavailable",
+            Arc::new(UnavailableCas),
+            TierAccess::ReadWrite,
+        ),
+        CasTier::new("second", second.clone(), TierAccess::ReadWrite),
+        CasTier::new("slow", slow, TierAccess::ReadOnly),
+    ]);
+
+    assert_eq!(
+        read(&tiered, &digest, &BlobRange::new(1_000_000, None).unwrap()).await,
+        data[1_000_007..]
+    );
+    assert_eq!(
+        read(first.as_ref(), &digest, &BlobRange::full()).await,
+        data[7..]
+    );
+    assert_eq!(
+        read(second.as_ref(), &digest, &BlobRange::full()).await,
+        data[7..]
+    );
+}
+
+#[tokio::test]
+async fn test_store_and_populate_refuse_data_of_another_blob() {
+    let first = Arc::new(LocalCas::new(temp_root("tiered-cas")));
+    let second = Arc::new(LocalCas::new(temp_root("tiered-cas")));
+    let data: Vec<u8> = (0..=255u8).cycle().take(256 * 1024).collect();
+    let digest = digest_of(&data);
+
+    let tiered = TieredCas::new(vec![
+        CasTier::new("first", first.clone(), TierAccess::ReadWrite),
+        CasTier::new("second", second.clone(), TierAccess::ReadWrite),
+    ]);
+
+    let mut corrupted = data.clone();
+    corrupted[1000] ^= 1;
+
+    assert!(
+        tiered
+            .store(&digest, Box::new(std::io::Cursor::new(corrupted.clone())))
+            .await
+            .is_err()
+    );
+    assert!(!first.contains(&digest).await);
+    assert!(!second.contains(&digest).await);
+
+    // the local cas stores what it reads, like a remote tier that serves broken data
+    let broken = Arc::new(LocalCas::new(temp_root("tiered-cas")));
+    broken
+        .store(&digest, Box::new(std::io::Cursor::new(corrupted)))
+        .await
+        .unwrap();
+
+    let tiered = TieredCas::new(vec![
+        CasTier::new("first", first.clone(), TierAccess::ReadWrite),
+        CasTier::new("broken", broken, TierAccess::ReadOnly),
+    ]);
+
+    assert!(tiered.fetch(&digest, &BlobRange::full()).await.is_err());
+    assert!(!first.contains(&digest).await);
+
+    let reports = tiered.reports();
+    assert_eq!((reports[1].hits, reports[1].misses), (0, 1));
+}
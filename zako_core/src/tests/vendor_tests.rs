@@ -0,0 +1,146 @@
+use std::path::PathBuf;
+
+use crate::consts::VENDOR_CONFIG_FILE_NAME;
+use crate::fetch::PackageCache;
+use crate::lockfile::Lockfile;
+use crate::package_source::PackageSource;
+use crate::tests::{Origin, memory_store, temp_root};
+use crate::vendor::{Vendor, VendorConfig, VendorError, VendorReport, VendoredPackage, vendor};
+
+fn git(repo: &str, checkout: Option<&str>) -> PackageSource {
+    PackageSource::Git {
+        repo: repo.into(),
+        checkout: checkout.map(Into::into),
+    }
+}
+
+#[tokio::test]
+async fn test_vendor_is_incremental() {
+    let root = temp_root("vendor");
+    let origin = Origin::new(&root);
+    let first = origin.commit("1.0.0");
+
+    let cache = PackageCache::new(root.join("cache"));
+    let cas = memory_store();
+    let vendor_dir = root.join("vendor");
+    let requested = git(&origin.url(), Some("main"));
+    let local = PackageSource::Path {
+        path: "../local".to_string(),
+    };
+
+    let mut lockfile = Lockfile::default();
+    let report = vendor(
+        &vendor_dir,
+        &mut lockfile,
+        &cache,
+        &cas,
+        [requested.clone(), local.clone()],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        report,
+        VendorReport {
+            copied: 1,
+            ..Default::default()
+        }
+    );
+
+    let pinned = git(&origin.url(), Some(&first));
+    assert_eq!(lockfile.get(&requested), Some(&pinned));
+
+    let opened = Vendor::open(&vendor_dir).unwrap().unwrap();
+    assert_eq!(opened.get_config().packages.len(), 1);
+    assert_eq!(opened.get_config().get(&local), None);
+
+    let (copy, replaced) = opened.replace(&requested, Some(&pinned)).unwrap();
+    assert_eq!(replaced, &pinned);
+    assert!(copy.join("zako.toml").is_file());
+    assert!(!copy.join(".git").exists());
+
+    // the copy of the pinned commit is reused without fetching
+    let report = vendor(
+        &vendor_dir,
+        &mut lockfile,
+        &cache,
+        &cas,
+        [requested.clone()],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        report,
+        VendorReport {
+            reused: 1,
+            ..Default::default()
+        }
+    );
+
+    // a new pin replaces the stale copy
+    let second = origin.commit("1.1.0");
+    let moved = git(&origin.url(), Some(&second));
+    lockfile.insert(requested.clone(), moved.clone());
+    assert!(matches!(
+        opened.replace(&requested, Some(&moved)),
+        Err(VendorError::Stale(source)) if source == requested
+    ));
+
+    // a dependency that is not vendored is never fetched
+    let other = git("https://example.com/other.git", None);
+    assert!(matches!(
+        opened.replace(&other, None),
+        Err(VendorError::NotVendored(source)) if source == other
+    ));
+
+    let report = vendor(
+        &vendor_dir,
+        &mut lockfile,
+        &cache,
+        &cas,
+        [requested.clone()],
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        report,
+        VendorReport {
+            copied: 1,
+            reused: 0,
+            removed: 1,
+        }
+    );
+    assert!(!copy.exists());
+
+    let opened = Vendor::open(&vendor_dir).unwrap().unwrap();
+    let (copy, _) = opened.replace(&requested, Some(&moved)).unwrap();
+    assert!(
+        std::fs::read_to_string(copy.join("zako.toml"))
+            .unwrap()
+            .contains("1.1.0")
+    );
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_vendor_config_is_deterministic() {
+    let mut config = VendorConfig::default();
+    config.packages.push(VendoredPackage {
+        requested: git("https://example.com/b.git", None),
+        pinned: git("https://example.com/b.git", Some("0123")),
+        path: "b-1.0.0-00000000".to_string(),
+    });
+    config.packages.push(VendoredPackage {
+        requested: git("https://example.com/a.git", None),
+        pinned: git("https://example.com/a.git", Some("4567")),
+        path: "a-1.0.0-00000000".to_string(),
+    });
+
+    let content = config.to_toml().unwrap();
+    let path = PathBuf::from(VENDOR_CONFIG_FILE_NAME);
+    let parsed = VendorConfig::parse(&path, &content).unwrap();
+
+    assert_eq!(parsed.packages[0].path, "a-1.0.0-00000000");
+    assert_eq!(parsed.to_toml().unwrap(), content);
+    assert_eq!(Vendor::open(&temp_root("vendor")).unwrap(), None);
+}
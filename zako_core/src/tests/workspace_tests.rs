@@ -0,0 +1,132 @@
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::tests::{TEST_INTERNER, temp_root};
+use crate::workspace::{Workspace, WorkspaceError};
+
+fn workspace_root() -> Utf8PathBuf {
+    let root = temp_root("workspace");
+    std::fs::create_dir_all(&root).unwrap();
+    Utf8PathBuf::from_path_buf(root.canonicalize().unwrap()).unwrap()
+}
+
+fn write_manifest(dir: &Utf8Path, artifact: &str, extra: &str) {
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(
+        dir.join("zako.toml"),
+        format!(
+            "group = \"test.ws\"\nartifact = \"{}\"\nversion = \"1.0.0\"\n{}",
+            artifact, extra
+        ),
+    )
+    .unwrap();
+}
+
+/// A workspace with `packages/*` members, a nested package and a package out of the patterns.
+fn workspace(root: &Utf8Path) {
+    write_manifest(root, "root", "workspaces = [\"packages/*\"]\n");
+    write_manifest(&root.join("packages").join("b"), "b", "");
+    write_manifest(&root.join("packages").join("a"), "a", "");
+    write_manifest(&root.join("tools").join("other"), "other", "");
+    std::fs::create_dir_all(root.join("packages").join("a").join("src")).unwrap();
+}
+
+fn ids(workspace: &Workspace) -> Vec<String> {
+    workspace
+        .get_members()
+        .iter()
+        .map(|member| member.get_id())
+        .collect()
+}
+
+#[test]
+fn test_open_discovers_members() {
+    let root = workspace_root();
+    workspace(&root);
+
+    let opened = Workspace::open(&root).unwrap();
+    assert_eq!(opened.get_root(), &root);
+    assert_eq!(
+        ids(&opened),
+        vec!["test.ws:root@1.0.0", "test.ws:a@1.0.0", "test.ws:b@1.0.0"]
+    );
+
+    let nested = root.join("packages").join("a").join("src");
+    assert_eq!(
+        opened.get_member_of(&nested).map(|member| member.get_id()),
+        Some("test.ws:a@1.0.0".to_string())
+    );
+    assert_eq!(
+        opened
+            .get_member_of(&root.join("tools"))
+            .map(|member| member.get_id()),
+        Some("test.ws:root@1.0.0".to_string())
+    );
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_discover_the_enclosing_workspace() {
+    let root = workspace_root();
+    workspace(&root);
+
+    // from a member and from a directory in a member
+    for start in [
+        root.join("packages").join("b"),
+        root.join("packages").join("a").join("src"),
+        root.clone(),
+    ] {
+        let discovered = Workspace::discover(&start).unwrap();
+        assert_eq!(discovered.get_root(), &root);
+    }
+
+    // a package that is not a member is a workspace of itself
+    let other = Workspace::discover(&root.join("tools").join("other")).unwrap();
+    assert_eq!(other.get_root(), &root.join("tools").join("other"));
+    assert_eq!(ids(&other), vec!["test.ws:other@1.0.0"]);
+
+    let empty = workspace_root();
+    assert!(matches!(
+        Workspace::discover(&empty),
+        Err(WorkspaceError::NoManifest(_))
+    ));
+
+    std::fs::remove_dir_all(&root).unwrap();
+    std::fs::remove_dir_all(&empty).unwrap();
+}
+
+#[test]
+fn test_member_package_id() {
+    let root = workspace_root();
+    workspace(&root);
+
+    let opened = Workspace::open(&root).unwrap();
+    let member = opened
+        .get_member_of(&root.join("packages").join("b"))
+        .unwrap();
+    let id = member.get_package_id(&TEST_INTERNER).unwrap();
+
+    assert_eq!(id.resolved(&TEST_INTERNER).unwrap(), "test.ws:b@1.0.0");
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_duplicate_members() {
+    let root = workspace_root();
+    workspace(&root);
+    write_manifest(&root.join("packages").join("c"), "a", "");
+
+    assert!(matches!(
+        Workspace::open(&root),
+        Err(WorkspaceError::DuplicatePackage { id, .. }) if id == "test.ws:a@1.0.0"
+    ));
+
+    write_manifest(&root, "root", "workspaces = [\"../outside/*\"]\n");
+    assert!(matches!(
+        Workspace::open(&root),
+        Err(WorkspaceError::Manifest(_))
+    ));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,359 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::io::StreamReader;
+use tracing::{trace, warn};
+use zako_digest::Digest;
+
+use crate::blob_range::BlobRange;
+use crate::cas::{Cas, CasError};
+
+/// The size of a chunk that [TieredCas] copies to the tiers.
+pub static FAN_OUT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The count of the chunks that a tier may fall behind the fastest one.
+pub static FAN_OUT_CHUNKS: usize = 4;
+
+/// A read-only tier is never stored to, neither by a store nor by a read-through population.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TierAccess {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// The hit and miss counters of a tier.
+#[derive(Debug, Default)]
+pub struct TierStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl TierStats {
+    pub fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn report(&self, name: &str) -> TierReport {
+        TierReport {
+            name: name.to_string(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A snapshot of [TierStats], printed at the end of a build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TierReport {
+    pub name: String,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl Display for TierReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} hits, {} misses",
+            self.name, self.hits, self.misses
+        )
+    }
+}
+
+/// A [Cas] in a [TieredCas].
+#[derive(Debug)]
+pub struct CasTier {
+    name: String,
+    cas: Arc<dyn Cas>,
+    access: TierAccess,
+    stats: TierStats,
+}
+
+impl CasTier {
+    pub fn new(name: impl Into<String>, cas: Arc<dyn Cas>, access: TierAccess) -> Self {
+        Self {
+            name: name.into(),
+            cas,
+            access,
+            stats: TierStats::default(),
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_cas(&self) -> &Arc<dyn Cas> {
+        &self.cas
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.access == TierAccess::ReadWrite
+    }
+
+    pub fn report(&self) -> TierReport {
+        self.stats.report(&self.name)
+    }
+}
+
+/// An ordered list of [CasTier], the faster one comes first.
+///
+/// - A fetch tries the tiers in order, a hit populates the faster writable tiers.
+/// - A store writes to all writable tiers.
+#[derive(Debug)]
+pub struct TieredCas {
+    tiers: Vec<CasTier>,
+}
+
+impl TieredCas {
+    pub fn new(tiers: Vec<CasTier>) -> Self {
+        Self { tiers }
+    }
+
+    pub fn get_tiers(&self) -> &[CasTier] {
+        &self.tiers
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.tiers.iter().any(CasTier::is_writable)
+    }
+
+    pub fn reports(&self) -> Vec<TierReport> {
+        self.tiers.iter().map(CasTier::report).collect()
+    }
+
+    /// Copy the whole blob from the tier at `hit` to the faster writable tiers.
+    ///
+    /// Returns the `range` of the blob from the fastest tier that has it now.
+    async fn populate(
+        &self,
+        digest: &Digest,
+        hit: usize,
+        range: &BlobRange,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, CasError> {
+        let reader = self.tiers[hit]
+            .cas
+            .fetch(digest, &BlobRange::full())
+            .await?;
+
+        let faster: Vec<&CasTier> = self.tiers[..hit]
+            .iter()
+            .filter(|tier| tier.is_writable())
+            .collect();
+
+        trace!(
+            "populate blob {} to {} cas tiers",
+            digest.hex_blake3(),
+            faster.len()
+        );
+
+        let results = store_all(digest, reader, &faster).await?;
+        let mut populated = None;
+
+        for (tier, result) in faster.iter().zip(results) {
+            match result {
+                Ok(()) => {
+                    populated.get_or_insert(*tier);
+                }
+                Err(err) => warn!(
+                    "failed to populate blob {} to cas tier {}: {}",
+                    digest.hex_blake3(),
+                    tier.name,
+                    err
+                ),
+            }
+        }
+
+        populated
+            .unwrap_or(&self.tiers[hit])
+            .cas
+            .fetch(digest, range)
+            .await
+    }
+}
+
+/// Store the `data` to every tier at the same time, the data is never buffered as a whole.
+///
+/// Returns the result of every tier, or the error of reading the `data`
+/// or of the `data` that does not match the `digest`.
+async fn store_all(
+    digest: &Digest,
+    mut data: impl AsyncRead + Unpin,
+    tiers: &[&CasTier],
+) -> Result<Vec<Result<(), CasError>>, CasError> {
+    let (senders, stores): (Vec<_>, Vec<_>) = tiers
+        .iter()
+        .map(|tier| {
+            let (sender, receiver) = mpsc::channel::<std::io::Result<Bytes>>(FAN_OUT_CHUNKS);
+            let reader = StreamReader::new(ReceiverStream::new(receiver));
+
+            (Some(sender), tier.cas.store(digest, Box::new(reader)))
+        })
+        .unzip();
+
+    let feed = async move {
+        let mut senders = senders;
+        let mut hasher = blake3::Hasher::new();
+        let mut size: u64 = 0;
+
+        loop {
+            let mut chunk = BytesMut::with_capacity(FAN_OUT_CHUNK_SIZE);
+
+            let chunk = match data.read_buf(&mut chunk).await {
+                Ok(0) if Digest::new(size, hasher.finalize().into()) == *digest => return Ok(()),
+                // the stores read the error instead of the end, so none of them keeps the blob
+                Ok(0) => Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("the data does not match the blob {}", digest.hex_blake3()),
+                )),
+                Ok(_) => {
+                    hasher.update(&chunk);
+                    size += chunk.len() as u64;
+                    Ok(chunk.freeze())
+                }
+                Err(err) => Err(err),
+            };
+
+            for slot in senders.iter_mut() {
+                let Some(sender) = slot.as_ref() else {
+                    continue;
+                };
+
+                // a store that reads a failed chunk fails instead of storing a part of the blob
+                let item = match &chunk {
+                    Ok(chunk) => Ok(chunk.clone()),
+                    Err(err) => Err(std::io::Error::new(err.kind(), err.to_string())),
+                };
+
+                // the store has failed and dropped its reader
+                if sender.send(item).await.is_err() {
+                    *slot = None;
+                }
+            }
+
+            chunk.map_err(|err| CasError::Io(err, None))?;
+        }
+    };
+
+    let (fed, results) = tokio::join!(feed, futures::future::join_all(stores));
+    fed?;
+
+    Ok(results)
+}
+
+#[async_trait]
+impl Cas for TieredCas {
+    async fn store(
+        &self,
+        digest: &Digest,
+        data: Box<dyn AsyncRead + Send + Unpin + 'static>,
+    ) -> Result<(), CasError> {
+        let writable: Vec<&CasTier> = self.tiers.iter().filter(|t| t.is_writable()).collect();
+
+        match writable.as_slice() {
+            [] => Ok(()),
+            [tier] => tier.cas.store(digest, data).await,
+            tiers => {
+                let mut result = Ok(());
+
+                for (tier, stored) in tiers.iter().zip(store_all(digest, data, tiers).await?) {
+                    if let Err(err) = stored {
+                        result = Err(CasError::Internal(format!(
+                            "failed to store blob to cas tier {}: {}",
+                            tier.name, err
+                        )));
+                    }
+                }
+
+                result
+            }
+        }
+    }
+
+    async fn check(&self, digest: &Digest) -> Option<u64> {
+        for tier in self.tiers.iter() {
+            if let Some(length) = tier.cas.check(digest).await {
+                return Some(length);
+            }
+        }
+
+        None
+    }
+
+    async fn contains(&self, digest: &Digest) -> bool {
+        for tier in self.tiers.iter() {
+            if tier.cas.contains(digest).await {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    async fn fetch(
+        &self,
+        digest: &Digest,
+        range: &BlobRange,
+    ) -> Result<Pin<Box<dyn AsyncRead + Send>>, CasError> {
+        let mut last_error = None;
+
+        for (index, tier) in self.tiers.iter().enumerate() {
+            if !tier.cas.contains(digest).await {
+                tier.stats.miss();
+                continue;
+            }
+
+            let needs_populate = self.tiers[..index].iter().any(CasTier::is_writable);
+
+            let result = if needs_populate {
+                self.populate(digest, index, range).await
+            } else {
+                tier.cas.fetch(digest, range).await
+            };
+
+            match result {
+                Ok(reader) => {
+                    tier.stats.hit();
+                    return Ok(reader);
+                }
+                Err(err @ CasError::RequestedIndexOutOfRange { .. }) => return Err(err),
+                Err(err) => {
+                    warn!(
+                        "failed to fetch blob {} from cas tier {}: {}",
+                        digest.hex_blake3(),
+                        tier.name,
+                        err
+                    );
+                    tier.stats.miss();
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            CasError::NotFound(*digest, PathBuf::from("<not found in any cas tier>"))
+        }))
+    }
+
+    async fn get_local_path(&self, digest: &Digest) -> Option<PathBuf> {
+        for tier in self.tiers.iter() {
+            if let Some(path) = tier.cas.get_local_path(digest).await {
+                return Some(path);
+            }
+        }
+
+        None
+    }
+}
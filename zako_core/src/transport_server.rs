@@ -1,4 +1,5 @@
 use crate::cas::{Cas, CasError};
+use crate::cas_token::{TokenInterceptor, TokenStore};
 use crate::protobuf::transport::upload_request::Payload::Metadata;
 use crate::protobuf::transport::{
     DownloadRequest, DownloadResponse, UploadRequest, UploadResponse,
@@ -8,6 +9,7 @@ use std::sync::Arc;
 use std::sync::atomic::AtomicU64;
 use tokio_stream::{Stream, StreamExt};
 use tonic::async_trait;
+use tonic::service::interceptor::InterceptedService;
 use tonic::{Request, Response, Status, Streaming};
 
 #[derive(Debug)]
@@ -15,10 +17,28 @@ pub struct TransportServer {
     cas: Arc<dyn Cas + 'static>,
 }
 
+pub type AuthenticatedTransportService = InterceptedService<
+    crate::protobuf::transport::transport_server::TransportServer<TransportServer>,
+    TokenInterceptor,
+>;
+
 impl TransportServer {
     pub fn new(cas: Arc<dyn Cas + 'static>) -> Self {
         Self { cas }
     }
+
+    /// Wrap the server into a service that requires a valid token from the [TokenStore].
+    ///
+    /// Use the same store with [crate::cas_server::CasServer] so the tokens it issues are accepted here.
+    pub fn into_authenticated_service(
+        self,
+        tokens: Arc<TokenStore>,
+    ) -> AuthenticatedTransportService {
+        crate::protobuf::transport::transport_server::TransportServer::with_interceptor(
+            self,
+            TokenInterceptor::new(tokens),
+        )
+    }
 }
 
 #[async_trait]
@@ -0,0 +1,340 @@
+//! The `vendor/` directory of a workspace, it holds a copy of every fetched dependency so the
+//! builds need no network.
+//!
+//! The [VendorConfig] in the directory replaces the requested sources with the copies,
+//! a copy is used only if it is what the lockfile pins.
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cas_store::CasStore;
+use crate::consts::VENDOR_CONFIG_FILE_NAME;
+use crate::fetch::{FetchError, PackageCache, copy_dir};
+use crate::lockfile::Lockfile;
+use crate::manifest;
+use crate::package::{Package, PackageResolveError};
+use crate::package_source::PackageSource;
+
+/// The version of the vendor config format that is written.
+pub static VENDOR_CONFIG_VERSION: u32 = 1;
+
+/// The comment at the top of the written vendor config.
+pub static VENDOR_CONFIG_HEADER: &str =
+    "# This file is generated by `zako vendor`, do not edit it by hand.\n";
+
+#[derive(Debug, thiserror::Error)]
+pub enum VendorError {
+    #[error("io error at {1:?}: {0}")]
+    Io(#[source] std::io::Error, PathBuf),
+    #[error("the vendor config {0:?} is invalid: {1}")]
+    Parse(PathBuf, #[source] toml::de::Error),
+    #[error("failed to serialize the vendor config: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    #[error(
+        "the vendor config version {0} is not supported, the supported version is {VENDOR_CONFIG_VERSION}"
+    )]
+    UnsupportedVersion(u32),
+    #[error(transparent)]
+    Fetch(#[from] FetchError),
+    #[error("the package {0:?} has no manifest")]
+    NoManifest(PathBuf),
+    #[error("failed to load the manifest of the package {0:?}: {1}")]
+    Manifest(PathBuf, #[source] Box<PackageResolveError>),
+    #[error("the package {0:?} is not vendored, run `zako vendor` again")]
+    NotVendored(PackageSource),
+    #[error(
+        "the vendored copy of the package {0:?} is not what the lockfile pins or is missing, run `zako vendor` again"
+    )]
+    Stale(PackageSource),
+}
+
+/// A dependency that is copied into the vendor directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VendoredPackage {
+    /// The source in the manifest, like [crate::lockfile::LockedPackage::requested].
+    pub requested: PackageSource,
+    /// The source that pins the copied content, like [crate::lockfile::LockedPackage::pinned].
+    pub pinned: PackageSource,
+    /// The directory of the copy in the vendor directory, like `example-1.0.0-1a2b3c4d`.
+    pub path: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VendorConfig {
+    pub version: u32,
+    #[serde(rename = "package", default)]
+    pub packages: Vec<VendoredPackage>,
+}
+
+impl Default for VendorConfig {
+    fn default() -> Self {
+        Self {
+            version: VENDOR_CONFIG_VERSION,
+            packages: Vec::new(),
+        }
+    }
+}
+
+fn sort_key(source: &PackageSource) -> String {
+    serde_json::to_string(source).unwrap_or_default()
+}
+
+impl VendorConfig {
+    pub fn parse(path: &Path, content: &str) -> Result<Self, VendorError> {
+        let config: Self =
+            toml::from_str(content).map_err(|err| VendorError::Parse(path.to_path_buf(), err))?;
+
+        if config.version != VENDOR_CONFIG_VERSION {
+            return Err(VendorError::UnsupportedVersion(config.version));
+        }
+
+        Ok(config)
+    }
+
+    /// Read the vendor config, `None` if it does not exist.
+    pub fn load(path: &Path) -> Result<Option<Self>, VendorError> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Ok(Some(Self::parse(path, &content)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(VendorError::Io(err, path.to_path_buf())),
+        }
+    }
+
+    /// The content to write, the same packages always give the same content.
+    pub fn to_toml(&self) -> Result<String, VendorError> {
+        let mut sorted = self.clone();
+        sorted
+            .packages
+            .sort_by_cached_key(|package| sort_key(&package.requested));
+
+        Ok(format!(
+            "{}{}",
+            VENDOR_CONFIG_HEADER,
+            toml::to_string(&sorted)?
+        ))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), VendorError> {
+        std::fs::write(path, self.to_toml()?)
+            .map_err(|err| VendorError::Io(err, path.to_path_buf()))
+    }
+
+    pub fn get(&self, requested: &PackageSource) -> Option<&VendoredPackage> {
+        self.packages
+            .iter()
+            .find(|package| &package.requested == requested)
+    }
+}
+
+/// The vendor directory that the builds resolve the dependencies from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vendor {
+    root: PathBuf,
+    config: VendorConfig,
+}
+
+impl Vendor {
+    pub fn new(root: PathBuf, config: VendorConfig) -> Self {
+        Self { root, config }
+    }
+
+    /// Open the vendor directory `root`, `None` if it has no vendor config.
+    pub fn open(root: &Path) -> Result<Option<Self>, VendorError> {
+        Ok(VendorConfig::load(&root.join(VENDOR_CONFIG_FILE_NAME))?
+            .map(|config| Self::new(root.to_path_buf(), config)))
+    }
+
+    pub fn get_root(&self) -> &PathBuf {
+        &self.root
+    }
+
+    pub fn get_config(&self) -> &VendorConfig {
+        &self.config
+    }
+
+    /// The copy of the `requested` source and what it pins.
+    ///
+    /// The builds never fetch when the dependencies are vendored, so a source that is not
+    /// vendored or whose copy is not what the `locked` source from the lockfile pins is an error.
+    pub fn replace(
+        &self,
+        requested: &PackageSource,
+        locked: Option<&PackageSource>,
+    ) -> Result<(PathBuf, &PackageSource), VendorError> {
+        let vendored = self
+            .config
+            .get(requested)
+            .ok_or_else(|| VendorError::NotVendored(requested.clone()))?;
+
+        let root = self.root.join(&vendored.path);
+
+        if locked.is_some_and(|locked| locked != &vendored.pinned) || !root.is_dir() {
+            return Err(VendorError::Stale(requested.clone()));
+        }
+
+        Ok((root, &vendored.pinned))
+    }
+}
+
+/// What [vendor] did.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VendorReport {
+    /// The packages that are copied into the vendor directory.
+    pub copied: usize,
+    /// The packages whose copies are up to date.
+    pub reused: usize,
+    /// The stale copies that are removed.
+    pub removed: usize,
+}
+
+/// The directory name of a copy, the hash of the `pinned` source keeps the copies of
+/// different commits or archives of the same version apart.
+fn vendored_name(artifact: &str, version: &str, pinned: &PackageSource) -> String {
+    format!(
+        "{}-{}-{}",
+        artifact,
+        version,
+        &blake3::hash(sort_key(pinned).as_bytes()).to_hex()[..8]
+    )
+}
+
+/// Copy the `root` into the vendor directory as `name` unless it exists already.
+async fn copy_into(vendor_dir: &Path, root: PathBuf, name: &str) -> Result<bool, VendorError> {
+    let target = vendor_dir.join(name);
+
+    if target.is_dir() {
+        return Ok(false);
+    }
+
+    // copy aside and rename, an interrupted copy is never taken as complete
+    let staged = vendor_dir.join(format!(".tmp_{}", uuid::Uuid::new_v4()));
+    let copy_staged = staged.clone();
+
+    tokio::task::spawn_blocking(move || copy_dir(&root, &copy_staged))
+        .await
+        .map_err(|err| VendorError::Io(std::io::Error::other(err), staged.clone()))??;
+
+    if let Err(err) = tokio::fs::rename(&staged, &target).await {
+        let _ = tokio::fs::remove_dir_all(&staged).await;
+        return Err(VendorError::Io(err, target));
+    }
+
+    Ok(true)
+}
+
+/// Copy the `requested` sources and their dependencies into the `vendor_dir`, then write
+/// the vendor config that replaces them. The path sources are skipped.
+///
+/// The pins of the `lockfile` are fetched and the new pins are recorded to it.
+/// A copy of what is pinned already is reused without fetching, the others are fetched
+/// through the `cache` so re-vendoring only fetches what changed.
+/// The copies that nothing needs any more are removed.
+pub async fn vendor(
+    vendor_dir: &Path,
+    lockfile: &mut Lockfile,
+    cache: &PackageCache,
+    cas: &CasStore,
+    requested: impl IntoIterator<Item = PackageSource>,
+) -> Result<VendorReport, VendorError> {
+    tokio::fs::create_dir_all(vendor_dir)
+        .await
+        .map_err(|err| VendorError::Io(err, vendor_dir.to_path_buf()))?;
+
+    let config_path = vendor_dir.join(VENDOR_CONFIG_FILE_NAME);
+    let previous = VendorConfig::load(&config_path)?.unwrap_or_default();
+
+    let mut config = VendorConfig::default();
+    let mut report = VendorReport::default();
+    let mut queue: Vec<PackageSource> = requested.into_iter().collect();
+    let mut seen = BTreeSet::new();
+
+    while let Some(source) = queue.pop() {
+        if matches!(source, PackageSource::Path { .. }) || !seen.insert(sort_key(&source)) {
+            continue;
+        }
+
+        let locked = lockfile.get(&source).cloned();
+
+        // the copy of the pinned content is reused as is
+        let reusable = previous.get(&source).filter(|vendored| {
+            locked.as_ref() == Some(&vendored.pinned) && vendor_dir.join(&vendored.path).is_dir()
+        });
+
+        let vendored = match reusable {
+            Some(vendored) => {
+                report.reused += 1;
+                vendored.clone()
+            }
+            None => {
+                let fetched = cache.fetch(locked.as_ref().unwrap_or(&source), cas).await?;
+                let package = load_manifest(&fetched.root)?;
+                let name = vendored_name(&package.artifact, &package.version, &fetched.pinned);
+
+                if copy_into(vendor_dir, fetched.root, &name).await? {
+                    report.copied += 1;
+                } else {
+                    report.reused += 1;
+                }
+
+                VendoredPackage {
+                    requested: source.clone(),
+                    pinned: fetched.pinned,
+                    path: name,
+                }
+            }
+        };
+
+        lockfile.insert(source, vendored.pinned.clone());
+
+        let package = load_manifest(&vendor_dir.join(&vendored.path))?;
+        queue.extend(
+            package
+                .dependencies
+                .into_iter()
+                .flatten()
+                .map(|(_, source)| source),
+        );
+
+        config.packages.push(vendored);
+    }
+
+    let used: BTreeSet<&str> = config
+        .packages
+        .iter()
+        .map(|package| package.path.as_str())
+        .collect();
+
+    // only the directories that the previous config names are removed, not the files of the user
+    let stale: BTreeSet<&str> = previous
+        .packages
+        .iter()
+        .map(|package| package.path.as_str())
+        .filter(|path| !used.contains(path))
+        .collect();
+
+    for path in stale {
+        let stale = vendor_dir.join(path);
+
+        if stale.is_dir() {
+            tokio::fs::remove_dir_all(&stale)
+                .await
+                .map_err(|err| VendorError::Io(err, stale.clone()))?;
+            report.removed += 1;
+        }
+    }
+
+    config.write(&config_path)?;
+
+    Ok(report)
+}
+
+fn load_manifest(root: &Path) -> Result<Package, VendorError> {
+    let utf8_root = camino::Utf8Path::from_path(root)
+        .ok_or_else(|| VendorError::NoManifest(root.to_path_buf()))?;
+    let (path, _) =
+        manifest::find(utf8_root).ok_or_else(|| VendorError::NoManifest(root.to_path_buf()))?;
+
+    Package::load(path.as_std_path())
+        .map_err(|err| VendorError::Manifest(root.to_path_buf(), Box::new(err)))
+}
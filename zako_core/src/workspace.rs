@@ -0,0 +1,289 @@
+//! A workspace is a package whose manifest lists the directories of its member packages
+//! with [Package::workspaces], so they are built together and share one `zako.lock`.
+//!
+//! A member is a directory that holds a [PACKAGE_MANIFEST_FILE_NAME] or a
+//! [PACKAGE_JSON_MANIFEST_FILE_NAME] and matches a pattern,
+//! the directories ignored by `.gitignore` and the hidden directories are skipped.
+use camino::{Utf8Path, Utf8PathBuf};
+use ignore::overrides::OverrideBuilder;
+
+use crate::consts::{PACKAGE_JSON_MANIFEST_FILE_NAME, PACKAGE_MANIFEST_FILE_NAME};
+use crate::diagnostic::Diagnostics;
+use crate::global_state::GlobalState;
+use crate::intern::{InternedAbsolutePath, Interner};
+use crate::manifest::{self, ManifestFormat, ManifestSource};
+use crate::package::{Package, PackageResolveError};
+use crate::package_id::{InternedPackageId, PackageIdParseError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum WorkspaceError {
+    #[error("io error at {1:?}: {0}")]
+    Io(#[source] std::io::Error, Utf8PathBuf),
+    #[error(
+        "no `{PACKAGE_MANIFEST_FILE_NAME}` or `{PACKAGE_JSON_MANIFEST_FILE_NAME}` is found in {0:?} or its parent directories"
+    )]
+    NoManifest(Utf8PathBuf),
+    #[error(transparent)]
+    Manifest(#[from] PackageResolveError),
+    #[error("the workspace pattern `{0}` is invalid: {1}")]
+    InvalidPattern(String, #[source] ignore::Error),
+    #[error("failed to walk the workspace {0:?}: {1}")]
+    Walk(Utf8PathBuf, #[source] ignore::Error),
+    #[error("the path {0:?} is not valid utf-8")]
+    NonUtf8Path(std::path::PathBuf),
+    #[error("the package `{id}` is in both {first:?} and {second:?}")]
+    DuplicatePackage {
+        id: String,
+        first: Utf8PathBuf,
+        second: Utf8PathBuf,
+    },
+    #[error("the package id `{0}` is invalid: {1}")]
+    InvalidId(String, #[source] PackageIdParseError),
+    #[error("interner error: {0}")]
+    Interner(#[from] eyre::Report),
+}
+
+/// A package of the [Workspace].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceMember {
+    /// The absolute path of the package root.
+    pub root: Utf8PathBuf,
+    pub manifest: Package,
+}
+
+impl WorkspaceMember {
+    fn load(root: Utf8PathBuf) -> Result<Self, WorkspaceError> {
+        let source = load_manifest(&root)?;
+        let manifest = source.parse().map_err(PackageResolveError::from)?;
+        source
+            .validate(&manifest)
+            .map_err(|err| PackageResolveError::from(Diagnostics::from(err)))?;
+
+        Ok(Self { root, manifest })
+    }
+
+    /// Like `com.example:name@1.0.0`.
+    pub fn get_id(&self) -> String {
+        format!(
+            "{}:{}@{}",
+            self.manifest.group, self.manifest.artifact, self.manifest.version
+        )
+    }
+
+    /// The id declared by the manifest.
+    pub fn get_package_id(&self, interner: &Interner) -> Result<InternedPackageId, WorkspaceError> {
+        let id = self.get_id();
+
+        InternedPackageId::try_parse(&id, interner)
+            .map_err(|err| WorkspaceError::InvalidId(id, err))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workspace {
+    root: Utf8PathBuf,
+    /// The root package is the first one, the others are sorted by their paths.
+    members: Vec<WorkspaceMember>,
+}
+
+fn has_manifest(dir: &Utf8Path) -> bool {
+    manifest::find(dir).is_some()
+}
+
+fn load_manifest(dir: &Utf8Path) -> Result<ManifestSource, WorkspaceError> {
+    let (path, _) =
+        manifest::find(dir).ok_or_else(|| WorkspaceError::NoManifest(dir.to_path_buf()))?;
+
+    Ok(ManifestSource::load(path.as_std_path())?)
+}
+
+fn canonicalize(path: &Utf8Path) -> Result<Utf8PathBuf, WorkspaceError> {
+    path.canonicalize_utf8()
+        .map_err(|err| WorkspaceError::Io(err, path.to_path_buf()))
+}
+
+impl Workspace {
+    /// Open the package at `root`, the members are discovered if it is a workspace.
+    pub fn open(root: &Utf8Path) -> Result<Self, WorkspaceError> {
+        let root = canonicalize(root)?;
+
+        if !has_manifest(&root) {
+            return Err(WorkspaceError::NoManifest(root));
+        }
+
+        let package = WorkspaceMember::load(root.clone())?;
+        let patterns = package.manifest.workspaces.clone().unwrap_or_default();
+
+        let mut members = vec![package];
+        members.extend(discover_members(&root, &patterns)?);
+
+        let this = Self { root, members };
+        this.check_duplicates()?;
+
+        Ok(this)
+    }
+
+    /// Find the workspace that encloses the directory `start`.
+    ///
+    /// The nearest workspace that has the nearest package as a member wins,
+    /// the nearest package is a workspace of itself if no workspace has it.
+    pub fn discover(start: &Utf8Path) -> Result<Self, WorkspaceError> {
+        let start = canonicalize(start)?;
+
+        let nearest = start
+            .ancestors()
+            .find(|dir| has_manifest(dir))
+            .ok_or_else(|| WorkspaceError::NoManifest(start.clone()))?;
+
+        for dir in nearest.ancestors() {
+            if !has_manifest(dir) {
+                continue;
+            }
+
+            let manifest = load_manifest(dir)?
+                .parse()
+                .map_err(PackageResolveError::from)?;
+            let Some(patterns) = manifest.workspaces.as_ref() else {
+                continue;
+            };
+
+            if dir == nearest || matches_member(dir, patterns, nearest)? {
+                return Self::open(dir);
+            }
+        }
+
+        Self::open(nearest)
+    }
+
+    pub fn get_root(&self) -> &Utf8PathBuf {
+        &self.root
+    }
+
+    pub fn get_root_package(&self) -> &WorkspaceMember {
+        &self.members[0]
+    }
+
+    /// All packages of the workspace, the root package is the first one.
+    pub fn get_members(&self) -> &[WorkspaceMember] {
+        &self.members
+    }
+
+    /// The nearest package that has the `path` in its directory.
+    pub fn get_member_of(&self, path: &Utf8Path) -> Option<&WorkspaceMember> {
+        self.members
+            .iter()
+            .filter(|member| path.starts_with(&member.root))
+            .max_by_key(|member| member.root.components().count())
+    }
+
+    pub fn get_member(&self, id: &str) -> Option<&WorkspaceMember> {
+        self.members.iter().find(|member| member.get_id() == id)
+    }
+
+    fn check_duplicates(&self) -> Result<(), WorkspaceError> {
+        for (index, member) in self.members.iter().enumerate() {
+            let id = member.get_id();
+
+            if let Some(other) = self.members[..index]
+                .iter()
+                .find(|other| other.get_id() == id)
+            {
+                return Err(WorkspaceError::DuplicatePackage {
+                    id,
+                    first: other.root.clone(),
+                    second: member.root.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Register the root of every package to [GlobalState::package_id_to_path].
+    pub fn register(
+        &self,
+        global_state: &GlobalState,
+    ) -> Result<Vec<InternedPackageId>, WorkspaceError> {
+        let interner = global_state.interner();
+        let mut registered = Vec::with_capacity(self.members.len());
+
+        for member in self.members.iter() {
+            let package = member.get_package_id(interner)?;
+            let root = InternedAbsolutePath::new(&member.root, interner)?;
+
+            global_state.package_id_to_path().insert(package, root);
+            registered.push(package);
+        }
+
+        Ok(registered)
+    }
+}
+
+fn build_matcher(
+    root: &Utf8Path,
+    patterns: &[smol_str::SmolStr],
+) -> Result<ignore::overrides::Override, WorkspaceError> {
+    let mut builder = OverrideBuilder::new(root);
+
+    for pattern in patterns {
+        builder
+            .add(pattern)
+            .map_err(|err| WorkspaceError::InvalidPattern(pattern.to_string(), err))?;
+    }
+
+    builder
+        .build()
+        .map_err(|err| WorkspaceError::InvalidPattern(patterns.join(", "), err))
+}
+
+/// Whether the directory `dir` matches the member `patterns` of the workspace at `root`.
+fn matches_member(
+    root: &Utf8Path,
+    patterns: &[smol_str::SmolStr],
+    dir: &Utf8Path,
+) -> Result<bool, WorkspaceError> {
+    Ok(build_matcher(root, patterns)?
+        .matched(dir, true)
+        .is_whitelist())
+}
+
+/// The member packages under `root` that match the `patterns`, sorted by their paths.
+fn discover_members(
+    root: &Utf8Path,
+    patterns: &[smol_str::SmolStr],
+) -> Result<Vec<WorkspaceMember>, WorkspaceError> {
+    if patterns.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let matcher = build_matcher(root, patterns)?;
+    let mut roots = Vec::new();
+
+    for entry in ignore::WalkBuilder::new(root).build() {
+        let entry = entry.map_err(|err| WorkspaceError::Walk(root.to_path_buf(), err))?;
+
+        if !entry
+            .file_name()
+            .to_str()
+            .is_some_and(ManifestFormat::is_manifest_file_name)
+            || !entry.file_type().is_some_and(|kind| kind.is_file())
+        {
+            continue;
+        }
+
+        let Some(dir) = entry.path().parent() else {
+            continue;
+        };
+
+        let dir = Utf8Path::from_path(dir)
+            .ok_or_else(|| WorkspaceError::NonUtf8Path(dir.to_path_buf()))?;
+
+        if dir != root && matcher.matched(dir, true).is_whitelist() {
+            roots.push(dir.to_path_buf());
+        }
+    }
+
+    roots.sort();
+
+    roots.into_iter().map(WorkspaceMember::load).collect()
+}